@@ -0,0 +1,56 @@
+//! Build metadata for `--version`: the git commit, the build date and the
+//! enabled cargo features, passed to the crate as environment variables.
+//! Packagers building from a tarball can set `DYNSIX_GIT_COMMIT`, and
+//! `SOURCE_DATE_EPOCH` makes the date reproducible.
+
+use std::{
+    path::Path,
+    process::Command,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+fn main() {
+    println!("cargo:rerun-if-env-changed=DYNSIX_GIT_COMMIT");
+    println!("cargo:rerun-if-env-changed=SOURCE_DATE_EPOCH");
+    for path in [".git/HEAD", ".git/refs/heads", ".git/packed-refs"] {
+        if Path::new(path).exists() {
+            println!("cargo:rerun-if-changed={path}");
+        }
+    }
+
+    let commit = std::env::var("DYNSIX_GIT_COMMIT")
+        .ok()
+        .or_else(git_commit)
+        .unwrap_or_else(|| "unknown".to_string());
+    println!("cargo:rustc-env=DYNSIX_GIT_COMMIT={commit}");
+
+    let seconds = std::env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .and_then(|epoch| epoch.parse().ok())
+        .unwrap_or_else(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |since| since.as_secs())
+        });
+    let date = humantime::format_rfc3339(UNIX_EPOCH + Duration::from_secs(seconds)).to_string();
+    println!("cargo:rustc-env=DYNSIX_BUILD_DATE={}", &date[..10]);
+
+    let mut features: Vec<_> = std::env::vars()
+        .filter_map(|(name, _)| {
+            let feature = name.strip_prefix("CARGO_FEATURE_")?;
+            Some(feature.to_lowercase().replace('_', "-"))
+        })
+        .collect();
+    features.sort();
+    println!("cargo:rustc-env=DYNSIX_FEATURES={}", features.join(","));
+}
+
+fn git_commit() -> Option<String> {
+    let output = Command::new("git")
+        .args(["rev-parse", "--short=12", "HEAD"])
+        .output()
+        .ok()?;
+    let commit = String::from_utf8(output.stdout).ok()?;
+    let commit = commit.trim();
+    (output.status.success() && !commit.is_empty()).then(|| commit.to_string())
+}
@@ -0,0 +1,267 @@
+//! HTTP API of the daemon:
+//!
+//! - `GET /status`: JSON with the last result and last change of every service
+//! - `POST /reconcile[?service=PATTERN...]`: runs immediately and answers with
+//!   the report of the run
+//! - `POST /prefix`: like `/reconcile`, with the prefix in the body instead of
+//!   asking the query server. Requires `Authorization: Bearer <prefix_token>`.
+//! - `GET /healthz`: 200 unless no run finished for two intervals, for
+//!   liveness probes and `HEALTHCHECK` of Docker
+//! - `GET /readyz`: 200 only if the last run succeeded and isn't stale either
+//! - `GET /events`: Server-Sent Events from then on, `prefix_detected`,
+//!   `record_updated` and `error`, each with its data as JSON
+
+use std::{
+    convert::Infallible,
+    future::Future,
+    net::{Ipv6Addr, SocketAddr},
+    time::{Duration, SystemTime},
+};
+
+use hyper::{
+    header::{AUTHORIZATION, CACHE_CONTROL, CONTENT_TYPE},
+    service::{make_service_fn, service_fn},
+    Body, Method, Request, Response, Server, StatusCode,
+};
+use serde::Serialize;
+use serde_json::json;
+use tokio::sync::{broadcast, oneshot};
+use tracing::warn;
+
+use crate::{
+    cli::parse_prefix,
+    daemon::{self, Event, Handle, Trigger, TriggerError},
+};
+
+/// How often a comment is sent on `/events` while nothing happens, so proxies
+/// keep the connection open
+const KEEP_ALIVE: Duration = Duration::from_secs(30);
+
+/// Binds `listen` right away, so a port in use fails the start of the daemon.
+/// The returned future serves requests until it is dropped.
+pub fn serve(listen: SocketAddr, handle: Handle) -> Result<impl Future<Output = ()>, hyper::Error> {
+    let server = Server::try_bind(&listen)?.serve(make_service_fn(move |_| {
+        let handle = handle.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |request| {
+                let handle = handle.clone();
+                async move { Ok::<_, Infallible>(route(request, handle).await) }
+            }))
+        }
+    }));
+
+    Ok(async move {
+        if let Err(e) = server.await {
+            warn!("HTTP API stopped: {e}");
+        }
+    })
+}
+
+async fn route(request: Request<Body>, handle: Handle) -> Response<Body> {
+    match (request.method(), request.uri().path()) {
+        (&Method::GET, "/status") => {
+            let status = handle.status.lock().unwrap().clone();
+            json_response(StatusCode::OK, &status)
+        }
+        (&Method::POST, "/reconcile") => {
+            let services = query_values(&request, "service");
+            reconcile(handle, services, None).await
+        }
+        (&Method::POST, "/prefix") => push_prefix(request, handle).await,
+        (&Method::GET, "/healthz") => {
+            let health = Health::of(&handle);
+            json_response(health.status(!health.stale), &health)
+        }
+        (&Method::GET, "/readyz") => {
+            let health = Health::of(&handle);
+            json_response(
+                health.status(!health.stale && health.succeeded == Some(true)),
+                &health,
+            )
+        }
+        (&Method::GET, "/events") => events(handle.events.subscribe()),
+        (_, "/status" | "/reconcile" | "/prefix" | "/healthz" | "/readyz" | "/events") => {
+            error(StatusCode::METHOD_NOT_ALLOWED, "method not allowed")
+        }
+        _ => error(StatusCode::NOT_FOUND, "not found"),
+    }
+}
+
+/// The answer of the probes, from the status in memory only
+#[derive(Serialize)]
+struct Health {
+    /// When the last run finished, `None` before the first
+    last_run: Option<String>,
+    /// Seconds since the last run finished, or else since the daemon started
+    age_seconds: u64,
+    stale_after_seconds: u64,
+    stale: bool,
+    /// Whether the last run and every service in it succeeded
+    succeeded: Option<bool>,
+}
+
+impl Health {
+    fn of(handle: &Handle) -> Self {
+        let status = handle.status.lock().unwrap();
+        let stale_after = *handle.stale_after.lock().unwrap();
+        let since = status
+            .last_run
+            .as_ref()
+            .and_then(|run| humantime::parse_rfc3339(&run.finished_at).ok())
+            .unwrap_or(handle.started);
+        let age = SystemTime::now().duration_since(since).unwrap_or_default();
+        Self {
+            last_run: status.last_run.as_ref().map(|run| run.finished_at.clone()),
+            age_seconds: age.as_secs(),
+            stale_after_seconds: stale_after.as_secs(),
+            stale: age > stale_after,
+            succeeded: status.last_run.as_ref().map(|run| {
+                run.error.is_none()
+                    && status
+                        .services
+                        .values()
+                        .all(|service| service.error.is_none())
+            }),
+        }
+    }
+
+    fn status(&self, ok: bool) -> StatusCode {
+        if ok {
+            StatusCode::OK
+        } else {
+            StatusCode::SERVICE_UNAVAILABLE
+        }
+    }
+}
+
+/// Accepts the prefix as plain text or as JSON `{"prefix": "..."}`, with an
+/// optional length of at most /64
+async fn push_prefix(request: Request<Body>, handle: Handle) -> Response<Body> {
+    let Some(token) = handle.prefix_token.lock().unwrap().clone() else {
+        return error(StatusCode::NOT_FOUND, "not found");
+    };
+    let authorized = request
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .is_some_and(|given| constant_time_eq(given.as_bytes(), token.expose().as_bytes()));
+    if !authorized {
+        return error(StatusCode::UNAUTHORIZED, "missing or wrong bearer token");
+    }
+
+    let services = query_values(&request, "service");
+    let body = match hyper::body::to_bytes(request.into_body()).await {
+        Ok(body) => body,
+        Err(e) => return error(StatusCode::BAD_REQUEST, &e.to_string()),
+    };
+    let body = String::from_utf8_lossy(&body);
+    let raw = match serde_json::from_str::<serde_json::Value>(&body) {
+        Ok(json) => match json.get("prefix").and_then(|prefix| prefix.as_str()) {
+            Some(prefix) => prefix.to_string(),
+            None => return error(StatusCode::BAD_REQUEST, "expected {\"prefix\": \"...\"}"),
+        },
+        Err(_) => body.trim().to_string(),
+    };
+
+    match parse_prefix(&raw) {
+        Ok(prefix) => reconcile(handle, services, Some(prefix)).await,
+        Err(e) => error(StatusCode::BAD_REQUEST, &e),
+    }
+}
+
+async fn reconcile(
+    handle: Handle,
+    services: Vec<String>,
+    prefix: Option<Ipv6Addr>,
+) -> Response<Body> {
+    let (reply, result) = oneshot::channel();
+    if handle
+        .requests
+        .send(daemon::Request::Reconcile(Trigger {
+            services,
+            prefix,
+            reply,
+        }))
+        .await
+        .is_err()
+    {
+        return error(StatusCode::SERVICE_UNAVAILABLE, "daemon is shutting down");
+    }
+
+    match result.await {
+        Ok(Ok(report)) => json_response(StatusCode::OK, &report),
+        Ok(Err(TriggerError::NoMatch(pattern))) => error(
+            StatusCode::BAD_REQUEST,
+            &format!("no configured service matches '{pattern}'"),
+        ),
+        Ok(Err(TriggerError::Failed(message))) => {
+            error(StatusCode::INTERNAL_SERVER_ERROR, &message)
+        }
+        Err(_) => error(StatusCode::SERVICE_UNAVAILABLE, "daemon is shutting down"),
+    }
+}
+
+/// Streams the events until the client goes away or the daemon stops
+fn events(mut events: broadcast::Receiver<Event>) -> Response<Body> {
+    let (mut sender, body) = Body::channel();
+    tokio::spawn(async move {
+        let mut keep_alive = tokio::time::interval(KEEP_ALIVE);
+        // The first tick is right away, which tells the client it's connected
+        loop {
+            let chunk = tokio::select! {
+                _ = keep_alive.tick() => ": keep-alive\n\n".to_string(),
+                event = events.recv() => match event {
+                    Ok(event) => format!(
+                        "event: {}\ndata: {}\n\n",
+                        event.name(),
+                        serde_json::to_string(&event).unwrap_or_default()
+                    ),
+                    Err(broadcast::error::RecvError::Lagged(missed)) => {
+                        warn!("A client of /events missed {missed} events");
+                        continue;
+                    }
+                    Err(broadcast::error::RecvError::Closed) => return,
+                },
+            };
+            if sender.send_data(chunk.into()).await.is_err() {
+                return;
+            }
+        }
+    });
+    Response::builder()
+        .header(CONTENT_TYPE, "text/event-stream")
+        .header(CACHE_CONTROL, "no-cache")
+        .body(body)
+        .unwrap()
+}
+
+/// All values of the query parameter `name`
+fn query_values(request: &Request<Body>, name: &str) -> Vec<String> {
+    let query = request.uri().query().unwrap_or_default();
+    form_urlencoded::parse(query.as_bytes())
+        .filter(|(key, _)| key == name)
+        .map(|(_, value)| value.into_owned())
+        .collect()
+}
+
+/// Compares without returning early, so timing does not reveal how much of
+/// the token was right
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+fn json_response<T: Serialize>(status: StatusCode, body: &T) -> Response<Body> {
+    let body = serde_json::to_string_pretty(body).unwrap_or_else(|e| {
+        json!({ "error": format!("failed to serialize response: {e}") }).to_string()
+    });
+    Response::builder()
+        .status(status)
+        .header(CONTENT_TYPE, "application/json")
+        .body(Body::from(body))
+        .unwrap()
+}
+
+fn error(status: StatusCode, message: &str) -> Response<Body> {
+    json_response(status, &json!({ "error": message }))
+}
@@ -0,0 +1,399 @@
+//! Tamper-evident log of every record dynsix creates, updates or deletes,
+//! kept apart from the regular logs in the file set with `audit.path`.
+//!
+//! Every line is a JSON object that carries the hash of the line before it
+//! as `prev` and its own SHA-256 as `hash`, computed over the line without
+//! the `hash` field. Changing or removing a line breaks the chain, which
+//! `dynsix audit` checks. Rotated files are renamed to `<path>.1`,
+//! `<path>.2`, ... and the chain continues across them.
+
+use std::{
+    ffi::CStr,
+    fs::{File, OpenOptions},
+    io::{BufRead, BufReader, ErrorKind, Write},
+    net::Ipv6Addr,
+    os::unix::io::AsRawFd,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::DynsixError;
+
+/// `prev` of the very first entry
+const GENESIS: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+/// The `[audit]` section of the config
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct AuditConfig {
+    /// File the entries are appended to, no audit log is written if unset
+    pub path: Option<PathBuf>,
+    /// Size in bytes after which the file is rotated
+    #[serde(default = "default_max_size")]
+    pub max_size: u64,
+    /// Rotated files kept, older ones are removed
+    #[serde(default = "default_keep")]
+    pub keep: usize,
+}
+
+impl Default for AuditConfig {
+    fn default() -> Self {
+        Self {
+            path: None,
+            max_size: default_max_size(),
+            keep: default_keep(),
+        }
+    }
+}
+
+impl AuditConfig {
+    pub(crate) fn validate(&self, problems: &mut Vec<String>) {
+        if self.max_size == 0 {
+            problems.push("audit.max_size must not be zero".to_string());
+        }
+        if self
+            .path
+            .as_ref()
+            .is_some_and(|path| path.file_name().is_none())
+        {
+            problems.push("audit.path must name a file".to_string());
+        }
+    }
+}
+
+fn default_max_size() -> u64 {
+    10 * 1024 * 1024
+}
+
+fn default_keep() -> usize {
+    5
+}
+
+/// Who changed a record
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Actor {
+    pub user: String,
+    pub uid: u32,
+    /// The user that ran dynsix through sudo
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sudo_user: Option<String>,
+    pub host: String,
+    pub pid: u32,
+    /// The command line of the process
+    pub command: String,
+}
+
+impl Actor {
+    /// The current process
+    pub fn current() -> Self {
+        // SAFETY: getuid can not fail
+        let uid = unsafe { libc::getuid() };
+        Self {
+            user: user_name(uid).unwrap_or_else(|| uid.to_string()),
+            uid,
+            sudo_user: std::env::var("SUDO_USER").ok(),
+            host: host_name().unwrap_or_default(),
+            pid: std::process::id(),
+            command: std::env::args().collect::<Vec<_>>().join(" "),
+        }
+    }
+}
+
+fn user_name(uid: libc::uid_t) -> Option<String> {
+    // SAFETY: getpwuid returns null or a pointer to a static entry, which is
+    // copied before any other call could overwrite it
+    unsafe {
+        let entry = libc::getpwuid(uid);
+        if entry.is_null() {
+            return None;
+        }
+        Some(
+            CStr::from_ptr((*entry).pw_name)
+                .to_string_lossy()
+                .into_owned(),
+        )
+    }
+}
+
+pub(crate) fn host_name() -> Option<String> {
+    let mut name = [0u8; 256];
+    // SAFETY: the buffer is valid for its length
+    if unsafe { libc::gethostname(name.as_mut_ptr().cast(), name.len()) } != 0 {
+        return None;
+    }
+    let end = name.iter().position(|&b| b == 0).unwrap_or(name.len());
+    Some(String::from_utf8_lossy(&name[..end]).into_owned())
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Operation {
+    Create,
+    Update,
+    Delete,
+}
+
+/// A change to a record, as passed to [`AuditLog::append`]
+#[derive(Debug, Clone)]
+pub struct Mutation<'a> {
+    pub operation: Operation,
+    pub service: &'a str,
+    pub provider: &'a str,
+    /// Named token of `[tokens]` the change was made with
+    pub account: Option<&'a str>,
+    pub fqdn: &'a str,
+    pub name: &'a str,
+    /// TTL and value written, `None` for deletions
+    pub ttl: Option<u32>,
+    pub value: Option<Ipv6Addr>,
+    /// Why the provider refused or failed the change
+    pub error: Option<String>,
+}
+
+/// One line of the audit log
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    /// RFC 3339 time of the change
+    pub time: String,
+    pub actor: Actor,
+    pub operation: Operation,
+    pub service: String,
+    pub provider: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub account: Option<String>,
+    pub fqdn: String,
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ttl: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub value: Option<String>,
+    /// `ok` or `failed`
+    pub outcome: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Hash of the entry before
+    pub prev: String,
+}
+
+/// Appends entries to the audit log. Writers of separate processes are
+/// serialized through `<path>.lock`.
+#[derive(Debug, Clone)]
+pub struct AuditLog {
+    path: PathBuf,
+    max_size: u64,
+    keep: usize,
+    actor: Actor,
+}
+
+impl AuditLog {
+    /// The log configured in `config`, `None` if it has no path
+    pub fn new(config: &AuditConfig) -> Option<Self> {
+        Some(Self {
+            path: config.path.clone()?,
+            max_size: config.max_size,
+            keep: config.keep,
+            actor: Actor::current(),
+        })
+    }
+
+    /// Attributes the entries to `actor` instead of the current process
+    pub fn with_actor(mut self, actor: Actor) -> Self {
+        self.actor = actor;
+        self
+    }
+
+    pub fn append(&self, mutation: &Mutation) -> Result<(), DynsixError> {
+        if let Some(parent) = self
+            .path
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+        {
+            std::fs::create_dir_all(parent).map_err(|e| DynsixError::io(parent, e))?;
+        }
+        let _lock = self.lock()?;
+
+        // A file rotated away before its first entry continues the chain
+        // of the one before
+        let prev = match last_hash(&self.path)? {
+            Some(hash) => hash,
+            None => last_hash(&rotated(&self.path, 1))?.unwrap_or_else(|| GENESIS.to_string()),
+        };
+        let size = match std::fs::metadata(&self.path) {
+            Ok(metadata) => metadata.len(),
+            Err(e) if e.kind() == ErrorKind::NotFound => 0,
+            Err(e) => return Err(DynsixError::io(&self.path, e)),
+        };
+        if size >= self.max_size {
+            self.rotate()?;
+        }
+
+        let entry = Entry {
+            time: humantime::format_rfc3339_millis(SystemTime::now()).to_string(),
+            actor: self.actor.clone(),
+            operation: mutation.operation,
+            service: mutation.service.to_string(),
+            provider: mutation.provider.to_string(),
+            account: mutation.account.map(str::to_string),
+            fqdn: mutation.fqdn.to_string(),
+            name: mutation.name.to_string(),
+            ttl: mutation.ttl,
+            value: mutation.value.map(|value| value.to_string()),
+            outcome: match mutation.error {
+                None => "ok",
+                Some(_) => "failed",
+            }
+            .to_string(),
+            error: mutation.error.clone(),
+            prev,
+        };
+        let body = serde_json::to_string(&entry).expect("entries serialize");
+        let line = format!(
+            "{},\"hash\":\"{}\"}}\n",
+            &body[..body.len() - 1],
+            hash(&body)
+        );
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .map_err(|e| DynsixError::io(&self.path, e))?;
+        file.write_all(line.as_bytes())
+            .and_then(|_| file.sync_data())
+            .map_err(|e| DynsixError::io(&self.path, e))
+    }
+
+    /// Shifts `<path>.N` to `<path>.N+1` and the current file to `<path>.1`,
+    /// dropping what is beyond `keep`
+    fn rotate(&self) -> Result<(), DynsixError> {
+        let remove = rotated(&self.path, self.keep.max(1));
+        match std::fs::remove_file(&remove) {
+            Err(e) if e.kind() != ErrorKind::NotFound => return Err(DynsixError::io(remove, e)),
+            _ => {}
+        }
+        for number in (1..self.keep).rev() {
+            let from = rotated(&self.path, number);
+            match std::fs::rename(&from, rotated(&self.path, number + 1)) {
+                Err(e) if e.kind() != ErrorKind::NotFound => return Err(DynsixError::io(from, e)),
+                _ => {}
+            }
+        }
+        if self.keep == 0 {
+            std::fs::remove_file(&self.path)
+        } else {
+            std::fs::rename(&self.path, rotated(&self.path, 1))
+        }
+        .map_err(|e| DynsixError::io(&self.path, e))
+    }
+
+    fn lock(&self) -> Result<File, DynsixError> {
+        let path = suffixed(&self.path, "lock");
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&path)
+            .map_err(|e| DynsixError::io(&path, e))?;
+        // SAFETY: the descriptor belongs to `file`, which is alive
+        while unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX) } != 0 {
+            let e = std::io::Error::last_os_error();
+            if e.kind() != ErrorKind::Interrupted {
+                return Err(DynsixError::io(path, e));
+            }
+        }
+        Ok(file)
+    }
+}
+
+/// Checks the chain of the audit log at `path` and its rotated files, oldest
+/// first, returning the number of entries. The first entry of the oldest file
+/// may refer to a file that was rotated away.
+pub fn verify(config: &AuditConfig) -> Result<usize, DynsixError> {
+    let Some(path) = &config.path else {
+        return Ok(0);
+    };
+    let mut files: Vec<_> = (1..=config.keep.max(1))
+        .rev()
+        .map(|number| rotated(path, number))
+        .collect();
+    files.push(path.clone());
+
+    let mut prev: Option<String> = None;
+    let mut entries = 0;
+    for file in files {
+        let reader = match File::open(&file) {
+            Ok(reader) => BufReader::new(reader),
+            Err(e) if e.kind() == ErrorKind::NotFound => continue,
+            Err(e) => return Err(DynsixError::io(file, e)),
+        };
+        for (number, line) in (1..).zip(reader.lines()) {
+            let line = line.map_err(|e| DynsixError::io(&file, e))?;
+            let error = |message: String| DynsixError::Audit {
+                path: file.clone(),
+                message: format!("line {number}: {message}"),
+            };
+
+            let (body, stored) = split_hash(&line).ok_or_else(|| error("no hash".to_string()))?;
+            if hash(&body) != stored {
+                return Err(error("hash does not match the entry".to_string()));
+            }
+            let entry: Entry =
+                serde_json::from_str(&body).map_err(|e| error(format!("invalid entry: {e}")))?;
+            if prev.as_ref().is_some_and(|prev| *prev != entry.prev) {
+                return Err(error(
+                    "prev does not match the entry before, one was removed or changed".to_string(),
+                ));
+            }
+            prev = Some(stored.to_string());
+            entries += 1;
+        }
+    }
+    Ok(entries)
+}
+
+/// The entry without its `hash` field and the hash
+fn split_hash(line: &str) -> Option<(String, &str)> {
+    let (body, rest) = line.rsplit_once(",\"hash\":\"")?;
+    let stored = rest.strip_suffix("\"}")?;
+    Some((format!("{body}}}"), stored))
+}
+
+fn hash(body: &str) -> String {
+    openssl::sha::sha256(body.as_bytes())
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+/// The hash of the last entry of `path`, `None` if it has none
+fn last_hash(path: &Path) -> Result<Option<String>, DynsixError> {
+    let reader = match File::open(path) {
+        Ok(reader) => BufReader::new(reader),
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(DynsixError::io(path, e)),
+    };
+    let mut last = None;
+    for line in reader.lines() {
+        let line = line.map_err(|e| DynsixError::io(path, e))?;
+        if !line.is_empty() {
+            last = Some(line);
+        }
+    }
+    Ok(last
+        .as_deref()
+        .and_then(split_hash)
+        .map(|(_, hash)| hash.to_string()))
+}
+
+fn rotated(path: &Path, number: usize) -> PathBuf {
+    suffixed(path, &number.to_string())
+}
+
+fn suffixed(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_os_string();
+    name.push(".");
+    name.push(suffix);
+    PathBuf::from(name)
+}
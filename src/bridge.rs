@@ -0,0 +1,431 @@
+//! A local HTTP proxy for connections reqwest can't make itself: through
+//! SOCKS5 proxies for `http.proxy = "socks5://..."` (RFC 1928, with the
+//! username/password authentication of RFC 1929) and from sockets bound to
+//! `http.bind_interface`.
+
+use std::{
+    collections::HashMap,
+    io,
+    net::{IpAddr, SocketAddr},
+    sync::Mutex,
+};
+
+use reqwest::Url;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpSocket, TcpStream},
+};
+use tracing::{debug, warn};
+
+use crate::{http_client::LocalAddress, DynsixError};
+
+const VERSION: u8 = 5;
+const NO_AUTHENTICATION: u8 = 0;
+const USERNAME_PASSWORD: u8 = 2;
+const CONNECT: u8 = 1;
+const IPV4: u8 = 1;
+const DOMAIN: u8 = 3;
+const IPV6: u8 = 4;
+/// Longest request head accepted from reqwest
+const MAX_HEAD: usize = 16 * 1024;
+
+/// Local bridges by route, started once per process
+static BRIDGES: Mutex<Option<HashMap<Route, String>>> = Mutex::new(None);
+
+/// How the bridge connects to the servers
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub(crate) struct Route {
+    /// Tunnel through this proxy instead of connecting directly
+    socks: Option<SocksProxy>,
+    /// Interface the sockets are bound to
+    interface: Option<String>,
+    /// Address the sockets are bound to
+    local_address: Option<IpAddr>,
+    /// Address family of direct connections
+    family: LocalAddress,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct SocksProxy {
+    host: String,
+    port: u16,
+    /// Name resolution is left to the proxy for `socks5h`
+    remote_dns: bool,
+    credentials: Option<(String, String)>,
+}
+
+impl Route {
+    pub(crate) fn new(
+        interface: Option<&str>,
+        local_address: Option<IpAddr>,
+        family: LocalAddress,
+    ) -> Self {
+        Self {
+            socks: None,
+            interface: interface.map(str::to_string),
+            local_address,
+            family,
+        }
+    }
+
+    /// Tunnels through the SOCKS5 proxy `url`
+    pub(crate) fn through_socks(mut self, url: &Url) -> Self {
+        let host = url.host_str().unwrap_or_default();
+        self.socks = Some(SocksProxy {
+            host: host
+                .trim_start_matches('[')
+                .trim_end_matches(']')
+                .to_string(),
+            port: url.port().unwrap_or(1080),
+            remote_dns: url.scheme() == "socks5h",
+            credentials: (!url.username().is_empty()).then(|| {
+                (
+                    decode(url.username()),
+                    decode(url.password().unwrap_or_default()),
+                )
+            }),
+        });
+        self
+    }
+}
+
+/// The URL of a local HTTP proxy making connections as set by `route`,
+/// starting it if needed. Has to be called within the Tokio runtime.
+pub(crate) fn bridge(route: Route) -> Result<String, DynsixError> {
+    let mut bridges = BRIDGES.lock().unwrap();
+    let bridges = bridges.get_or_insert_with(HashMap::new);
+    if let Some(bridge) = bridges.get(&route) {
+        return Ok(bridge.clone());
+    }
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0")
+        .and_then(|listener| listener.set_nonblocking(true).map(|()| listener))
+        .map_err(|e| DynsixError::io("proxy bridge", e))?;
+    let local = listener
+        .local_addr()
+        .map_err(|e| DynsixError::io("proxy bridge", e))?;
+    let listener =
+        TcpListener::from_std(listener).map_err(|e| DynsixError::io("proxy bridge", e))?;
+
+    // Other local users could reach the bridge, so it wants a password
+    let password = random_password();
+    tokio::spawn(serve(
+        listener,
+        route.clone(),
+        format!("Basic {}", base64::encode(format!("dynsix:{password}"))),
+    ));
+    match &route.socks {
+        Some(_) => debug!("Tunneling requests through SOCKS5 from {local}"),
+        None => debug!("Making bound connections from {local}"),
+    }
+
+    let bridge = format!("http://dynsix:{password}@{local}");
+    bridges.insert(route, bridge.clone());
+    Ok(bridge)
+}
+
+async fn serve(listener: TcpListener, route: Route, authorization: String) {
+    loop {
+        let Ok((stream, _)) = listener.accept().await else {
+            continue;
+        };
+        let route = route.clone();
+        let authorization = authorization.clone();
+        tokio::spawn(async move {
+            if let Err(e) = tunnel(stream, &route, &authorization).await {
+                match &route.socks {
+                    Some(proxy) => warn!("SOCKS5 proxy {}:{}: {e}", proxy.host, proxy.port),
+                    None => warn!("Proxy bridge: {e}"),
+                }
+            }
+        });
+    }
+}
+
+/// Serves one connection from reqwest, either a `CONNECT` for HTTPS or a
+/// plain HTTP request in absolute form
+async fn tunnel(mut client: TcpStream, route: &Route, authorization: &str) -> Result<(), String> {
+    let mut buffer = Vec::new();
+    let head_end = loop {
+        let mut chunk = [0; 4096];
+        let read = client.read(&mut chunk).await.map_err(|e| e.to_string())?;
+        if read == 0 {
+            return Ok(());
+        }
+        buffer.extend_from_slice(&chunk[..read]);
+        if let Some(position) = buffer.windows(4).position(|window| window == b"\r\n\r\n") {
+            break position + 4;
+        }
+        if buffer.len() > MAX_HEAD {
+            return Err("request head too long".to_string());
+        }
+    };
+    let head = String::from_utf8_lossy(&buffer[..head_end]).into_owned();
+    let mut lines = head.split("\r\n");
+    let request_line = lines.next().unwrap_or_default();
+    let headers: Vec<&str> = lines.filter(|line| !line.is_empty()).collect();
+
+    let authorized = headers.iter().any(|line| {
+        line.split_once(':').is_some_and(|(name, value)| {
+            name.eq_ignore_ascii_case("proxy-authorization") && value.trim() == authorization
+        })
+    });
+    if !authorized {
+        let _ = client
+            .write_all(b"HTTP/1.1 407 Proxy Authentication Required\r\nContent-Length: 0\r\n\r\n")
+            .await;
+        return Ok(());
+    }
+
+    let mut parts = request_line.split(' ');
+    let (method, target, version) = (
+        parts.next().unwrap_or_default(),
+        parts.next().unwrap_or_default(),
+        parts.next().unwrap_or("HTTP/1.1"),
+    );
+
+    if method == "CONNECT" {
+        let Some((host, port)) = target.rsplit_once(':') else {
+            return Err(format!("invalid CONNECT target {target}"));
+        };
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        let port = port
+            .parse()
+            .map_err(|_| format!("invalid port in {target}"))?;
+        let mut upstream = match connect(route, host, port).await {
+            Ok(upstream) => upstream,
+            Err(e) => return bad_gateway(&mut client, e).await,
+        };
+        client
+            .write_all(b"HTTP/1.1 200 Connection established\r\n\r\n")
+            .await
+            .map_err(|e| e.to_string())?;
+        let _ = tokio::io::copy_bidirectional(&mut client, &mut upstream).await;
+        return Ok(());
+    }
+
+    let url = Url::parse(target).map_err(|e| format!("invalid request target {target}: {e}"))?;
+    let host = url.host_str().unwrap_or_default();
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    let port = url.port_or_known_default().unwrap_or(80);
+    let mut upstream = match connect(route, host, port).await {
+        Ok(upstream) => upstream,
+        Err(e) => return bad_gateway(&mut client, e).await,
+    };
+
+    // The origin server gets the path only and none of the proxy headers
+    let path = match url.query() {
+        Some(query) => format!("{}?{query}", url.path()),
+        None => url.path().to_string(),
+    };
+    let mut rewritten = format!("{method} {path} {version}\r\n");
+    for header in headers.iter().filter(|line| {
+        !line
+            .split_once(':')
+            .is_some_and(|(name, _)| name.to_ascii_lowercase().starts_with("proxy-"))
+    }) {
+        rewritten.push_str(header);
+        rewritten.push_str("\r\n");
+    }
+    rewritten.push_str("\r\n");
+    upstream
+        .write_all(rewritten.as_bytes())
+        .await
+        .map_err(|e| e.to_string())?;
+    upstream
+        .write_all(&buffer[head_end..])
+        .await
+        .map_err(|e| e.to_string())?;
+    let _ = tokio::io::copy_bidirectional(&mut client, &mut upstream).await;
+    Ok(())
+}
+
+async fn bad_gateway(client: &mut TcpStream, message: String) -> Result<(), String> {
+    let response = format!(
+        "HTTP/1.1 502 Bad Gateway\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{message}",
+        message.len()
+    );
+    let _ = client.write_all(response.as_bytes()).await;
+    Err(message)
+}
+
+/// Opens a connection to `host:port`, through the SOCKS5 proxy if any
+async fn connect(route: &Route, host: &str, port: u16) -> Result<TcpStream, String> {
+    let Some(proxy) = &route.socks else {
+        return open(route, host, port, route.family)
+            .await
+            .map_err(|e| format!("connecting to {host}:{port} failed: {e}"));
+    };
+    // Only the address family towards the servers matters, not the one
+    // towards the proxy
+    let mut stream = open(route, &proxy.host, proxy.port, LocalAddress::Any)
+        .await
+        .map_err(|e| format!("connecting failed: {e}"))?;
+    let io = |e: io::Error| e.to_string();
+
+    let methods: &[u8] = match proxy.credentials {
+        Some(_) => &[NO_AUTHENTICATION, USERNAME_PASSWORD],
+        None => &[NO_AUTHENTICATION],
+    };
+    let mut greeting = vec![VERSION, methods.len() as u8];
+    greeting.extend_from_slice(methods);
+    stream.write_all(&greeting).await.map_err(io)?;
+    let mut choice = [0; 2];
+    stream.read_exact(&mut choice).await.map_err(io)?;
+    match (choice, &proxy.credentials) {
+        ([VERSION, NO_AUTHENTICATION], _) => {}
+        ([VERSION, USERNAME_PASSWORD], Some((username, password))) => {
+            if username.len() > 255 || password.len() > 255 {
+                return Err("username and password are limited to 255 bytes".to_string());
+            }
+            let mut request = vec![1, username.len() as u8];
+            request.extend_from_slice(username.as_bytes());
+            request.push(password.len() as u8);
+            request.extend_from_slice(password.as_bytes());
+            stream.write_all(&request).await.map_err(io)?;
+            let mut status = [0; 2];
+            stream.read_exact(&mut status).await.map_err(io)?;
+            if status[1] != 0 {
+                return Err("authentication failed".to_string());
+            }
+        }
+        _ => return Err("no acceptable authentication method".to_string()),
+    }
+
+    let mut request = vec![VERSION, CONNECT, 0];
+    let address = match host.parse::<IpAddr>() {
+        Ok(ip) => Some(ip),
+        Err(_) if proxy.remote_dns => None,
+        Err(_) => Some(
+            tokio::net::lookup_host((host, port))
+                .await
+                .map_err(|e| format!("resolving {host} failed: {e}"))?
+                .next()
+                .map(|address: SocketAddr| address.ip())
+                .ok_or_else(|| format!("{host} has no address"))?,
+        ),
+    };
+    match address {
+        Some(IpAddr::V4(ip)) => {
+            request.push(IPV4);
+            request.extend_from_slice(&ip.octets());
+        }
+        Some(IpAddr::V6(ip)) => {
+            request.push(IPV6);
+            request.extend_from_slice(&ip.octets());
+        }
+        None => {
+            if host.len() > 255 {
+                return Err(format!("{host} is too long"));
+            }
+            request.push(DOMAIN);
+            request.push(host.len() as u8);
+            request.extend_from_slice(host.as_bytes());
+        }
+    }
+    request.extend_from_slice(&port.to_be_bytes());
+    stream.write_all(&request).await.map_err(io)?;
+
+    let mut reply = [0; 4];
+    stream.read_exact(&mut reply).await.map_err(io)?;
+    if reply[1] != 0 {
+        return Err(format!(
+            "connecting to {host}:{port} failed: {}",
+            reply_message(reply[1])
+        ));
+    }
+    // The address the proxy bound is of no interest
+    let bound = match reply[3] {
+        IPV4 => 4,
+        IPV6 => 16,
+        DOMAIN => {
+            let mut length = [0; 1];
+            stream.read_exact(&mut length).await.map_err(io)?;
+            length[0] as usize
+        }
+        other => return Err(format!("unknown address type {other} in reply")),
+    };
+    let mut skipped = vec![0; bound + 2];
+    stream.read_exact(&mut skipped).await.map_err(io)?;
+    Ok(stream)
+}
+
+/// Connects to the first reachable address of `host` in `family`, from a
+/// socket bound as set by `route`
+async fn open(route: &Route, host: &str, port: u16, family: LocalAddress) -> io::Result<TcpStream> {
+    let family = match route.local_address {
+        Some(IpAddr::V4(_)) => LocalAddress::Ipv4,
+        Some(IpAddr::V6(_)) => LocalAddress::Ipv6,
+        None => family,
+    };
+    let mut last_error = None;
+    for address in tokio::net::lookup_host((host, port)).await? {
+        let socket = match (address, family) {
+            (SocketAddr::V4(_), LocalAddress::Ipv6) | (SocketAddr::V6(_), LocalAddress::Ipv4) => {
+                continue
+            }
+            (SocketAddr::V4(_), _) => TcpSocket::new_v4()?,
+            (SocketAddr::V6(_), _) => TcpSocket::new_v6()?,
+        };
+        if let Some(interface) = &route.interface {
+            bind_device(&socket, interface)?;
+        }
+        if let Some(local) = route.local_address {
+            socket.bind(SocketAddr::new(local, 0))?;
+        }
+        match socket.connect(address).await {
+            Ok(stream) => return Ok(stream),
+            Err(e) => last_error = Some(e),
+        }
+    }
+    Err(last_error.unwrap_or_else(|| {
+        let family = match family {
+            LocalAddress::Ipv4 => " IPv4",
+            LocalAddress::Ipv6 => " IPv6",
+            LocalAddress::Any => "",
+        };
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("{host} has no{family} address"),
+        )
+    }))
+}
+
+#[cfg(any(target_os = "android", target_os = "fuchsia", target_os = "linux"))]
+fn bind_device(socket: &TcpSocket, interface: &str) -> io::Result<()> {
+    socket.bind_device(Some(interface.as_bytes()))
+}
+
+#[cfg(not(any(target_os = "android", target_os = "fuchsia", target_os = "linux")))]
+fn bind_device(_: &TcpSocket, _: &str) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "binding to an interface is only supported on Linux",
+    ))
+}
+
+fn reply_message(code: u8) -> &'static str {
+    match code {
+        1 => "general failure",
+        2 => "not allowed by ruleset",
+        3 => "network unreachable",
+        4 => "host unreachable",
+        5 => "connection refused",
+        6 => "TTL expired",
+        7 => "command not supported",
+        8 => "address type not supported",
+        _ => "unknown error",
+    }
+}
+
+fn decode(value: &str) -> String {
+    percent_encoding::percent_decode_str(value)
+        .decode_utf8_lossy()
+        .into_owned()
+}
+
+fn random_password() -> String {
+    let mut bytes = [0; 16];
+    openssl::rand::rand_bytes(&mut bytes).expect("openssl random numbers");
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
@@ -0,0 +1,186 @@
+//! A read-only check of the published records for monitoring, in the plugin
+//! format of Nagios and Icinga with `dynsix check --nagios`
+
+use std::{collections::HashMap, fmt, net::Ipv6Addr};
+
+use crate::{
+    provider::display_name,
+    reconcile::{record_matches, Reconciler},
+    ServiceConfig,
+};
+
+/// The plugin states, whose numbers are the exit codes Nagios expects
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Status {
+    Ok = 0,
+    Warning = 1,
+    Critical = 2,
+    Unknown = 3,
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Ok => "OK",
+            Self::Warning => "WARNING",
+            Self::Critical => "CRITICAL",
+            Self::Unknown => "UNKNOWN",
+        })
+    }
+}
+
+/// How a record compares with the address of its service
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    UpToDate,
+    /// The record holds other values, which are given
+    Wrong(Vec<String>),
+    Missing,
+    /// The record could not be fetched, for the given reason
+    Unchecked(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecordCheck {
+    pub service: String,
+    /// The full name, e.g. `www.example.com`
+    pub record: String,
+    pub desired: Ipv6Addr,
+    pub outcome: Outcome,
+}
+
+/// The outcome of every record of the checked services
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Check {
+    pub records: Vec<RecordCheck>,
+}
+
+impl Check {
+    /// Fetches every record of the enabled `services` and compares it with the
+    /// address for `public_ip`, the way its service compares
+    pub async fn run(
+        reconciler: &Reconciler,
+        services: &HashMap<String, ServiceConfig>,
+        public_ip: Ipv6Addr,
+    ) -> Self {
+        let mut names: Vec<_> = services
+            .iter()
+            .filter(|(_, service)| service.enabled)
+            .map(|(name, _)| name)
+            .collect();
+        names.sort();
+
+        let mut records = Vec::new();
+        for name in names {
+            let service = &services[name];
+            let desired = match service.address(name, public_ip) {
+                Ok(desired) => desired,
+                Err(e) => {
+                    records.push(RecordCheck {
+                        service: name.clone(),
+                        record: display_name(&service.fqdn, &service.name),
+                        desired: public_ip,
+                        outcome: Outcome::Unchecked(e.to_string()),
+                    });
+                    continue;
+                }
+            };
+            for record_name in service.record_names() {
+                let outcome = match reconciler
+                    .fetch_record(name, service.provider, &service.fqdn, record_name)
+                    .await
+                {
+                    Ok(Some(record))
+                        if record_matches(&record.values, &desired, service.compare) =>
+                    {
+                        Outcome::UpToDate
+                    }
+                    Ok(Some(record)) => Outcome::Wrong(record.values),
+                    Ok(None) => Outcome::Missing,
+                    Err(e) => Outcome::Unchecked(e.to_string()),
+                };
+                records.push(RecordCheck {
+                    service: name.clone(),
+                    record: display_name(&service.fqdn, record_name),
+                    desired,
+                    outcome,
+                });
+            }
+        }
+        Self { records }
+    }
+
+    /// Critical if a record is wrong or missing, a warning if some could not
+    /// be checked and unknown if none could, or there are none
+    pub fn status(&self) -> Status {
+        let unchecked = self.count(|outcome| matches!(outcome, Outcome::Unchecked(_)));
+        if self.records.is_empty() {
+            Status::Unknown
+        } else if self.count(|outcome| matches!(outcome, Outcome::Wrong(_) | Outcome::Missing)) > 0
+        {
+            Status::Critical
+        } else if unchecked == self.records.len() {
+            Status::Unknown
+        } else if unchecked > 0 {
+            Status::Warning
+        } else {
+            Status::Ok
+        }
+    }
+
+    fn count(&self, filter: impl Fn(&Outcome) -> bool) -> usize {
+        self.records
+            .iter()
+            .filter(|record| filter(&record.outcome))
+            .count()
+    }
+
+    /// The plugin output: the status and a summary with performance data,
+    /// then a line for every record that isn't up to date
+    pub fn nagios(&self) -> String {
+        let total = self.records.len();
+        let up_to_date = self.count(|outcome| *outcome == Outcome::UpToDate);
+        let wrong = self.count(|outcome| matches!(outcome, Outcome::Wrong(_)));
+        let missing = self.count(|outcome| *outcome == Outcome::Missing);
+        let unchecked = self.count(|outcome| matches!(outcome, Outcome::Unchecked(_)));
+
+        let summary = match self.status() {
+            Status::Ok => format!("{total} records up to date"),
+            Status::Unknown if total == 0 => "no records to check".to_string(),
+            Status::Unknown => format!("none of the {total} records could be checked"),
+            _ => {
+                let mut problems = Vec::new();
+                if wrong > 0 {
+                    problems.push(format!("{wrong} wrong"));
+                }
+                if missing > 0 {
+                    problems.push(format!("{missing} missing"));
+                }
+                if unchecked > 0 {
+                    problems.push(format!("{unchecked} not checked"));
+                }
+                format!("{total} records, {}", problems.join(", "))
+            }
+        };
+        let mut output = format!(
+            "DYNSIX {} - {summary} | records={total};;;0 up_to_date={up_to_date};;;0 \
+             wrong={wrong};;;0 missing={missing};;;0 unchecked={unchecked};;;0",
+            self.status()
+        );
+        for record in &self.records {
+            let problem = match &record.outcome {
+                Outcome::UpToDate => continue,
+                Outcome::Wrong(values) => {
+                    format!("holds {} instead of {}", values.join(", "), record.desired)
+                }
+                Outcome::Missing => format!("is missing, should be {}", record.desired),
+                Outcome::Unchecked(reason) => format!("could not be checked: {reason}"),
+            };
+            output.push_str(&format!(
+                "\n{} ({}) {problem}",
+                record.record, record.service
+            ));
+        }
+        output
+    }
+}
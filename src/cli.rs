@@ -0,0 +1,525 @@
+use std::{net::Ipv6Addr, path::PathBuf, str::FromStr};
+
+use dynsix::{
+    config::{Config, ConfigFormat},
+    glob_match, import, DynsixError,
+};
+
+use crate::completions::Shell;
+
+const DEFAULT_CONFIG_PATH: &str = "/etc/dynsix/config.toml";
+
+#[derive(Debug)]
+pub struct Cli {
+    pub config_path: PathBuf,
+    pub config_format: Option<ConfigFormat>,
+    /// Decrypt the config with sops, given with `--sops`. Encrypted configs
+    /// are detected without it.
+    pub sops: bool,
+    /// Service name patterns given with `--service`, empty selects all services
+    pub services: Vec<String>,
+    /// Prefix given with `--prefix`, replaces the query server lookup
+    pub prefix: Option<Ipv6Addr>,
+    pub output: OutputFormat,
+    /// Wait for another run holding `lock_file` instead of exiting
+    pub wait: bool,
+    /// Log the traffic with the APIs, given with `--log-http`
+    pub log_http: bool,
+    pub command: Command,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// Only log output
+    Text,
+    /// Print a summary of the run as JSON to stdout
+    Json,
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(Self::Text),
+            "json" => Ok(Self::Json),
+            _ => Err(format!(
+                "unknown output format '{s}', expected text or json"
+            )),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Command {
+    /// Reconcile all services once
+    Run,
+    /// Keep running and reconcile periodically
+    Daemon,
+    /// Send a command to the control socket of a running daemon
+    Ctl(Vec<String>),
+    /// Watch a running daemon and trigger runs from the terminal
+    Tui,
+    /// Load the config and report semantic problems without touching any records
+    ConfigValidate,
+    /// Write a commented starter config, to the config path if none is given
+    ConfigInit {
+        path: Option<PathBuf>,
+        interactive: bool,
+        force: bool,
+    },
+    /// Show how the published records differ from the desired state
+    Plan {
+        out: Option<PathBuf>,
+    },
+    /// Execute a plan written by `plan --out`
+    Apply {
+        plan: PathBuf,
+    },
+    /// Check the token and list the organizations and domains it can access
+    Whoami,
+    /// Show the published records of all services next to their desired values
+    List,
+    /// Check that the published records hold the desired values, for monitoring
+    Check {
+        nagios: bool,
+    },
+    /// Delete the AAAA record of a service
+    Delete {
+        service: String,
+        yes: bool,
+    },
+    /// Show the last result and next check of every service
+    Status,
+    /// Reconcile right away from a pppd or dhcpcd hook script
+    Hook,
+    /// Show the changes kept in `history_file`
+    History,
+    /// Check the hash chain of the audit log
+    Audit,
+    /// Save all records of a Gandi domain, to `out` or stdout
+    Backup {
+        domain: String,
+        out: Option<PathBuf>,
+    },
+    /// Replace all records of a Gandi domain with those of a backup, of the
+    /// domain it was taken from unless `domain` is given
+    Restore {
+        backup: PathBuf,
+        domain: Option<String>,
+        yes: bool,
+    },
+    /// Publish the TLSA record of a certificate, `name` being relative to
+    /// `domain` or, without it, a full name in the domain of a service
+    Tlsa {
+        cert: PathBuf,
+        name: String,
+        domain: Option<String>,
+    },
+    /// List the records created for services that no longer exist, delete
+    /// them with `apply`
+    Gc {
+        apply: bool,
+    },
+    /// Convert the config of another updater, to `out` or stdout
+    Import {
+        from: import::Format,
+        path: PathBuf,
+        out: Option<PathBuf>,
+        force: bool,
+    },
+    /// Print a shell completion script
+    Completions(Shell),
+    /// Print or write systemd units running the daemon, or `run` on a timer
+    InstallSystemd {
+        timer: bool,
+        write: bool,
+    },
+    /// Print the configured service names, used by the completion scripts
+    Services,
+    /// List the IP sources, providers and other components compiled in
+    Features,
+    Help,
+    Version,
+}
+
+impl Cli {
+    pub fn parse() -> Result<Self, String> {
+        Self::parse_from(std::env::args().skip(1))
+    }
+
+    pub fn parse_from<I>(args: I) -> Result<Self, String>
+    where
+        I: IntoIterator<Item = String>,
+    {
+        let mut config_path = None;
+        let mut config_format = None;
+        let mut sops = false;
+        let mut interactive = false;
+        let mut force = false;
+        let mut yes = false;
+        let mut gc_apply = false;
+        let mut wait = false;
+        let mut log_http = false;
+        let mut timer = false;
+        let mut write = false;
+        let mut nagios = false;
+        let mut services = Vec::new();
+        let mut prefix = None;
+        let mut output = OutputFormat::Text;
+        let mut plan_out = None;
+        let mut plan_file = None;
+        let mut domain = None;
+        let mut cert = None;
+        let mut name = None;
+        let mut positional = Vec::new();
+
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "-c" | "--config" => {
+                    config_path = Some(PathBuf::from(required_value(&arg, args.next())?));
+                }
+                "--format" => {
+                    config_format = Some(required_value(&arg, args.next())?.parse()?);
+                }
+                "--sops" => sops = true,
+                "-s" | "--service" => services.push(required_value(&arg, args.next())?),
+                "-p" | "--prefix" => {
+                    prefix = Some(parse_prefix(&required_value(&arg, args.next())?)?)
+                }
+                "-o" | "--output" => output = required_value(&arg, args.next())?.parse()?,
+                "--out" => plan_out = Some(PathBuf::from(required_value(&arg, args.next())?)),
+                "--plan" => plan_file = Some(PathBuf::from(required_value(&arg, args.next())?)),
+                "--domain" => domain = Some(required_value(&arg, args.next())?),
+                "--cert" => cert = Some(PathBuf::from(required_value(&arg, args.next())?)),
+                "--name" => name = Some(required_value(&arg, args.next())?),
+                "-i" | "--interactive" => interactive = true,
+                "-f" | "--force" => force = true,
+                "-y" | "--yes" => yes = true,
+                "--apply" => gc_apply = true,
+                "-w" | "--wait" => wait = true,
+                "--log-http" => log_http = true,
+                "--timer" => timer = true,
+                "--write" => write = true,
+                "--nagios" => nagios = true,
+                "-h" | "--help" => return Ok(Self::help()),
+                "-V" | "--version" => {
+                    return Ok(Self {
+                        command: Command::Version,
+                        ..Self::help()
+                    })
+                }
+                _ if arg.starts_with('-') => return Err(format!("unknown option '{arg}'")),
+                _ => positional.push(arg),
+            }
+        }
+
+        let mut positional = positional.into_iter();
+        let command = match positional.next().as_deref() {
+            None | Some("run") | Some("once") => Command::Run,
+            Some("config") => match positional.next().as_deref() {
+                Some("validate") => Command::ConfigValidate,
+                Some("init") => Command::ConfigInit {
+                    path: positional.next().map(PathBuf::from),
+                    interactive,
+                    force,
+                },
+                Some(other) => return Err(format!("unknown config subcommand '{other}'")),
+                None => return Err("config requires a subcommand: validate, init".to_string()),
+            },
+            Some("plan") => Command::Plan {
+                out: plan_out.take(),
+            },
+            Some("apply") => Command::Apply {
+                plan: plan_file
+                    .take()
+                    .or_else(|| positional.next().map(PathBuf::from))
+                    .ok_or("apply requires --plan <FILE>")?,
+            },
+            Some("daemon") => Command::Daemon,
+            Some("ctl") => {
+                let command: Vec<_> = positional.by_ref().collect();
+                match command.first() {
+                    Some(name) if crate::control::COMMANDS.contains(&name.as_str()) => {}
+                    Some(name) => return Err(format!("unknown ctl command '{name}'")),
+                    None => {
+                        return Err(format!(
+                            "ctl requires a command: {}",
+                            crate::control::COMMANDS.join(", ")
+                        ))
+                    }
+                }
+                Command::Ctl(command)
+            }
+            Some("tui") => Command::Tui,
+            Some("whoami") => Command::Whoami,
+            Some("list") => Command::List,
+            Some("check") => Command::Check { nagios },
+            Some("delete") => Command::Delete {
+                service: positional.next().ok_or("delete requires a service name")?,
+                yes,
+            },
+            Some("tlsa") => Command::Tlsa {
+                cert: cert.take().ok_or("tlsa requires --cert <FILE>")?,
+                name: name.take().ok_or("tlsa requires --name <NAME>")?,
+                domain: domain.take(),
+            },
+            Some("gc") => Command::Gc { apply: gc_apply },
+            Some("status") => Command::Status,
+            Some("hook") => Command::Hook,
+            Some("history") => Command::History,
+            Some("audit") => Command::Audit,
+            Some("backup") => Command::Backup {
+                domain: domain.take().ok_or("backup requires --domain <FQDN>")?,
+                out: plan_out.take(),
+            },
+            Some("restore") => Command::Restore {
+                backup: positional
+                    .next()
+                    .map(PathBuf::from)
+                    .ok_or("restore requires a backup file")?,
+                domain: domain.take(),
+                yes,
+            },
+            Some("import") => Command::Import {
+                from: positional
+                    .next()
+                    .ok_or("import requires a format: ddclient or inadyn")?
+                    .parse()?,
+                path: positional
+                    .next()
+                    .map(PathBuf::from)
+                    .ok_or("import requires the file to convert")?,
+                out: plan_out.take(),
+                force,
+            },
+            Some("completions") => Command::Completions(
+                positional
+                    .next()
+                    .ok_or("completions requires a shell: bash, zsh or fish")?
+                    .parse()?,
+            ),
+            Some("install") => match positional.next().as_deref() {
+                Some("systemd") => Command::InstallSystemd { timer, write },
+                Some(other) => return Err(format!("unknown install target '{other}'")),
+                None => return Err("install requires a target: systemd".to_string()),
+            },
+            Some("__services") => Command::Services,
+            Some("features") => Command::Features,
+            Some("help") => Command::Help,
+            // Backwards compatible invocation with only the config path
+            Some(path) => {
+                config_path.get_or_insert_with(|| PathBuf::from(path));
+                Command::Run
+            }
+        };
+
+        if let Some(extra) = positional.next() {
+            return Err(format!("unexpected argument '{extra}'"));
+        }
+        if plan_out.is_some() || plan_file.is_some() {
+            return Err(
+                "--out is only valid for plan, backup and import and --plan only for apply"
+                    .to_string(),
+            );
+        }
+        if domain.is_some() {
+            return Err("--domain is only valid for backup, restore and tlsa".to_string());
+        }
+        if cert.is_some() || name.is_some() {
+            return Err("--cert and --name are only valid for tlsa".to_string());
+        }
+        if yes && !matches!(command, Command::Delete { .. } | Command::Restore { .. }) {
+            return Err("--yes is only valid for delete and restore".to_string());
+        }
+        if gc_apply && !matches!(command, Command::Gc { .. }) {
+            return Err("--apply is only valid for gc".to_string());
+        }
+        if wait
+            && !matches!(
+                command,
+                Command::Run | Command::Hook | Command::Apply { .. }
+            )
+        {
+            return Err("--wait is only valid for run, hook and apply".to_string());
+        }
+        if nagios && !matches!(command, Command::Check { .. }) {
+            return Err("--nagios is only valid for check".to_string());
+        }
+        if (timer || write) && !matches!(command, Command::InstallSystemd { .. }) {
+            return Err("--timer and --write are only valid for install systemd".to_string());
+        }
+        if interactive && !matches!(command, Command::ConfigInit { .. }) {
+            return Err("--interactive is only valid for config init".to_string());
+        }
+        if force && !matches!(command, Command::ConfigInit { .. } | Command::Import { .. }) {
+            return Err("--force is only valid for config init and import".to_string());
+        }
+
+        Ok(Self {
+            config_path: config_path.unwrap_or_else(|| PathBuf::from(DEFAULT_CONFIG_PATH)),
+            config_format,
+            sops,
+            services,
+            prefix,
+            output,
+            wait,
+            log_http,
+            command,
+        })
+    }
+
+    /// Whether the service `name` was selected on the command line
+    pub fn selects(&self, name: &str) -> bool {
+        self.services.is_empty()
+            || self
+                .services
+                .iter()
+                .any(|pattern| glob_match(pattern, name))
+    }
+
+    /// Fails if a `--service` pattern matches none of the configured services,
+    /// which usually is a typo
+    pub fn check_service_patterns(&self, config: &Config) -> Result<(), String> {
+        for pattern in &self.services {
+            if !config.services.keys().any(|name| glob_match(pattern, name)) {
+                return Err(format!("no configured service matches '{pattern}'"));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn load_config(&self) -> Result<Config, DynsixError> {
+        match (self.config_format, self.sops) {
+            (Some(format), false) => Config::load_as(&self.config_path, format),
+            (None, false) => Config::load(&self.config_path),
+            (format, true) => Config::load_sops(
+                &self.config_path,
+                format
+                    .or_else(|| ConfigFormat::from_path(&self.config_path))
+                    .unwrap_or(ConfigFormat::Toml),
+            ),
+        }
+    }
+
+    fn help() -> Self {
+        Self {
+            config_path: PathBuf::from(DEFAULT_CONFIG_PATH),
+            config_format: None,
+            sops: false,
+            services: Vec::new(),
+            prefix: None,
+            output: OutputFormat::Text,
+            wait: false,
+            log_http: false,
+            command: Command::Help,
+        }
+    }
+}
+
+fn required_value(option: &str, value: Option<String>) -> Result<String, String> {
+    value.ok_or_else(|| format!("{option} requires a value"))
+}
+
+/// Parses `2001:db8:1:2::/64` or a plain address. Only the upper 64 bits are used
+/// for merging, so longer prefixes would silently drop bits and are rejected.
+pub fn parse_prefix(value: &str) -> Result<Ipv6Addr, String> {
+    let (address, length) = match value.split_once('/') {
+        Some((address, length)) => (address, Some(length)),
+        None => (value, None),
+    };
+
+    let address = address
+        .parse::<Ipv6Addr>()
+        .map_err(|e| format!("invalid prefix '{value}': {e}"))?;
+    if let Some(length) = length {
+        let length: u8 = length
+            .parse()
+            .map_err(|e| format!("invalid prefix length in '{value}': {e}"))?;
+        if length > 64 {
+            return Err(format!("prefix '{value}' is longer than /64"));
+        }
+    }
+
+    Ok(address)
+}
+
+pub fn usage() -> String {
+    format!(
+        "Usage: {name} [OPTIONS] [COMMAND]
+
+Commands:
+  run               Reconcile all configured services (default)
+  once              Alias for run, usually combined with --prefix
+  hook              Run from a pppd ip-up/ipv6-up script or dhcpcd hook, with the prefix
+                    dhcpcd received; ignores dhcpcd events without a new IPv6 prefix
+  daemon            Keep running and reconcile every daemon.interval, see [daemon] in the config;
+                    SIGUSR1 triggers a run right away
+  ctl <COMMAND>     Control a running daemon: status, reconcile [SERVICE...], reload-config, logs
+  tui               Watch a running daemon's services and log on its control socket and
+                    reconcile them with a key press
+  plan              Show what run would change without applying it
+  apply --plan <FILE>
+                    Apply a plan saved with plan --out, e.g. on another machine after
+                    review, unless its records changed since
+  config validate   Check the configuration for problems without changing any records
+  config init [PATH]
+                    Write a commented starter configuration [default: the config path]
+  list              Show published records next to the values dynsix would publish
+  check             Check that every record holds the value dynsix would publish, without
+                    changing any; exits with 0 if all do, 1 if some couldn't be checked, 2 if
+                    any is wrong or missing and 3 if none could be checked
+  delete <SERVICE>  Delete the AAAA record of a service
+  status            Show the published value, last change, last result and next check of every
+                    service, from the daemon's control socket or else state_file
+  history           Show every change to a record kept in history_file, with --service for some
+  audit             Check that no entry of the audit log at audit.path was changed or removed
+  backup --domain <FQDN>
+                    Save all records of a Gandi domain as JSON, to --out or stdout
+  restore <FILE>    Replace all records of the domain of a backup, or of --domain, with its records
+  tlsa --cert <FILE> --name <NAME>
+                    Publish the DANE-EE TLSA record of a certificate, e.g. from a renewal hook;
+                    NAME is relative to --domain or a full name in the domain of a service
+  gc                List records dynsix created for services removed from the config since,
+                    needs state_file
+  whoami            Check the token and list the organizations and domains it can access
+  import ddclient|inadyn <FILE>
+                    Convert a ddclient.conf or inadyn.conf into a dynsix config, to --out or
+                    stdout
+  completions <SHELL>
+                    Print a completion script for bash, zsh or fish
+  install systemd   Print systemd units for the current binary and config
+  features          List the IP sources, providers, notifiers and cargo features compiled in
+  help              Print this message
+
+Options:
+  -c, --config <PATH>   Config file [default: {DEFAULT_CONFIG_PATH}]
+      --format <FMT>    Config format: toml, yaml or json [default: from file extension]
+      --sops            Decrypt the config with sops, encrypted configs are detected without it
+  -s, --service <NAME>  Only reconcile matching services, or show their status or history; may
+                        be repeated
+                        and contain * and ?
+  -p, --prefix <PREFIX> Use this prefix (e.g. 2001:db8:1:2::/64) instead of asking the query server
+  -o, --output <FMT>    Run summary, status or history on stdout: text or json [default: text]
+      --out <FILE>      plan: save the plan as JSON for a later apply; backup, import: write it
+                        there
+      --plan <FILE>     apply: the plan to execute
+      --domain <FQDN>   backup: the domain to save; restore: restore into this domain instead;
+                        tlsa: the domain of --name
+      --cert <FILE>     tlsa: the certificate, the first of the file, e.g. fullchain.pem
+      --name <NAME>     tlsa: the record, e.g. _443._tcp.www
+  -w, --wait            run, hook, apply: wait for a run holding lock_file instead of exiting with 3
+      --log-http        Log every HTTP request and response, with credentials masked
+  -y, --yes             delete, restore: do not ask for confirmation
+      --apply           gc: delete the listed records
+  -i, --interactive     config init: prompt for token, fqdn and suffix
+  -f, --force           config init, import: overwrite an existing file
+      --nagios          check: print a status line with performance data for Nagios or Icinga
+      --timer           install systemd: a oneshot run on a timer instead of the daemon
+      --write           install systemd: write the units to /etc/systemd/system
+  -h, --help            Print this message
+  -V, --version         Print the version, commit, build date and config schema version",
+        name = env!("CARGO_PKG_NAME")
+    )
+}
@@ -0,0 +1,201 @@
+//! Shell completion scripts. Service names are completed dynamically by calling
+//! the hidden `__services` subcommand, which honors a `--config` given on the
+//! command line being completed.
+
+use std::str::FromStr;
+
+const BIN: &str = env!("CARGO_PKG_NAME");
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+impl FromStr for Shell {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "bash" => Ok(Self::Bash),
+            "zsh" => Ok(Self::Zsh),
+            "fish" => Ok(Self::Fish),
+            _ => Err(format!(
+                "unsupported shell '{s}', expected bash, zsh or fish"
+            )),
+        }
+    }
+}
+
+pub fn script(shell: Shell) -> String {
+    let template = match shell {
+        Shell::Bash => BASH,
+        Shell::Zsh => ZSH,
+        Shell::Fish => FISH,
+    };
+    template
+        .replace("{bin}", BIN)
+        .replace("{fn}", &BIN.replace('-', "_"))
+}
+
+const BASH: &str = r#"_{fn}() {
+    local cur prev config i
+    cur="${COMP_WORDS[COMP_CWORD]}"
+    prev="${COMP_WORDS[COMP_CWORD-1]}"
+
+    for ((i = 1; i < COMP_CWORD; i++)); do
+        case "${COMP_WORDS[i]}" in
+            -c|--config) config="${COMP_WORDS[i+1]}" ;;
+        esac
+    done
+
+    case "$prev" in
+        -s|--service|delete)
+            COMPREPLY=($(compgen -W "$({bin} ${config:+--config "$config"} __services 2>/dev/null)" -- "$cur"))
+            return ;;
+        -c|--config|--out|--plan|restore)
+            COMPREPLY=($(compgen -f -- "$cur"))
+            return ;;
+        --format)
+            COMPREPLY=($(compgen -W "toml yaml json" -- "$cur"))
+            return ;;
+        -o|--output)
+            COMPREPLY=($(compgen -W "text json" -- "$cur"))
+            return ;;
+        -p|--prefix|--domain)
+            return ;;
+        config)
+            COMPREPLY=($(compgen -W "validate init" -- "$cur"))
+            return ;;
+        install)
+            COMPREPLY=($(compgen -W "systemd" -- "$cur"))
+            return ;;
+        import)
+            COMPREPLY=($(compgen -W "ddclient inadyn" -- "$cur"))
+            return ;;
+        ddclient|inadyn)
+            COMPREPLY=($(compgen -f -- "$cur"))
+            return ;;
+        completions)
+            COMPREPLY=($(compgen -W "bash zsh fish" -- "$cur"))
+            return ;;
+        ctl)
+            COMPREPLY=($(compgen -W "status reconcile reload-config logs" -- "$cur"))
+            return ;;
+        reconcile)
+            COMPREPLY=($(compgen -W "$({bin} ${config:+--config "$config"} __services 2>/dev/null)" -- "$cur"))
+            return ;;
+    esac
+
+    if [[ "$cur" == -* ]]; then
+        COMPREPLY=($(compgen -W "--config --format --sops --service --prefix --output --out --plan --domain --cert --name --wait --log-http --interactive --force --yes --apply --nagios --timer --write --help --version" -- "$cur"))
+    else
+        COMPREPLY=($(compgen -W "run once hook daemon ctl tui plan apply list check delete backup restore gc status history audit whoami config import install completions features help" -- "$cur"))
+    fi
+}
+complete -F _{fn} {bin}
+"#;
+
+const ZSH: &str = r#"#compdef {bin}
+
+_{fn}_services() {
+    local -a services
+    services=(${(f)"$({bin} ${opt_args[--config]:+--config ${opt_args[--config]}} __services 2>/dev/null)"})
+    _describe 'service' services
+}
+
+_{fn}() {
+    local state
+    _arguments -C \
+        '(-c --config)'{-c,--config}'[config file]:file:_files' \
+        '--format[config format]:format:(toml yaml json)' \
+        '--sops[decrypt the config with sops]' \
+        '*'{-s,--service}'[only reconcile matching services]:service:_{fn}_services' \
+        '(-p --prefix)'{-p,--prefix}'[use this prefix instead of the query server]:prefix:' \
+        '(-o --output)'{-o,--output}'[run summary format]:format:(text json)' \
+        '--out[plan: save the plan; backup, import: write it there]:file:_files' \
+        '--plan[apply: plan to execute]:file:_files' \
+        '--domain[backup, restore, tlsa: domain]:domain:' \
+        '--cert[tlsa: certificate]:file:_files' \
+        '--name[tlsa: record name]:name:' \
+        '(-w --wait)'{-w,--wait}'[run, apply: wait for a running invocation]' \
+        '--log-http[log HTTP requests and responses]' \
+        '(-i --interactive)'{-i,--interactive}'[config init: prompt for values]' \
+        '(-f --force)'{-f,--force}'[config init, import: overwrite existing file]' \
+        '(-y --yes)'{-y,--yes}'[delete, restore: do not ask for confirmation]' \
+        '--apply[gc: delete the listed records]' \
+        '--nagios[check: output for Nagios or Icinga]' \
+        '--timer[install systemd: oneshot run on a timer]' \
+        '--write[install systemd: write to /etc/systemd/system]' \
+        '(-h --help)'{-h,--help}'[print help]' \
+        '(-V --version)'{-V,--version}'[print version and build information]' \
+        '1:command:(run once hook daemon ctl tui plan apply list check delete backup restore gc status history audit whoami config import install completions features help)' \
+        '*::argument:->argument'
+
+    case "$state" in
+        argument)
+            case "${words[1]}" in
+                config) _values 'subcommand' validate init ;;
+                install) _values 'target' systemd ;;
+                import)
+                    if (( CURRENT == 2 )); then
+                        _values 'format' ddclient inadyn
+                    else
+                        _files
+                    fi ;;
+                completions) _values 'shell' bash zsh fish ;;
+                ctl) _values 'command' status reconcile reload-config logs ;;
+                delete) _{fn}_services ;;
+                restore) _files ;;
+            esac ;;
+    esac
+}
+
+_{fn} "$@"
+"#;
+
+const FISH: &str = r#"function __{fn}_services
+    set -l tokens (commandline -opc)
+    set -l args
+    for i in (seq (count $tokens))
+        if contains -- $tokens[$i] -c --config; and test $i -lt (count $tokens)
+            set args --config $tokens[(math $i + 1)]
+        end
+    end
+    {bin} $args __services 2>/dev/null
+end
+
+complete -c {bin} -f
+complete -c {bin} -n __fish_use_subcommand -a "run once hook daemon ctl tui plan apply list check delete backup restore gc status history audit whoami config import install completions features help"
+complete -c {bin} -n "__fish_seen_subcommand_from config" -a "validate init"
+complete -c {bin} -n "__fish_seen_subcommand_from install" -a "systemd"
+complete -c {bin} -n "__fish_seen_subcommand_from import; and not __fish_seen_subcommand_from ddclient inadyn" -a "ddclient inadyn"
+complete -c {bin} -n "__fish_seen_subcommand_from ddclient inadyn" -F
+complete -c {bin} -n "__fish_seen_subcommand_from completions" -a "bash zsh fish"
+complete -c {bin} -n "__fish_seen_subcommand_from ctl" -a "status reconcile reload-config logs"
+complete -c {bin} -n "__fish_seen_subcommand_from delete" -a "(__{fn}_services)"
+complete -c {bin} -n "__fish_seen_subcommand_from restore" -F
+complete -c {bin} -s c -l config -r -F -d "Config file"
+complete -c {bin} -l format -x -a "toml yaml json" -d "Config format"
+complete -c {bin} -l sops -d "Decrypt the config with sops"
+complete -c {bin} -s s -l service -x -a "(__{fn}_services)" -d "Only reconcile matching services"
+complete -c {bin} -s p -l prefix -x -d "Use this prefix instead of the query server"
+complete -c {bin} -s o -l output -x -a "text json" -d "Run summary format"
+complete -c {bin} -l out -r -F -d "plan: save the plan; backup, import: write it there"
+complete -c {bin} -l plan -r -F -d "apply: plan to execute"
+complete -c {bin} -l domain -x -d "backup, restore, tlsa: domain"
+complete -c {bin} -l cert -r -F -d "tlsa: certificate"
+complete -c {bin} -l name -x -d "tlsa: record name"
+complete -c {bin} -s w -l wait -d "run, apply: wait for a running invocation"
+complete -c {bin} -l log-http -d "log HTTP requests and responses"
+complete -c {bin} -s i -l interactive -d "config init: prompt for values"
+complete -c {bin} -s f -l force -d "config init, import: overwrite existing file"
+complete -c {bin} -s y -l yes -d "delete, restore: do not ask for confirmation"
+complete -c {bin} -l apply -d "gc: delete the listed records"
+complete -c {bin} -l nagios -d "check: output for Nagios or Icinga"
+complete -c {bin} -l timer -d "install systemd: oneshot run on a timer"
+complete -c {bin} -l write -d "install systemd: write to /etc/systemd/system"
+complete -c {bin} -s h -l help -d "Print help"
+complete -c {bin} -s V -l version -d "Print version and build information"
+"#;
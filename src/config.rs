@@ -1,35 +1,1104 @@
-use std::{collections::HashMap, error::Error, net::Ipv6Addr, path::Path};
+use std::{
+    collections::HashMap,
+    net::{Ipv6Addr, SocketAddr},
+    os::unix::fs::MetadataExt,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::{Duration, SystemTime},
+};
 
-use serde::Deserialize;
+use serde::{de::DeserializeOwned, Deserialize, Deserializer};
+
+use crate::{
+    audit::AuditConfig,
+    discovery::DiscoveryConfig,
+    http_client::HttpConfig,
+    idna,
+    ip::{merge_bits, with_subnet, Source},
+    metrics::MetricsConfig,
+    notify::NotifyConfig,
+    provider::{display_name, record_name, ProviderKind, ProvidersConfig},
+    reconcile::{Compare, ConcurrencyConfig},
+    retry::RetryConfig,
+    schedule::Schedule,
+    secret::{self, Secret},
+    sops,
+    srv::{self, SrvRecord},
+    sshfp,
+    suffix::Suffix,
+    ttl::LowTtl,
+    vault::VaultConfig,
+    yaml, DynsixError,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigFormat {
+    Toml,
+    Yaml,
+    Json,
+}
+
+/// Version of the config format, raised when a release changes the meaning
+/// of existing options so that older configs need updating
+pub const SCHEMA_VERSION: u32 = 1;
 
 #[derive(Deserialize, Debug)]
 pub struct Config {
     #[serde(default = "default_query_server")]
     pub query_server: String,
+    /// Where the prefix is read from instead of asking `query_server`
+    pub source: Option<Source>,
+    /// What to do when the address seen from outside isn't one of a local
+    /// interface
+    #[serde(default)]
+    pub prefix_check: PrefixCheck,
 
+    #[serde(default)]
     pub services: HashMap<String, ServiceConfig>,
-    pub token: String,
+    /// Gandi token, only required if a service uses Gandi
+    #[serde(default)]
+    pub token: Secret,
+    /// Named Gandi tokens, which services pick with `token_ref`
+    #[serde(default)]
+    pub tokens: HashMap<String, Secret>,
+    /// Fetches `token` from Vault instead
+    pub vault: Option<VaultConfig>,
+
+    #[serde(default)]
+    pub providers: ProvidersConfig,
+
+    #[serde(default)]
+    pub discovery: DiscoveryConfig,
+
+    #[serde(default)]
+    pub notify: NotifyConfig,
+
+    #[serde(default)]
+    pub metrics: MetricsConfig,
+
+    #[serde(default)]
+    pub daemon: DaemonConfig,
+
+    #[serde(default)]
+    pub http: HttpConfig,
+
+    #[serde(default)]
+    pub retry: RetryConfig,
+
+    #[serde(default)]
+    pub concurrency: ConcurrencyConfig,
+
+    #[serde(default)]
+    pub audit: AuditConfig,
+
+    /// Where information is kept between runs, nothing is kept if unset
+    pub state_file: Option<PathBuf>,
+
+    /// SQLite database every change to a record is added to
+    pub history_file: Option<PathBuf>,
+
+    /// Locked during runs so that separate invocations never overlap
+    pub lock_file: Option<PathBuf>,
+
+    /// Read records again right before changing them and leave them alone
+    /// if another updater changed them in the meantime
+    #[serde(default)]
+    pub check_races: bool,
+
+    /// Undo the changes to the records of a domain in a run if one of its
+    /// services fails
+    #[serde(default)]
+    pub transactional: bool,
+
+    /// Delete the records created for services that are gone from the
+    /// config during runs, as `gc --apply` does
+    #[serde(default)]
+    pub prune: bool,
+
+    /// Refuse config files holding secrets that others can read, instead of
+    /// warning about them
+    #[serde(default)]
+    pub strict_permissions: bool,
+
+    /// Where log lines are written
+    #[serde(default)]
+    pub log_output: LogOutput,
+
+    #[serde(default)]
+    pub log: LogConfig,
+
+    /// Problems found while loading that don't stop dynsix, like config
+    /// files with secrets that others can read
+    #[serde(skip)]
+    pub warnings: Vec<String>,
 }
 
-#[derive(Deserialize, Debug)]
+/// Whether the address the query server or DNS server sees is compared with
+/// the addresses of the local interfaces. Behind NAT66, a proxy or a tunnel
+/// ending on another host, its /64 isn't that of this host, so suffixes
+/// merged into it may not reach anything.
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum PrefixCheck {
+    Off,
+    /// Log a warning and publish anyway
+    #[default]
+    Warn,
+    /// Fail the run instead of publishing
+    Refuse,
+}
+
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum LogOutput {
+    #[default]
+    Stderr,
+    /// The native protocol of journald, with the fields of every line as
+    /// fields of its entry
+    Journald,
+}
+
+/// How log lines are written. `RUST_LOG` still applies on top of `level` and
+/// `levels`, its directives win.
+#[derive(Deserialize, Debug, Default, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct LogConfig {
+    /// Level of targets without one in `levels`, `error` if unset
+    pub level: Option<LogLevel>,
+    /// Levels by target prefix, e.g. `reqwest` or `dynsix::reconcile`, the
+    /// longest matching prefix wins
+    #[serde(default)]
+    pub levels: HashMap<String, LogLevel>,
+    #[serde(default)]
+    pub timestamps: Timestamps,
+    #[serde(default)]
+    pub color: ColorMode,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum LogLevel {
+    Off,
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+/// How each line starts
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Timestamps {
+    /// `2023-01-20T12:00:00Z`
+    #[default]
+    Rfc3339,
+    /// `2023-01-20T12:00:00.123Z`
+    Rfc3339Millis,
+    /// Nothing, e.g. when journald or docker add their own
+    None,
+}
+
+/// Whether the level of each line is colored
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ColorMode {
+    /// If stderr is a terminal and `NO_COLOR` is unset
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+#[derive(Deserialize, Debug, Clone)]
 pub struct ServiceConfig {
-    pub suffix: Ipv6Addr,
+    pub suffix: Suffix,
+    /// Subnet ID placed between a delegated prefix of `delegated_length`
+    /// and the suffix, e.g. `0x12` in the /64 of a VLAN out of a /56
+    pub subnet: Option<u64>,
+    /// Length of the delegated prefix, required with `subnet`
+    pub delegated_length: Option<u8>,
+    /// Record name, `names` publishes several with the same address instead
+    #[serde(default)]
     pub name: String,
+    #[serde(default)]
+    pub names: Vec<String>,
     pub fqdn: String,
     pub ttl: u32,
+    /// Disabled services are kept in the config but never reconciled
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    /// When published values count as up to date
+    #[serde(default)]
+    pub compare: Compare,
+    /// Where the record is published, Gandi by default
+    #[serde(default)]
+    pub provider: ProviderKind,
+    /// Options of the provider for this service alone, like its
+    /// `[providers.*]` section, e.g. to use another account
+    pub credentials: Option<serde_json::Value>,
+    /// Entry of `[tokens]` used instead of the top level Gandi token
+    pub token_ref: Option<String>,
+    /// Cron expression on which `dynsix daemon` reconciles this service
+    /// instead of every `daemon.interval`
+    pub schedule: Option<Schedule>,
+    /// Time between runs of this service in `dynsix daemon`, instead of
+    /// `daemon.interval`
+    #[serde(default, deserialize_with = "deserialize_optional_duration")]
+    pub interval: Option<Duration>,
+    /// A shorter TTL published around changes of the address
+    pub low_ttl: Option<LowTtl>,
+    /// Cron expression of the minutes in which changes to an existing,
+    /// working record are deferred, e.g. `* 8-17 * * 1-5`
+    pub quiet_hours: Option<Schedule>,
+    /// Public host keys whose fingerprints are published as SSHFP records
+    /// next to the AAAA records
+    #[serde(default)]
+    pub ssh_host_keys: Vec<PathBuf>,
+    /// SRV records in the domain of the service pointing at its host
+    #[serde(default)]
+    pub srv: Vec<SrvRecord>,
+}
+
+impl ServiceConfig {
+    /// The address the service `name` publishes for `prefix`
+    pub fn address(&self, name: &str, prefix: Ipv6Addr) -> Result<Ipv6Addr, DynsixError> {
+        let prefix = match (self.subnet, self.delegated_length) {
+            (Some(subnet), Some(length)) => with_subnet(prefix, length, subnet),
+            _ => prefix,
+        };
+        self.suffix.address(name, prefix)
+    }
+
+    /// Names of the records the service publishes
+    pub fn record_names(&self) -> Vec<&str> {
+        if self.names.is_empty() {
+            vec![self.name.as_str()]
+        } else {
+            self.names.iter().map(String::as_str).collect()
+        }
+    }
+}
+
+/// Settings of `dynsix daemon`
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct DaemonConfig {
+    /// Time between scheduled runs, e.g. `5m` or `1h 30m`
+    #[serde(
+        default = "default_interval",
+        deserialize_with = "deserialize_duration"
+    )]
+    pub interval: Duration,
+    /// Upper bound of a random delay before the first run
+    #[serde(default, deserialize_with = "deserialize_duration")]
+    pub splay: Duration,
+    /// Upper bound of a random delay added to every interval and scheduled run
+    #[serde(default, deserialize_with = "deserialize_duration")]
+    pub jitter: Duration,
+    /// Time between checks for records changed outside dynsix, disabled if zero
+    #[serde(default, deserialize_with = "deserialize_duration")]
+    pub drift_check: Duration,
+    /// Address of the HTTP API, disabled if unset
+    pub listen: Option<SocketAddr>,
+    /// Bearer token required by `POST /prefix`, which is disabled if unset
+    pub prefix_token: Option<Secret>,
+    /// Unix socket for `dynsix ctl`, disabled if unset
+    pub control_socket: Option<PathBuf>,
+    /// Address of the gRPC API, disabled if unset
+    pub grpc_listen: Option<SocketAddr>,
+    /// Bearer token required by every call of the gRPC API
+    pub grpc_token: Option<Secret>,
+    /// Time a run in progress gets to finish after SIGTERM or SIGINT
+    #[serde(
+        default = "default_shutdown_timeout",
+        deserialize_with = "deserialize_duration"
+    )]
+    pub shutdown_timeout: Duration,
+    /// Reload when the config file or a file in conf.d changes
+    #[serde(default)]
+    pub watch_config: bool,
+    /// User the daemon switches to once the HTTP API and control socket are
+    /// set up, if started as root
+    pub user: Option<String>,
+    /// Group switched to along with `user`, its primary group if unset
+    pub group: Option<String>,
+    /// Confine the daemon with Landlock and seccomp
+    #[serde(default)]
+    pub sandbox: bool,
+    /// Read-only paths the sandbox allows besides the system directories
+    #[serde(default)]
+    pub sandbox_paths: Vec<PathBuf>,
+}
+
+impl Default for DaemonConfig {
+    fn default() -> Self {
+        Self {
+            interval: default_interval(),
+            splay: Duration::ZERO,
+            jitter: Duration::ZERO,
+            drift_check: Duration::ZERO,
+            listen: None,
+            prefix_token: None,
+            control_socket: None,
+            grpc_listen: None,
+            grpc_token: None,
+            shutdown_timeout: default_shutdown_timeout(),
+            watch_config: false,
+            user: None,
+            group: None,
+            sandbox: false,
+            sandbox_paths: Vec::new(),
+        }
+    }
+}
+
+// Fragments from conf.d may only contribute services
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct ConfigFragment {
+    #[serde(default)]
+    services: HashMap<String, ServiceConfig>,
+}
+
+impl ConfigFormat {
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()? {
+            "toml" => Some(Self::Toml),
+            "yaml" | "yml" => Some(Self::Yaml),
+            "json" => Some(Self::Json),
+            _ => None,
+        }
+    }
+
+    fn parse<T: DeserializeOwned>(self, raw: &[u8]) -> Result<T, String> {
+        match self {
+            Self::Toml => toml::from_slice(raw).map_err(|e| e.to_string()),
+            Self::Json => serde_json::from_slice(raw).map_err(|e| e.to_string()),
+            Self::Yaml => {
+                let raw = std::str::from_utf8(raw).map_err(|e| e.to_string())?;
+                serde_json::from_value(yaml::from_str(raw)?).map_err(|e| e.to_string())
+            }
+        }
+    }
+}
+
+impl FromStr for ConfigFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "toml" => Ok(Self::Toml),
+            "yaml" | "yml" => Ok(Self::Yaml),
+            "json" => Ok(Self::Json),
+            _ => Err(format!(
+                "unknown config format '{s}', expected toml, yaml or json"
+            )),
+        }
+    }
 }
 
 impl Config {
-    pub fn load<P>(path: P) -> Result<Self, Box<dyn Error>>
+    /// Loads the config, detecting the format from the file extension (TOML by default)
+    pub fn load<P>(path: P) -> Result<Self, DynsixError>
+    where
+        P: AsRef<Path>,
+    {
+        let format = ConfigFormat::from_path(path.as_ref()).unwrap_or(ConfigFormat::Toml);
+        Self::load_as(path, format)
+    }
+
+    /// Loads the config, decrypting it with sops if it was encrypted
+    pub fn load_as<P>(path: P, format: ConfigFormat) -> Result<Self, DynsixError>
+    where
+        P: AsRef<Path>,
+    {
+        Self::load_from(path.as_ref(), format, false)
+    }
+
+    /// Loads the config, decrypting it with sops even if it does not look
+    /// encrypted
+    pub fn load_sops<P>(path: P, format: ConfigFormat) -> Result<Self, DynsixError>
     where
         P: AsRef<Path>,
     {
-        let config_raw = std::fs::read(path)?;
-        Ok(toml::from_slice(&config_raw)?)
+        Self::load_from(path.as_ref(), format, true)
+    }
+
+    fn load_from(path: &Path, format: ConfigFormat, sops: bool) -> Result<Self, DynsixError> {
+        let (mut config_raw, encrypted) = read_config(path, format, sops)?;
+        let parsed = format.parse(&config_raw);
+        // It holds the tokens, which now live in Secrets
+        secret::wipe(&mut config_raw);
+        let mut config: Config = parsed.map_err(|message| DynsixError::Config {
+            path: path.to_path_buf(),
+            message,
+        })?;
+        // The main file holds the tokens, unless they are encrypted
+        if !encrypted {
+            config
+                .warnings
+                .extend(check_permissions(path, config.strict_permissions)?);
+        }
+
+        for fragment_path in fragment_paths(&include_dir(path))? {
+            let fragment_format = ConfigFormat::from_path(&fragment_path).unwrap_or(format);
+            let (mut fragment_raw, encrypted) =
+                read_config(&fragment_path, fragment_format, false)?;
+            let parsed = fragment_format.parse(&fragment_raw);
+            secret::wipe(&mut fragment_raw);
+            let fragment: ConfigFragment = parsed.map_err(|message| DynsixError::Config {
+                path: fragment_path.clone(),
+                message,
+            })?;
+
+            if !encrypted
+                && fragment
+                    .services
+                    .values()
+                    .any(|service| service.credentials.is_some())
+            {
+                config.warnings.extend(check_permissions(
+                    &fragment_path,
+                    config.strict_permissions,
+                )?);
+            }
+            for (name, service) in fragment.services {
+                if config.services.contains_key(&name) {
+                    return Err(DynsixError::Config {
+                        path: fragment_path,
+                        message: format!("service '{name}' is already defined"),
+                    });
+                }
+                config.services.insert(name, service);
+            }
+        }
+
+        config.normalize();
+        config.load_secret_files()?;
+        config.load_credentials()?;
+        Ok(config)
+    }
+}
+
+/// The plain contents of a config file and whether they were decrypted by
+/// sops. `sops` decrypts the file even if it does not look encrypted.
+fn read_config(
+    path: &Path,
+    format: ConfigFormat,
+    sops: bool,
+) -> Result<(Vec<u8>, bool), DynsixError> {
+    let raw = std::fs::read(path).map_err(|e| DynsixError::io(path, e))?;
+    if sops || sops::is_encrypted(&raw) {
+        return Ok((sops::decrypt(path, format)?, true));
+    }
+    Ok((raw, false))
+}
+
+/// The contents of the file named by the environment variable `var`, if set.
+/// Unlike a missing credential, a missing file is an error as it was asked for.
+fn read_secret_file(var: &str) -> Result<Option<Secret>, DynsixError> {
+    let Some(path) = std::env::var_os(var).filter(|path| !path.is_empty()) else {
+        return Ok(None);
+    };
+    let secret = std::fs::read_to_string(&path).map_err(|e| DynsixError::io(&path, e))?;
+    Ok(Some(Secret::trimmed(secret)))
+}
+
+/// The commented starter configuration, also shipped as `config.toml.example`
+const EXAMPLE: &str = include_str!("../config.toml.example");
+
+/// Renders the commented starter configuration with the token, domain and
+/// suffix filled in. The result is checked against the struct definitions by
+/// `dynsix config init` before it is written.
+pub fn example(token: &str, fqdn: &str, suffix: &Ipv6Addr) -> String {
+    let string = |value: &str| toml::Value::String(value.to_string()).to_string();
+    EXAMPLE
+        .replacen(
+            r#"token = "your gandi token""#,
+            &format!("token = {}", string(token)),
+            1,
+        )
+        .replacen(
+            r#"suffix = "::1:cee:bad:c0de""#,
+            &format!("suffix = {}", string(&suffix.to_string())),
+            1,
+        )
+        .replacen(
+            r#"fqdn = "example.com""#,
+            &format!("fqdn = {}", string(fqdn)),
+            1,
+        )
+}
+
+/// Name of the systemd credential holding the Gandi token, passed with
+/// `LoadCredential=gandi-token:<file>`
+pub const TOKEN_CREDENTIAL: &str = "gandi-token";
+
+/// Environment variable naming a file with the Gandi token, e.g. a mounted
+/// Docker or Podman secret
+pub const TOKEN_FILE_ENV: &str = "DYNSIX_TOKEN_FILE";
+/// Environment variable naming a file with `daemon.prefix_token`
+pub const PREFIX_TOKEN_FILE_ENV: &str = "DYNSIX_PREFIX_TOKEN_FILE";
+
+// DNS limits on the ASCII form of names
+const MAX_LABEL_LENGTH: usize = 63;
+const MAX_NAME_LENGTH: usize = 253;
+
+impl Config {
+    /// Takes secrets from the files named by [`TOKEN_FILE_ENV`] and
+    /// [`PREFIX_TOKEN_FILE_ENV`], the convention for container secrets
+    fn load_secret_files(&mut self) -> Result<(), DynsixError> {
+        if let Some(token) = read_secret_file(TOKEN_FILE_ENV)? {
+            self.token = token;
+        }
+        if let Some(token) = read_secret_file(PREFIX_TOKEN_FILE_ENV)? {
+            self.daemon.prefix_token = Some(token);
+        }
+        Ok(())
+    }
+
+    /// Takes the Gandi token from the systemd credential [`TOKEN_CREDENTIAL`]
+    /// if the service was started with one
+    fn load_credentials(&mut self) -> Result<(), DynsixError> {
+        let Some(dir) = std::env::var_os("CREDENTIALS_DIRECTORY") else {
+            return Ok(());
+        };
+        let path = Path::new(&dir).join(TOKEN_CREDENTIAL);
+        match std::fs::read_to_string(&path) {
+            Ok(token) => {
+                self.token = Secret::trimmed(token);
+                Ok(())
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(DynsixError::io(&path, e)),
+        }
+    }
+
+    /// Brings domains and record names into the form they are compared and
+    /// published in, so `WWW` and `example.com.` match what providers return
+    fn normalize(&mut self) {
+        for service in self.services.values_mut() {
+            service.fqdn = normalize_host(&service.fqdn);
+            service.name = normalize_host(&service.name);
+            for name in &mut service.names {
+                *name = normalize_host(name);
+            }
+        }
+        for domain in &mut self.discovery.domains {
+            *domain = normalize_host(domain);
+        }
+    }
+
+    /// Brings TTLs into the range the provider of the service accepts, so a
+    /// run publishes the nearest TTL instead of failing on every request.
+    /// Returns a message for every TTL it changed, which [`Config::validate`]
+    /// reports as a problem instead.
+    pub fn clamp_ttls(&mut self) -> Vec<String> {
+        let mut clamped_ttls = Vec::new();
+        let mut names: Vec<_> = self.services.keys().cloned().collect();
+        names.sort();
+        for name in names {
+            let service = self.services.get_mut(&name).unwrap();
+            let range = service.provider.ttl_range();
+            let ttls = std::iter::once(("ttl", &mut service.ttl)).chain(
+                service
+                    .low_ttl
+                    .as_mut()
+                    .map(|low| ("low_ttl.ttl", &mut low.ttl)),
+            );
+            for (key, ttl) in ttls {
+                let clamped = (*ttl).clamp(*range.start(), *range.end());
+                if clamped != *ttl {
+                    clamped_ttls.push(format!(
+                        "service '{name}': {key} {ttl} is outside of the range {}..={} {} accepts, using {clamped}",
+                        range.start(),
+                        range.end(),
+                        service.provider
+                    ));
+                    *ttl = clamped;
+                }
+            }
+        }
+        clamped_ttls
+    }
+
+    /// Semantic checks that go beyond what deserialization enforces.
+    /// Returns a human readable description for every problem found.
+    pub fn validate(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+
+        let uses_gandi =
+            self.services.values().any(|service| {
+                service.provider == ProviderKind::Gandi && service.token_ref.is_none()
+            }) || self.discovery.is_enabled();
+        if uses_gandi && self.token.is_blank() && self.vault.is_none() {
+            problems.push("token is empty".to_string());
+        }
+        let mut token_names: Vec<_> = self.tokens.keys().collect();
+        token_names.sort();
+        for token_name in token_names {
+            if self.tokens[token_name].is_blank() {
+                problems.push(format!("tokens.{token_name} is empty"));
+            }
+        }
+        if let Err(e) = reqwest::Url::parse(&self.query_server) {
+            problems.push(format!(
+                "query_server '{}' is not a valid URL: {e}",
+                self.query_server
+            ));
+        }
+
+        if self.daemon.interval.is_zero() {
+            problems.push("daemon.interval must not be zero".to_string());
+        }
+        if self.daemon.jitter >= self.daemon.interval {
+            problems.push("daemon.jitter must be shorter than daemon.interval".to_string());
+        }
+        if self
+            .daemon
+            .prefix_token
+            .as_ref()
+            .is_some_and(|token| token.is_blank())
+        {
+            problems.push("daemon.prefix_token is empty".to_string());
+        }
+        match &self.daemon.grpc_token {
+            Some(token) if token.is_blank() => {
+                problems.push("daemon.grpc_token is empty".to_string())
+            }
+            None if self.daemon.grpc_listen.is_some() => {
+                problems.push("daemon.grpc_listen requires daemon.grpc_token".to_string())
+            }
+            _ => {}
+        }
+        if self.daemon.user.as_ref().is_some_and(String::is_empty) {
+            problems.push("daemon.user is empty".to_string());
+        }
+        if self.daemon.group.as_ref().is_some_and(String::is_empty) {
+            problems.push("daemon.group is empty".to_string());
+        }
+        if let Some(vault) = &self.vault {
+            vault.validate(&mut problems);
+        }
+        self.http.validate(&mut problems);
+        self.retry.validate(&mut problems);
+        self.concurrency.validate(&mut problems);
+        self.audit.validate(&mut problems);
+        self.providers.validate(&mut problems);
+        self.notify.validate(&mut problems);
+        if cfg!(not(feature = "history")) && self.history_file.is_some() {
+            problems.push("history_file needs dynsix built with the history feature".to_string());
+        }
+        if self.prune && self.state_file.is_none() {
+            problems.push(
+                "prune needs state_file, where dynsix keeps track of the records it created"
+                    .to_string(),
+            );
+        }
+        if let Some(email) = &self.notify.email {
+            if email.after_failures > 1 && self.state_file.is_none() {
+                problems.push(
+                    "notify.email.after_failures needs state_file to count failed runs".to_string(),
+                );
+            }
+        }
+
+        let mut names: Vec<_> = self.services.keys().collect();
+        names.sort();
+
+        let mut records: HashMap<String, &str> = HashMap::new();
+        for name in names {
+            let service = &self.services[name];
+
+            let split = match service.suffix {
+                Suffix::Address(suffix) => Some((suffix, 64)),
+                Suffix::Split { suffix, length } => Some((suffix, length)),
+                _ => None,
+            };
+            if let Some((suffix, length)) = split {
+                if merge_bits(suffix, Ipv6Addr::UNSPECIFIED, length) != Ipv6Addr::UNSPECIFIED {
+                    problems.push(format!(
+                        "service '{name}': suffix {suffix} has bits set in the upper {length} bits, which are replaced by the prefix",
+                    ));
+                }
+            }
+            let range = service.provider.ttl_range();
+            if !range.contains(&service.ttl) {
+                problems.push(format!(
+                    "service '{name}': ttl {} is outside of the range {}..={} {} accepts",
+                    service.ttl,
+                    range.start(),
+                    range.end(),
+                    service.provider
+                ));
+            }
+            match (service.subnet, service.delegated_length) {
+                (Some(_), None) => problems.push(format!(
+                    "service '{name}': subnet requires delegated_length"
+                )),
+                (_, Some(length)) if !(1..64).contains(&length) => problems.push(format!(
+                    "service '{name}': delegated_length must be between 1 and 63, not {length}"
+                )),
+                (Some(subnet), Some(length)) if subnet >> (64 - length) != 0 => {
+                    problems.push(format!(
+                        "service '{name}': subnet {subnet:#x} does not fit into the {} bits after a /{length}",
+                        64 - length
+                    ))
+                }
+                _ => {}
+            }
+            if let Some(interval) = service.interval {
+                if interval.is_zero() {
+                    problems.push(format!("service '{name}': interval must not be zero"));
+                }
+                if service.schedule.is_some() {
+                    problems.push(format!(
+                        "service '{name}': set either interval or schedule, not both"
+                    ));
+                }
+            }
+            if !service.ssh_host_keys.is_empty() {
+                if !service.provider.any_record_type() {
+                    problems.push(format!(
+                        "service '{name}': ssh_host_keys needs a provider publishing SSHFP records, {} only publishes AAAA records",
+                        service.provider
+                    ));
+                }
+                if let Err(e) = sshfp::values(&service.ssh_host_keys) {
+                    problems.push(format!("service '{name}': ssh_host_keys: {e}"));
+                }
+            }
+            if !service.srv.is_empty() && !service.provider.any_record_type() {
+                problems.push(format!(
+                    "service '{name}': srv needs a provider publishing SRV records, {} only publishes AAAA records",
+                    service.provider
+                ));
+            }
+            for record in &service.srv {
+                if let Some(problem) = srv::problem(record) {
+                    problems.push(format!("service '{name}': {problem}"));
+                }
+            }
+            if let Some(quiet_hours) = &service.quiet_hours {
+                if quiet_hours.next_after(SystemTime::now()).is_none() {
+                    problems.push(format!(
+                        "service '{name}': quiet_hours '{quiet_hours}' never match"
+                    ));
+                } else if quiet_hours.end_after(SystemTime::now()).is_none() {
+                    problems.push(format!(
+                        "service '{name}': quiet_hours '{quiet_hours}' never end, changes would be deferred forever"
+                    ));
+                }
+            }
+            if let Some(low_ttl) = &service.low_ttl {
+                if low_ttl.ttl >= service.ttl {
+                    problems.push(format!(
+                        "service '{name}': low_ttl.ttl {} is not lower than ttl {}",
+                        low_ttl.ttl, service.ttl
+                    ));
+                }
+                if !range.contains(&low_ttl.ttl) {
+                    problems.push(format!(
+                        "service '{name}': low_ttl.ttl {} is outside of the range {}..={} {} accepts",
+                        low_ttl.ttl,
+                        range.start(),
+                        range.end(),
+                        service.provider
+                    ));
+                }
+                if let Some(expected) = &low_ttl.expected_change {
+                    if expected.next_after(SystemTime::now()).is_none() {
+                        problems.push(format!(
+                            "service '{name}': low_ttl.expected_change '{expected}' never matches"
+                        ));
+                    }
+                }
+            }
+            if !service.name.is_empty() && !service.names.is_empty() {
+                problems.push(format!(
+                    "service '{name}': set either name or names, not both"
+                ));
+            }
+            for record in service.record_names() {
+                if let Some(problem) = record_name_problem(record, &service.fqdn) {
+                    problems.push(format!("service '{name}': {problem}"));
+                }
+            }
+            if service.fqdn.is_empty() {
+                problems.push(format!("service '{name}': fqdn is empty"));
+            } else if let Some(problem) = fqdn_problem(&service.fqdn) {
+                problems.push(format!("service '{name}': {problem}"));
+            }
+            if let Some(token_ref) = &service.token_ref {
+                if service.provider != ProviderKind::Gandi {
+                    problems.push(format!(
+                        "service '{name}': token_ref only applies to gandi, use credentials for {}",
+                        service.provider
+                    ));
+                } else if !self.tokens.contains_key(token_ref) {
+                    problems.push(format!(
+                        "service '{name}': token_ref '{token_ref}' is not in [tokens]"
+                    ));
+                }
+            }
+            match &service.credentials {
+                Some(credentials) => {
+                    match ProvidersConfig::for_service(service.provider, credentials) {
+                        Ok(config) => {
+                            let mut own = Vec::new();
+                            config.validate(&mut own);
+                            problems.extend(own.into_iter().map(|problem| {
+                                format!("service '{name}': credentials: {problem}")
+                            }));
+                        }
+                        Err(e) => problems.push(format!("service '{name}': credentials: {e}")),
+                    }
+                }
+                None if !self.providers.is_configured(service.provider) => {
+                    problems.push(format!(
+                        "service '{name}': provider {} has no [providers.{}] section",
+                        service.provider, service.provider
+                    ));
+                }
+                None => {}
+            }
+            if let Some(schedule) = &service.schedule {
+                if schedule.next_after(SystemTime::now()).is_none() {
+                    problems.push(format!(
+                        "service '{name}': schedule '{schedule}' never matches"
+                    ));
+                }
+            }
+
+            for record in service.record_names() {
+                // `bücher` and `xn--bcher-kva.` are the same record, as are
+                // `host.lab` in example.com and `host` in lab.example.com.
+                // Names that cannot be encoded were reported above.
+                let (Ok(fqdn), Ok(ascii)) = (
+                    idna::to_ascii(&normalize_host(&service.fqdn)),
+                    idna::to_ascii(&normalize_host(record)),
+                ) else {
+                    continue;
+                };
+                match records.insert(record_name(&fqdn, &ascii), name) {
+                    Some(other) if other == name => problems.push(format!(
+                        "service '{name}': record {} is listed twice",
+                        display_name(&service.fqdn, record)
+                    )),
+                    Some(other) => problems.push(format!(
+                        "services '{other}' and '{name}' both manage the AAAA record {}",
+                        display_name(&normalize_host(&service.fqdn), &normalize_host(record))
+                    )),
+                    None => {}
+                }
+            }
+        }
+
+        problems
+    }
+}
+
+/// What is wrong with the record name `name` in `fqdn`. Names are relative to
+/// the domain, which itself is `@`; a `*` is only a wildcard as the whole
+/// leftmost label.
+fn record_name_problem(name: &str, fqdn: &str) -> Option<String> {
+    if name.is_empty() {
+        return Some(format!("name is empty, use \"@\" for the apex of {fqdn}"));
+    }
+    if name.contains('@') && name != "@" {
+        return Some(format!(
+            "name '{name}': \"@\" is the apex of {fqdn} and stands alone"
+        ));
+    }
+    let wildcard = name.strip_prefix("*.").unwrap_or(name);
+    if wildcard.contains('*') && name != "*" {
+        return Some(format!(
+            "name '{name}': \"*\" is only a wildcard as the whole leftmost label, e.g. \"*\" or \"*.lab\""
+        ));
+    }
+    let lower = normalize_host(name);
+    let fqdn = normalize_host(fqdn);
+    if !fqdn.is_empty() && (lower == fqdn || lower.ends_with(&format!(".{fqdn}"))) {
+        return Some(format!(
+            "name '{name}' includes the domain, it is relative to {fqdn} (\"@\" for the apex)"
+        ));
+    }
+    if name == "@" {
+        return None;
+    }
+
+    let Ok(ascii) = idna::to_ascii(&lower) else {
+        return Some(format!("name '{name}' cannot be encoded as punycode"));
+    };
+    let labels = ascii.strip_prefix("*.").unwrap_or(&ascii);
+    if labels != "*" {
+        if let Some(problem) = labels.split('.').find_map(label_problem) {
+            return Some(format!("name '{name}' {problem}"));
+        }
+    }
+    match idna::to_ascii(&fqdn) {
+        Ok(fqdn) if ascii.len() + 1 + fqdn.len() > MAX_NAME_LENGTH => Some(format!(
+            "record {} is longer than {MAX_NAME_LENGTH} characters",
+            record_name(&fqdn, name)
+        )),
+        _ => None,
+    }
+}
+
+/// What is wrong with the domain `fqdn`, which may end with a dot
+fn fqdn_problem(fqdn: &str) -> Option<String> {
+    let Ok(ascii) = idna::to_ascii(&normalize_host(fqdn)) else {
+        return Some(format!("fqdn '{fqdn}' cannot be encoded as punycode"));
+    };
+    if ascii.len() > MAX_NAME_LENGTH {
+        return Some(format!(
+            "fqdn '{fqdn}' is longer than {MAX_NAME_LENGTH} characters"
+        ));
+    }
+    ascii
+        .split('.')
+        .find_map(label_problem)
+        .map(|problem| format!("fqdn '{fqdn}' {problem}"))
+}
+
+/// Checks a label of the ASCII form of a name against the rules of RFC 1035,
+/// also allowing `_` as used by names like `_acme-challenge`
+fn label_problem(label: &str) -> Option<String> {
+    if label.is_empty() {
+        Some("has an empty label".to_string())
+    } else if label.len() > MAX_LABEL_LENGTH {
+        Some(format!(
+            "has label '{label}' longer than {MAX_LABEL_LENGTH} characters"
+        ))
+    } else if !label
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        Some(format!(
+            "has label '{label}', labels may only contain letters, digits, '-' and '_'"
+        ))
+    } else if label.starts_with('-') || label.ends_with('-') {
+        Some(format!("has label '{label}' starting or ending with '-'"))
+    } else {
+        None
+    }
+}
+
+/// The form names are compared and published in: lowercase and without the
+/// trailing dot of an absolute name
+fn normalize_host(host: &str) -> String {
+    host.trim_end_matches('.').to_lowercase()
+}
+
+/// A warning about a file with secrets that others than its owner can read,
+/// or that belongs to someone else than the user running dynsix or root, like
+/// ssh gives for private keys. With `strict` the file is refused instead.
+fn check_permissions(path: &Path, strict: bool) -> Result<Option<String>, DynsixError> {
+    let metadata = std::fs::metadata(path).map_err(|e| DynsixError::io(path, e))?;
+    let mode = metadata.mode() & 0o777;
+    // SAFETY: geteuid has no preconditions and can not fail
+    let uid = unsafe { libc::geteuid() };
+
+    let problem = if mode & 0o044 != 0 {
+        format!(
+            "is readable by {} (mode {mode:03o}), restrict it with chmod 600",
+            if mode & 0o004 != 0 {
+                "everyone"
+            } else {
+                "its group"
+            }
+        )
+    } else if metadata.uid() != uid && metadata.uid() != 0 {
+        format!(
+            "belongs to uid {}, not to the user running dynsix or root",
+            metadata.uid()
+        )
+    } else {
+        return Ok(None);
+    };
+
+    if strict {
+        return Err(DynsixError::Config {
+            path: path.to_path_buf(),
+            message: format!("holds secrets and {problem}"),
+        });
+    }
+    Ok(Some(format!(
+        "{} holds secrets and {problem}",
+        path.display()
+    )))
+}
+
+/// The files a config loaded from `path` is made of, i.e. `path` itself and
+/// the fragments in its conf.d
+pub fn source_paths(path: &Path) -> Vec<PathBuf> {
+    let mut paths = vec![path.to_path_buf()];
+    paths.extend(fragment_paths(&include_dir(path)).unwrap_or_default());
+    paths
+}
+
+/// The include directory lives next to the main config file,
+/// e.g. `/etc/dynsix/conf.d` for `/etc/dynsix/config.toml`
+fn include_dir(config_path: &Path) -> PathBuf {
+    config_path
+        .parent()
+        .unwrap_or_else(|| Path::new("."))
+        .join("conf.d")
+}
+
+/// All `*.toml`, `*.yaml`/`*.yml` and `*.json` files in `dir`, sorted by name. A missing directory yields no fragments.
+fn fragment_paths(dir: &Path) -> Result<Vec<PathBuf>, DynsixError> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(DynsixError::io(dir, e)),
+    };
+
+    let mut paths = Vec::new();
+    for entry in entries {
+        let path = entry.map_err(|e| DynsixError::io(dir, e))?.path();
+        if path.is_file() && ConfigFormat::from_path(&path).is_some() {
+            paths.push(path);
+        }
     }
+    paths.sort();
+
+    Ok(paths)
+}
+
+pub(crate) fn deserialize_duration<'de, D>(deserializer: D) -> Result<Duration, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let raw = String::deserialize(deserializer)?;
+    humantime::parse_duration(&raw).map_err(serde::de::Error::custom)
+}
+
+pub(crate) fn deserialize_optional_duration<'de, D>(
+    deserializer: D,
+) -> Result<Option<Duration>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Option::<String>::deserialize(deserializer)?
+        .map(|raw| humantime::parse_duration(&raw).map_err(serde::de::Error::custom))
+        .transpose()
 }
 
 // Default implementations
 fn default_query_server() -> String {
     "https://ifconfig.co".to_string()
 }
+
+fn default_enabled() -> bool {
+    true
+}
+
+fn default_interval() -> Duration {
+    Duration::from_secs(300)
+}
+
+fn default_shutdown_timeout() -> Duration {
+    Duration::from_secs(30)
+}
@@ -0,0 +1,132 @@
+//! Local control of the daemon through a Unix socket, used by `dynsix ctl`.
+//! A client sends a single line and gets a single line of JSON back:
+//!
+//! - `status`: the same as `GET /status` of the HTTP API
+//! - `reconcile [PATTERN...]`: runs immediately and answers with the report
+//! - `reload-config`: reloads the config file
+//! - `logs`: the latest log lines up to `info`, as `{"lines": [...]}`
+
+use std::{future::Future, os::unix::fs::PermissionsExt, path::Path};
+
+use serde_json::{json, Value};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+    net::{UnixListener, UnixStream},
+    sync::oneshot,
+};
+use tracing::warn;
+
+use crate::daemon::{Handle, Request, Trigger, TriggerError};
+
+pub const COMMANDS: [&str; 4] = ["status", "reconcile", "reload-config", "logs"];
+
+/// Binds the socket right away, replacing one left behind by a daemon that
+/// is no longer running. The returned future accepts clients until dropped.
+pub fn serve(path: &Path, handle: Handle) -> std::io::Result<impl Future<Output = ()>> {
+    if path.exists() {
+        if std::os::unix::net::UnixStream::connect(path).is_ok() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::AddrInUse,
+                format!("{} is in use by another daemon", path.display()),
+            ));
+        }
+        std::fs::remove_file(path)?;
+    }
+    if let Some(parent) = path
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+    {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    let listener = UnixListener::bind(path)?;
+    // Anyone who can connect can trigger runs and reloads
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))?;
+
+    Ok(async move {
+        loop {
+            match listener.accept().await {
+                Ok((stream, _)) => {
+                    tokio::spawn(client(stream, handle.clone()));
+                }
+                Err(e) => warn!("Control socket: {e}"),
+            }
+        }
+    })
+}
+
+async fn client(stream: UnixStream, handle: Handle) {
+    let mut stream = BufReader::new(stream);
+    let mut line = String::new();
+    if let Err(e) = stream.read_line(&mut line).await {
+        warn!("Control socket: {e}");
+        return;
+    }
+
+    let response = execute(line.trim(), handle).await;
+    if let Err(e) = stream.write_all(format!("{response}\n").as_bytes()).await {
+        warn!("Control socket: {e}");
+    }
+}
+
+async fn execute(line: &str, handle: Handle) -> Value {
+    let mut words = line.split_whitespace();
+    match words.next() {
+        Some("status") => {
+            let status = handle.status.lock().unwrap().clone();
+            serde_json::to_value(status).unwrap_or_else(|e| json!({ "error": e.to_string() }))
+        }
+        Some("reconcile") => {
+            let (reply, result) = oneshot::channel();
+            let trigger = Trigger {
+                services: words.map(str::to_string).collect(),
+                prefix: None,
+                reply,
+            };
+            if handle
+                .requests
+                .send(Request::Reconcile(trigger))
+                .await
+                .is_err()
+            {
+                return json!({ "error": "daemon is shutting down" });
+            }
+            match result.await {
+                Ok(Ok(report)) => serde_json::to_value(report)
+                    .unwrap_or_else(|e| json!({ "error": e.to_string() })),
+                Ok(Err(TriggerError::NoMatch(pattern))) => {
+                    json!({ "error": format!("no configured service matches '{pattern}'") })
+                }
+                Ok(Err(TriggerError::Failed(message))) => json!({ "error": message }),
+                Err(_) => json!({ "error": "daemon is shutting down" }),
+            }
+        }
+        Some("reload-config") => {
+            let (reply, result) = oneshot::channel();
+            if handle.requests.send(Request::Reload(reply)).await.is_err() {
+                return json!({ "error": "daemon is shutting down" });
+            }
+            match result.await {
+                Ok(Ok(services)) => json!({ "reloaded": true, "services": services }),
+                Ok(Err(message)) => json!({ "error": message }),
+                Err(_) => json!({ "error": "daemon is shutting down" }),
+            }
+        }
+        Some("logs") => json!({ "lines": crate::logging::recent() }),
+        Some(other) => json!({
+            "error": format!("unknown command '{other}', expected one of {}", COMMANDS.join(", "))
+        }),
+        None => json!({ "error": "empty command" }),
+    }
+}
+
+/// Sends `command` to the daemon listening on `path` and returns its answer
+pub async fn send(path: &Path, command: &str) -> std::io::Result<Value> {
+    let mut stream = UnixStream::connect(path).await?;
+    stream.write_all(format!("{command}\n").as_bytes()).await?;
+
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await?;
+    serde_json::from_str(&response)
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+}
@@ -0,0 +1,738 @@
+//! `dynsix daemon`: reconciles on a fixed interval and whenever a run is
+//! triggered through the HTTP or gRPC API, the control socket or SIGUSR1. SIGTERM and
+//! SIGINT let a run in progress finish before exiting.
+
+use std::{
+    collections::{hash_map::RandomState, BTreeMap, HashSet},
+    hash::{BuildHasher, Hasher},
+    net::Ipv6Addr,
+    path::{Path, PathBuf},
+    process::ExitCode,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use dynsix::{
+    config::source_paths,
+    glob_match,
+    ip::{merge_ips, Source},
+    notify::sentry,
+    report::{Action, RunReport, Summary, EXIT_TOTAL_FAILURE},
+    schedule,
+    secret::Secret,
+    state::State,
+    Config,
+};
+use serde::{Deserialize, Serialize};
+use tokio::{
+    signal::unix::{signal, Signal, SignalKind},
+    sync::{broadcast, mpsc, oneshot},
+    time::Instant,
+};
+use tracing::{error, info, warn};
+
+use crate::{api, control, grpc, logging, privileges, runner::Runner, sandbox, Cli};
+
+/// How often the config files are checked for changes with `watch_config`
+const WATCH_INTERVAL: Duration = Duration::from_secs(2);
+
+/// What the daemon knows about the latest runs, served by `GET /status`
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct Status {
+    pub last_run: Option<RunStatus>,
+    pub services: BTreeMap<String, ServiceStatus>,
+    /// All runs added up, since the state file was created or else since
+    /// the daemon started
+    #[serde(default)]
+    pub totals: Summary,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RunStatus {
+    pub finished_at: String,
+    pub public_ip: Option<Ipv6Addr>,
+    /// Set if the run failed as a whole
+    pub error: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ServiceStatus {
+    pub record: String,
+    pub action: Action,
+    pub address: Ipv6Addr,
+    /// The address the record holds as far as dynsix knows
+    #[serde(default)]
+    pub published: Option<Ipv6Addr>,
+    pub error: Option<String>,
+    pub last_run: String,
+    pub last_change: Option<String>,
+    #[serde(default)]
+    pub next_check: Option<String>,
+}
+
+impl Status {
+    /// The status as far as the state file tells, without the next checks
+    /// only a running daemon knows
+    pub fn from_state(state: &State) -> Self {
+        let time = |unix: u64| timestamp(UNIX_EPOCH + Duration::from_secs(unix));
+        let services = state
+            .last_attempt
+            .iter()
+            .map(|(name, attempt)| {
+                let status = ServiceStatus {
+                    record: attempt.record.clone(),
+                    action: attempt.action,
+                    address: attempt.address,
+                    published: attempt.published,
+                    error: attempt.error.clone(),
+                    last_run: time(attempt.time),
+                    last_change: state.last_changed.get(name).copied().map(time),
+                    next_check: None,
+                };
+                (name.clone(), status)
+            })
+            .collect();
+        Self {
+            last_run: None,
+            services,
+            totals: state.totals,
+        }
+    }
+}
+
+/// A request for an immediate run
+#[derive(Debug)]
+pub struct Trigger {
+    /// Service name patterns, empty for all services
+    pub services: Vec<String>,
+    /// Replaces the configured way of detecting the prefix for this run
+    pub prefix: Option<Ipv6Addr>,
+    pub reply: oneshot::Sender<Result<RunReport, TriggerError>>,
+}
+
+#[derive(Debug)]
+pub enum TriggerError {
+    /// A pattern matched none of the configured services
+    NoMatch(String),
+    Failed(String),
+}
+
+/// What the interfaces controlling the daemon can ask of it
+#[derive(Debug)]
+pub enum Request {
+    Reconcile(Trigger),
+    /// Reload the config file, answered with the number of services
+    Reload(oneshot::Sender<Result<usize, String>>),
+    /// Sent on SIGTERM and SIGINT
+    Shutdown,
+}
+
+/// Shared between the daemon loop and the interfaces controlling it
+#[derive(Debug, Clone)]
+pub struct Handle {
+    pub status: Arc<Mutex<Status>>,
+    pub requests: mpsc::Sender<Request>,
+    /// Required for pushing a prefix, see `daemon.prefix_token`
+    pub prefix_token: Arc<Mutex<Option<Secret>>>,
+    /// Required by the gRPC API, see `daemon.grpc_token`
+    pub grpc_token: Arc<Mutex<Option<Secret>>>,
+    /// When the daemon started, the age of its health before the first run
+    pub started: SystemTime,
+    /// Age of the last run at which `/healthz` reports the daemon as stuck
+    pub stale_after: Arc<Mutex<Duration>>,
+    /// The /64 of the latest run that got as far as detecting one
+    pub prefix: Arc<Mutex<Option<Ipv6Addr>>>,
+    /// What happened in every run, for `GET /events` and `WatchEvents` of the
+    /// gRPC API
+    pub events: broadcast::Sender<Event>,
+}
+
+/// Something a run of the daemon did, serialized as the data of `GET /events`
+#[derive(Serialize, Debug, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Event {
+    /// A run detected another /64 than the run before, or the first one
+    PrefixDetected {
+        prefix: Ipv6Addr,
+        previous: Option<Ipv6Addr>,
+        time: String,
+    },
+    /// A record was created or updated
+    RecordUpdated(RecordEvent),
+    /// A service failed, or with `service` being `None` the whole run
+    Error {
+        service: Option<String>,
+        record: Option<String>,
+        message: String,
+        time: String,
+    },
+}
+
+impl Event {
+    /// The type in `data`, used as the name of the SSE event
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::PrefixDetected { .. } => "prefix_detected",
+            Self::RecordUpdated(_) => "record_updated",
+            Self::Error { .. } => "error",
+        }
+    }
+}
+
+/// A record the daemon created or updated
+#[derive(Serialize, Debug, Clone)]
+pub struct RecordEvent {
+    pub service: String,
+    pub record: String,
+    pub action: Action,
+    /// The values before, empty if there was no record
+    pub old: Vec<String>,
+    pub new: Ipv6Addr,
+    pub time: String,
+}
+
+pub async fn run(config: Config, cli: &Cli) -> Result<ExitCode, Box<dyn std::error::Error>> {
+    let listen = config.daemon.listen;
+    let grpc_listen = config.daemon.grpc_listen;
+    let control_socket = config.daemon.control_socket.clone();
+    // Runs of the daemon itself never overlap, only wait for other invocations
+    let mut runner = Runner::new(config)?.wait_for_lock(true);
+
+    let (requests, mut request_receiver) = mpsc::channel(16);
+    let handle = Handle {
+        status: Arc::new(Mutex::new(Status::default())),
+        requests,
+        prefix_token: Arc::new(Mutex::new(runner.config().daemon.prefix_token.clone())),
+        grpc_token: Arc::new(Mutex::new(runner.config().daemon.grpc_token.clone())),
+        started: SystemTime::now(),
+        stale_after: Arc::new(Mutex::new(stale_after(runner.config()))),
+        prefix: Arc::new(Mutex::new(None)),
+        // Subscribers that fall this far behind miss events
+        events: broadcast::channel(64).0,
+    };
+    if let Some(listen) = listen {
+        tokio::spawn(api::serve(listen, handle.clone())?);
+        info!("HTTP API listening on {listen}");
+    }
+    if let Some(listen) = grpc_listen {
+        tokio::spawn(grpc::serve(listen, handle.clone())?);
+        info!("gRPC API listening on {listen}");
+    }
+    if let Some(path) = &control_socket {
+        tokio::spawn(control::serve(path, handle.clone())?);
+        info!("Control socket listening on {}", path.display());
+    }
+    // Binding the sockets may have required root, running the rest does not
+    privileges::switch_user(&runner.config().daemon)?;
+    sandbox::restrict_syscalls(&runner.config().daemon)?;
+
+    if runner.config().daemon.watch_config {
+        tokio::spawn(watch(cli.config_path.clone(), handle.requests.clone()));
+        info!("Watching {} for changes", cli.config_path.display());
+    }
+    if let Some(Source::File(path)) = &runner.config().source {
+        tokio::spawn(watch_prefix(path.clone(), handle.requests.clone()));
+        info!("Watching {} for a new prefix", path.display());
+    }
+
+    // Skips the rest of the sleep, e.g. from a ppp hook
+    let mut usr1 = signal(SignalKind::user_defined1())?;
+
+    let stopping = Arc::new(AtomicBool::new(false));
+    tokio::spawn(shutdown_on_signal(
+        signal(SignalKind::terminate())?,
+        signal(SignalKind::interrupt())?,
+        handle.requests.clone(),
+        stopping.clone(),
+        runner.config().daemon.shutdown_timeout,
+        control_socket.clone(),
+    ));
+
+    info!(
+        "Reconciling every {}",
+        humantime::format_duration(runner.config().daemon.interval)
+    );
+    let splay = random_delay(runner.config().daemon.splay);
+    if !splay.is_zero() {
+        info!(
+            "Delaying the first run by {}",
+            humantime::format_duration(splay)
+        );
+    }
+    // For the services without a schedule of their own
+    let mut next_run = Instant::now() + splay;
+    let mut schedules = Schedules::new(runner.config(), Some(SystemTime::now() + splay));
+    let mut next_drift_check = drift_check_after(runner.config());
+    while !stopping.load(Ordering::SeqCst) {
+        publish_next_checks(&handle, runner.config(), &schedules, next_run);
+        let wake = schedules
+            .next()
+            .map_or(next_run, |time| next_run.min(instant_at(time)));
+        let wake = next_drift_check.map_or(wake, |check| wake.min(check));
+        let wakeup = tokio::select! {
+            _ = tokio::time::sleep_until(wake) => Wakeup::Due,
+            _ = usr1.recv() => Wakeup::Signal,
+            Some(request) = request_receiver.recv() => Wakeup::Request(request),
+        };
+        if stopping.load(Ordering::SeqCst) {
+            break;
+        }
+
+        match wakeup {
+            Wakeup::Due => {
+                if next_drift_check.is_some_and(|check| Instant::now() >= check) {
+                    if let Err(e) = runner.check_drift().await {
+                        warn!("Checking for records changed outside dynsix failed: {e}");
+                    }
+                    next_drift_check = drift_check_after(runner.config());
+                }
+
+                let due = schedules.take_due(runner.config(), SystemTime::now());
+                let interval_due = Instant::now() >= next_run;
+                if interval_due {
+                    next_run = Instant::now() + next_interval(runner.config());
+                }
+                if due.is_empty() && !interval_due {
+                    continue;
+                }
+
+                // The services run by the interval include those found by
+                // discovery, which aren't in the config
+                let services = runner.config().services.clone();
+                let selects = |name: &str| {
+                    cli.selects(name) && schedule::is_due(name, &services, &due, interval_due)
+                };
+                let result = runner.run(selects, cli.prefix).await;
+                if let Ok(report) = &result {
+                    schedules.defer(runner.config(), report);
+                }
+                record(&handle, &runner, result.as_ref().map_err(|e| e.to_string()));
+            }
+            Wakeup::Signal => {
+                info!("Received SIGUSR1, reconciling now");
+                next_run = Instant::now() + next_interval(runner.config());
+                let result = runner.run(|name| cli.selects(name), cli.prefix).await;
+                if let Ok(report) = &result {
+                    schedules.defer(runner.config(), report);
+                }
+                record(&handle, &runner, result.as_ref().map_err(|e| e.to_string()));
+            }
+            Wakeup::Request(Request::Reconcile(trigger)) => {
+                triggered(&handle, &mut runner, cli, trigger).await
+            }
+            Wakeup::Request(Request::Reload(reply)) => {
+                let result = reload(&handle, &mut runner, cli);
+                match &result {
+                    Ok(services) => {
+                        schedules = Schedules::new(runner.config(), None);
+                        next_drift_check = drift_check_after(runner.config());
+                        info!("Reloaded the config, {services} services")
+                    }
+                    Err(e) => error!("Keeping the current config: {e}"),
+                }
+                let _ = reply.send(result);
+            }
+            Wakeup::Request(Request::Shutdown) => break,
+        }
+    }
+
+    remove_control_socket(control_socket.as_deref());
+    info!("Stopped");
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Makes the daemon loop stop once the run in progress is done. If that takes
+/// longer than `timeout`, or another signal arrives, the process exits without
+/// waiting for the run.
+async fn shutdown_on_signal(
+    mut terminate: Signal,
+    mut interrupt: Signal,
+    requests: mpsc::Sender<Request>,
+    stopping: Arc<AtomicBool>,
+    timeout: Duration,
+    control_socket: Option<PathBuf>,
+) {
+    let name = tokio::select! {
+        _ = terminate.recv() => "SIGTERM",
+        _ = interrupt.recv() => "SIGINT",
+    };
+    info!("Received {name}, shutting down");
+    stopping.store(true, Ordering::SeqCst);
+    // Wakes up an idle loop, a busy one checks `stopping` after its run
+    let _ = requests.try_send(Request::Shutdown);
+
+    tokio::select! {
+        _ = tokio::time::sleep(timeout) => error!(
+            "The current run did not finish within {}, exiting",
+            humantime::format_duration(timeout)
+        ),
+        _ = terminate.recv() => warn!("Received another signal, exiting"),
+        _ = interrupt.recv() => warn!("Received another signal, exiting"),
+    }
+    remove_control_socket(control_socket.as_deref());
+    std::process::exit(EXIT_TOTAL_FAILURE.into());
+}
+
+fn remove_control_socket(path: Option<&Path>) {
+    if let Some(path) = path {
+        if let Err(e) = std::fs::remove_file(path) {
+            warn!("{}: {e}", path.display());
+        }
+    }
+}
+
+enum Wakeup {
+    /// The interval elapsed or a service schedule is due
+    Due,
+    /// SIGUSR1, reconciles all services right away
+    Signal,
+    Request(Request),
+}
+
+/// Next run of every service with a `schedule` or its own `interval`, or
+/// whose changes were deferred for its `quiet_hours`
+struct Schedules(BTreeMap<String, SystemTime>);
+
+impl Schedules {
+    /// `first` is the first run of the services with an interval, `None`
+    /// waits a full interval
+    fn new(config: &Config, first: Option<SystemTime>) -> Self {
+        let now = SystemTime::now();
+        Self(
+            config
+                .services
+                .iter()
+                .filter_map(|(name, service)| {
+                    let next = match (service.interval, first) {
+                        (Some(_), Some(first)) => first,
+                        (Some(interval), None) => now + interval,
+                        (None, _) => service.schedule.as_ref()?.next_after(now)?,
+                    };
+                    Some((name.clone(), next + random_delay(config.daemon.jitter)))
+                })
+                .collect(),
+        )
+    }
+
+    fn next(&self) -> Option<SystemTime> {
+        self.0.values().min().copied()
+    }
+
+    /// Runs the services whose changes `report` deferred once their quiet
+    /// hours end, unless they are scheduled to run before
+    fn defer(&mut self, config: &Config, report: &RunReport) {
+        let now = SystemTime::now();
+        for service in &report.services {
+            if service.action != Action::Deferred {
+                continue;
+            }
+            let Some(end) = config
+                .services
+                .get(&service.service)
+                .and_then(|config| config.quiet_hours.as_ref()?.end_after(now))
+            else {
+                continue;
+            };
+            info!(
+                service = %service.service,
+                "Applying the deferred change at {}",
+                timestamp(end)
+            );
+            self.0
+                .entry(service.service.clone())
+                .and_modify(|next| *next = (*next).min(end))
+                .or_insert(end);
+        }
+    }
+
+    /// Services that are due at `now`, moving them on to their next run
+    fn take_due(&mut self, config: &Config, now: SystemTime) -> HashSet<String> {
+        let mut due = HashSet::new();
+        self.0.retain(|name, next| {
+            if *next > now {
+                return true;
+            }
+            due.insert(name.clone());
+            let service = &config.services[name];
+            let later = match service.interval {
+                Some(interval) => Some(now + interval),
+                None => service.schedule.as_ref().and_then(|s| s.next_after(now)),
+            };
+            match later {
+                Some(later) => {
+                    *next = later + random_delay(config.daemon.jitter);
+                    true
+                }
+                None => false,
+            }
+        });
+        due
+    }
+}
+
+/// Time until the next run of the services without a schedule
+fn next_interval(config: &Config) -> Duration {
+    config.daemon.interval + random_delay(config.daemon.jitter)
+}
+
+/// Two scheduled runs missed, allowing for the longest delays
+fn stale_after(config: &Config) -> Duration {
+    2 * (config.daemon.interval + config.daemon.jitter) + config.daemon.splay
+}
+
+/// When to next look for records changed outside dynsix, `None` if disabled
+fn drift_check_after(config: &Config) -> Option<Instant> {
+    let every = config.daemon.drift_check;
+    (!every.is_zero()).then(|| Instant::now() + every)
+}
+
+/// A random duration of up to `max`. Spreading runs only needs the
+/// randomness of the std hasher keys, not a proper random number generator.
+fn random_delay(max: Duration) -> Duration {
+    if max.is_zero() {
+        return Duration::ZERO;
+    }
+    let random = RandomState::new().build_hasher().finish();
+    Duration::from_millis(random % (max.as_millis() as u64 + 1))
+}
+
+fn instant_at(time: SystemTime) -> Instant {
+    Instant::now() + time.duration_since(SystemTime::now()).unwrap_or_default()
+}
+
+async fn triggered(handle: &Handle, runner: &mut Runner, cli: &Cli, trigger: Trigger) {
+    if let Some(pattern) = trigger.services.iter().find(|pattern| {
+        !runner
+            .config()
+            .services
+            .keys()
+            .any(|name| glob_match(pattern, name))
+    }) {
+        let _ = trigger
+            .reply
+            .send(Err(TriggerError::NoMatch(pattern.clone())));
+        return;
+    }
+
+    match trigger.prefix {
+        Some(prefix) => info!("Triggered run with pushed prefix {prefix}"),
+        None => info!("Triggered run"),
+    }
+    let selects = |name: &str| {
+        trigger.services.is_empty()
+            || trigger
+                .services
+                .iter()
+                .any(|pattern| glob_match(pattern, name))
+    };
+    let result = runner.run(selects, trigger.prefix.or(cli.prefix)).await;
+    record(handle, runner, result.as_ref().map_err(|e| e.to_string()));
+    let _ = trigger
+        .reply
+        .send(result.map_err(|e| TriggerError::Failed(e.to_string())));
+}
+
+/// Replaces the config if the file loads and validates. The listen addresses,
+/// the control socket and watching the config and the prefix file only
+/// change with a restart.
+fn reload(handle: &Handle, runner: &mut Runner, cli: &Cli) -> Result<usize, String> {
+    let mut config = cli.load_config().map_err(|e| e.to_string())?;
+    for warning in &config.warnings {
+        eprintln!("warning: {warning}");
+    }
+    for message in config.clamp_ttls() {
+        eprintln!("warning: {message}");
+    }
+    let problems = config.validate();
+    if !problems.is_empty() {
+        return Err(problems.join("; "));
+    }
+
+    let prefix_token = config.daemon.prefix_token.clone();
+    let grpc_token = config.daemon.grpc_token.clone();
+    let stale = stale_after(&config);
+    let services = config.services.len();
+    runner.reload(config).map_err(|e| e.to_string())?;
+    logging::configure(runner.config());
+    sentry::report_panics(runner.config().notify.sentry.as_ref());
+    *handle.prefix_token.lock().unwrap() = prefix_token;
+    *handle.grpc_token.lock().unwrap() = grpc_token;
+    *handle.stale_after.lock().unwrap() = stale;
+    Ok(services)
+}
+
+/// Asks for a reload whenever the modification time or size of one of the
+/// config files changes, or files are added to or removed from conf.d
+async fn watch(path: PathBuf, requests: mpsc::Sender<Request>) {
+    let mut last = fingerprint(&path);
+    let mut interval = tokio::time::interval(WATCH_INTERVAL);
+    loop {
+        interval.tick().await;
+        let current = fingerprint(&path);
+        if current == last {
+            continue;
+        }
+        last = current;
+
+        info!("{} changed, reloading", path.display());
+        // The outcome is logged by the daemon loop
+        let (reply, _) = oneshot::channel();
+        if requests.send(Request::Reload(reply)).await.is_err() {
+            return;
+        }
+    }
+}
+
+/// Triggers a run whenever the modification time or size of the prefix file
+/// changes
+async fn watch_prefix(path: PathBuf, requests: mpsc::Sender<Request>) {
+    let modified = |path: &Path| {
+        std::fs::metadata(path)
+            .and_then(|metadata| Ok((metadata.modified()?, metadata.len())))
+            .ok()
+    };
+    let mut last = modified(&path);
+    let mut interval = tokio::time::interval(WATCH_INTERVAL);
+    loop {
+        interval.tick().await;
+        let current = modified(&path);
+        if current == last {
+            continue;
+        }
+        last = current;
+        if current.is_none() {
+            // Removed, or about to be replaced
+            continue;
+        }
+
+        info!("{} changed, reconciling", path.display());
+        // The outcome is logged by the daemon loop
+        let (reply, _) = oneshot::channel();
+        let trigger = Trigger {
+            services: Vec::new(),
+            prefix: None,
+            reply,
+        };
+        if requests.send(Request::Reconcile(trigger)).await.is_err() {
+            return;
+        }
+    }
+}
+
+fn fingerprint(path: &Path) -> Vec<(PathBuf, Option<(SystemTime, u64)>)> {
+    source_paths(path)
+        .into_iter()
+        .map(|path| {
+            let metadata = std::fs::metadata(&path)
+                .and_then(|metadata| Ok((metadata.modified()?, metadata.len())))
+                .ok();
+            (path, metadata)
+        })
+        .collect()
+}
+
+/// Sets when every service in the status runs next, `next_run` being the
+/// next run of the services without a schedule or interval of their own
+fn publish_next_checks(handle: &Handle, config: &Config, schedules: &Schedules, next_run: Instant) {
+    let next_run =
+        timestamp(SystemTime::now() + next_run.saturating_duration_since(Instant::now()));
+    let mut status = handle.status.lock().unwrap();
+    for (name, service) in status.services.iter_mut() {
+        service.next_check = match (schedules.0.get(name), config.services.get(name)) {
+            (Some(time), _) => Some(timestamp(*time)),
+            (None, Some(service)) if service.schedule.is_none() && service.interval.is_none() => {
+                Some(next_run.clone())
+            }
+            _ => None,
+        };
+    }
+}
+
+/// Updates the status after a run and tells watchers what happened. Sending
+/// fails only if no one is watching.
+fn record(handle: &Handle, runner: &Runner, result: Result<&RunReport, String>) {
+    let now = timestamp(SystemTime::now());
+    match &result {
+        Ok(report) => {
+            let prefix = merge_ips(report.public_ip, Ipv6Addr::UNSPECIFIED);
+            let previous = handle.prefix.lock().unwrap().replace(prefix);
+            if previous != Some(prefix) {
+                let _ = handle.events.send(Event::PrefixDetected {
+                    prefix,
+                    previous,
+                    time: now.clone(),
+                });
+            }
+        }
+        Err(e) => {
+            error!("{e}");
+            let _ = handle.events.send(Event::Error {
+                service: None,
+                record: None,
+                message: e.clone(),
+                time: now.clone(),
+            });
+        }
+    }
+
+    let mut status = handle.status.lock().unwrap();
+    status.last_run = Some(RunStatus {
+        finished_at: now.clone(),
+        public_ip: result.as_ref().ok().map(|report| report.public_ip),
+        error: result.as_ref().err().cloned(),
+    });
+    status.totals = runner.state().totals;
+
+    let Ok(report) = result else {
+        return;
+    };
+    for service in &report.services {
+        if matches!(service.action, Action::Created | Action::Updated) {
+            let _ = handle.events.send(Event::RecordUpdated(RecordEvent {
+                service: service.service.clone(),
+                record: service.record.clone(),
+                action: service.action,
+                old: service.old.clone().unwrap_or_default(),
+                new: service.new,
+                time: now.clone(),
+            }));
+        }
+        if let Some(message) = &service.error {
+            let _ = handle.events.send(Event::Error {
+                service: Some(service.service.clone()),
+                record: Some(service.record.clone()),
+                message: message.clone(),
+                time: now.clone(),
+            });
+        }
+        let last_change = runner
+            .state()
+            .last_changed
+            .get(&service.service)
+            .map(|changed| timestamp(UNIX_EPOCH + Duration::from_secs(*changed)));
+        status.services.insert(
+            service.service.clone(),
+            ServiceStatus {
+                record: service.record.clone(),
+                action: service.action,
+                address: service.new,
+                published: runner
+                    .state()
+                    .last_attempt
+                    .get(&service.service)
+                    .and_then(|attempt| attempt.published),
+                error: service.error.clone(),
+                last_run: now.clone(),
+                last_change,
+                next_check: None,
+            },
+        );
+    }
+}
+
+fn timestamp(time: SystemTime) -> String {
+    humantime::format_rfc3339_seconds(time).to_string()
+}
@@ -0,0 +1,138 @@
+//! Finding records to manage on Gandi instead of listing each as a service.
+//! Every AAAA record whose name matches one of the configured patterns is
+//! kept on the current prefix, with the suffix it is published with today.
+
+use std::{collections::HashMap, net::Ipv6Addr};
+
+use serde::Deserialize;
+use tracing::{debug, warn};
+
+use crate::{
+    config::ServiceConfig,
+    gandi::{self, GandiListResponse},
+    glob_match, idna,
+    provider::{display_name, record_name},
+    suffix::Suffix,
+    DynsixError,
+};
+
+/// The `[discovery]` section of the config
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct DiscoveryConfig {
+    /// Shell patterns of the record names to manage, e.g. `*-dyn`.
+    /// Discovery is disabled if empty.
+    #[serde(default)]
+    pub names: Vec<String>,
+    /// Domains searched, all LiveDNS domains of the token if empty
+    #[serde(default)]
+    pub domains: Vec<String>,
+}
+
+impl DiscoveryConfig {
+    pub fn is_enabled(&self) -> bool {
+        !self.names.is_empty()
+    }
+}
+
+/// Services for the matching AAAA records, named `<name>.<fqdn>` or `<fqdn>`
+/// for the apex. Records
+/// already covered by `configured` services are left to them.
+pub async fn discover(
+    client: &gandi::Client,
+    config: &DiscoveryConfig,
+    configured: &HashMap<String, ServiceConfig>,
+) -> Result<HashMap<String, ServiceConfig>, DynsixError> {
+    let domains = if config.domains.is_empty() {
+        match client.list_domains().await? {
+            GandiListResponse::List(domains) => {
+                domains.into_iter().map(|domain| domain.fqdn).collect()
+            }
+            GandiListResponse::Error(e) => return Err(DynsixError::gandi("discovering", None, e)),
+        }
+    } else {
+        config.domains.clone()
+    };
+
+    let mut discovered = HashMap::new();
+    for fqdn in domains {
+        let records = match client.list_records(&fqdn).await? {
+            GandiListResponse::List(records) => records,
+            GandiListResponse::Error(e) => {
+                return Err(DynsixError::gandi("discovering", Some(&fqdn), e))
+            }
+        };
+
+        for record in records {
+            let matches = record.rrset_type == "AAAA"
+                && config
+                    .names
+                    .iter()
+                    .any(|pattern| glob_match(pattern, &record.rrset_name));
+            if !matches {
+                continue;
+            }
+            let covered = configured.values().any(|service| {
+                idna::same_domain(&service.fqdn, &fqdn)
+                    && service
+                        .record_names()
+                        .iter()
+                        .any(|name| idna::same_domain(name, &record.rrset_name))
+            });
+            if covered {
+                debug!(
+                    "{} is already a configured service",
+                    display_name(&fqdn, &record.rrset_name)
+                );
+                continue;
+            }
+
+            // The host part of today's address is kept
+            let Some(Ok(published)) = record.rrset_values.first().map(|v| v.parse::<Ipv6Addr>())
+            else {
+                warn!(
+                    "Ignoring {}, its value is not an IPv6 address",
+                    display_name(&fqdn, &record.rrset_name)
+                );
+                continue;
+            };
+            let segments = published.segments();
+            let suffix = Ipv6Addr::new(
+                0,
+                0,
+                0,
+                0,
+                segments[4],
+                segments[5],
+                segments[6],
+                segments[7],
+            );
+
+            discovered.insert(
+                record_name(&fqdn, &record.rrset_name),
+                ServiceConfig {
+                    suffix: Suffix::Address(suffix),
+                    subnet: None,
+                    delegated_length: None,
+                    name: record.rrset_name,
+                    names: Vec::new(),
+                    fqdn: fqdn.clone(),
+                    ttl: record.rrset_ttl,
+                    enabled: true,
+                    compare: Default::default(),
+                    provider: Default::default(),
+                    credentials: None,
+                    token_ref: None,
+                    schedule: None,
+                    interval: None,
+                    low_ttl: None,
+                    quiet_hours: None,
+                    ssh_host_keys: Vec::new(),
+                    srv: Vec::new(),
+                },
+            );
+        }
+    }
+
+    Ok(discovered)
+}
@@ -0,0 +1,187 @@
+//! A minimal DNS client over UDP, enough to ask a resolver like OpenDNS for
+//! the AAAA record of `myip.opendns.com`, which it answers with the address
+//! the query came from
+
+use std::{
+    net::{IpAddr, Ipv6Addr, SocketAddr},
+    time::Duration,
+};
+
+use tokio::net::UdpSocket;
+
+use crate::DynsixError;
+
+const TYPE_AAAA: u16 = 28;
+const CLASS_IN: u16 = 1;
+
+/// Flags of the header: recursion desired
+const FLAGS_RD: u16 = 0x0100;
+const FLAG_QR: u16 = 0x8000;
+const FLAG_TC: u16 = 0x0200;
+
+/// Names of the response codes, by code
+const RCODES: [&str; 6] = [
+    "NOERROR", "FORMERR", "SERVFAIL", "NXDOMAIN", "NOTIMP", "REFUSED",
+];
+
+/// A query for the AAAA record of `name`
+pub fn query(id: u16, name: &str) -> Result<Vec<u8>, String> {
+    let mut message = Vec::with_capacity(18 + name.len());
+    message.extend_from_slice(&id.to_be_bytes());
+    message.extend_from_slice(&FLAGS_RD.to_be_bytes());
+    // One question, no answers, authorities or additional records
+    message.extend_from_slice(&[0, 1, 0, 0, 0, 0, 0, 0]);
+    for label in name.trim_end_matches('.').split('.') {
+        if label.is_empty() || label.len() > 63 {
+            return Err(format!("'{name}' is not a valid name"));
+        }
+        message.push(label.len() as u8);
+        message.extend_from_slice(label.as_bytes());
+    }
+    message.push(0);
+    message.extend_from_slice(&TYPE_AAAA.to_be_bytes());
+    message.extend_from_slice(&CLASS_IN.to_be_bytes());
+    Ok(message)
+}
+
+/// The first AAAA record in the answer to the query `id`, following CNAMEs
+/// being left to the resolver
+pub fn parse_aaaa(response: &[u8], id: u16) -> Result<Ipv6Addr, String> {
+    let mut reader = Reader(response);
+    let header = reader.take(12)?;
+    let field = |index: usize| u16::from_be_bytes([header[index], header[index + 1]]);
+    if field(0) != id {
+        return Err("answer to a different query".to_string());
+    }
+    let flags = field(2);
+    if flags & FLAG_QR == 0 {
+        return Err("not an answer".to_string());
+    }
+    if flags & FLAG_TC != 0 {
+        return Err("the answer is truncated".to_string());
+    }
+    let rcode = flags & 0xf;
+    if rcode != 0 {
+        let name = RCODES.get(usize::from(rcode)).copied().unwrap_or("error");
+        return Err(format!("the server answered {name} ({rcode})"));
+    }
+
+    for _ in 0..field(4) {
+        reader.skip_name()?;
+        reader.take(4)?;
+    }
+    for _ in 0..field(6) {
+        reader.skip_name()?;
+        let fixed = reader.take(10)?;
+        let kind = u16::from_be_bytes([fixed[0], fixed[1]]);
+        let length = usize::from(u16::from_be_bytes([fixed[8], fixed[9]]));
+        let data = reader.take(length)?;
+        if kind == TYPE_AAAA {
+            let octets: [u8; 16] = data
+                .try_into()
+                .map_err(|_| "AAAA record of the wrong length".to_string())?;
+            return Ok(Ipv6Addr::from(octets));
+        }
+    }
+    Err("the answer holds no AAAA record".to_string())
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, length: usize) -> Result<&'a [u8], String> {
+        if self.0.len() < length {
+            return Err("the answer is cut short".to_string());
+        }
+        let (taken, rest) = self.0.split_at(length);
+        self.0 = rest;
+        Ok(taken)
+    }
+
+    /// Skips a name, which ends with an empty label or a pointer to another
+    fn skip_name(&mut self) -> Result<(), String> {
+        loop {
+            let length = self.take(1)?[0];
+            match length {
+                0 => return Ok(()),
+                _ if length & 0xc0 == 0xc0 => {
+                    self.take(1)?;
+                    return Ok(());
+                }
+                _ => {
+                    self.take(usize::from(length))?;
+                }
+            }
+        }
+    }
+}
+
+/// Asks `server`, a host name or address with an optional port, for the
+/// AAAA record of `name` over IPv6, from `bind` if given
+pub async fn lookup_aaaa(
+    server: &str,
+    name: &str,
+    timeout: Duration,
+    bind: Option<Ipv6Addr>,
+) -> Result<Ipv6Addr, DynsixError> {
+    let error = |message: String| DynsixError::Dns {
+        server: server.to_string(),
+        name: name.to_string(),
+        message,
+    };
+    let lookup = async {
+        let address = server_address(server).await?;
+        let socket = UdpSocket::bind((bind.unwrap_or(Ipv6Addr::UNSPECIFIED), 0)).await?;
+        socket.connect(address).await?;
+
+        let id = query_id();
+        let query = query(id, name).map_err(std::io::Error::other)?;
+        socket.send(&query).await?;
+        let mut buffer = [0; 512];
+        loop {
+            let received = socket.recv(&mut buffer).await?;
+            match parse_aaaa(&buffer[..received], id) {
+                // Late answers to earlier queries from the same port
+                Err(e) if e.starts_with("answer to a different") => continue,
+                result => return result.map_err(std::io::Error::other),
+            }
+        }
+    };
+    match tokio::time::timeout(timeout, lookup).await {
+        Ok(result) => result.map_err(|e| error(e.to_string())),
+        Err(_) => Err(error(format!(
+            "no answer within {}",
+            humantime::format_duration(timeout)
+        ))),
+    }
+}
+
+/// The first IPv6 address of `server`, port 53 unless it names one
+async fn server_address(server: &str) -> std::io::Result<SocketAddr> {
+    if let Ok(address) = server.parse::<SocketAddr>() {
+        return Ok(address);
+    }
+    if let Ok(ip) = server.parse::<IpAddr>() {
+        return Ok(SocketAddr::new(ip, 53));
+    }
+    let mut addresses: Vec<_> = match server.rsplit_once(':') {
+        Some((_, port)) if port.parse::<u16>().is_ok() => {
+            tokio::net::lookup_host(server).await?.collect()
+        }
+        _ => tokio::net::lookup_host((server, 53)).await?.collect(),
+    };
+    addresses.retain(SocketAddr::is_ipv6);
+    addresses.first().copied().ok_or_else(|| {
+        std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            "the server has no IPv6 address",
+        )
+    })
+}
+
+/// Not guessable by an off-path attacker, like the random source port
+fn query_id() -> u16 {
+    let mut id = [0; 2];
+    openssl::rand::rand_bytes(&mut id).expect("openssl random numbers");
+    u16::from_be_bytes(id)
+}
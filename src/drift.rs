@@ -0,0 +1,71 @@
+//! Noticing records that someone else changed since dynsix last wrote them,
+//! e.g. a manual edit in the Gandi web interface
+
+use std::{collections::HashMap, net::Ipv6Addr};
+
+use tracing::warn;
+
+use crate::{
+    reconcile::{record_matches, Reconciler},
+    state::State,
+    ServiceConfig,
+};
+
+/// A record that no longer holds what dynsix wrote
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Drift {
+    pub service: String,
+    /// The full name, e.g. `www.example.com`
+    pub record: String,
+    /// The address dynsix last wrote
+    pub written: Ipv6Addr,
+    /// The published values, `None` if the record was deleted
+    pub found: Option<Vec<String>>,
+}
+
+/// Fetches every record in `state` that dynsix wrote for one of `services`
+/// and returns those whose values changed since, compared the way their
+/// service compares. Records that can not be fetched are logged and skipped.
+pub async fn detect(
+    reconciler: &Reconciler,
+    state: &State,
+    services: &HashMap<String, ServiceConfig>,
+) -> Vec<Drift> {
+    let mut drifts = Vec::new();
+    for record in &state.managed {
+        let Some(written) = record.address else {
+            continue;
+        };
+        // Records of removed services are left to gc, those of disabled ones
+        // alone
+        let Some(service) = services
+            .get(&record.service)
+            .filter(|service| service.enabled && record.is_managed_by(service))
+        else {
+            continue;
+        };
+
+        let found = match reconciler
+            .fetch_record(&record.service, record.provider, &record.fqdn, &record.name)
+            .await
+        {
+            Ok(found) => found.map(|found| found.values),
+            Err(e) => {
+                warn!(record = %record.display_name(), "Failed to check for changes: {e}");
+                continue;
+            }
+        };
+        let unchanged = found
+            .as_ref()
+            .is_some_and(|values| record_matches(values, &written, service.compare));
+        if !unchanged {
+            drifts.push(Drift {
+                service: record.service.clone(),
+                record: record.display_name(),
+                written,
+                found,
+            });
+        }
+    }
+    drifts
+}
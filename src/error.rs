@@ -0,0 +1,249 @@
+use std::{net::Ipv6Addr, path::PathBuf};
+
+use thiserror::Error;
+
+use crate::{gandi::GandiError, idna};
+
+#[derive(Error, Debug)]
+pub enum DynsixError {
+    #[error("{}: {source}", path.display())]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+
+    #[error("{}: {message}", path.display())]
+    Config { path: PathBuf, message: String },
+
+    #[error("failed to decrypt {} with sops: {message}", path.display())]
+    Sops { path: PathBuf, message: String },
+
+    #[error("found {} problem(s) in the configuration", .0.len())]
+    InvalidConfig(Vec<String>),
+
+    #[error("service '{service}' can't be reconciled: {message}")]
+    InvalidService { service: String, message: String },
+
+    #[error("failed to get public IP from {server}: {source}")]
+    IpSource {
+        server: String,
+        #[source]
+        source: reqwest::Error,
+    },
+
+    #[error("failed to look up {name} at {server}: {message}")]
+    Dns {
+        server: String,
+        name: String,
+        message: String,
+    },
+
+    #[error(
+        "refusing to publish {detected}: {reason}; set prefix_check = \"warn\" if this is \
+         expected"
+    )]
+    PrefixMismatch { detected: Ipv6Addr, reason: String },
+
+    #[error("failed to get the address from a UPnP gateway: {0}")]
+    Upnp(String),
+
+    #[error("Gandi API error while {operation} record: [{code}][{object}] {message}")]
+    Gandi {
+        operation: &'static str,
+        code: u32,
+        object: String,
+        message: String,
+    },
+
+    #[error(
+        "Gandi rejected the token while {operation}: it is invalid, expired or revoked, \
+         create a personal access token at https://account.gandi.net"
+    )]
+    GandiTokenInvalid { operation: &'static str },
+
+    #[error(
+        "the Gandi token lacks the LiveDNS scope needed for {operation}: give it the \
+         permission \"Manage domain name technical configurations\""
+    )]
+    GandiTokenScope { operation: &'static str },
+
+    #[error(
+        "domain {domain} is not accessible to the Gandi token while {operation}: create it \
+         in the organization owning the domain, or add the domain if the token is \
+         restricted to some"
+    )]
+    GandiDomainForbidden {
+        operation: &'static str,
+        domain: String,
+    },
+
+    #[error("unexpected response from Gandi while {operation} (HTTP {status}): {message}: {body}")]
+    GandiParse {
+        operation: &'static str,
+        status: u16,
+        body: String,
+        message: String,
+    },
+
+    #[error("unexpected response from Gandi while {operation} record: {response}")]
+    UnexpectedResponse {
+        operation: &'static str,
+        response: String,
+    },
+
+    #[error("{provider} API error while {operation} record: {message}")]
+    Provider {
+        provider: &'static str,
+        operation: &'static str,
+        message: String,
+    },
+
+    #[error(
+        "AAAA record {record} changed from {expected} to {found} while being reconciled, \
+         another updater is at work"
+    )]
+    RaceDetected {
+        record: String,
+        expected: String,
+        found: String,
+    },
+
+    #[error(
+        "the plan is out of date, {} changed since it was made; make a new plan",
+        changed.join(", ")
+    )]
+    StalePlan { changed: Vec<String> },
+
+    #[error("provider {provider} is not configured")]
+    ProviderNotConfigured { provider: &'static str },
+
+    #[error("interface {interface}: {source}")]
+    Interface {
+        interface: String,
+        #[source]
+        source: std::io::Error,
+    },
+
+    #[error("failed to parse {what}: {message}")]
+    Parse { what: String, message: String },
+
+    #[error("service '{service}': {source}")]
+    Service {
+        service: String,
+        #[source]
+        source: Box<DynsixError>,
+    },
+
+    #[error("another run holds the lock on {}", path.display())]
+    Locked { path: PathBuf },
+
+    #[error("failed to notify {backend}: {source}")]
+    Notify {
+        backend: &'static str,
+        #[source]
+        source: reqwest::Error,
+    },
+
+    #[error("failed to fetch the token from vault at {address}: {message}")]
+    Vault { address: String, message: String },
+
+    #[error("failed to send mail through {host}: {message}")]
+    Smtp { host: String, message: String },
+
+    #[error("failed to publish to MQTT broker {host}: {message}")]
+    Mqtt { host: String, message: String },
+
+    #[error("audit log {}: {message}", path.display())]
+    Audit { path: PathBuf, message: String },
+
+    #[error("history {}: {message}", path.display())]
+    History { path: PathBuf, message: String },
+
+    #[error("failed to send metrics to {address}: {source}")]
+    Statsd {
+        address: String,
+        #[source]
+        source: std::io::Error,
+    },
+
+    #[error(transparent)]
+    Http(#[from] reqwest::Error),
+}
+
+impl DynsixError {
+    /// What kind of error this is, as a label of `dynsix_errors_total`
+    pub fn class(&self) -> &'static str {
+        match self {
+            Self::Config { .. }
+            | Self::Sops { .. }
+            | Self::InvalidConfig(_)
+            | Self::InvalidService { .. }
+            | Self::ProviderNotConfigured { .. } => "config",
+            Self::Io { .. } | Self::Audit { .. } | Self::History { .. } => "io",
+            Self::IpSource { .. }
+            | Self::Dns { .. }
+            | Self::Upnp(_)
+            | Self::Interface { .. }
+            | Self::PrefixMismatch { .. } => "address",
+            Self::GandiTokenInvalid { .. }
+            | Self::GandiTokenScope { .. }
+            | Self::GandiDomainForbidden { .. }
+            | Self::Vault { .. } => "auth",
+            Self::Gandi { .. }
+            | Self::GandiParse { .. }
+            | Self::UnexpectedResponse { .. }
+            | Self::Provider { .. }
+            | Self::Parse { .. } => "api",
+            Self::RaceDetected { .. } | Self::StalePlan { .. } => "race",
+            Self::Locked { .. } => "lock",
+            Self::Notify { .. } | Self::Smtp { .. } | Self::Mqtt { .. } | Self::Statsd { .. } => {
+                "notify"
+            }
+            Self::Http(e) if e.is_timeout() => "timeout",
+            Self::Http(_) => "network",
+            Self::Service { source, .. } => source.class(),
+        }
+    }
+
+    pub(crate) fn io(path: impl Into<PathBuf>, source: std::io::Error) -> Self {
+        Self::Io {
+            path: path.into(),
+            source,
+        }
+    }
+
+    /// Attaches the name of the service the error occurred for
+    pub fn for_service(self, service: &str) -> Self {
+        Self::Service {
+            service: service.to_string(),
+            source: Box::new(self),
+        }
+    }
+
+    /// The error Gandi answered with for a request about `domain`, if the
+    /// request was about one. Authentication and permission errors get a
+    /// variant of their own saying how to fix them.
+    pub fn gandi(operation: &'static str, domain: Option<&str>, error: GandiError) -> Self {
+        let about_scope = |text: &str| {
+            let text = text.to_lowercase();
+            text.contains("scope") || text.contains("permission")
+        };
+        match (error.code, domain) {
+            (401, _) => Self::GandiTokenInvalid { operation },
+            (403, Some(domain)) if !about_scope(&error.message) && !about_scope(&error.cause) => {
+                Self::GandiDomainForbidden {
+                    operation,
+                    domain: idna::to_unicode(domain),
+                }
+            }
+            (403, _) => Self::GandiTokenScope { operation },
+            _ => Self::Gandi {
+                operation,
+                code: error.code,
+                object: error.object,
+                message: error.message,
+            },
+        }
+    }
+}
@@ -0,0 +1,735 @@
+//! Minimal client for the parts of the Gandi LiveDNS API dynsix needs
+
+use std::{collections::HashMap, fmt::Display, net::Ipv6Addr};
+
+use reqwest::{header::HeaderValue, Method, Response};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use tracing::{debug, warn};
+
+use crate::{
+    http_log::SendLogged,
+    notify::BoxFuture,
+    provider::{self, Provider, Record},
+    retry::RetryPolicy,
+    secret::Secret,
+    DynsixError,
+};
+
+pub const BASE_URL: &str = "https://api.gandi.net/v5";
+
+#[allow(dead_code)]
+#[derive(Deserialize, Debug)]
+pub struct GandiError {
+    pub object: String,
+    pub cause: String,
+    pub message: String,
+    pub code: u32,
+}
+
+impl Display for GandiError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&format!(
+            "[{}][{}] {}",
+            self.code, self.object, self.message
+        ))
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct GandiRecordRequest {
+    pub rrset_values: Vec<String>,
+    pub rrset_ttl: u32,
+}
+
+/// Replaces all records of a domain
+#[derive(Serialize, Debug)]
+pub struct GandiZoneRequest<'a> {
+    pub items: &'a [GandiRecord],
+}
+
+/// Body of a request, serialized as the inner value
+#[derive(Serialize, Debug)]
+#[serde(untagged)]
+enum Body<'a> {
+    Record(&'a GandiRecordRequest),
+    Zone(GandiZoneRequest<'a>),
+}
+
+#[allow(dead_code)]
+#[derive(Deserialize, Debug)]
+pub struct GandiRecordResponse {
+    pub rrset_values: Vec<String>,
+    pub rrset_ttl: u32,
+}
+
+#[allow(dead_code)]
+#[derive(Deserialize, Debug)]
+pub struct GandiMessage {
+    pub message: String,
+}
+
+/// What Gandi answered, told apart by the HTTP status
+#[derive(Debug)]
+pub enum GandiResponse {
+    Error(GandiError),
+    GandiRecordResponse(GandiRecordResponse),
+    Message(GandiMessage),
+}
+
+#[allow(dead_code)]
+#[derive(Deserialize, Debug)]
+pub struct GandiDomain {
+    pub fqdn: String,
+}
+
+/// A record set as listed for a whole domain
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct GandiRecord {
+    pub rrset_name: String,
+    pub rrset_type: String,
+    pub rrset_ttl: u32,
+    pub rrset_values: Vec<String>,
+}
+
+#[allow(dead_code)]
+#[derive(Deserialize, Debug)]
+pub struct GandiOrganization {
+    pub id: String,
+    pub name: String,
+    #[serde(rename = "type")]
+    pub kind: String,
+}
+
+/// What Gandi answered to a listing, told apart by the HTTP status
+#[derive(Debug)]
+pub enum GandiListResponse<T> {
+    Error(GandiError),
+    List(Vec<T>),
+}
+
+/// Authenticated access to the Gandi API
+#[derive(Debug, Clone)]
+pub struct Client {
+    http: reqwest::Client,
+    /// Used again for requests that could not reach the API with `http`
+    ipv4: Option<reqwest::Client>,
+    retry: RetryPolicy,
+    /// Built once from the token, which is not kept
+    authorization: HeaderValue,
+    base_url: String,
+}
+
+impl Client {
+    pub fn new(http: reqwest::Client, token: impl Into<Secret>) -> Self {
+        Self::with_base_url(http, token, BASE_URL)
+    }
+
+    /// Talks to another API endpoint than [`BASE_URL`], e.g. a mock server in tests
+    pub fn with_base_url(
+        http: reqwest::Client,
+        token: impl Into<Secret>,
+        base_url: impl Into<String>,
+    ) -> Self {
+        Self {
+            http,
+            ipv4: None,
+            retry: RetryPolicy::NEVER,
+            authorization: token.into().header("ApiKey"),
+            base_url: base_url.into().trim_end_matches('/').to_string(),
+        }
+    }
+
+    /// Retries requests with `ipv4`, a client limited to IPv4, when the API
+    /// can't be reached otherwise, e.g. as the IPv6 route is broken right
+    /// after a prefix change
+    pub fn with_ipv4_fallback(mut self, ipv4: reqwest::Client) -> Self {
+        self.ipv4 = Some(ipv4);
+        self
+    }
+
+    /// Retries failed requests as set by `retry`
+    pub fn with_retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// A client for another token, sharing the connections of this one
+    pub fn with_token(&self, token: impl Into<Secret>) -> Self {
+        Self {
+            http: self.http.clone(),
+            ipv4: self.ipv4.clone(),
+            retry: self.retry,
+            authorization: token.into().header("ApiKey"),
+            base_url: self.base_url.clone(),
+        }
+    }
+
+    async fn send(
+        &self,
+        method: Method,
+        path: &str,
+        body: Option<Body<'_>>,
+    ) -> Result<Response, reqwest::Error> {
+        let request = |http: &reqwest::Client| {
+            let request = http
+                .request(method.clone(), format!("{}{path}", self.base_url))
+                .header("Accept", "application/json")
+                .header("Authorization", self.authorization.clone());
+            match &body {
+                Some(body) => request.json(body),
+                None => request,
+            }
+        };
+
+        let result = request(&self.http).send_logged().await;
+        let Some(ipv4) = &self.ipv4 else {
+            return result;
+        };
+        match result {
+            // Requests that timed out may have been processed, only creating
+            // a record twice would fail
+            Err(e) if e.is_connect() || (e.is_timeout() && method != Method::POST) => {
+                warn!("Gandi API unreachable, retrying over IPv4: {e}");
+                request(ipv4).send_logged().await
+            }
+            result => result,
+        }
+    }
+
+    fn record_path(fqdn: &str, name: &str) -> String {
+        Self::rrset_path(fqdn, name, "AAAA")
+    }
+
+    fn rrset_path(fqdn: &str, name: &str, kind: &str) -> String {
+        format!(
+            "/livedns/domains/{}/records/{}/{kind}",
+            fqdn,
+            provider::path_segment(name)
+        )
+    }
+
+    /// Fetches the AAAA record `name` in the domain `fqdn`
+    pub async fn get_record(&self, fqdn: &str, name: &str) -> Result<GandiResponse, DynsixError> {
+        self.get_rrset(fqdn, name, "AAAA").await
+    }
+
+    /// Fetches the records of type `kind` named `name` in the domain `fqdn`
+    pub async fn get_rrset(
+        &self,
+        fqdn: &str,
+        name: &str,
+        kind: &str,
+    ) -> Result<GandiResponse, DynsixError> {
+        let response = self
+            .send(Method::GET, &Self::rrset_path(fqdn, name, kind), None)
+            .await?;
+        Ok(match parse("fetching", response).await? {
+            Ok(record) => GandiResponse::GandiRecordResponse(record),
+            Err(e) => GandiResponse::Error(e),
+        })
+    }
+
+    /// Creates the records of type `kind` or replaces those there are
+    pub async fn put_rrset(
+        &self,
+        fqdn: &str,
+        name: &str,
+        kind: &str,
+        ttl: u32,
+        values: &[String],
+    ) -> Result<GandiResponse, DynsixError> {
+        let body = GandiRecordRequest {
+            rrset_values: values.to_vec(),
+            rrset_ttl: ttl,
+        };
+        let response = self
+            .send(
+                Method::PUT,
+                &Self::rrset_path(fqdn, name, kind),
+                Some(Body::Record(&body)),
+            )
+            .await?;
+        Ok(message(parse("setting", response).await?))
+    }
+
+    pub async fn create_record(
+        &self,
+        fqdn: &str,
+        name: &str,
+        ttl: u32,
+        ip: &Ipv6Addr,
+    ) -> Result<GandiResponse, DynsixError> {
+        let body = GandiRecordRequest {
+            rrset_values: vec![ip.to_string()],
+            rrset_ttl: ttl,
+        };
+        let response = self
+            .send(
+                Method::POST,
+                &Self::record_path(fqdn, name),
+                Some(Body::Record(&body)),
+            )
+            .await?;
+        Ok(message(parse("setting", response).await?))
+    }
+
+    /// Replaces the values of an existing AAAA record
+    pub async fn update_record(
+        &self,
+        fqdn: &str,
+        name: &str,
+        ttl: u32,
+        ip: &Ipv6Addr,
+    ) -> Result<GandiResponse, DynsixError> {
+        let body = GandiRecordRequest {
+            rrset_values: vec![ip.to_string()],
+            rrset_ttl: ttl,
+        };
+        let response = self
+            .send(
+                Method::PUT,
+                &Self::record_path(fqdn, name),
+                Some(Body::Record(&body)),
+            )
+            .await?;
+        Ok(message(parse("updating", response).await?))
+    }
+
+    /// Deletes the AAAA record. Gandi answers with an empty body on success.
+    pub async fn delete_record(
+        &self,
+        fqdn: &str,
+        name: &str,
+    ) -> Result<Option<GandiError>, DynsixError> {
+        let response = self
+            .send(Method::DELETE, &Self::record_path(fqdn, name), None)
+            .await?;
+
+        if response.status().is_success() {
+            Ok(None)
+        } else {
+            parse::<GandiMessage>("deleting", response)
+                .await
+                .map(Result::err)
+        }
+    }
+
+    /// Domains managed by LiveDNS that the token can access
+    pub async fn list_domains(&self) -> Result<GandiListResponse<GandiDomain>, DynsixError> {
+        let response = self.send(Method::GET, "/livedns/domains", None).await?;
+        Ok(list(parse("listing domains", response).await?))
+    }
+
+    /// All records of the domain `fqdn`
+    pub async fn list_records(
+        &self,
+        fqdn: &str,
+    ) -> Result<GandiListResponse<GandiRecord>, DynsixError> {
+        let response = self
+            .send(
+                Method::GET,
+                &format!("/livedns/domains/{fqdn}/records"),
+                None,
+            )
+            .await?;
+        Ok(list(parse("listing records", response).await?))
+    }
+
+    /// Replaces all records of the domain `fqdn` with `records`
+    pub async fn replace_records(
+        &self,
+        fqdn: &str,
+        records: &[GandiRecord],
+    ) -> Result<GandiResponse, DynsixError> {
+        let response = self
+            .send(
+                Method::PUT,
+                &format!("/livedns/domains/{fqdn}/records"),
+                Some(Body::Zone(GandiZoneRequest { items: records })),
+            )
+            .await?;
+        Ok(message(parse("replacing records", response).await?))
+    }
+
+    pub async fn list_organizations(
+        &self,
+    ) -> Result<GandiListResponse<GandiOrganization>, DynsixError> {
+        let response = self
+            .send(Method::GET, "/organization/organizations", None)
+            .await?;
+        Ok(list(parse("listing organizations", response).await?))
+    }
+}
+
+/// Reads the body of `response` as `T` if the status is a success and as a
+/// [`GandiError`] otherwise, rather than guessing from the shape of the body.
+/// Bodies that are neither are logged along with the status.
+async fn parse<T: DeserializeOwned>(
+    operation: &'static str,
+    response: Response,
+) -> Result<Result<T, GandiError>, DynsixError> {
+    let status = response.status();
+    let body = response.bytes().await?;
+    let parsed = if status.is_success() {
+        serde_json::from_slice(&body).map(Ok)
+    } else {
+        serde_json::from_slice(&body).map(Err)
+    };
+    parsed.map_err(|e| {
+        let body = String::from_utf8_lossy(&body);
+        debug!(%status, %body, "Unexpected response from Gandi while {operation}");
+        DynsixError::GandiParse {
+            operation,
+            status: status.as_u16(),
+            body: truncate(&body, MAX_BODY_LENGTH),
+            message: e.to_string(),
+        }
+    })
+}
+
+fn message(parsed: Result<GandiMessage, GandiError>) -> GandiResponse {
+    match parsed {
+        Ok(message) => GandiResponse::Message(message),
+        Err(e) => GandiResponse::Error(e),
+    }
+}
+
+fn list<T>(parsed: Result<Vec<T>, GandiError>) -> GandiListResponse<T> {
+    match parsed {
+        Ok(items) => GandiListResponse::List(items),
+        Err(e) => GandiListResponse::Error(e),
+    }
+}
+
+/// Longest body kept in [`DynsixError::GandiParse`], error pages can be long
+const MAX_BODY_LENGTH: usize = 500;
+
+fn truncate(text: &str, length: usize) -> String {
+    match text.char_indices().nth(length) {
+        Some((end, _)) => format!("{}…", &text[..end]),
+        None => text.to_string(),
+    }
+}
+
+impl Client {
+    async fn fetch(&self, fqdn: &str, name: &str) -> Result<Option<Record>, DynsixError> {
+        self.fetch_rrset(fqdn, name, "AAAA").await
+    }
+
+    async fn fetch_rrset(
+        &self,
+        fqdn: &str,
+        name: &str,
+        kind: &str,
+    ) -> Result<Option<Record>, DynsixError> {
+        match self.get_rrset(fqdn, name, kind).await? {
+            GandiResponse::Error(GandiError { code: 404, .. }) => Ok(None),
+            GandiResponse::Error(e) => Err(DynsixError::gandi("fetching", Some(fqdn), e)),
+            GandiResponse::GandiRecordResponse(record) => Ok(Some(Record {
+                values: record.rrset_values,
+                ttl: Some(record.rrset_ttl),
+            })),
+            other => Err(DynsixError::UnexpectedResponse {
+                operation: "fetching",
+                response: format!("{other:?}"),
+            }),
+        }
+    }
+
+    async fn create(
+        &self,
+        fqdn: &str,
+        name: &str,
+        ttl: u32,
+        ip: Ipv6Addr,
+    ) -> Result<(), DynsixError> {
+        match Client::create_record(self, fqdn, name, ttl, &ip).await? {
+            GandiResponse::Error(e) => Err(DynsixError::gandi("setting", Some(fqdn), e)),
+            GandiResponse::Message(message) => {
+                debug!("Gandi answered: {}", message.message);
+                Ok(())
+            }
+            other => Err(DynsixError::UnexpectedResponse {
+                operation: "setting",
+                response: format!("{other:?}"),
+            }),
+        }
+    }
+
+    async fn update(
+        &self,
+        fqdn: &str,
+        name: &str,
+        ttl: u32,
+        ip: Ipv6Addr,
+    ) -> Result<(), DynsixError> {
+        match Client::update_record(self, fqdn, name, ttl, &ip).await? {
+            GandiResponse::Error(e) => Err(DynsixError::gandi("updating", Some(fqdn), e)),
+            GandiResponse::Message(message) => {
+                debug!("Gandi answered: {}", message.message);
+                Ok(())
+            }
+            other => Err(DynsixError::UnexpectedResponse {
+                operation: "updating",
+                response: format!("{other:?}"),
+            }),
+        }
+    }
+
+    async fn put(
+        &self,
+        fqdn: &str,
+        name: &str,
+        kind: &str,
+        ttl: u32,
+        values: &[String],
+    ) -> Result<(), DynsixError> {
+        match self.put_rrset(fqdn, name, kind, ttl, values).await? {
+            GandiResponse::Error(e) => Err(DynsixError::gandi("setting", Some(fqdn), e)),
+            GandiResponse::Message(message) => {
+                debug!("Gandi answered: {}", message.message);
+                Ok(())
+            }
+            other => Err(DynsixError::UnexpectedResponse {
+                operation: "setting",
+                response: format!("{other:?}"),
+            }),
+        }
+    }
+
+    async fn delete(&self, fqdn: &str, name: &str) -> Result<(), DynsixError> {
+        match Client::delete_record(self, fqdn, name).await? {
+            None => Ok(()),
+            Some(e) => Err(DynsixError::gandi("deleting", Some(fqdn), e)),
+        }
+    }
+
+    /// The AAAA records of the domain by name, in one request
+    async fn aaaa_records(&self, fqdn: &str) -> Result<HashMap<String, Record>, DynsixError> {
+        match self.list_records(fqdn).await? {
+            GandiListResponse::List(records) => Ok(records
+                .into_iter()
+                .filter(|record| record.rrset_type == "AAAA")
+                .map(|record| {
+                    let values = record.rrset_values;
+                    let ttl = Some(record.rrset_ttl);
+                    (record.rrset_name, Record { values, ttl })
+                })
+                .collect()),
+            GandiListResponse::Error(e) => {
+                Err(DynsixError::gandi("listing records", Some(fqdn), e))
+            }
+        }
+    }
+
+    /// Reads the record, retrying as set by the retry policy
+    async fn fetch_retrying(&self, fqdn: &str, name: &str) -> Result<Option<Record>, DynsixError> {
+        let what = format!("Fetching {}", provider::record_name(fqdn, name));
+        self.retry
+            .run(
+                &what,
+                || self.fetch(fqdn, name),
+                |e| failure(e) != Failure::Permanent,
+            )
+            .await
+    }
+
+    /// Runs the change `attempt`, retrying as set by the retry policy. After
+    /// a failure that leaves open whether Gandi made the change, the record
+    /// is read first and the change only tried again unless it is `applied`.
+    async fn write<'a, Fut>(
+        &'a self,
+        fqdn: &'a str,
+        name: &'a str,
+        attempt: impl Fn() -> Fut,
+        applied: impl Fn(Option<&Record>) -> bool,
+    ) -> Result<(), DynsixError>
+    where
+        Fut: std::future::Future<Output = Result<(), DynsixError>>,
+    {
+        let what = format!("Changing {}", provider::record_name(fqdn, name));
+        let mut retry = 0;
+        loop {
+            let error = match attempt().await {
+                Ok(()) => return Ok(()),
+                Err(error) => error,
+            };
+            let failure = failure(&error);
+            retry += 1;
+            if retry >= self.retry.attempts || failure == Failure::Permanent {
+                return Err(error);
+            }
+            self.retry.wait(&what, retry, &error).await;
+
+            if failure == Failure::Ambiguous {
+                match self.fetch_retrying(fqdn, name).await {
+                    Ok(record) if applied(record.as_ref()) => {
+                        debug!("{what} went through despite the error");
+                        return Ok(());
+                    }
+                    Ok(_) => {}
+                    // Without knowing the current state, another try could
+                    // undo a change made in the meantime
+                    Err(_) => return Err(error),
+                }
+            }
+        }
+    }
+}
+
+/// How a request failed, as far as retrying it is concerned
+#[derive(Debug, PartialEq, Eq)]
+enum Failure {
+    /// Trying again won't help
+    Permanent,
+    /// The request was refused before doing anything
+    Unprocessed,
+    /// Gandi may or may not have processed the request
+    Ambiguous,
+}
+
+fn failure(error: &DynsixError) -> Failure {
+    match error {
+        DynsixError::Http(e) if e.is_connect() => Failure::Unprocessed,
+        DynsixError::Http(e)
+            if e.is_timeout() || e.is_request() || e.is_body() || e.is_decode() =>
+        {
+            Failure::Ambiguous
+        }
+        DynsixError::Gandi { code: 429, .. } => Failure::Unprocessed,
+        DynsixError::GandiParse { status: 429, .. } => Failure::Unprocessed,
+        // Neither success nor error, e.g. the error page of a proxy
+        DynsixError::GandiParse { .. } => Failure::Ambiguous,
+        DynsixError::Gandi { code, .. } if *code >= 500 => Failure::Ambiguous,
+        _ => Failure::Permanent,
+    }
+}
+
+/// Whether `record` holds exactly `ip` with `ttl`
+fn holds(record: Option<&Record>, ttl: u32, ip: Ipv6Addr) -> bool {
+    record.is_some_and(|record| {
+        record.ttl == Some(ttl)
+            && record.values.len() == 1
+            && record.values[0].parse::<Ipv6Addr>().ok() == Some(ip)
+    })
+}
+
+impl Provider for Client {
+    fn name(&self) -> &'static str {
+        "gandi"
+    }
+
+    fn fetch_record<'a>(
+        &'a self,
+        fqdn: &'a str,
+        name: &'a str,
+    ) -> BoxFuture<'a, Result<Option<Record>, DynsixError>> {
+        Box::pin(self.fetch_retrying(fqdn, name))
+    }
+
+    fn create_record<'a>(
+        &'a self,
+        fqdn: &'a str,
+        name: &'a str,
+        ttl: u32,
+        ip: Ipv6Addr,
+    ) -> BoxFuture<'a, Result<(), DynsixError>> {
+        Box::pin(self.write(
+            fqdn,
+            name,
+            move || self.create(fqdn, name, ttl, ip),
+            move |record| holds(record, ttl, ip),
+        ))
+    }
+
+    fn update_record<'a>(
+        &'a self,
+        fqdn: &'a str,
+        name: &'a str,
+        ttl: u32,
+        ip: Ipv6Addr,
+    ) -> BoxFuture<'a, Result<(), DynsixError>> {
+        Box::pin(self.write(
+            fqdn,
+            name,
+            move || self.update(fqdn, name, ttl, ip),
+            move |record| holds(record, ttl, ip),
+        ))
+    }
+
+    fn delete_record<'a>(
+        &'a self,
+        fqdn: &'a str,
+        name: &'a str,
+    ) -> BoxFuture<'a, Result<(), DynsixError>> {
+        Box::pin(self.write(
+            fqdn,
+            name,
+            move || self.delete(fqdn, name),
+            |record| record.is_none(),
+        ))
+    }
+
+    fn fetch_zone<'a>(
+        &'a self,
+        fqdn: &'a str,
+    ) -> BoxFuture<'a, Result<Option<HashMap<String, Record>>, DynsixError>> {
+        let what = format!("Listing the records of {fqdn}");
+        Box::pin(async move {
+            self.retry
+                .run(
+                    &what,
+                    || self.aaaa_records(fqdn),
+                    |e| failure(e) != Failure::Permanent,
+                )
+                .await
+                .map(Some)
+        })
+    }
+
+    fn fetch_records<'a>(
+        &'a self,
+        fqdn: &'a str,
+        name: &'a str,
+        kind: &'a str,
+    ) -> BoxFuture<'a, Result<Option<Record>, DynsixError>> {
+        let what = format!(
+            "Fetching the {kind} records of {}",
+            provider::record_name(fqdn, name)
+        );
+        Box::pin(async move {
+            self.retry
+                .run(
+                    &what,
+                    || self.fetch_rrset(fqdn, name, kind),
+                    |e| failure(e) != Failure::Permanent,
+                )
+                .await
+        })
+    }
+
+    /// Replacing the records is idempotent, so any failure but a permanent
+    /// one is retried
+    fn set_records<'a>(
+        &'a self,
+        fqdn: &'a str,
+        name: &'a str,
+        kind: &'a str,
+        ttl: u32,
+        values: &'a [String],
+    ) -> BoxFuture<'a, Result<(), DynsixError>> {
+        let what = format!(
+            "Setting the {kind} records of {}",
+            provider::record_name(fqdn, name)
+        );
+        Box::pin(async move {
+            self.retry
+                .run(
+                    &what,
+                    || self.put(fqdn, name, kind, ttl, values),
+                    |e| failure(e) != Failure::Permanent,
+                )
+                .await
+        })
+    }
+}
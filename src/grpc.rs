@@ -0,0 +1,299 @@
+//! gRPC API of the daemon, the service `dynsix.v1.Dynsix` of
+//! `proto/dynsix.proto`:
+//!
+//! - `Status`: the last result and last change of every service
+//! - `Reconcile`: runs immediately and answers with the report of the run
+//! - `WatchEvents`: streams every record created or updated from then on
+//!
+//! Served over HTTP/2 without TLS. Every call needs the metadata
+//! `authorization: Bearer <grpc_token>`.
+
+use std::{convert::Infallible, future::Future, net::SocketAddr};
+
+use dynsix::{protobuf::Encoder, report::Action};
+use hyper::{
+    body::Bytes,
+    header::{HeaderValue, AUTHORIZATION, CONTENT_TYPE},
+    service::{make_service_fn, service_fn},
+    Body, HeaderMap, Method, Request, Response, Server,
+};
+use percent_encoding::{utf8_percent_encode, AsciiSet, CONTROLS};
+use tokio::sync::{broadcast, oneshot};
+use tracing::warn;
+
+use crate::{
+    api::constant_time_eq,
+    daemon::{self, Event, Handle, RecordEvent, Trigger, TriggerError},
+};
+
+/// Status codes of gRPC
+const OK: u8 = 0;
+const INVALID_ARGUMENT: u8 = 3;
+const UNIMPLEMENTED: u8 = 12;
+const INTERNAL: u8 = 13;
+const UNAVAILABLE: u8 = 14;
+const UNAUTHENTICATED: u8 = 16;
+
+/// Characters percent-encoded in `grpc-message`
+const MESSAGE: &AsciiSet = &CONTROLS.add(b'%');
+
+/// Binds `listen` right away, so a port in use fails the start of the daemon.
+/// The returned future serves calls until it is dropped.
+pub fn serve(listen: SocketAddr, handle: Handle) -> Result<impl Future<Output = ()>, hyper::Error> {
+    let server = Server::try_bind(&listen)?
+        .http2_only(true)
+        .serve(make_service_fn(move |_| {
+            let handle = handle.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |request| {
+                    let handle = handle.clone();
+                    async move { Ok::<_, Infallible>(route(request, handle).await) }
+                }))
+            }
+        }));
+
+    Ok(async move {
+        if let Err(e) = server.await {
+            warn!("gRPC API stopped: {e}");
+        }
+    })
+}
+
+async fn route(request: Request<Body>, handle: Handle) -> Response<Body> {
+    let Some(token) = handle.grpc_token.lock().unwrap().clone() else {
+        return status(UNAVAILABLE, "daemon.grpc_token is not configured");
+    };
+    let authorized = request
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .is_some_and(|given| constant_time_eq(given.as_bytes(), token.expose().as_bytes()));
+    if !authorized {
+        return status(UNAUTHENTICATED, "missing or wrong bearer token");
+    }
+    if request.method() != Method::POST {
+        return status(UNIMPLEMENTED, "calls are POST requests");
+    }
+
+    let path = request.uri().path().to_string();
+    let message = match hyper::body::to_bytes(request.into_body()).await {
+        Ok(body) => match unframe(&body) {
+            Ok(message) => message,
+            Err(e) => return status(INVALID_ARGUMENT, &e),
+        },
+        Err(e) => return status(INVALID_ARGUMENT, &e.to_string()),
+    };
+    match path.as_str() {
+        "/dynsix.v1.Dynsix/Status" => reply(status_reply(&handle)),
+        "/dynsix.v1.Dynsix/Reconcile" => match dynsix::protobuf::decode(&message) {
+            Ok(fields) => {
+                let services = fields
+                    .iter()
+                    .filter(|(field, _)| *field == 1)
+                    .filter_map(|(_, value)| value.as_str())
+                    .map(str::to_string)
+                    .collect();
+                reconcile(handle, services).await
+            }
+            Err(e) => status(INVALID_ARGUMENT, &e),
+        },
+        "/dynsix.v1.Dynsix/WatchEvents" => watch(handle.events.subscribe()),
+        _ => status(UNIMPLEMENTED, &format!("unknown method {path}")),
+    }
+}
+
+/// The message of a request, which is a single uncompressed one
+fn unframe(body: &[u8]) -> Result<Vec<u8>, String> {
+    let Some((header, message)) = body.split_first_chunk::<5>() else {
+        return Err("request without a message".to_string());
+    };
+    if header[0] != 0 {
+        return Err("compressed messages are not supported".to_string());
+    }
+    let length = u32::from_be_bytes([header[1], header[2], header[3], header[4]]) as usize;
+    if message.len() != length {
+        return Err("expected a single message".to_string());
+    }
+    Ok(message.to_vec())
+}
+
+/// A message with its length prefix
+fn frame(message: Encoder) -> Bytes {
+    let message = message.finish();
+    let mut framed = Vec::with_capacity(message.len() + 5);
+    framed.push(0);
+    framed.extend_from_slice(&(message.len() as u32).to_be_bytes());
+    framed.extend_from_slice(&message);
+    framed.into()
+}
+
+fn trailers(code: u8, message: &str) -> HeaderMap {
+    let mut trailers = HeaderMap::new();
+    trailers.insert("grpc-status", HeaderValue::from(u16::from(code)));
+    if !message.is_empty() {
+        let message = utf8_percent_encode(message, MESSAGE).to_string();
+        if let Ok(message) = HeaderValue::from_str(&message) {
+            trailers.insert("grpc-message", message);
+        }
+    }
+    trailers
+}
+
+/// A failed call, with the status in the headers and no messages
+fn status(code: u8, message: &str) -> Response<Body> {
+    let mut response = Response::builder()
+        .header(CONTENT_TYPE, "application/grpc")
+        .body(Body::empty())
+        .unwrap();
+    response.headers_mut().extend(trailers(code, message));
+    response
+}
+
+/// A successful unary call
+fn reply(message: Encoder) -> Response<Body> {
+    let (mut sender, body) = Body::channel();
+    tokio::spawn(async move {
+        if sender.send_data(frame(message)).await.is_ok() {
+            let _ = sender.send_trailers(trailers(OK, "")).await;
+        }
+    });
+    Response::builder()
+        .header(CONTENT_TYPE, "application/grpc")
+        .body(body)
+        .unwrap()
+}
+
+/// Streams the changed records until the client goes away or the daemon stops
+fn watch(mut events: broadcast::Receiver<Event>) -> Response<Body> {
+    let (mut sender, body) = Body::channel();
+    tokio::spawn(async move {
+        loop {
+            match events.recv().await {
+                Ok(Event::RecordUpdated(event)) => {
+                    if sender.send_data(frame(record_event(&event))).await.is_err() {
+                        return;
+                    }
+                }
+                Ok(_) => {}
+                Err(broadcast::error::RecvError::Lagged(missed)) => {
+                    warn!("A gRPC client watching events missed {missed} of them");
+                }
+                Err(broadcast::error::RecvError::Closed) => break,
+            }
+        }
+        let _ = sender.send_trailers(trailers(OK, "")).await;
+    });
+    Response::builder()
+        .header(CONTENT_TYPE, "application/grpc")
+        .body(body)
+        .unwrap()
+}
+
+async fn reconcile(handle: Handle, services: Vec<String>) -> Response<Body> {
+    let (reply_to, result) = oneshot::channel();
+    if handle
+        .requests
+        .send(daemon::Request::Reconcile(Trigger {
+            services,
+            prefix: None,
+            reply: reply_to,
+        }))
+        .await
+        .is_err()
+    {
+        return status(UNAVAILABLE, "daemon is shutting down");
+    }
+
+    match result.await {
+        Ok(Ok(report)) => {
+            let mut message = Encoder::new();
+            message.string(1, &report.public_ip.to_string());
+            for service in &report.services {
+                let mut result = Encoder::new();
+                result
+                    .string(1, &service.service)
+                    .string(2, &service.record)
+                    .string(3, action(service.action));
+                for old in service.old.iter().flatten() {
+                    result.repeated_string(4, old);
+                }
+                result
+                    .string(5, &service.new.to_string())
+                    .string(6, service.error.as_deref().unwrap_or_default());
+                message.message(2, &result);
+            }
+            reply(message)
+        }
+        Ok(Err(TriggerError::NoMatch(pattern))) => status(
+            INVALID_ARGUMENT,
+            &format!("no configured service matches '{pattern}'"),
+        ),
+        Ok(Err(TriggerError::Failed(message))) => status(INTERNAL, &message),
+        Err(_) => status(UNAVAILABLE, "daemon is shutting down"),
+    }
+}
+
+fn status_reply(handle: &Handle) -> Encoder {
+    let status = handle.status.lock().unwrap().clone();
+    let mut message = Encoder::new();
+    if let Some(run) = &status.last_run {
+        let mut last_run = Encoder::new();
+        last_run
+            .string(1, &run.finished_at)
+            .string(
+                2,
+                &run.public_ip.map(|ip| ip.to_string()).unwrap_or_default(),
+            )
+            .string(3, run.error.as_deref().unwrap_or_default());
+        message.message(1, &last_run);
+    }
+    for (name, service) in &status.services {
+        let mut entry = Encoder::new();
+        entry
+            .string(1, name)
+            .string(2, &service.record)
+            .string(3, action(service.action))
+            .string(4, &service.address.to_string())
+            .string(
+                5,
+                &service
+                    .published
+                    .map(|ip| ip.to_string())
+                    .unwrap_or_default(),
+            )
+            .string(6, service.error.as_deref().unwrap_or_default())
+            .string(7, &service.last_run)
+            .string(8, service.last_change.as_deref().unwrap_or_default())
+            .string(9, service.next_check.as_deref().unwrap_or_default());
+        message.message(2, &entry);
+    }
+    message
+}
+
+fn record_event(event: &RecordEvent) -> Encoder {
+    let mut message = Encoder::new();
+    message
+        .string(1, &event.service)
+        .string(2, &event.record)
+        .string(3, action(event.action));
+    for old in &event.old {
+        message.repeated_string(4, old);
+    }
+    message
+        .string(5, &event.new.to_string())
+        .string(6, &event.time);
+    message
+}
+
+/// The name of an action as in the JSON of the HTTP API
+fn action(action: Action) -> &'static str {
+    match action {
+        Action::Unchanged => "unchanged",
+        Action::Created => "created",
+        Action::Updated => "updated",
+        Action::Deferred => "deferred",
+        Action::Skipped => "skipped",
+        Action::Failed => "failed",
+    }
+}
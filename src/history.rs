@@ -0,0 +1,168 @@
+//! Every change to a record with its outcome, kept in the SQLite database
+//! configured as `history_file` and shown by `dynsix history`
+
+use std::{
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::Serialize;
+
+use crate::{
+    report::{Action, RunReport},
+    sqlite::{Connection, Value},
+    DynsixError,
+};
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS changes (
+        id INTEGER PRIMARY KEY,
+        time INTEGER NOT NULL,
+        service TEXT NOT NULL,
+        record TEXT NOT NULL,
+        old TEXT,
+        new TEXT NOT NULL,
+        outcome TEXT NOT NULL,
+        error TEXT,
+        latency_ms INTEGER NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS changes_by_service ON changes (service, time);
+";
+
+/// One attempt to create, update or delete a record
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct Change {
+    /// Unix time of the run
+    pub time: u64,
+    pub service: String,
+    pub record: String,
+    /// Values before the change, `None` if there was no record
+    pub old: Option<String>,
+    pub new: String,
+    /// `created`, `updated`, `deleted` or `failed`
+    pub outcome: String,
+    pub error: Option<String>,
+    /// How long the requests to the provider took
+    pub latency_ms: u64,
+}
+
+pub struct History {
+    connection: Connection,
+    path: PathBuf,
+}
+
+impl History {
+    /// Opens the database, creating it if needed
+    pub fn open(path: &Path) -> Result<Self, DynsixError> {
+        if let Some(parent) = path
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+        {
+            std::fs::create_dir_all(parent).map_err(|e| DynsixError::io(parent, e))?;
+        }
+        let error = |message| DynsixError::History {
+            path: path.to_path_buf(),
+            message,
+        };
+        let connection = Connection::open(path).map_err(error)?;
+        connection.execute_batch(SCHEMA).map_err(error)?;
+        Ok(Self {
+            connection,
+            path: path.to_path_buf(),
+        })
+    }
+
+    /// Adds the records a run created, updated, deleted or failed to change
+    pub fn record(&self, report: &RunReport) -> Result<(), DynsixError> {
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let mut changes = Vec::new();
+        for service in &report.services {
+            let outcome = match service.action {
+                Action::Created => "created",
+                Action::Updated => "updated",
+                Action::Failed => "failed",
+                Action::Unchanged | Action::Deferred | Action::Skipped => continue,
+            };
+            changes.push(Change {
+                time,
+                service: service.service.clone(),
+                record: service.record.clone(),
+                old: service.old.as_ref().map(|old| old.join(",")),
+                new: service.new.to_string(),
+                outcome: outcome.to_string(),
+                error: service.error.clone(),
+                latency_ms: service.duration_ms.try_into().unwrap_or(u64::MAX),
+            });
+        }
+        for record in &report.deleted {
+            changes.push(Change {
+                time,
+                service: record.service.clone(),
+                record: record.display_name(),
+                old: record.address.map(|address| address.to_string()),
+                new: String::new(),
+                outcome: "deleted".to_string(),
+                error: None,
+                latency_ms: 0,
+            });
+        }
+
+        self.connection
+            .execute_batch("BEGIN")
+            .map_err(|e| self.error(e))?;
+        for change in &changes {
+            let result = self.connection.execute(
+                "INSERT INTO changes (time, service, record, old, new, outcome, error, latency_ms)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+                &[
+                    Value::Integer(change.time as i64),
+                    Value::Text(&change.service),
+                    Value::Text(&change.record),
+                    change.old.as_deref().map_or(Value::Null, Value::Text),
+                    Value::Text(&change.new),
+                    Value::Text(&change.outcome),
+                    change.error.as_deref().map_or(Value::Null, Value::Text),
+                    Value::Integer(change.latency_ms as i64),
+                ],
+            );
+            if let Err(e) = result {
+                let _ = self.connection.execute_batch("ROLLBACK");
+                return Err(self.error(e));
+            }
+        }
+        self.connection
+            .execute_batch("COMMIT")
+            .map_err(|e| self.error(e))
+    }
+
+    /// All changes, oldest first
+    pub fn changes(&self) -> Result<Vec<Change>, DynsixError> {
+        self.connection
+            .query(
+                "SELECT time, service, record, old, new, outcome, error, latency_ms
+                 FROM changes ORDER BY time, id",
+                &[],
+                |row| Change {
+                    time: row.integer(0) as u64,
+                    service: row.text(1).unwrap_or_default(),
+                    record: row.text(2).unwrap_or_default(),
+                    old: row.text(3),
+                    new: row.text(4).unwrap_or_default(),
+                    outcome: row.text(5).unwrap_or_default(),
+                    error: row.text(6),
+                    latency_ms: row.integer(7) as u64,
+                },
+            )
+            .map_err(|e| self.error(e))
+    }
+
+    fn error(&self, message: String) -> DynsixError {
+        DynsixError::History {
+            path: self.path.clone(),
+            message,
+        }
+    }
+}
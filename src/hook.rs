@@ -0,0 +1,76 @@
+//! `dynsix hook`, called from pppd `ip-up`/`ipv6-up` scripts and dhcpcd exit
+//! hooks, which describe the event in environment variables
+
+/// Reasons of dhcpcd for which the interface may have a new IPv6 prefix
+const DHCPCD_REASONS: [&str; 7] = [
+    "BOUND6",
+    "RENEW6",
+    "REBIND6",
+    "REBOOT6",
+    "INFORM6",
+    "DELEGATED6",
+    "ROUTERADVERT",
+];
+
+/// What a hook was called for
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Event {
+    /// The interface came up or got a new prefix. `prefix` is e.g.
+    /// `2001:db8:1::/56`, `None` if the hook does not tell.
+    Up {
+        interface: String,
+        prefix: Option<String>,
+    },
+    /// Nothing that changes the IPv6 prefix, e.g. an IPv4 lease or the
+    /// interface going down
+    Ignored { interface: String, reason: String },
+}
+
+/// Reads the event from the variables of dhcpcd (`reason`, `interface`) or
+/// pppd (`IFNAME`), `var` looks up one of them
+pub fn event<F>(var: F) -> Result<Event, String>
+where
+    F: Fn(&str) -> Option<String>,
+{
+    if let (Some(reason), Some(interface)) = (var("reason"), var("interface")) {
+        if !DHCPCD_REASONS.contains(&reason.as_str()) {
+            return Ok(Event::Ignored { interface, reason });
+        }
+        let with_length = |prefix: &str, length: &str| {
+            var(prefix).map(|prefix| match var(length) {
+                Some(length) => format!("{prefix}/{length}"),
+                None => prefix,
+            })
+        };
+        let prefix = with_length(
+            "new_dhcp6_ia_pd1_prefix1",
+            "new_dhcp6_ia_pd1_prefix1_length",
+        )
+        .or_else(|| {
+            // Several prefixes delegated to this interface, separated by spaces
+            var("new_delegated_dhcp6_prefix")
+                .and_then(|prefixes| prefixes.split_whitespace().next().map(str::to_string))
+        })
+        .or_else(|| {
+            with_length(
+                "new_nd1_prefix_information1_prefix",
+                "new_nd1_prefix_information1_length",
+            )
+        });
+        return Ok(Event::Up { interface, prefix });
+    }
+
+    match var("IFNAME") {
+        // pppd only negotiates the link, the prefix comes later
+        Some(interface) => Ok(Event::Up {
+            interface,
+            prefix: None,
+        }),
+        None => Err(
+            "neither the dhcpcd variables reason and interface nor the pppd variable \
+             IFNAME are set, run dynsix hook from a dhcpcd hook or a pppd ip-up or ipv6-up \
+             script"
+                .to_string(),
+        ),
+    }
+}
@@ -0,0 +1,185 @@
+//! Logging of the HTTP traffic with the APIs for `--log-http`. Sensitive
+//! headers, query parameters, JSON fields and Telegram bot tokens are masked
+//! and bodies are cut off after [`MAX_BODY`] bytes.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use reqwest::{header::HeaderMap, RequestBuilder, Response, ResponseBuilderExt, Url};
+use tracing::info;
+
+/// Bytes of a body that are logged
+const MAX_BODY: usize = 2048;
+const REDACTED: &str = "[redacted]";
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Logs every request sent from now on
+pub fn enable() {
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+pub(crate) trait SendLogged {
+    /// `send`, logging the request and response if enabled
+    async fn send_logged(self) -> Result<Response, reqwest::Error>;
+}
+
+impl SendLogged for RequestBuilder {
+    async fn send_logged(self) -> Result<Response, reqwest::Error> {
+        if !is_enabled() {
+            return self.send().await;
+        }
+
+        if let Some(Ok(request)) = self.try_clone().map(RequestBuilder::build) {
+            info!(
+                target: "dynsix::http",
+                "> {} {}{}{}",
+                request.method(),
+                redact_url(request.url()),
+                headers(request.headers()),
+                body(request.body().and_then(|body| body.as_bytes()).unwrap_or_default())
+            );
+        }
+
+        let response = self.send().await?;
+        let url = response.url().clone();
+        let status = response.status();
+        let version = response.version();
+        let response_headers = response.headers().clone();
+        let bytes = response.bytes().await?;
+        info!(
+            target: "dynsix::http",
+            "< {status} {}{}{}",
+            redact_url(&url),
+            headers(&response_headers),
+            body(&bytes)
+        );
+
+        // The body was consumed for logging, the caller gets a copy
+        let mut rebuilt = http::Response::builder()
+            .status(status)
+            .version(version)
+            .url(url);
+        if let Some(headers) = rebuilt.headers_mut() {
+            *headers = response_headers;
+        }
+        Ok(rebuilt
+            .body(bytes)
+            .expect("parts of a valid response")
+            .into())
+    }
+}
+
+/// Whether a header, query parameter or JSON field named `name` likely
+/// carries credentials
+fn is_sensitive(name: &str) -> bool {
+    let name = name.to_ascii_lowercase();
+    ["auth", "token", "key", "secret", "password", "signature"]
+        .iter()
+        .any(|part| name.contains(part))
+}
+
+/// `text` with the sensitive query parameters of the URLs in it masked, e.g.
+/// the message of an error about a request
+pub fn redact_text(text: &str) -> String {
+    text.split(' ')
+        .map(|word| {
+            let Some(start) = word.find("http://").or_else(|| word.find("https://")) else {
+                return word.to_string();
+            };
+            let end = word[start..]
+                .find(['(', ')', '"', '\'', '<', '>'])
+                .map_or(word.len(), |end| start + end);
+            let url = word[start..end].trim_end_matches([':', ',', '.']);
+            match Url::parse(url) {
+                Ok(parsed) => word.replacen(url, &redact_url(&parsed), 1),
+                Err(_) => word.to_string(),
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// The URL as logged, without the credentials in its query or, as with
+/// Telegram, its path
+fn redact_url(url: &Url) -> String {
+    let mut redacted = url.clone();
+    // Telegram bot tokens like 123456:ABC-DEF are part of /bot<token>/
+    let path: Vec<String> = url
+        .path()
+        .split('/')
+        .map(|segment| match segment.strip_prefix("bot") {
+            Some(token) if token.contains(':') => format!("bot{REDACTED}"),
+            _ => segment.to_string(),
+        })
+        .collect();
+    redacted.set_path(&path.join("/"));
+    let Some(query) = url.query() else {
+        return redacted.to_string();
+    };
+    redacted.set_query(None);
+
+    let query: Vec<String> = query
+        .split('&')
+        .map(|pair| match pair.split_once('=') {
+            Some((name, _)) if is_sensitive(name) => format!("{name}={REDACTED}"),
+            _ => pair.to_string(),
+        })
+        .collect();
+    format!("{redacted}?{}", query.join("&"))
+}
+
+fn headers(headers: &HeaderMap) -> String {
+    headers
+        .iter()
+        .map(|(name, value)| {
+            let value = if value.is_sensitive() || is_sensitive(name.as_str()) {
+                REDACTED
+            } else {
+                value.to_str().unwrap_or("[binary]")
+            };
+            format!("\n  {name}: {value}")
+        })
+        .collect()
+}
+
+fn body(bytes: &[u8]) -> String {
+    if bytes.is_empty() {
+        return String::new();
+    }
+
+    let text = match serde_json::from_slice::<serde_json::Value>(bytes) {
+        Ok(mut json) => {
+            redact_json(&mut json);
+            json.to_string()
+        }
+        Err(_) => String::from_utf8_lossy(bytes).into_owned(),
+    };
+    if text.len() <= MAX_BODY {
+        return format!("\n  {text}");
+    }
+    let mut end = MAX_BODY;
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    format!("\n  {}... ({} bytes)", &text[..end], text.len())
+}
+
+fn redact_json(json: &mut serde_json::Value) {
+    match json {
+        serde_json::Value::Object(fields) => {
+            for (name, value) in fields.iter_mut() {
+                if is_sensitive(name) && !value.is_object() && !value.is_array() {
+                    *value = REDACTED.into();
+                } else {
+                    redact_json(value);
+                }
+            }
+        }
+        serde_json::Value::Array(values) => values.iter_mut().for_each(redact_json),
+        _ => {}
+    }
+}
@@ -0,0 +1,42 @@
+//! Internationalized domain names. DNS APIs only take the ASCII form, where a
+//! label like `bücher` is written as its punycode `xn--bcher-kva` (RFC 3492).
+
+use crate::DynsixError;
+
+const PREFIX: &str = "xn--";
+
+/// The ASCII form of `domain`, mapped and punycode encoded as in UTS #46 if
+/// it has other characters. ASCII names, including `@` and `*`, are kept as
+/// is.
+pub fn to_ascii(domain: &str) -> Result<String, DynsixError> {
+    if domain.is_ascii() {
+        return Ok(domain.to_string());
+    }
+    idna::domain_to_ascii(domain).map_err(|e| DynsixError::Parse {
+        what: format!("domain name '{domain}'"),
+        message: e.to_string(),
+    })
+}
+
+/// `domain` for display, with punycode labels decoded. Labels that are not
+/// valid punycode are kept as they are.
+pub fn to_unicode(domain: &str) -> String {
+    domain
+        .split('.')
+        .map(|label| {
+            let punycode = label
+                .get(..PREFIX.len())
+                .is_some_and(|prefix| prefix.eq_ignore_ascii_case(PREFIX));
+            match idna::domain_to_unicode(label) {
+                (decoded, Ok(())) if punycode => decoded,
+                _ => label.to_string(),
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(".")
+}
+
+/// Whether the configured `domain` is `ascii` as returned by an API
+pub fn same_domain(domain: &str, ascii: &str) -> bool {
+    to_ascii(domain).is_ok_and(|domain| domain.eq_ignore_ascii_case(ascii))
+}
@@ -0,0 +1,242 @@
+//! Converts the configuration of another dynamic DNS updater into a dynsix
+//! config, written by `dynsix import`. What can't be converted is logged and
+//! kept as a comment at the top of the result.
+
+use std::{fmt::Write, path::Path, str::FromStr};
+
+use tracing::warn;
+
+use crate::{provider::ProviderKind, DynsixError};
+
+pub mod ddclient;
+pub mod inadyn;
+
+/// The updaters configs can be imported from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Ddclient,
+    Inadyn,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ddclient" => Ok(Self::Ddclient),
+            "inadyn" => Ok(Self::Inadyn),
+            _ => Err(format!(
+                "can't import from '{s}', expected ddclient or inadyn"
+            )),
+        }
+    }
+}
+
+impl Format {
+    /// Reads and converts the config at `path`
+    pub fn import(self, path: &Path) -> Result<Imported, DynsixError> {
+        let text = std::fs::read_to_string(path).map_err(|e| DynsixError::io(path, e))?;
+        let converted = match self {
+            Self::Ddclient => ddclient::convert(&text),
+            Self::Inadyn => inadyn::convert(&text),
+        };
+        converted.map_err(|message| DynsixError::Config {
+            path: path.to_path_buf(),
+            message,
+        })
+    }
+}
+
+/// Options of a `[providers.*]` section, or of a service's `credentials`
+pub type Options = Vec<(&'static str, String)>;
+
+/// A config converted from another updater
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Imported {
+    /// The top level Gandi token
+    pub token: Option<String>,
+    /// Further Gandi tokens, picked by services with `token_ref`
+    pub tokens: Vec<(String, String)>,
+    /// Interface the address is taken from instead of asking the query server
+    pub interface: Option<String>,
+    /// The `[providers.*]` sections, in the order they were first used
+    pub providers: Vec<(ProviderKind, Options)>,
+    pub services: Vec<ImportedService>,
+    /// What wasn't converted
+    pub notes: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImportedService {
+    /// Key in `[services]`, the host name of the record
+    pub service: String,
+    pub name: String,
+    pub fqdn: String,
+    pub provider: ProviderKind,
+    pub ttl: u32,
+    /// Set if the options differ from the `[providers.*]` section
+    pub credentials: Option<Options>,
+    pub token_ref: Option<String>,
+}
+
+impl Imported {
+    /// Adds a service publishing `host`, which lies in the domain `zone` or,
+    /// without one, in everything after its first label
+    pub fn add(
+        &mut self,
+        host: &str,
+        zone: Option<&str>,
+        provider: ProviderKind,
+        options: Options,
+        ttl: Option<u32>,
+    ) {
+        let Some((name, fqdn)) = split_host(host, zone) else {
+            return self.note(format!(
+                "{host}: not a name in the domain {}",
+                zone.unwrap_or("")
+            ));
+        };
+
+        let mut credentials = None;
+        let mut token_ref = None;
+        if provider == ProviderKind::Gandi {
+            let token = options
+                .into_iter()
+                .find(|(key, _)| *key == "token")
+                .map(|(_, token)| token)
+                .unwrap_or_default();
+            match &self.token {
+                None => self.token = Some(token),
+                Some(first) if *first == token => {}
+                Some(_) => {
+                    token_ref = Some(match self.tokens.iter().find(|(_, t)| *t == token) {
+                        Some((name, _)) => name.clone(),
+                        None => {
+                            let name = format!("gandi{}", self.tokens.len() + 2);
+                            self.tokens.push((name.clone(), token));
+                            name
+                        }
+                    });
+                }
+            }
+        } else {
+            match self.providers.iter().find(|(kind, _)| *kind == provider) {
+                None => self.providers.push((provider, options)),
+                Some((_, first)) if *first == options => {}
+                Some(_) => credentials = Some(options),
+            }
+        }
+
+        let range = provider.ttl_range();
+        let mut service = host.to_string();
+        for n in 2.. {
+            if !self.services.iter().any(|s| s.service == service) {
+                break;
+            }
+            service = format!("{host}-{n}");
+        }
+        self.services.push(ImportedService {
+            service,
+            name,
+            fqdn,
+            provider,
+            ttl: ttl.unwrap_or(600).clamp(*range.start(), *range.end()),
+            credentials,
+            token_ref,
+        });
+    }
+
+    /// Records and logs something that wasn't converted
+    pub fn note(&mut self, note: String) {
+        warn!("{note}");
+        self.notes.push(note);
+    }
+
+    /// The config as TOML, with a comment naming `origin`
+    pub fn to_toml(&self, origin: &str) -> String {
+        let mut toml = format!(
+            "# Converted from {origin} by dynsix import, check it with `dynsix config validate`.\n\
+             # Only AAAA records are published, each with the detected address as it is.\n"
+        );
+        if !self.notes.is_empty() {
+            toml.push_str("#\n# Not converted:\n");
+            for note in &self.notes {
+                let _ = writeln!(toml, "# - {note}");
+            }
+        }
+        toml.push('\n');
+
+        if let Some(token) = &self.token {
+            let _ = writeln!(toml, "token = {}", quote(token));
+        }
+        if let Some(interface) = &self.interface {
+            let _ = writeln!(toml, "source = {{ interface = {} }}", quote(interface));
+        }
+        if !self.tokens.is_empty() {
+            toml.push_str("\n[tokens]\n");
+            for (name, token) in &self.tokens {
+                let _ = writeln!(toml, "{} = {}", key(name), quote(token));
+            }
+        }
+        for (provider, options) in &self.providers {
+            let _ = writeln!(toml, "\n[providers.{provider}]");
+            for (option, value) in options {
+                let _ = writeln!(toml, "{option} = {}", quote(value));
+            }
+        }
+        for service in &self.services {
+            let _ = writeln!(toml, "\n[services.{}]", key(&service.service));
+            toml.push_str("suffix = \"::/128\"\n");
+            let _ = writeln!(toml, "name = {}", quote(&service.name));
+            let _ = writeln!(toml, "fqdn = {}", quote(&service.fqdn));
+            let _ = writeln!(toml, "ttl = {}", service.ttl);
+            if service.provider != ProviderKind::Gandi {
+                let _ = writeln!(toml, "provider = \"{}\"", service.provider);
+            }
+            if let Some(credentials) = &service.credentials {
+                let options: Vec<_> = credentials
+                    .iter()
+                    .map(|(option, value)| format!("{option} = {}", quote(value)))
+                    .collect();
+                let _ = writeln!(toml, "credentials = {{ {} }}", options.join(", "));
+            }
+            if let Some(token_ref) = &service.token_ref {
+                let _ = writeln!(toml, "token_ref = {}", quote(token_ref));
+            }
+        }
+        toml
+    }
+}
+
+/// Splits `host` into the name of its record and the domain, `zone` if given
+/// or else everything after the first label
+fn split_host(host: &str, zone: Option<&str>) -> Option<(String, String)> {
+    let host = host.trim_end_matches('.').to_ascii_lowercase();
+    match zone.map(|zone| zone.trim_end_matches('.').to_ascii_lowercase()) {
+        Some(zone) if host == zone => Some(("@".to_string(), zone)),
+        Some(zone) => {
+            let name = host.strip_suffix(&zone)?.strip_suffix('.')?;
+            Some((name.to_string(), zone))
+        }
+        None => {
+            let (name, fqdn) = host.split_once('.')?;
+            Some((name.to_string(), fqdn.to_string()))
+        }
+    }
+}
+
+fn quote(value: &str) -> String {
+    toml::Value::String(value.to_string()).to_string()
+}
+
+fn key(key: &str) -> String {
+    if !key.is_empty()
+        && key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        key.to_string()
+    } else {
+        quote(key)
+    }
+}
@@ -0,0 +1,217 @@
+//! ddclient.conf: `key=value` settings, separated by commas, followed by the
+//! hosts they apply to. Lines without hosts change the defaults of all the
+//! lines below them. Lines continue after a trailing backslash.
+
+use std::collections::HashMap;
+
+use super::{Imported, Options};
+use crate::provider::ProviderKind;
+
+/// A host and the settings it is updated with
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Host {
+    pub host: String,
+    pub settings: HashMap<String, String>,
+}
+
+/// Converts the hosts of a ddclient.conf
+pub fn convert(text: &str) -> Result<Imported, String> {
+    let mut imported = Imported::default();
+    for host in parse(text)? {
+        convert_host(&mut imported, &host);
+    }
+    if imported.services.is_empty() {
+        return Err("found no host that can be converted".to_string());
+    }
+    Ok(imported)
+}
+
+/// The hosts of a ddclient.conf with the settings in effect for each
+pub fn parse(text: &str) -> Result<Vec<Host>, String> {
+    let mut defaults = HashMap::new();
+    let mut hosts = Vec::new();
+    let mut line = String::new();
+    let mut first = 0;
+    for (number, raw) in text.lines().enumerate() {
+        if line.is_empty() {
+            first = number + 1;
+        }
+        let raw = strip_comment(raw);
+        match raw.trim_end().strip_suffix('\\') {
+            Some(continued) => {
+                line.push_str(continued);
+                line.push(' ');
+                continue;
+            }
+            None => line.push_str(raw),
+        }
+
+        let (settings, names) =
+            split_line(&line).map_err(|message| format!("line {first}: {message}"))?;
+        line.clear();
+        if names.is_empty() {
+            defaults.extend(settings);
+            continue;
+        }
+        for name in names {
+            let mut effective = defaults.clone();
+            effective.extend(settings.clone());
+            hosts.push(Host {
+                host: name,
+                settings: effective,
+            });
+        }
+    }
+    Ok(hosts)
+}
+
+/// The line up to a `#` outside of quotes
+fn strip_comment(line: &str) -> &str {
+    let mut quote = None;
+    for (i, c) in line.char_indices() {
+        match (c, quote) {
+            ('\'' | '"', None) => quote = Some(c),
+            (c, Some(open)) if c == open => quote = None,
+            ('#', None) => return &line[..i],
+            _ => {}
+        }
+    }
+    line
+}
+
+/// The settings of a line and the hosts it names
+fn split_line(line: &str) -> Result<(HashMap<String, String>, Vec<String>), String> {
+    let mut settings = HashMap::new();
+    let mut names = Vec::new();
+    let mut chars = line.chars().peekable();
+    loop {
+        while chars.next_if(|c| c.is_whitespace() || *c == ',').is_some() {}
+        let word: String =
+            std::iter::from_fn(|| chars.next_if(|c| !c.is_whitespace() && !"=,".contains(*c)))
+                .collect();
+        if word.is_empty() && chars.peek().is_none() {
+            break;
+        }
+        if chars.next_if_eq(&'=').is_none() {
+            names.push(word);
+            continue;
+        }
+
+        let value = match chars.next_if(|c| *c == '\'' || *c == '"') {
+            Some(quote) => {
+                let value: String = std::iter::from_fn(|| chars.next_if(|c| *c != quote)).collect();
+                if chars.next().is_none() {
+                    return Err(format!("unterminated quote in the value of {word}"));
+                }
+                value
+            }
+            None => {
+                std::iter::from_fn(|| chars.next_if(|c| !c.is_whitespace() && *c != ',')).collect()
+            }
+        };
+        if word.is_empty() {
+            return Err(format!("missing setting name before '={value}'"));
+        }
+        settings.insert(word.to_ascii_lowercase().replace('_', "-"), value);
+    }
+    Ok((settings, names))
+}
+
+fn convert_host(imported: &mut Imported, host: &Host) {
+    let setting = |key: &str| host.settings.get(key).map(String::as_str);
+    let name = host.host.as_str();
+    let password = setting("password").unwrap_or_default().to_string();
+    let ttl = setting("ttl").and_then(|ttl| ttl.parse().ok());
+
+    match setting("usev6").or(setting("use")) {
+        None | Some("web" | "webv6") => {}
+        Some("if" | "ifv6") => match setting("ifv6").or(setting("if")) {
+            Some(interface) if imported.interface.is_none() => {
+                imported.interface = Some(interface.to_string())
+            }
+            Some(interface) if imported.interface.as_deref() != Some(interface) => {
+                imported.note(format!(
+                    "{name}: the address of interface {interface}, all hosts use {}",
+                    imported.interface.as_deref().unwrap_or_default()
+                ))
+            }
+            _ => {}
+        },
+        Some(other) => imported.note(format!(
+            "{name}: use={other}, the address is looked up with the query server instead"
+        )),
+    }
+
+    let protocol = setting("protocol").unwrap_or("dyndns2");
+    let (provider, zone, options): (_, _, Options) = match protocol {
+        "gandi" => (
+            ProviderKind::Gandi,
+            setting("zone"),
+            vec![("token", password)],
+        ),
+        "hetzner" => (
+            ProviderKind::Hetzner,
+            setting("zone"),
+            vec![("token", password)],
+        ),
+        "porkbun" => (
+            ProviderKind::Porkbun,
+            match setting("on-root-domain") {
+                Some("yes" | "true" | "1") => Some(name),
+                _ => setting("root-domain"),
+            },
+            vec![
+                ("api_key", setting("apikey").unwrap_or_default().to_string()),
+                (
+                    "secret_api_key",
+                    setting("secretapikey").unwrap_or_default().to_string(),
+                ),
+            ],
+        ),
+        "duckdns" => {
+            let subdomain = name.trim_end_matches(".duckdns.org");
+            let host = format!("{subdomain}.duckdns.org");
+            let options = vec![
+                (
+                    "url",
+                    "https://www.duckdns.org/update?domains={name}&token={token}&ipv6={ip}"
+                        .to_string(),
+                ),
+                ("token", password),
+                ("success", "OK".to_string()),
+            ];
+            imported.add(&host, Some("duckdns.org"), ProviderKind::Http, options, ttl);
+            return;
+        }
+        // deSEC's dyndns2 server, its token is the password
+        "dyndns2" if setting("server").is_some_and(|server| server.contains("dedyn.io")) => (
+            ProviderKind::Desec,
+            setting("login"),
+            vec![("token", password)],
+        ),
+        "dyndns2" | "noip" => {
+            let server = setting("server").unwrap_or(match protocol {
+                "noip" => "dynupdate.no-ip.com",
+                _ => "members.dyndns.org",
+            });
+            let server = match (server.contains("://"), setting("ssl")) {
+                (true, _) => server.to_string(),
+                (false, Some("no" | "false" | "0")) => format!("http://{server}"),
+                (false, _) => format!("https://{server}"),
+            };
+            (
+                ProviderKind::Dyndns2,
+                None,
+                vec![
+                    ("server", server),
+                    ("username", setting("login").unwrap_or_default().to_string()),
+                    ("password", password),
+                ],
+            )
+        }
+        other => {
+            return imported.note(format!("{name}: protocol {other} is not supported"));
+        }
+    };
+    imported.add(name, zone, provider, options, ttl);
+}
@@ -0,0 +1,320 @@
+//! inadyn.conf: global `key = value` settings and a `provider NAME { ... }`
+//! or `custom NAME { ... }` block per account, naming its hosts with
+//! `hostname = name` or `hostname = { "a", "b" }`.
+
+use std::collections::HashMap;
+
+use super::{Imported, Options};
+use crate::provider::ProviderKind;
+
+/// A `provider` or `custom` block
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Block {
+    /// The provider, e.g. `default@no-ip.com`, or the name of a custom one
+    pub name: String,
+    pub custom: bool,
+    pub settings: HashMap<String, Vec<String>>,
+}
+
+/// The global settings and the blocks of an inadyn.conf
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct InadynConfig {
+    pub settings: HashMap<String, Vec<String>>,
+    pub blocks: Vec<Block>,
+}
+
+/// Converts the provider blocks of an inadyn.conf
+pub fn convert(text: &str) -> Result<Imported, String> {
+    let config = parse(text)?;
+    let mut imported = Imported {
+        interface: setting(&config.settings, "iface").map(str::to_string),
+        ..Imported::default()
+    };
+    for block in &config.blocks {
+        convert_block(&mut imported, block);
+    }
+    if imported.services.is_empty() {
+        return Err("found no provider block that can be converted".to_string());
+    }
+    Ok(imported)
+}
+
+pub fn parse(text: &str) -> Result<InadynConfig, String> {
+    let mut tokens = tokenize(text)?.into_iter();
+    let mut config = InadynConfig::default();
+    while let Some((line, token)) = tokens.next() {
+        let Token::Word(word) = token else {
+            return Err(format!("line {line}: expected a setting or block"));
+        };
+        match tokens.next() {
+            Some((_, Token::Equals)) => {
+                let values = values(&mut tokens, line)?;
+                config.settings.insert(word, values);
+            }
+            Some((_, Token::Word(name))) if word == "provider" || word == "custom" => {
+                if !matches!(tokens.next(), Some((_, Token::Open))) {
+                    return Err(format!("line {line}: expected {{ after {word} {name}"));
+                }
+                let mut settings = HashMap::new();
+                loop {
+                    match tokens.next() {
+                        Some((_, Token::Close)) => break,
+                        Some((line, Token::Word(key))) => {
+                            if !matches!(tokens.next(), Some((_, Token::Equals))) {
+                                return Err(format!("line {line}: expected = after {key}"));
+                            }
+                            let values = values(&mut tokens, line)?;
+                            settings.insert(key, values);
+                        }
+                        Some((line, _)) => return Err(format!("line {line}: expected a setting")),
+                        None => return Err(format!("line {line}: {word} {name} is not closed")),
+                    }
+                }
+                config.blocks.push(Block {
+                    name,
+                    custom: word == "custom",
+                    settings,
+                });
+            }
+            _ => return Err(format!("line {line}: expected = after {word}")),
+        }
+    }
+    Ok(config)
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Token {
+    Word(String),
+    Equals,
+    Open,
+    Close,
+    Comma,
+}
+
+/// The tokens of the config with the line they start on
+fn tokenize(text: &str) -> Result<Vec<(usize, Token)>, String> {
+    let mut tokens = Vec::new();
+    let mut line = 1;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        let token = match c {
+            '\n' => {
+                line += 1;
+                continue;
+            }
+            c if c.is_whitespace() => continue,
+            '#' => {
+                while chars.next_if(|c| *c != '\n').is_some() {}
+                continue;
+            }
+            '/' if chars.next_if_eq(&'/').is_some() => {
+                while chars.next_if(|c| *c != '\n').is_some() {}
+                continue;
+            }
+            '=' => Token::Equals,
+            '{' => Token::Open,
+            '}' => Token::Close,
+            ',' => Token::Comma,
+            '"' | '\'' => {
+                let mut value = String::new();
+                loop {
+                    match chars.next() {
+                        Some(end) if end == c => break,
+                        Some('\\') if c == '"' => value.extend(chars.next()),
+                        Some(other) => {
+                            if other == '\n' {
+                                line += 1;
+                            }
+                            value.push(other)
+                        }
+                        None => return Err(format!("line {line}: unterminated quote")),
+                    }
+                }
+                Token::Word(value)
+            }
+            c => {
+                let mut word = c.to_string();
+                word.extend(std::iter::from_fn(|| {
+                    chars.next_if(|c| !c.is_whitespace() && !"={},#".contains(*c))
+                }));
+                Token::Word(word)
+            }
+        };
+        tokens.push((line, token));
+    }
+    Ok(tokens)
+}
+
+/// A value, or a list of values in braces
+fn values(
+    tokens: &mut impl Iterator<Item = (usize, Token)>,
+    line: usize,
+) -> Result<Vec<String>, String> {
+    match tokens.next() {
+        Some((_, Token::Word(value))) => Ok(vec![value]),
+        Some((_, Token::Open)) => {
+            let mut values = Vec::new();
+            loop {
+                match tokens.next() {
+                    Some((_, Token::Word(value))) => values.push(value),
+                    Some((_, Token::Comma)) => {}
+                    Some((_, Token::Close)) => return Ok(values),
+                    _ => return Err(format!("line {line}: list is not closed")),
+                }
+            }
+        }
+        _ => Err(format!("line {line}: missing value")),
+    }
+}
+
+fn setting<'a>(settings: &'a HashMap<String, Vec<String>>, key: &str) -> Option<&'a str> {
+    settings
+        .get(key)
+        .and_then(|values| values.first())
+        .map(String::as_str)
+}
+
+/// dyndns2 servers of the providers inadyn knows
+const DYNDNS2: &[(&str, &str)] = &[
+    ("dyndns.org", "https://members.dyndns.org"),
+    ("no-ip.com", "https://dynupdate.no-ip.com"),
+    ("dynu.com", "https://api.dynu.com"),
+    ("strato.com", "https://dyndns.strato.com"),
+    ("nsupdate.info", "https://ipv6.nsupdate.info"),
+];
+
+fn convert_block(imported: &mut Imported, block: &Block) {
+    let setting = |key: &str| setting(&block.settings, key);
+    let hosts = block.settings.get("hostname").cloned().unwrap_or_default();
+    if hosts.is_empty() {
+        return imported.note(format!("{}: names no hostname", block.name));
+    }
+    let username = setting("username").unwrap_or_default().to_string();
+    let password = setting("password").unwrap_or_default().to_string();
+    let ttl = setting("ttl").and_then(|ttl| ttl.parse().ok());
+    for ignored in ["checkip-server", "checkip-command"] {
+        if block.settings.contains_key(ignored) {
+            imported.note(format!(
+                "{}: {ignored}, the address is looked up with the query server instead",
+                block.name
+            ));
+        }
+    }
+
+    // e.g. default@no-ip.com, ipv6@dynv6.com or default@no-ip.com:2
+    let service = block.name.split(':').next().unwrap_or_default();
+    let service = service
+        .split_once('@')
+        .map_or(service, |(_, domain)| domain);
+    let (provider, zone, options): (_, _, Options) = match service {
+        _ if block.custom => match custom(block, &username, &password) {
+            Ok(converted) => converted,
+            Err(note) => return imported.note(format!("{}: {note}", block.name)),
+        },
+        "duckdns.org" => {
+            let options = vec![
+                (
+                    "url",
+                    "https://www.duckdns.org/update?domains={name}&token={token}&ipv6={ip}"
+                        .to_string(),
+                ),
+                ("token", username),
+                ("success", "OK".to_string()),
+            ];
+            for host in &hosts {
+                let subdomain = host.trim_end_matches(".duckdns.org");
+                imported.add(
+                    &format!("{subdomain}.duckdns.org"),
+                    Some("duckdns.org"),
+                    ProviderKind::Http,
+                    options.clone(),
+                    ttl,
+                );
+            }
+            return;
+        }
+        "desec.io" | "dedyn.io" => {
+            for host in &hosts {
+                let options = vec![("token", password.clone())];
+                imported.add(host, Some(host), ProviderKind::Desec, options, ttl);
+            }
+            return;
+        }
+        "he.net" => {
+            // Each host has its own key and is its own user
+            for host in &hosts {
+                let options = vec![
+                    ("server", "https://dyn.dns.he.net".to_string()),
+                    ("username", host.clone()),
+                    ("password", password.clone()),
+                ];
+                imported.add(host, None, ProviderKind::Dyndns2, options, ttl);
+            }
+            return;
+        }
+        _ => match DYNDNS2.iter().find(|(domain, _)| *domain == service) {
+            Some((_, server)) => (
+                ProviderKind::Dyndns2,
+                None,
+                vec![
+                    ("server", server.to_string()),
+                    ("username", username),
+                    ("password", password),
+                ],
+            ),
+            None => {
+                return imported.note(format!(
+                    "{}: the provider is not supported, {} left out",
+                    block.name,
+                    hosts.join(", ")
+                ))
+            }
+        },
+    };
+    for host in &hosts {
+        imported.add(host, zone, provider, options.clone(), ttl);
+    }
+}
+
+/// A custom provider: dyndns2 if it uses its path, else a URL template
+fn custom(
+    block: &Block,
+    username: &str,
+    password: &str,
+) -> Result<(ProviderKind, Option<&'static str>, Options), String> {
+    let setting = |key: &str| setting(&block.settings, key);
+    let server = setting("ddns-server").ok_or("no ddns-server")?;
+    let server = match (server.contains("://"), setting("ssl")) {
+        (true, _) => server.to_string(),
+        (false, Some("false" | "no" | "off")) => format!("http://{server}"),
+        (false, _) => format!("https://{server}"),
+    };
+    let path = setting("ddns-path").unwrap_or("/nic/update");
+    if path.starts_with("/nic/update") {
+        return Ok((
+            ProviderKind::Dyndns2,
+            None,
+            vec![
+                ("server", server),
+                ("username", username.to_string()),
+                ("password", password.to_string()),
+            ],
+        ));
+    }
+
+    if !username.is_empty() && !path.contains("%u") {
+        return Err(
+            "sends the username as HTTP authentication, which is not supported".to_string(),
+        );
+    }
+    let url = format!("{}{path}", server.trim_end_matches('/'))
+        .replace("%u", username)
+        .replace("%p", "{token}")
+        .replace("%h", "{name}.{fqdn}")
+        .replace("%i", "{ip}");
+    let mut options = vec![("url", url)];
+    if !password.is_empty() {
+        options.push(("token", password.to_string()));
+    }
+    Ok((ProviderKind::Http, None, options))
+}
@@ -0,0 +1,133 @@
+//! `dynsix install systemd`: renders units that run the current binary with
+//! the current config, either as a daemon or as a oneshot service on a timer
+
+use std::path::{Path, PathBuf};
+
+use dynsix::{config::TOKEN_CREDENTIAL, sops::AGE_KEY_CREDENTIAL};
+
+const UNIT_DIR: &str = "/etc/systemd/system";
+const NAME: &str = "dynsix";
+
+/// Prints the units, or writes them to [`UNIT_DIR`] if `write` is set
+pub fn systemd(
+    config_path: &Path,
+    timer: bool,
+    write: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let binary = std::env::current_exe()?;
+    let config_path = std::path::absolute(config_path)?;
+
+    let mut units = vec![(
+        format!("{NAME}.service"),
+        service(&binary, &config_path, timer),
+    )];
+    if timer {
+        units.push((format!("{NAME}.timer"), TIMER.to_string()));
+    }
+
+    if !write {
+        for (index, (name, content)) in units.iter().enumerate() {
+            if index > 0 {
+                println!();
+            }
+            println!("# {UNIT_DIR}/{name}");
+            print!("{content}");
+        }
+        return Ok(());
+    }
+
+    for (name, content) in &units {
+        let path = PathBuf::from(UNIT_DIR).join(name);
+        std::fs::write(&path, content).map_err(|e| format!("{}: {e}", path.display()))?;
+        println!("Wrote {}", path.display());
+    }
+    let enable = if timer { "timer" } else { "service" };
+    println!("Activate with: systemctl daemon-reload && systemctl enable --now {NAME}.{enable}");
+    Ok(())
+}
+
+fn service(binary: &Path, config_path: &Path, timer: bool) -> String {
+    // The oneshot service is only started by the timer and not enabled itself
+    let (kind, command, restart, install) = if timer {
+        ("oneshot", "run", "", "")
+    } else {
+        (
+            "simple",
+            "daemon",
+            "Restart=on-failure\nRestartSec=30s\n",
+            "\n[Install]\nWantedBy=multi-user.target\n",
+        )
+    };
+
+    format!(
+        "[Unit]
+Description=Keep Gandi LiveDNS AAAA records in sync with the IPv6 prefix
+Wants=network-online.target
+After=network-online.target
+
+[Service]
+Type={kind}
+ExecStart={binary} --config {config} {command}
+{restart}
+# Keeps state_file, lock_file and control_socket at their default locations,
+# /var/lib/dynsix and /run/dynsix
+StateDirectory={NAME}
+RuntimeDirectory={NAME}
+RuntimeDirectoryPreserve=yes
+UMask=0077
+# Keeps the token out of the config, it takes precedence over `token`
+#LoadCredential={credential}:/etc/dynsix/{credential}
+# The age key for a config encrypted with sops
+#LoadCredential={age_key}:/etc/dynsix/{age_key}
+
+# Hardening, add ReadWritePaths= for metrics.textfile outside of /var/lib/dynsix
+CapabilityBoundingSet=
+NoNewPrivileges=yes
+ProtectSystem=strict
+ProtectHome=yes
+PrivateTmp=yes
+PrivateDevices=yes
+ProtectClock=yes
+ProtectControlGroups=yes
+ProtectHostname=yes
+ProtectKernelLogs=yes
+ProtectKernelModules=yes
+ProtectKernelTunables=yes
+RestrictAddressFamilies=AF_INET AF_INET6 AF_UNIX AF_NETLINK
+RestrictNamespaces=yes
+RestrictRealtime=yes
+RestrictSUIDSGID=yes
+LockPersonality=yes
+MemoryDenyWriteExecute=yes
+SystemCallArchitectures=native
+SystemCallFilter=@system-service
+SystemCallFilter=~@privileged @resources
+{install}",
+        binary = quote(binary),
+        config = quote(config_path),
+        credential = TOKEN_CREDENTIAL,
+        age_key = AGE_KEY_CREDENTIAL,
+    )
+}
+
+const TIMER: &str = "[Unit]
+Description=Reconcile Gandi LiveDNS AAAA records periodically
+
+[Timer]
+OnBootSec=1min
+OnUnitActiveSec=5min
+RandomizedDelaySec=30s
+
+[Install]
+WantedBy=timers.target
+";
+
+/// systemd splits command lines on whitespace unless quoted
+fn quote(path: &Path) -> String {
+    let path = path.display().to_string();
+    if path.contains(char::is_whitespace) {
+        format!("\"{}\"", path.replace('\\', "\\\\").replace('"', "\\\""))
+    } else {
+        path
+    }
+}
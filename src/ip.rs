@@ -0,0 +1,302 @@
+//! Public address detection and merging of prefixes with host suffixes
+
+use std::{
+    net::{IpAddr, Ipv6Addr},
+    path::{Path, PathBuf},
+};
+
+use serde::Deserialize;
+
+use crate::{
+    dns,
+    http_client::{self, HttpConfig, LocalAddress},
+    http_log::SendLogged,
+    upnp, DynsixError,
+};
+
+/// Where the prefix comes from instead of the query server
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+pub enum Source {
+    /// A file holding the prefix, e.g. `2001:db8:1::/56`, as written by a
+    /// router script. `dynsix daemon` reconciles whenever it changes.
+    File(PathBuf),
+    /// A stable global address of a local interface, see [`select_address`]
+    Interface(String),
+    /// The AAAA record of a name that a DNS server answers with the address
+    /// asking for it, without any HTTP
+    Dns(DnsSource),
+    /// The Internet Gateway Device of the local network, asked over UPnP
+    Upnp(UpnpSource),
+}
+
+/// `myip.opendns.com` at `resolver1.opendns.com` by default. Akamai's
+/// authoritative servers do the same for `whoami.akamai.net`, e.g. at
+/// `ns1-1.akamaitech.net`.
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct DnsSource {
+    #[serde(default = "default_dns_name")]
+    pub name: String,
+    /// Host name or address, with an optional port
+    #[serde(default = "default_dns_server")]
+    pub server: String,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+#[serde(deny_unknown_fields)]
+pub struct UpnpSource {
+    /// URL of the description of the gateway, e.g.
+    /// `http://192.168.178.1:49000/igddesc.xml`, searched for with SSDP if
+    /// unset
+    pub location: Option<String>,
+}
+
+fn default_dns_name() -> String {
+    "myip.opendns.com".to_string()
+}
+
+fn default_dns_server() -> String {
+    "resolver1.opendns.com".to_string()
+}
+
+/// Where the kernel lists the IPv6 addresses of all interfaces
+const IF_INET6: &str = "/proc/net/if_inet6";
+
+// linux/if_addr.h, only the lower 8 bits are listed in /proc/net/if_inet6
+const IFA_F_TEMPORARY: u8 = 0x01;
+const IFA_F_DADFAILED: u8 = 0x08;
+const IFA_F_DEPRECATED: u8 = 0x20;
+const IFA_F_TENTATIVE: u8 = 0x40;
+const IFA_F_PERMANENT: u8 = 0x80;
+
+/// An address as listed in `/proc/net/if_inet6`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InterfaceAddress {
+    pub address: Ipv6Addr,
+    pub interface: String,
+    /// 0 is global, unique local addresses included
+    pub scope: u8,
+    /// `IFA_F_*`
+    pub flags: u8,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct IpInfo {
+    pub ip: Ipv6Addr,
+}
+
+/// HTTP client for the query server, by default bound to `::` so that it
+/// can only see our IPv6 address. Through a proxy it sees the proxy's
+/// address instead.
+pub fn query_client(config: &HttpConfig) -> Result<reqwest::Client, DynsixError> {
+    Ok(http_client::builder(config, config.query_local_address)?.build()?)
+}
+
+/// Asks `ip_query_server` for our public address. The server has to answer
+/// with JSON of the form `{"ip": "..."}`.
+pub async fn get_public_ip(
+    client: &reqwest::Client,
+    ip_query_server: &str,
+) -> Result<Ipv6Addr, DynsixError> {
+    let query = async {
+        client
+            .get(ip_query_server)
+            .header("Accept", "application/json")
+            .send_logged()
+            .await?
+            .json::<IpInfo>()
+            .await
+    };
+
+    match query.await {
+        Ok(ip_info) => Ok(ip_info.ip),
+        Err(source) => Err(DynsixError::IpSource {
+            server: ip_query_server.to_string(),
+            source,
+        }),
+    }
+}
+
+/// Asks the server of `source` for our address, over IPv6 so that it sees
+/// the IPv6 address, from `bind_address` if that is one
+pub async fn dns_address(source: &DnsSource, config: &HttpConfig) -> Result<Ipv6Addr, DynsixError> {
+    let bind = match config.bind_address {
+        Some(IpAddr::V6(address)) => Some(address),
+        _ => None,
+    };
+    dns::lookup_aaaa(&source.server, &source.name, config.timeout, bind).await
+}
+
+/// Asks the gateway of `source` for our address. It is on the local network,
+/// so never behind the proxy.
+pub async fn upnp_address(
+    source: &UpnpSource,
+    config: &HttpConfig,
+) -> Result<Ipv6Addr, DynsixError> {
+    let client = http_client::builder(config, LocalAddress::Any)?
+        .no_proxy()
+        .build()?;
+    let location = match &source.location {
+        Some(location) => location.clone(),
+        None => upnp::discover().await.map_err(DynsixError::Upnp)?,
+    };
+    upnp::external_address(&client, &location)
+        .await
+        .map_err(DynsixError::Upnp)
+}
+
+/// Reads the prefix from `path`, an address with an optional length of at
+/// most /64
+pub fn read_prefix_file(path: &Path) -> Result<Ipv6Addr, DynsixError> {
+    let raw = std::fs::read_to_string(path).map_err(|e| DynsixError::io(path, e))?;
+    let raw = raw.trim();
+    let error = |message: String| DynsixError::Parse {
+        what: format!("prefix in {}", path.display()),
+        message,
+    };
+
+    let (address, length) = match raw.split_once('/') {
+        Some((address, length)) => (address, Some(length)),
+        None => (raw, None),
+    };
+    let address = address
+        .parse::<Ipv6Addr>()
+        .map_err(|e| error(format!("'{raw}': {e}")))?;
+    if let Some(length) = length {
+        let length: u8 = length
+            .parse()
+            .map_err(|e| error(format!("invalid length in '{raw}': {e}")))?;
+        if length > 64 {
+            return Err(error(format!("'{raw}' is longer than /64")));
+        }
+    }
+    Ok(address)
+}
+
+/// Parses the lines of `/proc/net/if_inet6`, skipping those it can't
+pub fn parse_if_inet6(raw: &str) -> Vec<InterfaceAddress> {
+    raw.lines()
+        .filter_map(|line| {
+            let fields: Vec<_> = line.split_whitespace().collect();
+            let [address, _index, _length, scope, flags, interface] = fields[..] else {
+                return None;
+            };
+            Some(InterfaceAddress {
+                address: u128::from_str_radix(address, 16).ok()?.into(),
+                interface: interface.to_string(),
+                scope: u8::from_str_radix(scope, 16).ok()?,
+                flags: u8::from_str_radix(flags, 16).ok()?,
+            })
+        })
+        .collect()
+}
+
+/// The address of `interface` to take the prefix from: a global one outside
+/// of fc00::/7, and neither a temporary privacy address, which rotates, nor
+/// deprecated or still tentative. Permanent, i.e. manually configured,
+/// addresses are preferred.
+pub fn select_address(addresses: &[InterfaceAddress], interface: &str) -> Option<Ipv6Addr> {
+    let mut candidates: Vec<_> = addresses
+        .iter()
+        .filter(|address| address.interface == interface && address.scope == 0)
+        .filter(|address| address.address.segments()[0] & 0xfe00 != 0xfc00)
+        .filter(|address| {
+            address.flags & (IFA_F_TEMPORARY | IFA_F_DADFAILED | IFA_F_DEPRECATED | IFA_F_TENTATIVE)
+                == 0
+        })
+        .collect();
+    // Stable, so the kernel's order decides between equals
+    candidates.sort_by_key(|address| address.flags & IFA_F_PERMANENT == 0);
+    candidates.first().map(|address| address.address)
+}
+
+/// The IPv6 addresses of all local interfaces
+pub fn local_addresses() -> Result<Vec<InterfaceAddress>, DynsixError> {
+    let raw = std::fs::read_to_string(IF_INET6).map_err(|e| DynsixError::io(IF_INET6, e))?;
+    Ok(parse_if_inet6(&raw))
+}
+
+/// The address `select_address` picks from the current addresses of
+/// `interface`
+pub fn interface_address(interface: &str) -> Result<Ipv6Addr, DynsixError> {
+    select_address(&local_addresses()?, interface).ok_or_else(|| DynsixError::Interface {
+        interface: interface.to_string(),
+        source: std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            "no global IPv6 address that is neither temporary nor deprecated",
+        ),
+    })
+}
+
+/// Why `detected`, the address a server saw us come from, isn't in the /64
+/// of a global address of the `local` interfaces, if it isn't
+pub fn prefix_mismatch(detected: Ipv6Addr, local: &[InterfaceAddress]) -> Option<String> {
+    let (unique_local, global): (Vec<_>, Vec<_>) = local
+        .iter()
+        .filter(|address| address.scope == 0 && address.flags & IFA_F_DADFAILED == 0)
+        .map(|address| address.address)
+        .partition(|address| address.segments()[0] & 0xfe00 == 0xfc00);
+    let upper = |address: Ipv6Addr| u128::from(address) >> 64;
+    if global
+        .iter()
+        .any(|&address| upper(address) == upper(detected))
+    {
+        return None;
+    }
+
+    // Hurricane Electric's tunnel broker
+    let tunnel = |address: &Ipv6Addr| address.segments()[..2] == [0x2001, 0x470];
+    Some(if tunnel(&detected) {
+        "it is from a Hurricane Electric tunnel ending on another host".to_string()
+    } else if global.iter().any(tunnel) {
+        "local addresses are from a Hurricane Electric tunnel, but the query didn't go through it"
+            .to_string()
+    } else if global.is_empty() && !unique_local.is_empty() {
+        "only unique local addresses are assigned here, the network translates them (NAT66)"
+            .to_string()
+    } else if global.is_empty() {
+        "no global IPv6 address is assigned here, the query went through a proxy or NAT66"
+            .to_string()
+    } else {
+        "no local interface has an address in its /64, the query went through NAT66, a proxy \
+         or a tunnel"
+            .to_string()
+    })
+}
+
+/// Combines the upper 64 bits of `prefix` with the lower 64 bits of `suffix`
+pub fn merge_ips(prefix: Ipv6Addr, suffix: Ipv6Addr) -> Ipv6Addr {
+    let prefix_segments = prefix.segments();
+    let suffix_segments = suffix.segments();
+
+    Ipv6Addr::new(
+        prefix_segments[0],
+        prefix_segments[1],
+        prefix_segments[2],
+        prefix_segments[3],
+        suffix_segments[4],
+        suffix_segments[5],
+        suffix_segments[6],
+        suffix_segments[7],
+    )
+}
+
+/// Combines the first `length` bits of `prefix` with the rest of `suffix`
+pub fn merge_bits(prefix: Ipv6Addr, suffix: Ipv6Addr, length: u8) -> Ipv6Addr {
+    let mask = u128::MAX
+        .checked_shl(128 - u32::from(length.min(128)))
+        .unwrap_or(0);
+    Ipv6Addr::from((u128::from(prefix) & mask) | (u128::from(suffix) & !mask))
+}
+
+/// Replaces the bits of `prefix` from `length` up to 64 with `subnet`
+pub fn with_subnet(prefix: Ipv6Addr, length: u8, subnet: u64) -> Ipv6Addr {
+    let subnet_bits = 64 - u32::from(length.min(64));
+    let mask = 1u64
+        .checked_shl(subnet_bits)
+        .map_or(u64::MAX, |bit| bit - 1);
+    let bits = u128::from(prefix);
+    let upper = ((bits >> 64) as u64 & !mask) | (subnet & mask);
+    Ipv6Addr::from((u128::from(upper) << 64) | (bits & u128::from(u64::MAX)))
+}
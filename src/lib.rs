@@ -0,0 +1,72 @@
+//! Keeps Gandi LiveDNS AAAA records in sync with the public IPv6 prefix of a host.
+//!
+//! Every configured service combines the detected /64 prefix with its own host
+//! suffix (see [`merge_ips`]) and publishes the result as `<name>.<fqdn>`.
+//! [`Reconciler`] compares that desired state with what Gandi serves and creates
+//! or updates records as needed:
+//!
+//! ```no_run
+//! # async fn example() -> Result<(), dynsix::DynsixError> {
+//! let config = dynsix::Config::load("/etc/dynsix/config.toml")?;
+//! let query = dynsix::ip::query_client(&config.http)?;
+//! let public_ip = dynsix::ip::get_public_ip(&query, &config.query_server).await?;
+//!
+//! let http = dynsix::http_client::client(&config.http)?;
+//!
+//! let reconciler = dynsix::Reconciler::new(dynsix::gandi::Client::new(http, &config.token));
+//! let report = reconciler.reconcile(&config.services, public_ip, |_| true).await;
+//! println!("{} services reconciled", report.services.len());
+//! # Ok(())
+//! # }
+//! ```
+
+pub mod audit;
+mod bridge;
+pub mod check;
+pub mod config;
+pub mod discovery;
+pub mod dns;
+pub mod drift;
+mod error;
+pub mod gandi;
+mod glob;
+#[cfg(feature = "history")]
+pub mod history;
+pub mod hook;
+pub mod http_client;
+pub mod http_log;
+pub mod idna;
+pub mod import;
+pub mod ip;
+pub mod lock;
+pub mod metrics;
+pub mod notify;
+pub mod plan;
+pub mod protobuf;
+pub mod provider;
+pub mod reconcile;
+pub mod report;
+pub mod retry;
+pub mod schedule;
+pub mod secret;
+pub mod sops;
+#[cfg(feature = "history")]
+mod sqlite;
+pub mod srv;
+pub mod sshfp;
+pub mod state;
+pub mod suffix;
+pub mod tlsa;
+pub mod ttl;
+pub mod upnp;
+pub mod vault;
+pub mod version;
+mod xml;
+mod yaml;
+pub mod zone;
+
+pub use config::{Config, ServiceConfig};
+pub use error::DynsixError;
+pub use glob::glob_match;
+pub use ip::merge_ips;
+pub use reconcile::Reconciler;
@@ -0,0 +1,61 @@
+//! Keeps runs of separate invocations, e.g. from cron, from racing each other
+//! on the same records
+
+use std::{
+    fs::{File, OpenOptions},
+    io::{ErrorKind, Write},
+    os::unix::io::AsRawFd,
+    path::Path,
+};
+
+use crate::DynsixError;
+
+/// Held until dropped, the lock is released with the file descriptor. The
+/// file itself stays, removing it would let two processes lock different files.
+#[derive(Debug)]
+pub struct RunLock {
+    _file: File,
+}
+
+impl RunLock {
+    /// Locks `path`, creating the file if needed. If another process holds the
+    /// lock, this waits for it if `wait` is set and returns `None` otherwise.
+    pub fn acquire(path: &Path, wait: bool) -> Result<Option<Self>, DynsixError> {
+        if let Some(parent) = path
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+        {
+            std::fs::create_dir_all(parent).map_err(|e| DynsixError::io(parent, e))?;
+        }
+        let mut file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(path)
+            .map_err(|e| DynsixError::io(path, e))?;
+
+        let operation = if wait {
+            libc::LOCK_EX
+        } else {
+            libc::LOCK_EX | libc::LOCK_NB
+        };
+        loop {
+            // SAFETY: the descriptor belongs to `file`, which is alive
+            if unsafe { libc::flock(file.as_raw_fd(), operation) } == 0 {
+                break;
+            }
+            let e = std::io::Error::last_os_error();
+            match e.kind() {
+                ErrorKind::Interrupted => continue,
+                ErrorKind::WouldBlock => return Ok(None),
+                _ => return Err(DynsixError::io(path, e)),
+            }
+        }
+
+        // Only informational, tells a human which process holds the lock
+        file.set_len(0)
+            .and_then(|_| writeln!(file, "{}", std::process::id()))
+            .map_err(|e| DynsixError::io(path, e))?;
+        Ok(Some(Self { _file: file }))
+    }
+}
@@ -0,0 +1,577 @@
+//! A small `tracing` subscriber writing one line per event to stderr, prefixed
+//! with the fields of the spans it happened in:
+//!
+//! ```text
+//! 2023-01-20T12:00:00Z INFO  dynsix::reconcile reconcile{public_ip=2001:db8::}:service{service=www fqdn=example.com name=www new=2001:db8::c0de old=["2001:db8::c0de"]}: Found an existing AAAA record
+//! ```
+//!
+//! Verbosity is taken from `[log]` in the config and `RUST_LOG` on top of it,
+//! e.g. `info` or `warn,dynsix=debug`, which also sets it until the config is
+//! loaded. `[log]` also chooses the timestamps and colors. Records emitted through the `log` crate by
+//! dependencies such as reqwest are written the same way. The daemon also
+//! keeps the latest lines up to `info` whatever the level, for `dynsix tui`.
+//!
+//! With `log_output = "journald"` lines are sent to journald instead, each
+//! field as a field of the entry: `service` becomes `SERVICE=`, the `old`
+//! and `new` addresses `OLD_IP=` and `NEW_IP=`.
+
+use std::{
+    cell::RefCell,
+    collections::{HashMap, VecDeque},
+    fmt::{self, Write as _},
+    io::IsTerminal,
+    os::unix::net::UnixDatagram,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex, OnceLock, RwLock,
+    },
+    time::SystemTime,
+};
+
+use dynsix::config::{ColorMode, Config, LogConfig, LogLevel, LogOutput, Timestamps};
+use tracing::{
+    field::{Field, Visit},
+    level_filters::LevelFilter,
+    span, warn, Event, Level, Metadata, Subscriber,
+};
+
+/// Installs the subscriber and the `log` bridge, configured from `RUST_LOG`
+/// until [`configure`] is called. `log_http` shows the traffic logged by
+/// `--log-http` whatever the level, `keep_recent` keeps the latest lines for
+/// [`recent`].
+pub fn init(log_http: bool, keep_recent: bool) {
+    let logger = Logger(Arc::new(Inner {
+        format: RwLock::new(Format::new(&LogConfig::default(), log_http)),
+        log_http,
+        keep_recent,
+        spans: Mutex::new(HashMap::new()),
+        next_id: AtomicU64::new(1),
+    }));
+    logger.0.set_max_level();
+
+    let _ = LOGGER.set(logger.clone());
+    let _ = log::set_logger(Box::leak(Box::new(logger.clone())));
+    let _ = tracing::subscriber::set_global_default(logger);
+}
+
+/// Applies `log` and `log_output` of the loaded config, again when it's
+/// reloaded
+pub fn configure(config: &Config) {
+    if let Some(logger) = LOGGER.get() {
+        *logger.0.format.write().unwrap() = Format::new(&config.log, logger.0.log_http);
+        logger.0.set_max_level();
+        // Callsites remember whether they are enabled
+        tracing::callsite::rebuild_interest_cache();
+    }
+    set_output(config.log_output);
+}
+
+static LOGGER: OnceLock<Logger> = OnceLock::new();
+
+const HTTP_TARGET: &str = "dynsix::http";
+
+/// How many lines [`recent`] returns at most
+const RECENT_LINES: usize = 200;
+
+static RECENT: Mutex<VecDeque<String>> = Mutex::new(VecDeque::new());
+
+/// The latest lines up to `info`, oldest first, if kept
+pub fn recent() -> Vec<String> {
+    RECENT.lock().unwrap().iter().cloned().collect()
+}
+
+const JOURNAL_SOCKET: &str = "/run/systemd/journal/socket";
+
+/// Connected to journald if lines go there instead of stderr
+static JOURNAL: Mutex<Option<UnixDatagram>> = Mutex::new(None);
+
+/// Sends the lines to `output` from now on. Stays with stderr if journald
+/// can't be reached.
+fn set_output(output: LogOutput) {
+    let journal = match output {
+        LogOutput::Stderr => None,
+        LogOutput::Journald => {
+            match UnixDatagram::unbound().and_then(|socket| {
+                socket.connect(JOURNAL_SOCKET)?;
+                Ok(socket)
+            }) {
+                Ok(socket) => Some(socket),
+                Err(e) => {
+                    *JOURNAL.lock().unwrap() = None;
+                    warn!("Logging to stderr instead of journald, {JOURNAL_SOCKET}: {e}");
+                    return;
+                }
+            }
+        }
+    };
+    *JOURNAL.lock().unwrap() = journal;
+}
+
+thread_local! {
+    /// Spans entered on this thread, innermost last
+    static STACK: RefCell<Vec<span::Id>> = const { RefCell::new(Vec::new()) };
+}
+
+#[derive(Clone)]
+struct Logger(Arc<Inner>);
+
+struct Inner {
+    format: RwLock<Format>,
+    log_http: bool,
+    keep_recent: bool,
+    spans: Mutex<HashMap<u64, SpanData>>,
+    next_id: AtomicU64,
+}
+
+struct SpanData {
+    name: &'static str,
+    fields: Vec<(&'static str, String)>,
+    parent: Option<span::Id>,
+    refs: usize,
+}
+
+impl Inner {
+    /// `name{fields}:` of the current span and all its parents, outermost first
+    fn scope(&self) -> String {
+        let Some(mut current) = STACK.with(|stack| stack.borrow().last().cloned()) else {
+            return String::new();
+        };
+
+        let spans = self.spans.lock().unwrap();
+        let mut scope = Vec::new();
+        while let Some(span) = spans.get(&current.into_u64()) {
+            scope.push(format!("{}{{{}}}:", span.name, join(&span.fields)));
+            match &span.parent {
+                Some(parent) => current = parent.clone(),
+                None => break,
+            }
+        }
+        scope.reverse();
+        scope.concat()
+    }
+
+    /// The fields of the current span and all its parents, outermost first
+    fn scope_fields(&self) -> Vec<(&'static str, String)> {
+        let mut current = STACK.with(|stack| stack.borrow().last().cloned());
+        let spans = self.spans.lock().unwrap();
+        let mut fields = Vec::new();
+        while let Some(span) = current.and_then(|id| spans.get(&id.into_u64())) {
+            fields.splice(0..0, span.fields.iter().cloned());
+            current = span.parent.clone();
+        }
+        fields
+    }
+
+    /// Whether events of `level` are written or kept
+    fn enabled(&self, target: &str, level: Level) -> bool {
+        self.format.read().unwrap().filter.enabled(target, level)
+            || (self.keep_recent && level <= Level::INFO)
+    }
+
+    /// Lets the `log` crate skip what is filtered out anyway
+    fn set_max_level(&self) {
+        let mut max_level = self.format.read().unwrap().filter.max_level();
+        if self.keep_recent {
+            max_level = max_level.max(LevelFilter::INFO);
+        }
+        log::set_max_level(to_log_filter(max_level));
+    }
+
+    /// Writes a line, `fields` being those of the event that aren't part of
+    /// `message` already
+    fn write(&self, level: Level, target: &str, message: &str, fields: &[(&'static str, String)]) {
+        let mut message = message.to_string();
+        if !fields.is_empty() {
+            if !message.is_empty() {
+                message.push(' ');
+            }
+            message.push_str(&join(fields));
+        }
+        let now = SystemTime::now();
+        let scope = self.scope();
+        let separator = if scope.is_empty() { "" } else { " " };
+        let rest = format!("{target}{separator}{scope} {message}");
+        if self.keep_recent && level <= Level::INFO {
+            let timestamp = humantime::format_rfc3339_seconds(now);
+            let mut recent = RECENT.lock().unwrap();
+            if recent.len() == RECENT_LINES {
+                recent.pop_front();
+            }
+            recent.push_back(format!("{timestamp} {level:<5} {rest}"));
+        }
+        let format = self.format.read().unwrap();
+        if !format.filter.enabled(target, level) {
+            return;
+        }
+        if let Some(journal) = &*JOURNAL.lock().unwrap() {
+            let mut all = self.scope_fields();
+            all.extend_from_slice(fields);
+            if journal
+                .send(&journal_entry(level, target, &message, &all))
+                .is_ok()
+            {
+                return;
+            }
+        }
+        eprintln!("{}", format.line(now, level, &rest));
+    }
+}
+
+impl Subscriber for Logger {
+    fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+        // Spans are always created so that errors carry the service they belong to
+        metadata.is_span() || self.0.enabled(metadata.target(), *metadata.level())
+    }
+
+    fn max_level_hint(&self) -> Option<LevelFilter> {
+        Some(LevelFilter::TRACE)
+    }
+
+    fn new_span(&self, attrs: &span::Attributes<'_>) -> span::Id {
+        let mut fields = FieldWriter::default();
+        attrs.record(&mut fields);
+
+        let parent = if attrs.is_root() {
+            None
+        } else if attrs.is_contextual() {
+            STACK.with(|stack| stack.borrow().last().cloned())
+        } else {
+            attrs.parent().cloned()
+        };
+
+        let id = self.0.next_id.fetch_add(1, Ordering::Relaxed);
+        if let Some(parent) = &parent {
+            self.clone_span(parent);
+        }
+        self.0.spans.lock().unwrap().insert(
+            id,
+            SpanData {
+                name: attrs.metadata().name(),
+                fields: fields.fields,
+                parent,
+                refs: 1,
+            },
+        );
+        span::Id::from_u64(id)
+    }
+
+    fn record(&self, span: &span::Id, values: &span::Record<'_>) {
+        let mut spans = self.0.spans.lock().unwrap();
+        if let Some(span) = spans.get_mut(&span.into_u64()) {
+            let mut fields = FieldWriter {
+                fields: std::mem::take(&mut span.fields),
+                message: None,
+            };
+            values.record(&mut fields);
+            span.fields = fields.fields;
+        }
+    }
+
+    fn record_follows_from(&self, _span: &span::Id, _follows: &span::Id) {}
+
+    fn event(&self, event: &Event<'_>) {
+        let mut fields = FieldWriter::default();
+        event.record(&mut fields);
+
+        self.0.write(
+            *event.metadata().level(),
+            event.metadata().target(),
+            &fields.message.unwrap_or_default(),
+            &fields.fields,
+        );
+    }
+
+    fn enter(&self, span: &span::Id) {
+        STACK.with(|stack| stack.borrow_mut().push(span.clone()));
+    }
+
+    fn exit(&self, span: &span::Id) {
+        STACK.with(|stack| {
+            let mut stack = stack.borrow_mut();
+            if let Some(index) = stack.iter().rposition(|entered| entered == span) {
+                stack.remove(index);
+            }
+        });
+    }
+
+    fn clone_span(&self, span: &span::Id) -> span::Id {
+        if let Some(data) = self.0.spans.lock().unwrap().get_mut(&span.into_u64()) {
+            data.refs += 1;
+        }
+        span.clone()
+    }
+
+    fn try_close(&self, span: span::Id) -> bool {
+        let parent = {
+            let mut spans = self.0.spans.lock().unwrap();
+            let Some(data) = spans.get_mut(&span.into_u64()) else {
+                return false;
+            };
+            data.refs -= 1;
+            if data.refs > 0 {
+                return false;
+            }
+            spans.remove(&span.into_u64()).and_then(|data| data.parent)
+        };
+
+        // Spans hold a reference to their parent while they are alive
+        if let Some(parent) = parent {
+            self.try_close(parent);
+        }
+        true
+    }
+}
+
+impl log::Log for Logger {
+    fn enabled(&self, metadata: &log::Metadata<'_>) -> bool {
+        self.0
+            .enabled(metadata.target(), to_tracing_level(metadata.level()))
+    }
+
+    fn log(&self, record: &log::Record<'_>) {
+        if log::Log::enabled(self, record.metadata()) {
+            self.0.write(
+                to_tracing_level(record.level()),
+                record.target(),
+                &record.args().to_string(),
+                &[],
+            );
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+/// Collects the fields of a span or event, keeping the message of an event
+/// apart
+#[derive(Default)]
+struct FieldWriter {
+    fields: Vec<(&'static str, String)>,
+    message: Option<String>,
+}
+
+impl Visit for FieldWriter {
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if field.name() == "message" {
+            self.message = Some(format!("{value:?}"));
+            return;
+        }
+        self.fields.push((field.name(), format!("{value:?}")));
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.record_debug(field, &format_args!("{value}"));
+    }
+}
+
+/// Fields as `key=value` pairs
+fn join(fields: &[(&'static str, String)]) -> String {
+    let mut joined = String::new();
+    for (name, value) in fields {
+        if !joined.is_empty() {
+            joined.push(' ');
+        }
+        let _ = write!(joined, "{name}={value}");
+    }
+    joined
+}
+
+/// A datagram of the native journal protocol: `NAME=value` lines, or for
+/// values spanning lines the name, the length as 64 bit little endian and
+/// the value
+fn journal_entry(
+    level: Level,
+    target: &str,
+    message: &str,
+    fields: &[(&'static str, String)],
+) -> Vec<u8> {
+    let priority = match level {
+        Level::ERROR => "3",
+        Level::WARN => "4",
+        Level::INFO => "6",
+        _ => "7",
+    };
+    let mut entry = Vec::new();
+    let mut add = |name: &str, value: &str| {
+        entry.extend_from_slice(name.as_bytes());
+        if value.contains('\n') {
+            entry.push(b'\n');
+            entry.extend_from_slice(&(value.len() as u64).to_le_bytes());
+        } else {
+            entry.push(b'=');
+        }
+        entry.extend_from_slice(value.as_bytes());
+        entry.push(b'\n');
+    };
+    add("MESSAGE", message);
+    add("PRIORITY", priority);
+    add("SYSLOG_IDENTIFIER", "dynsix");
+    add("TARGET", target);
+    for (name, value) in fields {
+        let name = match *name {
+            "old" => "OLD_IP".to_string(),
+            "new" => "NEW_IP".to_string(),
+            // Journal fields are upper case letters, digits and underscores,
+            // those starting with an underscore are reserved
+            name => name
+                .trim_start_matches(|c: char| c == '_' || c.is_ascii_digit())
+                .chars()
+                .map(|c| match c {
+                    'a'..='z' | 'A'..='Z' | '0'..='9' => c.to_ascii_uppercase(),
+                    _ => '_',
+                })
+                .collect(),
+        };
+        if name.is_empty() {
+            continue;
+        }
+        // Lists such as the old values as `a, b` rather than `["a", "b"]`
+        match value.strip_prefix('[').and_then(|v| v.strip_suffix(']')) {
+            Some(list) => add(&name, &list.replace('"', "")),
+            None => add(&name, value),
+        }
+    }
+    entry
+}
+
+/// How lines are written, from `[log]` and `RUST_LOG`
+struct Format {
+    filter: Filter,
+    timestamps: Timestamps,
+    color: bool,
+}
+
+impl Format {
+    fn new(config: &LogConfig, log_http: bool) -> Self {
+        let mut filter = Filter {
+            default: config.level.map_or(LevelFilter::ERROR, to_level_filter),
+            targets: config
+                .levels
+                .iter()
+                .map(|(target, level)| (target.clone(), to_level_filter(*level)))
+                .collect(),
+        };
+        // Directives of RUST_LOG come later and win over those for the same target
+        filter.add(&std::env::var("RUST_LOG").unwrap_or_default());
+        if log_http
+            && !filter
+                .targets
+                .iter()
+                .any(|(target, _)| target == HTTP_TARGET)
+        {
+            filter
+                .targets
+                .push((HTTP_TARGET.to_string(), LevelFilter::INFO));
+        }
+        filter.targets.sort_by_key(|(target, _)| target.len());
+
+        let color = match config.color {
+            ColorMode::Auto => {
+                std::io::stderr().is_terminal() && std::env::var_os("NO_COLOR").is_none()
+            }
+            ColorMode::Always => true,
+            ColorMode::Never => false,
+        };
+        Self {
+            filter,
+            timestamps: config.timestamps,
+            color,
+        }
+    }
+
+    /// `rest` being the target, spans and message
+    fn line(&self, now: SystemTime, level: Level, rest: &str) -> String {
+        let timestamp = match self.timestamps {
+            Timestamps::Rfc3339 => format!("{} ", humantime::format_rfc3339_seconds(now)),
+            Timestamps::Rfc3339Millis => format!("{} ", humantime::format_rfc3339_millis(now)),
+            Timestamps::None => String::new(),
+        };
+        let color = match level {
+            _ if !self.color => "",
+            Level::ERROR => "\x1b[31m",
+            Level::WARN => "\x1b[33m",
+            Level::INFO => "\x1b[32m",
+            _ => "\x1b[2m",
+        };
+        let reset = if self.color { "\x1b[0m" } else { "" };
+        format!("{timestamp}{color}{level:<5}{reset} {rest}")
+    }
+}
+
+/// `RUST_LOG` style directives: a default level and levels per target prefix,
+/// the longest matching prefix wins
+struct Filter {
+    default: LevelFilter,
+    targets: Vec<(String, LevelFilter)>,
+}
+
+impl Filter {
+    /// Adds the directives of `spec`, the targets still need sorting
+    fn add(&mut self, spec: &str) {
+        for directive in spec.split(',').map(str::trim).filter(|d| !d.is_empty()) {
+            match directive.split_once('=') {
+                Some((target, level)) => {
+                    if let Ok(level) = LevelFilter::from_str(level) {
+                        self.targets.push((target.to_string(), level));
+                    }
+                }
+                None => match LevelFilter::from_str(directive) {
+                    Ok(level) => self.default = level,
+                    Err(_) => self
+                        .targets
+                        .push((directive.to_string(), LevelFilter::TRACE)),
+                },
+            }
+        }
+    }
+
+    fn enabled(&self, target: &str, level: Level) -> bool {
+        let filter = self
+            .targets
+            .iter()
+            .rev()
+            .find(|(prefix, _)| target.starts_with(prefix.as_str()))
+            .map_or(self.default, |(_, level)| *level);
+        level <= filter
+    }
+
+    fn max_level(&self) -> LevelFilter {
+        self.targets
+            .iter()
+            .map(|(_, level)| *level)
+            .fold(self.default, LevelFilter::max)
+    }
+}
+
+fn to_level_filter(level: LogLevel) -> LevelFilter {
+    match level {
+        LogLevel::Off => LevelFilter::OFF,
+        LogLevel::Error => LevelFilter::ERROR,
+        LogLevel::Warn => LevelFilter::WARN,
+        LogLevel::Info => LevelFilter::INFO,
+        LogLevel::Debug => LevelFilter::DEBUG,
+        LogLevel::Trace => LevelFilter::TRACE,
+    }
+}
+
+fn to_tracing_level(level: log::Level) -> Level {
+    match level {
+        log::Level::Error => Level::ERROR,
+        log::Level::Warn => Level::WARN,
+        log::Level::Info => Level::INFO,
+        log::Level::Debug => Level::DEBUG,
+        log::Level::Trace => Level::TRACE,
+    }
+}
+
+fn to_log_filter(level: LevelFilter) -> log::LevelFilter {
+    match level.into_level() {
+        None => log::LevelFilter::Off,
+        Some(Level::ERROR) => log::LevelFilter::Error,
+        Some(Level::WARN) => log::LevelFilter::Warn,
+        Some(Level::INFO) => log::LevelFilter::Info,
+        Some(Level::DEBUG) => log::LevelFilter::Debug,
+        Some(_) => log::LevelFilter::Trace,
+    }
+}
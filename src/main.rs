@@ -1,233 +1,1072 @@
-use config::{Config, ServiceConfig};
-use log::*;
-use reqwest::Client;
-use serde::{Deserialize, Serialize};
+use cli::{parse_prefix, Cli, Command, OutputFormat};
+use daemon::Status;
+use dynsix::{
+    check::{self, Check},
+    config::{self, Config, ConfigFormat},
+    gandi::GandiListResponse,
+    hook, idna, import,
+    notify::sentry,
+    plan::Plan,
+    provider::{display_name, ProviderKind},
+    reconcile::record_matches,
+    report::{Action, RunReport, EXIT_LOCKED, EXIT_PARTIAL_FAILURE, EXIT_TOTAL_FAILURE},
+    state::{self, ManagedRecord, State},
+    tlsa, ttl, version,
+    zone::{self, ZoneBackup},
+    DynsixError,
+};
+use runner::{
+    build_reconciler, fetch_vault_token, gandi_client, resolve_public_ip, services, Runner,
+};
 use std::{
-    net::{IpAddr, Ipv6Addr},
-    str::FromStr, fmt::Display,
+    io::Write, net::Ipv6Addr, os::unix::fs::OpenOptionsExt, path::Path, process::ExitCode,
+    str::FromStr, time::SystemTime,
 };
+use term::{table, Colors};
+use tracing::{info, warn};
 
-mod config;
+mod api;
+mod cli;
+mod completions;
+mod control;
+mod daemon;
+mod grpc;
+mod install;
+mod logging;
+mod privileges;
+mod runner;
+mod sandbox;
+mod term;
+mod tui;
 
-#[derive(Deserialize, Debug)]
-struct IpInfo {
-    ip: Ipv6Addr,
+fn main() -> ExitCode {
+    match start() {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("Error: {e}");
+            ExitCode::from(EXIT_TOTAL_FAILURE)
+        }
+    }
 }
 
-#[allow(dead_code)]
-#[derive(Deserialize, Debug)]
-struct GandiError {
-    object: String,
-    cause: String,
-    message: String,
-    code: u32,
+fn start() -> Result<ExitCode, Box<dyn std::error::Error>> {
+    let cli = Cli::parse()?;
+    logging::init(cli.log_http, cli.command == Command::Daemon);
+    if cli.log_http {
+        dynsix::http_log::enable();
+    }
+
+    // Landlock only confines threads started afterwards, so the daemon's
+    // config is loaded before the runtime starts its threads
+    let config = match cli.command {
+        Command::Daemon => {
+            let config = cli.load_config()?;
+            logging::configure(&config);
+            sentry::report_panics(config.notify.sentry.as_ref());
+            sandbox::restrict_paths(&config, &cli.config_path)?;
+            Some(config)
+        }
+        _ => None,
+    };
+
+    tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()?
+        .block_on(try_main(cli, config))
 }
 
-impl Display for GandiError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.write_str(&format!("[{}][{}] {}", self.code, self.object, self.message))
+/// `config` is the config if it was loaded already
+async fn try_main(
+    cli: Cli,
+    config: Option<Config>,
+) -> Result<ExitCode, Box<dyn std::error::Error>> {
+    match &cli.command {
+        Command::Help => {
+            println!("{}", cli::usage());
+            return Ok(ExitCode::SUCCESS);
+        }
+        Command::Version => {
+            println!("{}", version::long_version(env!("CARGO_PKG_NAME")));
+            return Ok(ExitCode::SUCCESS);
+        }
+        Command::Features => {
+            for (kind, names) in version::features() {
+                let names = if names.is_empty() {
+                    "none".to_string()
+                } else {
+                    names.join(", ")
+                };
+                println!("{kind}: {names}");
+            }
+            return Ok(ExitCode::SUCCESS);
+        }
+        Command::Completions(shell) => {
+            print!("{}", completions::script(*shell));
+            return Ok(ExitCode::SUCCESS);
+        }
+        Command::InstallSystemd { timer, write } => {
+            install::systemd(&cli.config_path, *timer, *write)?;
+            return Ok(ExitCode::SUCCESS);
+        }
+        Command::Import {
+            from,
+            path,
+            out,
+            force,
+        } => {
+            import_config(*from, path, out.as_deref(), *force)?;
+            return Ok(ExitCode::SUCCESS);
+        }
+        Command::ConfigInit {
+            path,
+            interactive,
+            force,
+        } => {
+            let path = path.as_ref().unwrap_or(&cli.config_path);
+            init(path, *interactive, *force)?;
+            return Ok(ExitCode::SUCCESS);
+        }
+        _ => {}
+    }
+    let mut config = match config {
+        Some(config) => config,
+        None => {
+            let config = cli.load_config()?;
+            logging::configure(&config);
+            sentry::report_panics(config.notify.sentry.as_ref());
+            config
+        }
+    };
+    for warning in &config.warnings {
+        eprintln!("warning: {warning}");
+    }
+    // `config validate` reports them as problems instead
+    if !matches!(cli.command, Command::ConfigValidate) {
+        for message in config.clamp_ttls() {
+            eprintln!("warning: {message}");
+        }
+    }
+    // Runs and the daemon fetch it themselves, again when it expires
+    if matches!(
+        cli.command,
+        Command::Plan { .. }
+            | Command::Apply { .. }
+            | Command::Whoami
+            | Command::Delete { .. }
+            | Command::Tlsa { .. }
+            | Command::Gc { .. }
+            | Command::List
+            | Command::Check { .. }
+            | Command::Backup { .. }
+            | Command::Restore { .. }
+    ) {
+        fetch_vault_token(&mut config).await?;
+    }
+
+    match cli.command {
+        Command::ConfigValidate => {
+            validate(&config)?;
+            Ok(ExitCode::SUCCESS)
+        }
+        Command::Plan { ref out } => {
+            cli.check_service_patterns(&config)?;
+            plan(config, &cli, out.as_deref()).await
+        }
+        Command::Apply { ref plan } => apply(config, &cli, plan).await,
+        Command::Whoami => whoami(config).await,
+        Command::Services => {
+            let mut names: Vec<_> = config.services.keys().collect();
+            names.sort();
+            for name in names {
+                println!("{name}");
+            }
+            Ok(ExitCode::SUCCESS)
+        }
+        Command::Delete { ref service, yes } => delete(config, service, yes).await,
+        Command::Gc { apply } => gc(config, apply).await,
+        Command::Status => status(&config, &cli).await,
+        Command::History => history(&config, &cli),
+        Command::Audit => audit(&config),
+        Command::Backup {
+            ref domain,
+            ref out,
+        } => backup(&config, domain, out.as_deref()).await,
+        Command::Restore {
+            ref backup,
+            ref domain,
+            yes,
+        } => restore(&config, backup, domain.as_deref(), yes).await,
+        Command::Tlsa {
+            ref cert,
+            ref name,
+            ref domain,
+        } => tlsa(&config, cert, name, domain.as_deref()).await,
+        Command::Ctl(ref command) => ctl(&config, command).await,
+        Command::Daemon => {
+            cli.check_service_patterns(&config)?;
+            daemon::run(config, &cli).await
+        }
+        Command::List => {
+            cli.check_service_patterns(&config)?;
+            list(config, &cli).await
+        }
+        Command::Tui => {
+            let socket = config
+                .daemon
+                .control_socket
+                .clone()
+                .ok_or("daemon.control_socket is not configured")?;
+            tui::run(socket).await?;
+            Ok(ExitCode::SUCCESS)
+        }
+        Command::Check { nagios } => {
+            cli.check_service_patterns(&config)?;
+            check(config, &cli, nagios).await
+        }
+        Command::Hook => {
+            cli.check_service_patterns(&config)?;
+            hook(config, &cli).await
+        }
+        _ => {
+            cli.check_service_patterns(&config)?;
+            run(config, &cli, cli.prefix).await
+        }
     }
 }
 
-#[derive(Serialize, Debug)]
-struct GandiRecordRequest {
-    rrset_values: Vec<String>,
-    rrset_ttl: u32,
+fn init(path: &Path, interactive: bool, force: bool) -> Result<(), Box<dyn std::error::Error>> {
+    if ConfigFormat::from_path(path).is_some_and(|format| format != ConfigFormat::Toml) {
+        return Err(format!(
+            "{} is not a TOML file, the example configuration is only written as TOML",
+            path.display()
+        )
+        .into());
+    }
+    if path.exists() && !force {
+        return Err(format!(
+            "{} already exists, use --force to overwrite it",
+            path.display()
+        )
+        .into());
+    }
+
+    let (token, fqdn, suffix) = if interactive {
+        (
+            prompt("Gandi token", "your gandi token")?,
+            prompt("Domain (fqdn)", "example.com")?,
+            prompt("Host suffix", "::1")?.parse::<Ipv6Addr>()?,
+        )
+    } else {
+        (
+            "your gandi token".to_string(),
+            "example.com".to_string(),
+            Ipv6Addr::from_str("::1:cee:bad:c0de")?,
+        )
+    };
+
+    let rendered = config::example(&token, &fqdn, &suffix);
+    toml::from_str::<Config>(&rendered)?;
+
+    write_config(path, &rendered)?;
+    println!("Wrote example configuration to {}", path.display());
+
+    Ok(())
 }
 
-#[allow(dead_code)]
-#[derive(Deserialize, Debug)]
-struct GandiRecordResponse {
-    rrset_values: Vec<String>,
-    rrset_ttl: u32,
+/// Writes a config, which holds tokens, so that only the owner may read it
+fn write_config(path: &Path, config: &str) -> Result<(), Box<dyn std::error::Error>> {
+    if let Some(parent) = path
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+    {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(path)?
+        .write_all(config.as_bytes())?;
+    Ok(())
 }
 
-#[allow(dead_code)]
-#[derive(Deserialize, Debug)]
-struct GandiMessage {
-    message: String,
+/// Converts the config of another updater
+fn import_config(
+    from: import::Format,
+    path: &Path,
+    out: Option<&Path>,
+    force: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let imported = from.import(path)?;
+    let rendered = imported.to_toml(&path.display().to_string());
+    toml::from_str::<Config>(&rendered)?;
+
+    match out {
+        Some(out) if out.exists() && !force => Err(format!(
+            "{} already exists, use --force to overwrite it",
+            out.display()
+        )
+        .into()),
+        Some(out) => {
+            write_config(out, &rendered)?;
+            info!(
+                "Wrote {} services converted from {} to {}",
+                imported.services.len(),
+                path.display(),
+                out.display()
+            );
+            Ok(())
+        }
+        None => {
+            print!("{rendered}");
+            Ok(())
+        }
+    }
 }
 
-#[derive(Deserialize, Debug)]
-#[serde(untagged)]
-enum GandiResponse {
-    Error(GandiError),
-    GandiRecordResponse(GandiRecordResponse),
-    Message(GandiMessage),
+fn prompt(question: &str, default: &str) -> std::io::Result<String> {
+    print!("{question} [{default}]: ");
+    std::io::stdout().flush()?;
+
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer)?;
+    let answer = answer.trim();
+
+    Ok(if answer.is_empty() { default } else { answer }.to_string())
 }
 
-#[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    // Setup
-    env_logger::init();
-    let config_path = std::env::args()
-        .nth(1)
-        .unwrap_or_else(|| "/etc/dynsix/config.toml".to_string());
-    let config = Config::load(config_path)?;
+fn confirm(question: &str) -> std::io::Result<bool> {
+    let answer = prompt(question, "y/N")?;
+    Ok(matches!(answer.to_lowercase().as_str(), "y" | "yes"))
+}
 
-    let client = Client::builder()
-        .local_address(IpAddr::from_str("::0").ok())
-        .build()?;
+fn validate(config: &Config) -> Result<(), Box<dyn std::error::Error>> {
+    let problems = config.validate();
+    if problems.is_empty() {
+        println!(
+            "Configuration is valid ({} services)",
+            config.services.len()
+        );
+        return Ok(());
+    }
+
+    for problem in &problems {
+        eprintln!("error: {problem}");
+    }
+    Err(DynsixError::InvalidConfig(problems).into())
+}
+
+async fn run(
+    config: Config,
+    cli: &Cli,
+    prefix: Option<Ipv6Addr>,
+) -> Result<ExitCode, Box<dyn std::error::Error>> {
+    let result = Runner::new(config)?
+        .wait_for_lock(cli.wait)
+        .run(|name| cli.selects(name), prefix)
+        .await;
+    let report = match result {
+        Err(e) if matches!(e.downcast_ref(), Some(DynsixError::Locked { .. })) => {
+            warn!("{e}, not running");
+            return Ok(ExitCode::from(EXIT_LOCKED));
+        }
+        result => result?,
+    };
+
+    if cli.output == OutputFormat::Json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    }
+
+    Ok(report.exit_code())
+}
+
+/// Runs for the event a pppd or dhcpcd hook script describes in its
+/// environment, with the prefix dhcpcd received unless `--prefix` is given
+async fn hook(config: Config, cli: &Cli) -> Result<ExitCode, Box<dyn std::error::Error>> {
+    let (interface, prefix) = match hook::event(|name| std::env::var(name).ok())? {
+        hook::Event::Up { interface, prefix } => (interface, prefix),
+        hook::Event::Ignored { interface, reason } => {
+            info!("Nothing to do for {reason} on {interface}");
+            return Ok(ExitCode::SUCCESS);
+        }
+    };
+    let prefix = match (cli.prefix, prefix) {
+        (Some(prefix), _) => Some(prefix),
+        (None, Some(prefix)) => Some(parse_prefix(&prefix)?),
+        (None, None) => None,
+    };
+    match prefix {
+        Some(prefix) => info!("{interface} is up with prefix {prefix}, reconciling"),
+        None => info!("{interface} is up, reconciling"),
+    }
+    run(config, cli, prefix).await
+}
 
-    // Resolve the public ip
-    let ip_info = get_ip(&client, &config.query_server)
+/// Sends a command to the control socket of a running daemon
+async fn ctl(config: &Config, command: &[String]) -> Result<ExitCode, Box<dyn std::error::Error>> {
+    let path = config
+        .daemon
+        .control_socket
+        .as_ref()
+        .ok_or("daemon.control_socket is not configured")?;
+    let response = control::send(path, &command.join(" "))
         .await
-        .expect("Failed to get public IP");
-    debug!("Got public ip: {}", ip_info.ip);
-
-    for (name, service) in config.services {
-        let service_ip = merge_ips(ip_info.ip, service.suffix);
-        debug!(
-            target: &format!("service-{name}"),
-            "Merged IP: {service_ip}"
-        );
+        .map_err(|e| format!("{}: {e}", path.display()))?;
 
-        match get_gandi_ip(&client, &config.token, &service.fqdn, &service.name).await? {
-            GandiResponse::Error(GandiError { code: 404, .. }) => {
-                debug!(
-                    target: &format!("service-{name}"),
-                    "No AAAA record found for {}.{}", service.fqdn, service.name
-                );
-                match set_gandi_record(&client, &config.token, &service, &service_ip).await? {
-                    GandiResponse::Error(e) => error!(
-                        target: &format!("service-{name}"),
-                        "Ran into an error while setting record: {e:?}"
-                    ),
-                    GandiResponse::Message(record) => info!(
-                        target: &format!("service-{name}"),
-                        "Successfully set AAAA record: {record:?}"
-                    ),
-                    _ => {}
-                }
+    println!("{}", serde_json::to_string_pretty(&response)?);
+    Ok(if response.get("error").is_some() {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    })
+}
+
+/// Prints the last result of every selected service, as a running daemon
+/// reports it on its control socket, otherwise as kept in `state_file`
+async fn status(config: &Config, cli: &Cli) -> Result<ExitCode, Box<dyn std::error::Error>> {
+    let from_daemon = match &config.daemon.control_socket {
+        // Not listening means the daemon is not running
+        Some(path) => match control::send(path, "status").await {
+            Ok(response) => match response.get("error").and_then(|e| e.as_str()) {
+                Some(error) => return Err(format!("{}: {error}", path.display()).into()),
+                None => Some(serde_json::from_value::<Status>(response)?),
+            },
+            Err(_) => None,
+        },
+        None => None,
+    };
+    let mut status = match from_daemon {
+        Some(status) => status,
+        None => {
+            let path = config.state_file.as_ref().ok_or(
+                "the daemon is not running on daemon.control_socket and state_file is not configured",
+            )?;
+            Status::from_state(&State::load(path)?)
+        }
+    };
+    status.services.retain(|name, _| cli.selects(name));
+    if cli.output == OutputFormat::Json {
+        println!("{}", serde_json::to_string_pretty(&status)?);
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    let colors = Colors::stdout();
+    let mut rows = vec![[
+        "SERVICE".to_string(),
+        "RECORD".to_string(),
+        "PUBLISHED".to_string(),
+        "CHANGED".to_string(),
+        "LAST RUN".to_string(),
+        "RESULT".to_string(),
+        "NEXT CHECK".to_string(),
+    ]];
+    for (name, service) in &status.services {
+        let action = serde_json::to_value(service.action)?;
+        let action = action.as_str().unwrap_or_default();
+        rows.push([
+            name.clone(),
+            service.record.clone(),
+            service
+                .published
+                .map_or_else(|| "-".to_string(), |address| address.to_string()),
+            service
+                .last_change
+                .clone()
+                .unwrap_or_else(|| "-".to_string()),
+            service.last_run.clone(),
+            match &service.error {
+                Some(error) => format!("{action}: {error}"),
+                None => action.to_string(),
+            },
+            service
+                .next_check
+                .clone()
+                .unwrap_or_else(|| "-".to_string()),
+        ]);
+    }
+
+    let services: Vec<_> = status.services.values().collect();
+    for (index, line) in table(&rows).iter().enumerate() {
+        if index > 0 && services[index - 1].action == Action::Failed {
+            println!("{}{line}{}", colors.red, colors.reset);
+        } else {
+            println!("{line}");
+        }
+    }
+    if status.totals.runs > 0 {
+        println!("\n{} runs: {}", status.totals.runs, status.totals);
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Prints how the published records differ from the desired state
+async fn plan(
+    config: Config,
+    cli: &Cli,
+    out: Option<&Path>,
+) -> Result<ExitCode, Box<dyn std::error::Error>> {
+    let mut reconciler = build_reconciler(&config)?;
+    let state = match &config.state_file {
+        Some(path) => State::load(path)?,
+        None => State::default(),
+    };
+    if config.prune {
+        reconciler = reconciler.with_pruning(state.managed.iter().cloned());
+    }
+    let public_ip = resolve_public_ip(&config, cli.prefix).await?;
+
+    let mut services = runner::services(&config).await?;
+    if services.values().any(|service| service.low_ttl.is_some()) {
+        ttl::lower(services.to_mut(), &state, SystemTime::now());
+    }
+    let plan = reconciler
+        .plan(&services, public_ip, |name| cli.selects(name))
+        .await;
+    match cli.output {
+        OutputFormat::Text => print!("{}", term::render_plan(&plan)),
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&plan)?),
+    }
+    if let Some(out) = out {
+        plan.save(out)?;
+        info!("Saved plan to {}", out.display());
+    }
+
+    Ok(
+        match (
+            plan.failed.is_empty(),
+            plan.changes.is_empty() && plan.deletions.is_empty(),
+        ) {
+            (true, _) => ExitCode::SUCCESS,
+            (false, false) => ExitCode::from(EXIT_PARTIAL_FAILURE),
+            (false, true) => ExitCode::from(EXIT_TOTAL_FAILURE),
+        },
+    )
+}
+
+/// Executes a plan previously written by `plan --out`
+async fn apply(
+    config: Config,
+    cli: &Cli,
+    plan_path: &Path,
+) -> Result<ExitCode, Box<dyn std::error::Error>> {
+    let reconciler = build_reconciler(&config)?;
+    let plan = Plan::load(plan_path)?;
+    let _lock = match &config.lock_file {
+        Some(path) => match runner::lock(path, cli.wait).await {
+            Ok(lock) => Some(lock),
+            Err(e @ DynsixError::Locked { .. }) => {
+                warn!("{e}, not applying");
+                return Ok(ExitCode::from(EXIT_LOCKED));
             }
-            GandiResponse::Error(e) => println!("{e:?}"),
-            GandiResponse::GandiRecordResponse(record) => {
-                info!(
-                    target: &format!("service-{name}"),
-                    "Found an existing AAAA record for {}.{}: {:?}",
-                    service.name,
-                    service.fqdn,
-                    record.rrset_values
-                );
-                if !Ipv6Addr::from_str(&record.rrset_values[0])
-                    .unwrap()
-                    .eq(&service_ip)
-                {
-                    debug!(target: &format!("service-{name}"), "Record differs");
-                    match update_gandi_record(&client, &config.token, &service, &service_ip).await?
-                    {
-                        GandiResponse::Error(e) => error!(
-                            target: &format!("service-{name}"),
-                            "Ran into an error while setting record: {e:?}"
-                        ),
-                        GandiResponse::Message(record) => info!(
-                            target: &format!("service-{name}"),
-                            "Successfully updated AAAA record: {record:?}"
-                        ),
-                        _ => {}
-                    }
-                } else {
-                    info!(
-                        target: &format!("service-{name}"),
-                        "Record was already set to the correct address"
-                    );
-                }
+            Err(e) => return Err(e.into()),
+        },
+        None => None,
+    };
+
+    let report = reconciler.apply(plan, |name| cli.selects(name)).await?;
+    let services = services(&config).await?;
+    runner::record_changes(&config, &report, &services)?;
+
+    if cli.output == OutputFormat::Json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    }
+
+    Ok(report.exit_code())
+}
+
+/// Compares the published records with the desired addresses without
+/// changing them, exiting with the plugin status of Nagios
+async fn check(
+    config: Config,
+    cli: &Cli,
+    nagios: bool,
+) -> Result<ExitCode, Box<dyn std::error::Error>> {
+    let checked = async {
+        let reconciler = build_reconciler(&config)?;
+        let public_ip = resolve_public_ip(&config, cli.prefix).await?;
+        let mut services = services(&config).await?.into_owned();
+        services.retain(|name, _| cli.selects(name));
+        Ok::<_, Box<dyn std::error::Error>>(Check::run(&reconciler, &services, public_ip).await)
+    };
+    let check = match checked.await {
+        Ok(check) => check,
+        Err(e) if nagios => {
+            println!("DYNSIX {} - {e}", check::Status::Unknown);
+            return Ok(ExitCode::from(check::Status::Unknown as u8));
+        }
+        Err(e) => return Err(e),
+    };
+
+    if nagios {
+        println!("{}", check.nagios());
+    } else {
+        let colors = Colors::stdout();
+        let mut rows = vec![[
+            "SERVICE".to_string(),
+            "RECORD".to_string(),
+            "DESIRED".to_string(),
+            "RESULT".to_string(),
+        ]];
+        for record in &check.records {
+            rows.push([
+                record.service.clone(),
+                record.record.clone(),
+                record.desired.to_string(),
+                match &record.outcome {
+                    check::Outcome::UpToDate => "up to date".to_string(),
+                    check::Outcome::Wrong(values) => format!("holds {}", values.join(",")),
+                    check::Outcome::Missing => "missing".to_string(),
+                    check::Outcome::Unchecked(reason) => reason.clone(),
+                },
+            ]);
+        }
+        for (index, line) in table(&rows).iter().enumerate() {
+            if index > 0 && check.records[index - 1].outcome != check::Outcome::UpToDate {
+                println!("{}{line}{}", colors.red, colors.reset);
+            } else {
+                println!("{line}");
             }
-            _ => {}
         }
     }
+    Ok(ExitCode::from(check.status() as u8))
+}
 
-    Ok(())
+/// Prints the published records of all selected services next to the value
+/// dynsix would publish
+async fn list(config: Config, cli: &Cli) -> Result<ExitCode, Box<dyn std::error::Error>> {
+    let reconciler = build_reconciler(&config)?;
+    let public_ip = resolve_public_ip(&config, cli.prefix).await?;
+    let colors = Colors::stdout();
+
+    let mut names: Vec<_> = config
+        .services
+        .keys()
+        .filter(|name| cli.selects(name))
+        .collect();
+    names.sort();
+
+    let mut rows = vec![[
+        "SERVICE".to_string(),
+        "FQDN".to_string(),
+        "NAME".to_string(),
+        "TYPE".to_string(),
+        "TTL".to_string(),
+        "VALUES".to_string(),
+        "DESIRED".to_string(),
+    ]];
+    let mut mismatches = Vec::new();
+    for name in names {
+        let service = &config.services[name];
+        let desired = service
+            .address(name, public_ip)
+            .map_err(|e| format!("service '{name}': {e}"))?;
+        for record_name in service.record_names() {
+            let record = reconciler
+                .fetch_record(name, service.provider, &service.fqdn, record_name)
+                .await
+                .map_err(|e| format!("service '{name}': {e}"))?;
+
+            let (ttl, values, matches) = match record {
+                Some(record) => (
+                    record.ttl.map_or("-".to_string(), |ttl| ttl.to_string()),
+                    record.values.join(","),
+                    record_matches(&record.values, &desired, service.compare),
+                ),
+                None => ("-".to_string(), "-".to_string(), false),
+            };
+            mismatches.push(!matches);
+            rows.push([
+                name.clone(),
+                service.fqdn.clone(),
+                record_name.to_string(),
+                "AAAA".to_string(),
+                ttl,
+                values,
+                desired.to_string(),
+            ]);
+        }
+    }
+
+    for (index, line) in table(&rows).iter().enumerate() {
+        if index > 0 && mismatches[index - 1] {
+            println!("{}{line}{}", colors.red, colors.reset);
+        } else {
+            println!("{line}");
+        }
+    }
+
+    Ok(ExitCode::SUCCESS)
 }
 
-fn merge_ips(prefix: Ipv6Addr, suffix: Ipv6Addr) -> Ipv6Addr {
-    let prefix_segments = prefix.segments();
-    let suffix_segments = suffix.segments();
+/// Deletes the AAAA records of a service after confirmation
+async fn delete(
+    config: Config,
+    service_name: &str,
+    yes: bool,
+) -> Result<ExitCode, Box<dyn std::error::Error>> {
+    let service = config
+        .services
+        .get(service_name)
+        .ok_or_else(|| format!("no service named '{service_name}'"))?;
+    let records: Vec<_> = service
+        .record_names()
+        .into_iter()
+        .map(|name| (name, display_name(&service.fqdn, name)))
+        .collect();
+    let listed = records
+        .iter()
+        .map(|(_, record)| record.as_str())
+        .collect::<Vec<_>>()
+        .join(", ");
 
-    Ipv6Addr::new(
-        prefix_segments[0],
-        prefix_segments[1],
-        prefix_segments[2],
-        prefix_segments[3],
-        suffix_segments[4],
-        suffix_segments[5],
-        suffix_segments[6],
-        suffix_segments[7],
-    )
+    if !yes && !confirm(&format!("Delete the AAAA record of {listed}?"))? {
+        println!("Aborted");
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    let reconciler = build_reconciler(&config)?;
+    let mut report = RunReport::new(Ipv6Addr::UNSPECIFIED);
+    let mut result = Ok(());
+    for (name, record) in records {
+        result = reconciler
+            .delete_record(service_name, service.provider, &service.fqdn, name)
+            .await;
+        if result.is_err() {
+            break;
+        }
+        info!(service = %service_name, "Deleted AAAA record {record}");
+        report.deleted.push(ManagedRecord {
+            provider: service.provider,
+            fqdn: service.fqdn.clone(),
+            name: name.to_string(),
+            service: service_name.to_string(),
+            address: None,
+        });
+    }
+    report.finish();
+    runner::record_changes(&config, &report, &config.services)?;
+    result?;
+    Ok(ExitCode::SUCCESS)
 }
 
-async fn set_gandi_record(
-    client: &Client,
-    token: &str,
-    service: &ServiceConfig,
-    ip: &Ipv6Addr,
-) -> Result<GandiResponse, reqwest::Error> {
-    debug!("Fetching public ip");
-    client
-        .post(format!(
-            "https://api.gandi.net/v5/livedns/domains/{}/records/{}/AAAA",
-            service.fqdn, service.name
-        ))
-        .header("Accept", "application/json")
-        .header("Authorization", format!("ApiKey {}", token))
-        .json(&GandiRecordRequest {
-            rrset_values: vec![ip.to_string()],
-            rrset_ttl: service.ttl,
-        })
-        .send()
-        .await?
-        .json()
-        .await
+/// Saves all records of a Gandi domain
+async fn backup(
+    config: &Config,
+    domain: &str,
+    out: Option<&Path>,
+) -> Result<ExitCode, Box<dyn std::error::Error>> {
+    let backup = ZoneBackup::fetch(&gandi_client(config)?, domain).await?;
+    match out {
+        Some(out) => {
+            backup.save(out)?;
+            info!(
+                "Saved {} records of {domain} to {}",
+                backup.records.len(),
+                out.display()
+            );
+        }
+        None => println!("{}", serde_json::to_string_pretty(&backup)?),
+    }
+    Ok(ExitCode::SUCCESS)
 }
 
-async fn update_gandi_record(
-    client: &Client,
-    token: &str,
-    service: &ServiceConfig,
-    ip: &Ipv6Addr,
-) -> Result<GandiResponse, reqwest::Error> {
-    client
-        .put(format!(
-            "https://api.gandi.net/v5/livedns/domains/{}/records/{}/AAAA",
-            service.fqdn, service.name
-        ))
-        .header("Accept", "application/json")
-        .header("Authorization", format!("ApiKey {}", token))
-        .json(&GandiRecordRequest {
-            rrset_values: vec![ip.to_string()],
-            rrset_ttl: service.ttl,
-        })
-        .send()
-        .await?
-        .json()
-        .await
+/// Replaces all records of a Gandi domain with those of a backup
+async fn restore(
+    config: &Config,
+    path: &Path,
+    domain: Option<&str>,
+    yes: bool,
+) -> Result<ExitCode, Box<dyn std::error::Error>> {
+    let backup = ZoneBackup::load(path)?;
+    let domain = domain.unwrap_or(&backup.domain);
+    let client = gandi_client(config)?;
+    let current = zone::records(&client, domain).await?;
+
+    let question = format!(
+        "Replace the {} records of {domain} with the {} records from {} of {}?",
+        current.len(),
+        backup.records.len(),
+        backup.domain,
+        backup.created
+    );
+    if !yes && !confirm(&question)? {
+        println!("Aborted");
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    backup.restore(&client, domain).await?;
+    info!("Restored {} records of {domain}", backup.records.len());
+    let services = services(config).await?;
+    let report = zone::restore_report(domain, &current, &backup.records, &services);
+    runner::record_changes(config, &report, &services)?;
+    Ok(ExitCode::SUCCESS)
 }
 
-async fn get_gandi_ip(
-    client: &Client,
-    token: &str,
-    fqdn: &str,
+/// Publishes the TLSA record of a certificate through the provider and with
+/// the TTL of the first service in its domain, or with the top level Gandi
+/// token
+async fn tlsa(
+    config: &Config,
+    cert: &Path,
     name: &str,
-) -> Result<GandiResponse, reqwest::Error> {
-    client
-        .get(format!(
-            "https://api.gandi.net/v5/livedns/domains/{}/records/{}/AAAA",
-            fqdn, name
-        ))
-        .header("Accept", "application/json")
-        .header("Authorization", format!("ApiKey {}", token))
-        .send()
-        .await?
-        .json()
-        .await
+    domain: Option<&str>,
+) -> Result<ExitCode, Box<dyn std::error::Error>> {
+    let pem = std::fs::read(cert).map_err(|e| format!("{}: {e}", cert.display()))?;
+    let value = tlsa::value(&pem).map_err(|e| format!("{}: {e}", cert.display()))?;
+
+    let same = |a: &str, b: &str| {
+        a.trim_end_matches('.')
+            .eq_ignore_ascii_case(b.trim_end_matches('.'))
+    };
+    let (domain, name) = match domain {
+        Some(domain) => (domain.to_string(), name.to_string()),
+        None => config
+            .services
+            .values()
+            .filter_map(|service| {
+                let fqdn = service.fqdn.trim_end_matches('.');
+                let prefix = name.trim_end_matches('.').strip_suffix(fqdn)?;
+                prefix
+                    .strip_suffix('.')
+                    .map(|name| (fqdn.to_string(), name.to_string()))
+            })
+            .max_by_key(|(fqdn, _)| fqdn.len())
+            .ok_or_else(|| {
+                format!("{name} is in the domain of no service, give the domain with --domain")
+            })?,
+    };
+    let mut services: Vec<_> = config
+        .services
+        .iter()
+        .filter(|(_, service)| same(&service.fqdn, &domain))
+        .collect();
+    services.sort_by_key(|(service, _)| *service);
+    let (service, provider, ttl) = services.first().map_or(
+        ("", ProviderKind::Gandi, tlsa::DEFAULT_TTL),
+        |(service, config)| (service.as_str(), config.provider, config.ttl),
+    );
+    if !provider.any_record_type() {
+        return Err(format!("{provider} can't publish TLSA records, only AAAA records").into());
+    }
+
+    let record = display_name(&domain, &name);
+    let changed = build_reconciler(config)?
+        .publish_records(
+            service,
+            provider,
+            &domain,
+            &name,
+            tlsa::RECORD_TYPE,
+            ttl,
+            std::slice::from_ref(&value),
+        )
+        .await?;
+    if changed {
+        info!("Published TLSA record {record}: {value}");
+    } else {
+        info!("TLSA record {record} is up to date");
+    }
+    Ok(ExitCode::SUCCESS)
 }
 
-async fn get_ip(client: &Client, ip_query_server: &str) -> Result<IpInfo, reqwest::Error> {
-    client
-        .get(ip_query_server)
-        .header("Accept", "application/json")
-        .send()
-        .await?
-        .json::<IpInfo>()
-        .await
+/// Checks the hash chain of the audit log and its rotated files
+fn audit(config: &Config) -> Result<ExitCode, Box<dyn std::error::Error>> {
+    let path = config
+        .audit
+        .path
+        .as_ref()
+        .ok_or("audit.path is not configured")?;
+    let entries = dynsix::audit::verify(&config.audit)?;
+    println!(
+        "{entries} entries of {} and its rotated files are intact",
+        path.display()
+    );
+    Ok(ExitCode::SUCCESS)
+}
+
+#[cfg(not(feature = "history"))]
+fn history(_config: &Config, _cli: &Cli) -> Result<ExitCode, Box<dyn std::error::Error>> {
+    Err("dynsix was built without the history feature".into())
+}
+
+/// Prints the changes kept in `history_file` for the selected services
+#[cfg(feature = "history")]
+fn history(config: &Config, cli: &Cli) -> Result<ExitCode, Box<dyn std::error::Error>> {
+    let path = config
+        .history_file
+        .as_ref()
+        .ok_or("history_file is not configured")?;
+    let changes: Vec<dynsix::history::Change> = dynsix::history::History::open(path)?
+        .changes()?
+        .into_iter()
+        .filter(|change| cli.selects(&change.service))
+        .collect();
+    if cli.output == OutputFormat::Json {
+        println!("{}", serde_json::to_string_pretty(&changes)?);
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    let colors = Colors::stdout();
+    let mut rows = vec![[
+        "TIME".to_string(),
+        "SERVICE".to_string(),
+        "RECORD".to_string(),
+        "OLD".to_string(),
+        "NEW".to_string(),
+        "OUTCOME".to_string(),
+        "LATENCY".to_string(),
+    ]];
+    for change in &changes {
+        let time = std::time::UNIX_EPOCH + std::time::Duration::from_secs(change.time);
+        rows.push([
+            humantime::format_rfc3339_seconds(time).to_string(),
+            change.service.clone(),
+            change.record.clone(),
+            change.old.clone().unwrap_or_else(|| "-".to_string()),
+            change.new.clone(),
+            match &change.error {
+                Some(error) => format!("{}: {error}", change.outcome),
+                None => change.outcome.clone(),
+            },
+            format!("{}ms", change.latency_ms),
+        ]);
+    }
+
+    for (index, line) in table(&rows).iter().enumerate() {
+        if index > 0 && changes[index - 1].outcome == "failed" {
+            println!("{}{line}{}", colors.red, colors.reset);
+        } else {
+            println!("{line}");
+        }
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Lists the records dynsix created or updated for services that are gone
+/// from the config, and deletes them with `apply`
+async fn gc(config: Config, apply: bool) -> Result<ExitCode, Box<dyn std::error::Error>> {
+    let path = config
+        .state_file
+        .as_ref()
+        .ok_or("gc needs state_file, where dynsix keeps track of the records it created")?;
+    let _lock = match &config.lock_file {
+        Some(path) => Some(runner::lock(path, false).await?),
+        None => None,
+    };
+    let mut state = State::load(path)?;
+    let services = services(&config).await?;
+    let orphans = state::orphans(&state.managed, &services);
+    if orphans.is_empty() {
+        println!("No records of removed services");
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    let reconciler = build_reconciler(&config)?;
+    let colors = Colors::stdout();
+    let mut existing = Vec::new();
+    for record in &orphans {
+        let current = reconciler
+            .fetch_record(&record.service, record.provider, &record.fqdn, &record.name)
+            .await
+            .map_err(|e| format!("{}: {e}", record.display_name()))?;
+        let origin = format!("service '{}', {}", record.service, record.provider.name());
+        match current {
+            Some(current) => {
+                println!(
+                    "{}- {} AAAA {} ({origin}){}",
+                    colors.red,
+                    record.display_name(),
+                    current.values.join(","),
+                    colors.reset
+                );
+                existing.push(true);
+            }
+            None => {
+                println!("  {} is gone already ({origin})", record.display_name());
+                existing.push(false);
+            }
+        }
+    }
+    if !apply {
+        println!("\nRun gc --apply to delete them");
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    let mut report = RunReport::new(Ipv6Addr::UNSPECIFIED);
+    let mut result = Ok(());
+    for (record, exists) in orphans.into_iter().zip(existing) {
+        if !exists {
+            state.managed.remove(&record);
+            continue;
+        }
+        result = reconciler
+            .delete_record(&record.service, record.provider, &record.fqdn, &record.name)
+            .await;
+        if result.is_err() {
+            break;
+        }
+        info!(service = %record.service, "Deleted AAAA record {}", record.display_name());
+        report.deleted.push(record);
+    }
+    state.save(path)?;
+    report.finish();
+    runner::record_changes(&config, &report, &services)?;
+    result?;
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Checks the top level token against the Gandi API and lists what it has
+/// access to
+async fn whoami(config: Config) -> Result<ExitCode, Box<dyn std::error::Error>> {
+    let client = gandi_client(&config)?;
+
+    match client.list_organizations().await? {
+        GandiListResponse::Error(e) => {
+            return Err(DynsixError::gandi("listing organizations", None, e).into())
+        }
+        GandiListResponse::List(organizations) => {
+            println!("Organizations:");
+            for organization in organizations {
+                println!(
+                    "  {} ({}, {})",
+                    organization.name, organization.kind, organization.id
+                );
+            }
+        }
+    }
+
+    let domains = match client.list_domains().await? {
+        GandiListResponse::Error(e) => {
+            return Err(DynsixError::gandi("listing domains", None, e).into())
+        }
+        GandiListResponse::List(domains) => domains,
+    };
+    println!("LiveDNS domains:");
+    for domain in &domains {
+        println!("  {}", idna::to_unicode(&domain.fqdn));
+    }
+
+    let mut unreachable: Vec<_> = config
+        .services
+        .values()
+        .filter(|service| service.provider == ProviderKind::Gandi && service.token_ref.is_none())
+        .map(|service| service.fqdn.as_str())
+        .filter(|fqdn| {
+            !domains
+                .iter()
+                .any(|domain| idna::same_domain(fqdn, &domain.fqdn))
+        })
+        .collect();
+    unreachable.sort();
+    unreachable.dedup();
+    for fqdn in &unreachable {
+        warn!("Configured domain {fqdn} is not accessible with this token");
+    }
+
+    Ok(if unreachable.is_empty() {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::from(EXIT_PARTIAL_FAILURE)
+    })
 }
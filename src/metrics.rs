@@ -0,0 +1,18 @@
+//! Metrics about runs for monitoring systems
+
+use std::path::PathBuf;
+
+use serde::Deserialize;
+
+pub mod statsd;
+pub mod textfile;
+
+/// The `[metrics]` section of the config
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct MetricsConfig {
+    /// Prometheus text file written after every run, e.g. into the directory of
+    /// node_exporter's textfile collector
+    pub textfile: Option<PathBuf>,
+    pub statsd: Option<statsd::StatsdConfig>,
+}
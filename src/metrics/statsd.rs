@@ -0,0 +1,171 @@
+//! Counters and timers sent to a StatsD server over UDP. With the DogStatsD
+//! flavor service and outcome are tags, plain StatsD has them in the name:
+//!
+//! ```text
+//! dynsix.service.reconciled:1|c|#service:www,outcome:updated
+//! dynsix.service.www.updated.reconciled:1|c
+//! ```
+
+use serde::Deserialize;
+use tokio::net::UdpSocket;
+
+use crate::{
+    report::{Action, RunReport},
+    state::State,
+    DynsixError,
+};
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Flavor {
+    Statsd,
+    #[default]
+    Dogstatsd,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct StatsdConfig {
+    /// `host:port` of the server, usually port 8125
+    pub address: String,
+    #[serde(default = "default_prefix")]
+    pub prefix: String,
+    #[serde(default)]
+    pub flavor: Flavor,
+}
+
+#[derive(Debug, Clone)]
+pub struct Statsd {
+    config: StatsdConfig,
+}
+
+impl Statsd {
+    pub fn new(config: &StatsdConfig) -> Self {
+        Self {
+            config: config.clone(),
+        }
+    }
+
+    /// Sends the metrics of a run, `result` being the report or the class of
+    /// the error the run failed with. `state` already accounts for the run.
+    pub async fn send(
+        &self,
+        result: Result<&RunReport, &str>,
+        state: &State,
+    ) -> Result<(), DynsixError> {
+        self.send_lines(&self.lines(result, state))
+            .await
+            .map_err(|source| DynsixError::Statsd {
+                address: self.config.address.clone(),
+                source,
+            })
+    }
+
+    fn lines(&self, result: Result<&RunReport, &str>, state: &State) -> Vec<String> {
+        let outcome = match result {
+            Err(_) => "error",
+            Ok(report) if report.failures().next().is_some() => "failed",
+            Ok(_) => "success",
+        };
+
+        let mut lines = vec![self.metric("run.count", &[("outcome", outcome)], "1|c")];
+        let report = match result {
+            Ok(report) => report,
+            Err(class) => {
+                lines.push(self.metric("run.errors", &[("class", class)], "1|c"));
+                return lines;
+            }
+        };
+
+        lines.push(self.metric(
+            "run.duration",
+            &[("outcome", outcome)],
+            &format!("{}|ms", report.duration_ms),
+        ));
+        for service in &report.services {
+            let outcome = match service.action {
+                Action::Unchanged => "unchanged",
+                Action::Created => "created",
+                Action::Updated => "updated",
+                Action::Deferred => "deferred",
+                Action::Skipped => "skipped",
+                Action::Failed => "failed",
+            };
+            let tags = [("service", service.service.as_str()), ("outcome", outcome)];
+            lines.push(self.metric("service.reconciled", &tags, "1|c"));
+            lines.push(self.metric(
+                "service.duration",
+                &tags,
+                &format!("{}|ms", service.duration_ms),
+            ));
+            if let Some(class) = service.error_class {
+                lines.push(self.metric("run.errors", &[("class", class)], "1|c"));
+            }
+
+            let tags = [("service", service.service.as_str())];
+            for (name, times) in [
+                ("service.last_success", &state.last_succeeded),
+                ("service.last_change", &state.last_changed),
+            ] {
+                if let Some(time) = times.get(&service.service) {
+                    lines.push(self.metric(name, &tags, &format!("{time}|g")));
+                }
+            }
+        }
+
+        lines
+    }
+
+    fn metric(&self, name: &str, tags: &[(&str, &str)], value: &str) -> String {
+        let prefix = self.config.prefix.trim_end_matches('.');
+        match self.config.flavor {
+            Flavor::Dogstatsd => {
+                let tags: Vec<_> = tags
+                    .iter()
+                    .map(|(key, value)| format!("{key}:{}", sanitize(value)))
+                    .collect();
+                format!("{prefix}.{name}:{value}|#{}", tags.join(","))
+            }
+            Flavor::Statsd => {
+                // run.count with outcome success becomes run.success.count
+                let (group, metric) = name.split_once('.').unwrap_or(("", name));
+                let path: Vec<_> = tags.iter().map(|(_, value)| sanitize(value)).collect();
+                format!("{prefix}.{group}.{}.{metric}:{value}", path.join("."))
+            }
+        }
+    }
+
+    async fn send_lines(&self, lines: &[String]) -> std::io::Result<()> {
+        let target = tokio::net::lookup_host(&self.config.address)
+            .await?
+            .next()
+            .ok_or_else(|| {
+                std::io::Error::new(std::io::ErrorKind::NotFound, "address did not resolve")
+            })?;
+        let local = if target.is_ipv6() {
+            "[::]:0"
+        } else {
+            "0.0.0.0:0"
+        };
+        let socket = UdpSocket::bind(local).await?;
+        for line in lines {
+            socket.send_to(line.as_bytes(), target).await?;
+        }
+        Ok(())
+    }
+}
+
+/// Characters with a meaning in the line protocol are replaced
+fn sanitize(value: &str) -> String {
+    value
+        .chars()
+        .map(|c| match c {
+            ':' | '|' | ',' | '#' | '@' | '.' | ' ' => '_',
+            c => c,
+        })
+        .collect()
+}
+
+fn default_prefix() -> String {
+    "dynsix".to_string()
+}
@@ -0,0 +1,233 @@
+//! Metrics in the Prometheus text format, written to a file that is picked up
+//! by node_exporter's textfile collector
+
+use std::{
+    collections::HashMap,
+    fmt::Write as _,
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    report::{Action, RunReport},
+    state::State,
+    DynsixError,
+};
+
+/// Renders the outcome of a run in the Prometheus text format. `report` is
+/// `None` if the run failed as a whole, `state` already accounts for the run.
+pub fn render(report: Option<&RunReport>, state: &State) -> String {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let succeeded = report.is_some_and(|report| report.failures().next().is_none());
+
+    let mut out = String::new();
+    gauge(
+        &mut out,
+        "dynsix_last_run_timestamp_seconds",
+        "Unix time of the last run",
+        &[(String::new(), now.to_string())],
+    );
+    gauge(
+        &mut out,
+        "dynsix_last_run_success",
+        "Whether every service was reconciled in the last run",
+        &[(String::new(), u8::from(succeeded).to_string())],
+    );
+    gauge(
+        &mut out,
+        "dynsix_consecutive_failed_runs",
+        "Runs in a row in which something failed",
+        &[(String::new(), state.consecutive_failures.to_string())],
+    );
+
+    let totals = &state.totals;
+    counter(
+        &mut out,
+        "dynsix_runs_total",
+        "Runs so far, counted across restarts with state_file",
+        &[(String::new(), totals.runs.to_string())],
+    );
+    let actions: Vec<_> = [
+        ("unchanged", totals.unchanged),
+        ("created", totals.created),
+        ("updated", totals.updated),
+        ("deferred", totals.deferred),
+        ("skipped", totals.skipped),
+        ("failed", totals.failed),
+    ]
+    .into_iter()
+    .map(|(action, count)| (format!("{{action=\"{action}\"}}"), count.to_string()))
+    .collect();
+    counter(
+        &mut out,
+        "dynsix_services_total",
+        "Services reconciled so far, by outcome",
+        &actions,
+    );
+    counter(
+        &mut out,
+        "dynsix_api_calls_total",
+        "Requests for records made to providers so far",
+        &[(String::new(), totals.api_calls.to_string())],
+    );
+    let errors: Vec<_> = state
+        .errors
+        .iter()
+        .map(|(class, count)| {
+            (
+                format!("{{class=\"{}\"}}", escape(class)),
+                count.to_string(),
+            )
+        })
+        .collect();
+    counter(
+        &mut out,
+        "dynsix_errors_total",
+        "Errors of services and failed runs so far, by class",
+        &errors,
+    );
+
+    // From the state, so that they stay put while runs fail as a whole
+    gauge(
+        &mut out,
+        "dynsix_last_success_timestamp_seconds",
+        "Unix time at which the record of the service was last verified or set",
+        &by_service(&state.last_succeeded),
+    );
+    gauge(
+        &mut out,
+        "dynsix_last_change_timestamp_seconds",
+        "Unix time at which the record of the service was last created or updated",
+        &by_service(&state.last_changed),
+    );
+
+    let Some(report) = report else {
+        return out;
+    };
+
+    gauge(
+        &mut out,
+        "dynsix_last_run_duration_seconds",
+        "Duration of the last run",
+        &[(String::new(), seconds(report.duration_ms))],
+    );
+
+    let labels = |service: &str, record: &str| {
+        format!(
+            "{{service=\"{}\",record=\"{}\"}}",
+            escape(service),
+            escape(record)
+        )
+    };
+    let success: Vec<_> = report
+        .services
+        .iter()
+        .map(|service| {
+            (
+                labels(&service.service, &service.record),
+                u8::from(service.action != Action::Failed).to_string(),
+            )
+        })
+        .collect();
+    gauge(
+        &mut out,
+        "dynsix_service_success",
+        "Whether the record of the service was reconciled in the last run",
+        &success,
+    );
+
+    let durations: Vec<_> = report
+        .services
+        .iter()
+        .map(|service| {
+            (
+                labels(&service.service, &service.record),
+                seconds(service.duration_ms),
+            )
+        })
+        .collect();
+    gauge(
+        &mut out,
+        "dynsix_service_duration_seconds",
+        "Time spent reconciling the service in the last run",
+        &durations,
+    );
+
+    let last_changed: Vec<_> = report
+        .services
+        .iter()
+        .filter_map(|service| {
+            let changed = state.last_changed.get(&service.service)?;
+            Some((
+                labels(&service.service, &service.record),
+                changed.to_string(),
+            ))
+        })
+        .collect();
+    gauge(
+        &mut out,
+        "dynsix_service_last_change_timestamp_seconds",
+        "Unix time at which the record was last created or updated",
+        &last_changed,
+    );
+
+    out
+}
+
+/// Samples labeled with the service, sorted by it
+fn by_service(times: &HashMap<String, u64>) -> Vec<(String, String)> {
+    let mut samples: Vec<_> = times
+        .iter()
+        .map(|(service, time)| {
+            (
+                format!("{{service=\"{}\"}}", escape(service)),
+                time.to_string(),
+            )
+        })
+        .collect();
+    samples.sort();
+    samples
+}
+
+/// Replaces `path` with the rendered metrics by renaming a temporary file, so
+/// the collector never reads a partial file
+pub fn write(path: &Path, report: Option<&RunReport>, state: &State) -> Result<(), DynsixError> {
+    // node_exporter only picks up *.prom, the temporary file must not match
+    let temporary = path.with_extension("prom.tmp");
+    std::fs::write(&temporary, render(report, state))
+        .map_err(|e| DynsixError::io(&temporary, e))?;
+    std::fs::rename(&temporary, path).map_err(|e| DynsixError::io(path, e))
+}
+
+fn gauge(out: &mut String, name: &str, help: &str, samples: &[(String, String)]) {
+    metric(out, "gauge", name, help, samples);
+}
+
+fn counter(out: &mut String, name: &str, help: &str, samples: &[(String, String)]) {
+    metric(out, "counter", name, help, samples);
+}
+
+fn metric(out: &mut String, kind: &str, name: &str, help: &str, samples: &[(String, String)]) {
+    if samples.is_empty() {
+        return;
+    }
+    let _ = writeln!(out, "# HELP {name} {help}");
+    let _ = writeln!(out, "# TYPE {name} {kind}");
+    for (labels, value) in samples {
+        let _ = writeln!(out, "{name}{labels} {value}");
+    }
+}
+
+fn seconds(millis: u128) -> String {
+    format!("{:.3}", millis as f64 / 1000.0)
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
@@ -0,0 +1,467 @@
+//! Telling the outside world how runs went. A run produces [`Event`]s which
+//! are routed to every configured [`Notifier`] that subscribed to their
+//! [`EventKind`], either through its `events` option or its defaults.
+
+use std::{fmt::Debug, future::Future, net::Ipv6Addr, pin::Pin, sync::Arc};
+
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tracing::warn;
+
+use crate::{
+    drift::Drift,
+    merge_ips,
+    report::{Action, RunReport},
+    state::{address_changed, State},
+    DynsixError,
+};
+
+pub mod discord;
+pub mod email;
+pub mod healthchecks;
+pub mod matrix;
+pub mod mqtt;
+pub mod ntfy;
+pub mod sentry;
+pub mod slack;
+pub mod telegram;
+
+/// The `[notify]` section of the config
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct NotifyConfig {
+    pub discord: Option<discord::DiscordConfig>,
+    pub email: Option<email::EmailConfig>,
+    pub healthchecks: Option<healthchecks::HealthchecksConfig>,
+    pub matrix: Option<matrix::MatrixConfig>,
+    pub mqtt: Option<mqtt::MqttConfig>,
+    pub ntfy: Option<ntfy::NtfyConfig>,
+    pub sentry: Option<sentry::SentryConfig>,
+    pub slack: Option<slack::SlackConfig>,
+    pub telegram: Option<telegram::TelegramConfig>,
+}
+
+impl NotifyConfig {
+    pub(crate) fn validate(&self, problems: &mut Vec<String>) {
+        if let Some(discord) = &self.discord {
+            check_url("notify.discord.webhook_url", &discord.webhook_url, problems);
+        }
+        if let Some(email) = &self.email {
+            if email.to.is_empty() {
+                problems.push("notify.email.to has no recipients".to_string());
+            }
+            if email.after_failures == 0 {
+                problems.push("notify.email.after_failures must be at least 1".to_string());
+            }
+        }
+        if let Some(healthchecks) = &self.healthchecks {
+            check_url("notify.healthchecks.url", &healthchecks.url, problems);
+        }
+        if let Some(matrix) = &self.matrix {
+            check_url("notify.matrix.homeserver", &matrix.homeserver, problems);
+            if !matrix.room_id.starts_with('!') {
+                problems.push(format!(
+                    "notify.matrix.room_id '{}' is not a room id like !abcdef:example.org",
+                    matrix.room_id
+                ));
+            }
+        }
+        if let Some(ntfy) = &self.ntfy {
+            check_url("notify.ntfy.server", &ntfy.server, problems);
+            if ntfy.topic.is_empty() {
+                problems.push("notify.ntfy.topic is empty".to_string());
+            }
+            if let Some(priority) = &ntfy.priority {
+                if !ntfy::PRIORITIES.contains(&priority.as_str()) {
+                    problems.push(format!(
+                        "notify.ntfy.priority '{priority}' is not one of {}",
+                        ntfy::PRIORITIES.join(", ")
+                    ));
+                }
+            }
+        }
+        if let Some(mqtt) = &self.mqtt {
+            if mqtt.topic.is_empty() {
+                problems.push("notify.mqtt.topic is empty".to_string());
+            }
+        }
+        if let Some(sentry) = &self.sentry {
+            if let Err(e) = sentry::Dsn::parse(sentry.dsn.expose()) {
+                problems.push(format!("notify.sentry.dsn is not a valid DSN: {e}"));
+            }
+        }
+        if let Some(slack) = &self.slack {
+            check_url("notify.slack.webhook_url", &slack.webhook_url, problems);
+        }
+        if let Some(telegram) = &self.telegram {
+            check_url("notify.telegram.api_url", &telegram.api_url, problems);
+            if telegram.bot_token.is_blank() {
+                problems.push("notify.telegram.bot_token is empty".to_string());
+            }
+            if telegram.chat_id.is_empty() {
+                problems.push("notify.telegram.chat_id is empty".to_string());
+            }
+        }
+    }
+}
+
+fn check_url(option: &str, url: &str, problems: &mut Vec<String>) {
+    if let Err(e) = reqwest::Url::parse(url) {
+        problems.push(format!("{option} '{url}' is not a valid URL: {e}"));
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EventKind {
+    RunStarted,
+    RecordCreated,
+    RecordUpdated,
+    UpdateFailed,
+    PrefixChanged,
+    RecordDrifted,
+    RunFinished,
+}
+
+/// Something that happened during a run
+#[derive(Debug, Clone)]
+pub enum Event<'a> {
+    /// A run is about to start
+    RunStarted,
+    RecordCreated {
+        service: &'a str,
+        record: &'a str,
+        address: Ipv6Addr,
+    },
+    RecordUpdated {
+        service: &'a str,
+        record: &'a str,
+        old: &'a [String],
+        new: Ipv6Addr,
+    },
+    /// A record could not be reconciled. `service` and `record` are `None` if
+    /// the run failed as a whole, e.g. because the prefix could not be detected.
+    UpdateFailed {
+        service: Option<&'a str>,
+        record: Option<&'a str>,
+        error: &'a str,
+        /// Failed runs in a row including this one, needs `state_file`
+        consecutive_failures: u32,
+    },
+    /// Records were moved over from another /64
+    PrefixChanged {
+        old: Ipv6Addr,
+        new: Ipv6Addr,
+        records: Vec<String>,
+    },
+    /// A record no longer holds what dynsix last wrote to it, found by the
+    /// daemon's `drift_check`
+    RecordDrifted {
+        service: &'a str,
+        record: &'a str,
+        written: Ipv6Addr,
+        /// `None` if the record was deleted
+        found: Option<&'a [String]>,
+    },
+    /// The run is over, with its report or the error that aborted it
+    RunFinished {
+        result: Result<&'a RunReport, &'a str>,
+        consecutive_failures: u32,
+    },
+}
+
+impl Event<'_> {
+    pub fn kind(&self) -> EventKind {
+        match self {
+            Self::RunStarted => EventKind::RunStarted,
+            Self::RecordCreated { .. } => EventKind::RecordCreated,
+            Self::RecordUpdated { .. } => EventKind::RecordUpdated,
+            Self::UpdateFailed { .. } => EventKind::UpdateFailed,
+            Self::PrefixChanged { .. } => EventKind::PrefixChanged,
+            Self::RecordDrifted { .. } => EventKind::RecordDrifted,
+            Self::RunFinished { .. } => EventKind::RunFinished,
+        }
+    }
+}
+
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+/// A notification backend
+pub trait Notifier: Debug + Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// Events routed to the backend unless its `events` option says otherwise
+    fn default_events(&self) -> &'static [EventKind];
+
+    /// Delivers `event`, backends ignore events they have nothing to say about
+    fn notify<'a>(&'a self, event: &'a Event<'a>) -> BoxFuture<'a, Result<(), DynsixError>>;
+}
+
+/// Events announcing record changes and failures, the defaults of the chat backends
+pub(crate) const MESSAGE_EVENTS: &[EventKind] = &[
+    EventKind::RecordCreated,
+    EventKind::RecordUpdated,
+    EventKind::UpdateFailed,
+    EventKind::RecordDrifted,
+];
+
+/// A short human readable message about something that happened during a run
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Notification {
+    pub title: String,
+    pub body: String,
+    /// Something went wrong, as opposed to a record that changed
+    pub failure: bool,
+}
+
+impl Notification {
+    /// The message for events that announce a change or a failure
+    pub fn from_event(event: &Event) -> Option<Self> {
+        let (title, body, failure) = match event {
+            Event::RecordCreated {
+                record, address, ..
+            } => (
+                format!("{record} created"),
+                format!("Now pointing to {address}"),
+                false,
+            ),
+            Event::RecordUpdated {
+                record, old, new, ..
+            } => (
+                format!("{record} updated"),
+                format!("Changed from {} to {new}", old.join(", ")),
+                false,
+            ),
+            Event::UpdateFailed {
+                record: Some(record),
+                error,
+                ..
+            } => (
+                format!("{record} failed to update"),
+                error.to_string(),
+                true,
+            ),
+            Event::UpdateFailed {
+                record: None,
+                error,
+                ..
+            } => ("dynsix run failed".to_string(), error.to_string(), true),
+            Event::PrefixChanged { old, new, records } => (
+                "Prefix changed".to_string(),
+                format!("From {old}/64 to {new}/64, updated {}", records.join(", ")),
+                false,
+            ),
+            Event::RecordDrifted {
+                record,
+                written,
+                found,
+                ..
+            } => (
+                format!("{record} changed outside dynsix"),
+                match found {
+                    Some(found) => format!("Set to {written} by dynsix, now {}", found.join(", ")),
+                    None => format!("Set to {written} by dynsix, now deleted"),
+                },
+                true,
+            ),
+            Event::RunStarted | Event::RunFinished { .. } => return None,
+        };
+
+        Some(Self {
+            title,
+            body,
+            failure,
+        })
+    }
+
+    /// Fills `{title}` and `{body}` in a user provided template
+    pub fn render(&self, template: &str) -> String {
+        template
+            .replace("{title}", &self.title)
+            .replace("{body}", &self.body)
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Route {
+    notifier: Arc<dyn Notifier>,
+    events: Vec<EventKind>,
+}
+
+/// All configured notification backends. Delivery problems are logged and
+/// never fail the run itself.
+#[derive(Debug, Clone, Default)]
+pub struct Notifiers {
+    routes: Vec<Route>,
+}
+
+impl Notifiers {
+    pub fn new(http: reqwest::Client, config: &NotifyConfig) -> Self {
+        let mut notifiers = Self::default();
+        if let Some(config) = &config.discord {
+            notifiers.add(discord::Discord::new(http.clone(), config), &config.events);
+        }
+        if let Some(config) = &config.email {
+            notifiers.add(email::Email::new(config), &config.events);
+        }
+        if let Some(config) = &config.healthchecks {
+            notifiers.add(
+                healthchecks::Healthchecks::new(http.clone(), config),
+                &config.events,
+            );
+        }
+        if let Some(config) = &config.matrix {
+            notifiers.add(matrix::Matrix::new(http.clone(), config), &config.events);
+        }
+        if let Some(config) = &config.mqtt {
+            notifiers.add(mqtt::Mqtt::new(config), &config.events);
+        }
+        if let Some(config) = &config.ntfy {
+            notifiers.add(ntfy::Ntfy::new(http.clone(), config), &config.events);
+        }
+        if let Some(config) = &config.sentry {
+            notifiers.add(sentry::Sentry::new(http.clone(), config), &config.events);
+        }
+        if let Some(config) = &config.slack {
+            notifiers.add(slack::Slack::new(http.clone(), config), &config.events);
+        }
+        if let Some(config) = &config.telegram {
+            notifiers.add(telegram::Telegram::new(http, config), &config.events);
+        }
+        notifiers
+    }
+
+    /// Routes `events` to `notifier`, its defaults if none are given
+    pub fn add<N>(&mut self, notifier: N, events: &Option<Vec<EventKind>>)
+    where
+        N: Notifier + 'static,
+    {
+        let events = events
+            .clone()
+            .unwrap_or_else(|| notifier.default_events().to_vec());
+        self.routes.push(Route {
+            notifier: Arc::new(notifier),
+            events,
+        });
+    }
+
+    /// Sends `event` to every backend it is routed to
+    pub async fn dispatch(&self, event: &Event<'_>) {
+        for route in &self.routes {
+            if !route.events.contains(&event.kind()) {
+                continue;
+            }
+            if let Err(e) = route.notifier.notify(event).await {
+                warn!(backend = route.notifier.name(), "{e}");
+            }
+        }
+    }
+
+    pub async fn drifted(&self, drift: &Drift) {
+        self.dispatch(&Event::RecordDrifted {
+            service: &drift.service,
+            record: &drift.record,
+            written: drift.written,
+            found: drift.found.as_deref(),
+        })
+        .await;
+    }
+
+    pub async fn started(&self) {
+        self.dispatch(&Event::RunStarted).await;
+    }
+
+    /// `result` is the report of the run or the error that aborted it, `state`
+    /// already accounts for this run
+    pub async fn finished(&self, result: Result<&RunReport, String>, state: &State) {
+        let consecutive_failures = state.consecutive_failures;
+        match &result {
+            Ok(report) => {
+                for service in &report.services {
+                    let event = match service.action {
+                        Action::Unchanged | Action::Deferred | Action::Skipped => continue,
+                        // Only the TTL changed, see low_ttl
+                        Action::Updated if !address_changed(service) => continue,
+                        Action::Created => Event::RecordCreated {
+                            service: &service.service,
+                            record: &service.record,
+                            address: service.new,
+                        },
+                        Action::Updated => Event::RecordUpdated {
+                            service: &service.service,
+                            record: &service.record,
+                            old: service.old.as_deref().unwrap_or_default(),
+                            new: service.new,
+                        },
+                        Action::Failed => Event::UpdateFailed {
+                            service: Some(&service.service),
+                            record: Some(&service.record),
+                            error: service.error.as_deref().unwrap_or_default(),
+                            consecutive_failures,
+                        },
+                    };
+                    self.dispatch(&event).await;
+                }
+                if let Some((old, new, records)) = prefix_change(report) {
+                    self.dispatch(&Event::PrefixChanged { old, new, records })
+                        .await;
+                }
+            }
+            Err(error) => {
+                self.dispatch(&Event::UpdateFailed {
+                    service: None,
+                    record: None,
+                    error,
+                    consecutive_failures,
+                })
+                .await
+            }
+        }
+
+        self.dispatch(&Event::RunFinished {
+            result: result
+                .as_ref()
+                .map(|report| *report)
+                .map_err(String::as_str),
+            consecutive_failures,
+        })
+        .await;
+    }
+}
+
+/// The previous and the current /64 if records were moved over from another
+/// prefix, together with those records
+fn prefix_change(report: &RunReport) -> Option<(Ipv6Addr, Ipv6Addr, Vec<String>)> {
+    let new = merge_ips(report.public_ip, Ipv6Addr::UNSPECIFIED);
+    let mut old = None;
+    let mut records = Vec::new();
+    for service in &report.services {
+        let previous = service
+            .old
+            .as_ref()
+            .and_then(|values| values.first())
+            .and_then(|value| value.parse().ok())
+            .map(|ip| merge_ips(ip, Ipv6Addr::UNSPECIFIED));
+        if service.action == Action::Updated && previous.is_some_and(|previous| previous != new) {
+            old = old.or(previous);
+            records.push(service.record.clone());
+        }
+    }
+
+    old.map(|old| (old, new, records))
+}
+
+/// Connections of the backends speaking their own protocol, plain or TLS
+trait Stream: AsyncRead + AsyncWrite + Unpin + Send {}
+impl<S: AsyncRead + AsyncWrite + Unpin + Send> Stream for S {}
+
+async fn tls_connect<S>(host: &str, stream: S) -> Result<tokio_native_tls::TlsStream<S>, String>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let connector = native_tls::TlsConnector::new().map_err(|e| e.to_string())?;
+    tokio_native_tls::TlsConnector::from(connector)
+        .connect(host, stream)
+        .await
+        .map_err(|e| e.to_string())
+}
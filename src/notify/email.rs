@@ -0,0 +1,290 @@
+//! Mails through an SMTP relay, sent when runs keep failing, the prefix
+//! changed or a record was changed outside dynsix. Speaks just enough SMTP
+//! for a submission server: EHLO, STARTTLS or implicit TLS, AUTH PLAIN and a
+//! single plain text message.
+
+use std::net::Ipv6Addr;
+
+use serde::Deserialize;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::TcpStream,
+};
+
+use super::{tls_connect, BoxFuture, Event, EventKind, Notification, Notifier, Stream};
+use crate::{secret::Secret, DynsixError};
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Tls {
+    /// Upgrade a plain connection with STARTTLS, usually on port 587
+    #[default]
+    Starttls,
+    /// TLS from the first byte, usually on port 465
+    Tls,
+    /// No encryption at all, only sensible for a relay on localhost
+    None,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct EmailConfig {
+    pub host: String,
+    #[serde(default = "default_port")]
+    pub port: u16,
+    #[serde(default)]
+    pub tls: Tls,
+    pub username: Option<String>,
+    pub password: Option<Secret>,
+    pub from: String,
+    pub to: Vec<String>,
+    /// Failed runs in a row before a mail is sent, counting needs `state_file`
+    #[serde(default = "default_after_failures")]
+    pub after_failures: u32,
+    /// Events sent to this backend, see [`EventKind`]
+    pub events: Option<Vec<EventKind>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Email {
+    config: EmailConfig,
+}
+
+impl Email {
+    pub fn new(config: &EmailConfig) -> Self {
+        Self {
+            config: config.clone(),
+        }
+    }
+
+    /// Mails once when the number of failed runs in a row reaches the threshold
+    pub async fn failed(
+        &self,
+        consecutive_failures: u32,
+        errors: &[String],
+    ) -> Result<(), DynsixError> {
+        if consecutive_failures != self.config.after_failures {
+            return Ok(());
+        }
+
+        let subject = if consecutive_failures == 1 {
+            "dynsix: updating records failed".to_string()
+        } else {
+            format!("dynsix: updating records failed {consecutive_failures} times in a row")
+        };
+        self.send(&subject, &errors.join("\n")).await
+    }
+
+    pub async fn prefix_changed(
+        &self,
+        old: Ipv6Addr,
+        new: Ipv6Addr,
+        records: &[String],
+    ) -> Result<(), DynsixError> {
+        let body = format!(
+            "The public prefix changed from {old}/64 to {new}/64.\n\nUpdated records:\n{}",
+            records.join("\n")
+        );
+        self.send("dynsix: prefix changed", &body).await
+    }
+
+    async fn send(&self, subject: &str, body: &str) -> Result<(), DynsixError> {
+        self.deliver(subject, body)
+            .await
+            .map_err(|message| DynsixError::Smtp {
+                host: self.config.host.clone(),
+                message,
+            })
+    }
+
+    async fn deliver(&self, subject: &str, body: &str) -> Result<(), String> {
+        let config = &self.config;
+        let tcp = TcpStream::connect((config.host.as_str(), config.port))
+            .await
+            .map_err(|e| e.to_string())?;
+        let mut smtp = match config.tls {
+            Tls::Tls => Connection::new(Box::new(tls_connect(&config.host, tcp).await?)),
+            Tls::Starttls | Tls::None => Connection::new(Box::new(tcp)),
+        };
+
+        smtp.expect(220).await?;
+        smtp.command("EHLO dynsix", 250).await?;
+        if config.tls == Tls::Starttls {
+            smtp.command("STARTTLS", 220).await?;
+            let tcp = smtp.into_inner();
+            smtp = Connection::new(Box::new(tls_connect(&config.host, tcp).await?));
+            smtp.command("EHLO dynsix", 250).await?;
+        }
+
+        if let Some(username) = &config.username {
+            let credentials = format!(
+                "\0{username}\0{}",
+                config
+                    .password
+                    .as_ref()
+                    .map(Secret::expose)
+                    .unwrap_or_default()
+            );
+            smtp.command(&format!("AUTH PLAIN {}", base64::encode(credentials)), 235)
+                .await?;
+        }
+
+        smtp.command(&format!("MAIL FROM:<{}>", config.from), 250)
+            .await?;
+        for to in &config.to {
+            smtp.command(&format!("RCPT TO:<{to}>"), 250).await?;
+        }
+        smtp.command("DATA", 354).await?;
+        smtp.command(&self.message(subject, body), 250).await?;
+        smtp.command("QUIT", 221).await
+    }
+
+    /// The message terminated by `.`, with lines starting with a dot escaped
+    fn message(&self, subject: &str, body: &str) -> String {
+        let mut message = format!(
+            "From: {}\r\nTo: {}\r\nSubject: {subject}\r\nContent-Type: text/plain; charset=utf-8\r\n\r\n",
+            self.config.from,
+            self.config.to.join(", ")
+        );
+        for line in body.lines() {
+            if line.starts_with('.') {
+                message.push('.');
+            }
+            message.push_str(line);
+            message.push_str("\r\n");
+        }
+        message.push('.');
+        message
+    }
+}
+
+impl Notifier for Email {
+    fn name(&self) -> &'static str {
+        "email"
+    }
+
+    fn default_events(&self) -> &'static [EventKind] {
+        &[
+            EventKind::RunFinished,
+            EventKind::PrefixChanged,
+            EventKind::RecordDrifted,
+        ]
+    }
+
+    /// A run that finished with failures is mailed as one message listing all
+    /// of them, `update_failed` sends one message per failure
+    fn notify<'a>(&'a self, event: &'a Event<'a>) -> BoxFuture<'a, Result<(), DynsixError>> {
+        Box::pin(async move {
+            match event {
+                Event::RunFinished {
+                    result,
+                    consecutive_failures,
+                } => {
+                    let errors: Vec<_> = match result {
+                        Ok(report) => report
+                            .failures()
+                            .map(|service| {
+                                format!(
+                                    "{}: {}",
+                                    service.record,
+                                    service.error.as_deref().unwrap_or_default()
+                                )
+                            })
+                            .collect(),
+                        Err(error) => vec![error.to_string()],
+                    };
+                    if errors.is_empty() {
+                        return Ok(());
+                    }
+                    self.failed(*consecutive_failures, &errors).await
+                }
+                Event::UpdateFailed {
+                    record,
+                    error,
+                    consecutive_failures,
+                    ..
+                } => {
+                    let error = match record {
+                        Some(record) => format!("{record}: {error}"),
+                        None => error.to_string(),
+                    };
+                    self.failed(*consecutive_failures, &[error]).await
+                }
+                Event::PrefixChanged { old, new, records } => {
+                    self.prefix_changed(*old, *new, records).await
+                }
+                Event::RecordDrifted { .. } => {
+                    let Some(notification) = Notification::from_event(event) else {
+                        return Ok(());
+                    };
+                    let subject = format!("dynsix: {}", notification.title);
+                    self.send(&subject, &notification.body).await
+                }
+                _ => Ok(()),
+            }
+        })
+    }
+}
+
+struct Connection {
+    stream: BufReader<Box<dyn Stream>>,
+}
+
+impl Connection {
+    fn new(stream: Box<dyn Stream>) -> Self {
+        Self {
+            stream: BufReader::new(stream),
+        }
+    }
+
+    fn into_inner(self) -> Box<dyn Stream> {
+        self.stream.into_inner()
+    }
+
+    async fn command(&mut self, line: &str, code: u16) -> Result<(), String> {
+        self.stream
+            .write_all(format!("{line}\r\n").as_bytes())
+            .await
+            .map_err(|e| e.to_string())?;
+        self.stream.flush().await.map_err(|e| e.to_string())?;
+        self.expect(code).await
+    }
+
+    /// Reads a possibly multi-line reply and checks its code
+    async fn expect(&mut self, code: u16) -> Result<(), String> {
+        let mut reply = String::new();
+        loop {
+            let mut line = String::new();
+            if self
+                .stream
+                .read_line(&mut line)
+                .await
+                .map_err(|e| e.to_string())?
+                == 0
+            {
+                return Err("connection closed by server".to_string());
+            }
+            reply.push_str(&line);
+            // `250-...` continues the reply, `250 ...` ends it
+            if line.as_bytes().get(3) != Some(&b'-') {
+                break;
+            }
+        }
+
+        match reply.get(..3).and_then(|status| status.parse::<u16>().ok()) {
+            Some(status) if status == code => Ok(()),
+            _ => Err(format!(
+                "expected {code}, server replied: {}",
+                reply.trim_end()
+            )),
+        }
+    }
+}
+
+fn default_port() -> u16 {
+    587
+}
+
+fn default_after_failures() -> u32 {
+    1
+}
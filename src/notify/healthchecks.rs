@@ -0,0 +1,105 @@
+//! Dead man's switch pings in the format of healthchecks.io: `<url>/start` when
+//! a run begins, `<url>` when it succeeded and `<url>/fail` when it did not.
+//! A check that stops receiving pings alerts on its own.
+
+use serde::Deserialize;
+
+use super::{BoxFuture, Event, EventKind, Notifier};
+use crate::{http_log::SendLogged, report::RunReport, DynsixError};
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct HealthchecksConfig {
+    /// Ping URL of the check, e.g. `https://hc-ping.com/<uuid>`
+    pub url: String,
+    /// Events sent to this backend, see [`EventKind`]
+    pub events: Option<Vec<EventKind>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Healthchecks {
+    http: reqwest::Client,
+    url: String,
+}
+
+impl Healthchecks {
+    pub fn new(http: reqwest::Client, config: &HealthchecksConfig) -> Self {
+        Self {
+            http,
+            url: config.url.trim_end_matches('/').to_string(),
+        }
+    }
+
+    pub async fn start(&self) -> Result<(), DynsixError> {
+        self.ping("/start", String::new()).await
+    }
+
+    pub async fn success(&self) -> Result<(), DynsixError> {
+        self.ping("", String::new()).await
+    }
+
+    /// `reason` is shown in the event log of the check
+    pub async fn fail(&self, reason: String) -> Result<(), DynsixError> {
+        self.ping("/fail", reason).await
+    }
+
+    /// Succeeds the check if every service was reconciled, fails it with the
+    /// errors otherwise
+    pub async fn report(&self, report: &RunReport) -> Result<(), DynsixError> {
+        let failures: Vec<_> = report
+            .failures()
+            .map(|service| {
+                format!(
+                    "{}: {}",
+                    service.service,
+                    service.error.as_deref().unwrap_or_default()
+                )
+            })
+            .collect();
+
+        if failures.is_empty() {
+            self.success().await
+        } else {
+            self.fail(failures.join("\n")).await
+        }
+    }
+
+    async fn ping(&self, path: &str, body: String) -> Result<(), DynsixError> {
+        self.http
+            .post(format!("{}{path}", self.url))
+            .body(body)
+            .send_logged()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map(|_| ())
+            .map_err(|source| DynsixError::Notify {
+                backend: "healthchecks",
+                source,
+            })
+    }
+}
+
+impl Notifier for Healthchecks {
+    fn name(&self) -> &'static str {
+        "healthchecks"
+    }
+
+    fn default_events(&self) -> &'static [EventKind] {
+        &[EventKind::RunStarted, EventKind::RunFinished]
+    }
+
+    fn notify<'a>(&'a self, event: &'a Event<'a>) -> BoxFuture<'a, Result<(), DynsixError>> {
+        Box::pin(async move {
+            match event {
+                Event::RunStarted => self.start().await,
+                Event::RunFinished {
+                    result: Ok(report), ..
+                } => self.report(report).await,
+                Event::RunFinished {
+                    result: Err(error), ..
+                } => self.fail(error.to_string()).await,
+                _ => Ok(()),
+            }
+        })
+    }
+}
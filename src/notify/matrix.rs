@@ -0,0 +1,96 @@
+//! Messages posted into a Matrix room through the client-server API
+
+use std::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+use serde::Deserialize;
+use serde_json::json;
+
+use super::{BoxFuture, Event, EventKind, Notification, Notifier, MESSAGE_EVENTS};
+use crate::{http_log::SendLogged, secret::Secret, DynsixError};
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct MatrixConfig {
+    /// Base URL of the homeserver, e.g. `https://matrix.example.org`
+    pub homeserver: String,
+    pub access_token: Secret,
+    /// Internal id of the room, e.g. `!abcdef:example.org`, the account has to be joined
+    pub room_id: String,
+
+    /// Events sent to this backend, see [`EventKind`]
+    pub events: Option<Vec<EventKind>>,
+}
+
+#[derive(Debug)]
+pub struct Matrix {
+    http: reqwest::Client,
+    config: MatrixConfig,
+    /// Makes transaction ids unique within a process
+    transactions: AtomicU64,
+}
+
+impl Matrix {
+    pub fn new(http: reqwest::Client, config: &MatrixConfig) -> Self {
+        Self {
+            http,
+            config: config.clone(),
+            transactions: AtomicU64::new(0),
+        }
+    }
+
+    pub async fn send(&self, notification: &Notification) -> Result<(), DynsixError> {
+        self.http
+            .put(self.message_url())
+            .bearer_auth(self.config.access_token.expose())
+            .json(&json!({
+                "msgtype": "m.text",
+                "body": format!("{}\n{}", notification.title, notification.body),
+            }))
+            .send_logged()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map(|_| ())
+            .map_err(|source| DynsixError::Notify {
+                backend: "matrix",
+                source,
+            })
+    }
+
+    /// Every message needs its own transaction id, Matrix deduplicates retries by it
+    fn message_url(&self) -> String {
+        let started = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis();
+        let transaction = self.transactions.fetch_add(1, Ordering::Relaxed);
+
+        format!(
+            "{}/_matrix/client/v3/rooms/{}/send/m.room.message/dynsix-{started}-{transaction}",
+            self.config.homeserver.trim_end_matches('/'),
+            utf8_percent_encode(&self.config.room_id, NON_ALPHANUMERIC)
+        )
+    }
+}
+
+impl Notifier for Matrix {
+    fn name(&self) -> &'static str {
+        "matrix"
+    }
+
+    fn default_events(&self) -> &'static [EventKind] {
+        MESSAGE_EVENTS
+    }
+
+    fn notify<'a>(&'a self, event: &'a Event<'a>) -> BoxFuture<'a, Result<(), DynsixError>> {
+        Box::pin(async move {
+            match Notification::from_event(event) {
+                Some(notification) => self.send(&notification).await,
+                None => Ok(()),
+            }
+        })
+    }
+}
@@ -0,0 +1,292 @@
+//! Publishes the current prefix and the state of every service to an MQTT
+//! broker as retained messages, optionally with Home Assistant discovery so
+//! each service shows up as a sensor. Only the MQTT 3.1.1 packets needed for
+//! QoS 0 publishing are implemented.
+
+use std::{net::Ipv6Addr, time::SystemTime};
+
+use serde::Deserialize;
+use serde_json::json;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+};
+
+use super::{tls_connect, BoxFuture, Event, EventKind, Notifier, Stream};
+use crate::{
+    merge_ips,
+    report::{Action, RunReport},
+    secret::Secret,
+    DynsixError,
+};
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct MqttConfig {
+    pub host: String,
+    #[serde(default = "default_port")]
+    pub port: u16,
+    #[serde(default)]
+    pub tls: bool,
+    pub username: Option<String>,
+    pub password: Option<Secret>,
+    #[serde(default = "default_client_id")]
+    pub client_id: String,
+    /// Topics are published below `<topic>/`
+    #[serde(default = "default_topic")]
+    pub topic: String,
+    /// Publish Home Assistant MQTT discovery messages
+    #[serde(default)]
+    pub discovery: bool,
+    #[serde(default = "default_discovery_prefix")]
+    pub discovery_prefix: String,
+    /// Events sent to this backend, see [`EventKind`]
+    pub events: Option<Vec<EventKind>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Mqtt {
+    config: MqttConfig,
+}
+
+impl Mqtt {
+    pub fn new(config: &MqttConfig) -> Self {
+        Self {
+            config: config.clone(),
+        }
+    }
+
+    /// Publishes, all retained:
+    /// - `<topic>/prefix`: the detected /64
+    /// - `<topic>/<service>/state`: JSON with the record, address, action and error
+    /// - `<topic>/<service>/last_changed`: RFC 3339 time of the last change
+    pub async fn publish(&self, report: &RunReport) -> Result<(), DynsixError> {
+        let topic = self.config.topic.trim_end_matches('/');
+        let now = humantime::format_rfc3339_seconds(SystemTime::now()).to_string();
+
+        let mut messages = vec![(
+            format!("{topic}/prefix"),
+            format!("{}/64", merge_ips(report.public_ip, Ipv6Addr::UNSPECIFIED)),
+        )];
+        if self.config.discovery {
+            messages.push(self.discovery_prefix_sensor(topic));
+        }
+        for service in &report.services {
+            let state = json!({
+                "record": service.record,
+                "address": service.new,
+                "action": service.action,
+                "error": service.error,
+            });
+            messages.push((
+                format!("{topic}/{}/state", service.service),
+                state.to_string(),
+            ));
+            if matches!(service.action, Action::Created | Action::Updated) {
+                messages.push((
+                    format!("{topic}/{}/last_changed", service.service),
+                    now.clone(),
+                ));
+            }
+            if self.config.discovery {
+                messages.extend(self.discovery_service_sensors(
+                    topic,
+                    &service.service,
+                    &service.record,
+                ));
+            }
+        }
+
+        self.send(&messages)
+            .await
+            .map_err(|message| DynsixError::Mqtt {
+                host: self.config.host.clone(),
+                message,
+            })
+    }
+
+    fn discovery_prefix_sensor(&self, topic: &str) -> (String, String) {
+        let config = json!({
+            "name": "dynsix prefix",
+            "unique_id": format!("{}_prefix", self.config.client_id),
+            "state_topic": format!("{topic}/prefix"),
+            "icon": "mdi:ip-network",
+        });
+        (
+            format!(
+                "{}/sensor/{}_prefix/config",
+                self.config.discovery_prefix, self.config.client_id
+            ),
+            config.to_string(),
+        )
+    }
+
+    /// The published address and the time of the last change of a service
+    fn discovery_service_sensors(
+        &self,
+        topic: &str,
+        service: &str,
+        record: &str,
+    ) -> Vec<(String, String)> {
+        let id = format!("{}_{}", self.config.client_id, service);
+        let address = json!({
+            "name": format!("{record} address"),
+            "unique_id": format!("{id}_address"),
+            "state_topic": format!("{topic}/{service}/state"),
+            "value_template": "{{ value_json.address }}",
+            "json_attributes_topic": format!("{topic}/{service}/state"),
+            "icon": "mdi:dns",
+        });
+        let last_changed = json!({
+            "name": format!("{record} last changed"),
+            "unique_id": format!("{id}_last_changed"),
+            "state_topic": format!("{topic}/{service}/last_changed"),
+            "device_class": "timestamp",
+        });
+
+        let prefix = &self.config.discovery_prefix;
+        vec![
+            (
+                format!("{prefix}/sensor/{id}_address/config"),
+                address.to_string(),
+            ),
+            (
+                format!("{prefix}/sensor/{id}_last_changed/config"),
+                last_changed.to_string(),
+            ),
+        ]
+    }
+
+    async fn send(&self, messages: &[(String, String)]) -> Result<(), String> {
+        let config = &self.config;
+        let tcp = TcpStream::connect((config.host.as_str(), config.port))
+            .await
+            .map_err(|e| e.to_string())?;
+        let mut stream: Box<dyn Stream> = if config.tls {
+            Box::new(tls_connect(&config.host, tcp).await?)
+        } else {
+            Box::new(tcp)
+        };
+
+        stream
+            .write_all(&connect_packet(config))
+            .await
+            .map_err(|e| e.to_string())?;
+        let mut connack = [0; 4];
+        stream
+            .read_exact(&mut connack)
+            .await
+            .map_err(|e| e.to_string())?;
+        match connack {
+            [0x20, 2, _, 0] => {}
+            [0x20, 2, _, 4 | 5] => return Err("broker refused the credentials".to_string()),
+            [0x20, 2, _, code] => return Err(format!("broker refused the connection ({code})")),
+            _ => return Err("unexpected answer to CONNECT".to_string()),
+        }
+
+        for (topic, payload) in messages {
+            stream
+                .write_all(&publish_packet(topic, payload.as_bytes()))
+                .await
+                .map_err(|e| e.to_string())?;
+        }
+        stream
+            .write_all(&[0xe0, 0])
+            .await
+            .map_err(|e| e.to_string())?;
+        stream.flush().await.map_err(|e| e.to_string())
+    }
+}
+
+impl Notifier for Mqtt {
+    fn name(&self) -> &'static str {
+        "mqtt"
+    }
+
+    fn default_events(&self) -> &'static [EventKind] {
+        &[EventKind::RunFinished]
+    }
+
+    fn notify<'a>(&'a self, event: &'a Event<'a>) -> BoxFuture<'a, Result<(), DynsixError>> {
+        Box::pin(async move {
+            match event {
+                Event::RunFinished {
+                    result: Ok(report), ..
+                } => self.publish(report).await,
+                _ => Ok(()),
+            }
+        })
+    }
+}
+
+fn connect_packet(config: &MqttConfig) -> Vec<u8> {
+    // Clean session
+    let mut flags = 0x02;
+    let mut payload = Vec::new();
+    write_string(&mut payload, config.client_id.as_bytes());
+    if let Some(username) = &config.username {
+        flags |= 0x80;
+        write_string(&mut payload, username.as_bytes());
+        if let Some(password) = &config.password {
+            flags |= 0x40;
+            write_string(&mut payload, password.expose().as_bytes());
+        }
+    }
+
+    let mut body = Vec::new();
+    write_string(&mut body, b"MQTT");
+    // Protocol level 4 (3.1.1), flags and a keep alive of 60 seconds
+    body.extend([4, flags, 0, 60]);
+    body.extend(payload);
+
+    packet(0x10, body)
+}
+
+/// QoS 0 with the retain flag set
+fn publish_packet(topic: &str, payload: &[u8]) -> Vec<u8> {
+    let mut body = Vec::new();
+    write_string(&mut body, topic.as_bytes());
+    body.extend(payload);
+
+    packet(0x31, body)
+}
+
+fn packet(header: u8, body: Vec<u8>) -> Vec<u8> {
+    let mut packet = vec![header];
+    // Remaining length, 7 bits per byte with the high bit marking continuation
+    let mut length = body.len();
+    loop {
+        let mut byte = (length % 128) as u8;
+        length /= 128;
+        if length > 0 {
+            byte |= 0x80;
+        }
+        packet.push(byte);
+        if length == 0 {
+            break;
+        }
+    }
+    packet.extend(body);
+    packet
+}
+
+fn write_string(buffer: &mut Vec<u8>, value: &[u8]) {
+    buffer.extend((value.len() as u16).to_be_bytes());
+    buffer.extend(value);
+}
+
+fn default_port() -> u16 {
+    1883
+}
+
+fn default_client_id() -> String {
+    "dynsix".to_string()
+}
+
+fn default_topic() -> String {
+    "dynsix".to_string()
+}
+
+fn default_discovery_prefix() -> String {
+    "homeassistant".to_string()
+}
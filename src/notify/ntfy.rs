@@ -0,0 +1,98 @@
+//! Push notifications through an [ntfy](https://ntfy.sh) server
+
+use serde::Deserialize;
+
+use super::{BoxFuture, Event, EventKind, Notification, Notifier, MESSAGE_EVENTS};
+use crate::{http_log::SendLogged, secret::Secret, DynsixError};
+
+/// Priorities understood by ntfy, from lowest to highest
+pub const PRIORITIES: [&str; 5] = ["min", "low", "default", "high", "urgent"];
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct NtfyConfig {
+    #[serde(default = "default_server")]
+    pub server: String,
+    pub topic: String,
+    /// Access token, takes precedence over `username`/`password`
+    pub token: Option<Secret>,
+    pub username: Option<String>,
+    pub password: Option<Secret>,
+    /// One of [`PRIORITIES`], the server default if unset
+    pub priority: Option<String>,
+    /// Events sent to this backend, see [`EventKind`]
+    pub events: Option<Vec<EventKind>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Ntfy {
+    http: reqwest::Client,
+    config: NtfyConfig,
+}
+
+impl Ntfy {
+    pub fn new(http: reqwest::Client, config: &NtfyConfig) -> Self {
+        Self {
+            http,
+            config: config.clone(),
+        }
+    }
+
+    pub async fn send(&self, notification: &Notification) -> Result<(), DynsixError> {
+        let url = format!(
+            "{}/{}",
+            self.config.server.trim_end_matches('/'),
+            self.config.topic
+        );
+        let mut request = self
+            .http
+            .post(url)
+            .header("Title", &notification.title)
+            .body(notification.body.clone());
+        if let Some(priority) = &self.config.priority {
+            request = request.header("Priority", priority);
+        }
+        if notification.failure {
+            request = request.header("Tags", "warning");
+        }
+        if let Some(token) = &self.config.token {
+            request = request.bearer_auth(token.expose());
+        } else if let Some(username) = &self.config.username {
+            request =
+                request.basic_auth(username, self.config.password.as_ref().map(Secret::expose));
+        }
+
+        request
+            .send_logged()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map(|_| ())
+            .map_err(|source| DynsixError::Notify {
+                backend: "ntfy",
+                source,
+            })
+    }
+}
+
+impl Notifier for Ntfy {
+    fn name(&self) -> &'static str {
+        "ntfy"
+    }
+
+    fn default_events(&self) -> &'static [EventKind] {
+        MESSAGE_EVENTS
+    }
+
+    fn notify<'a>(&'a self, event: &'a Event<'a>) -> BoxFuture<'a, Result<(), DynsixError>> {
+        Box::pin(async move {
+            match Notification::from_event(event) {
+                Some(notification) => self.send(&notification).await,
+                None => Ok(()),
+            }
+        })
+    }
+}
+
+fn default_server() -> String {
+    "https://ntfy.sh".to_string()
+}
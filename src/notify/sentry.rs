@@ -0,0 +1,278 @@
+//! Errors reported to [Sentry](https://sentry.io) or a compatible server such
+//! as GlitchTip, so that failures on many hosts end up in one place: failed
+//! updates and runs as events tagged with their service and record, and
+//! panics of the process. URLs in messages have their credentials masked.
+
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+    panic::PanicHookInfo,
+    sync::{mpsc, Mutex, Once},
+    time::{Duration, SystemTime},
+};
+
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use super::{BoxFuture, Event, EventKind, Notification, Notifier};
+use crate::{
+    audit::host_name,
+    http_log::{redact_text, SendLogged},
+    secret::Secret,
+    DynsixError,
+};
+
+/// How long a panic waits for its event to be sent before going on
+const PANIC_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct SentryConfig {
+    /// `https://<public key>@<host>/<project id>`, from the client keys of
+    /// the project
+    pub dsn: Secret,
+    /// Sent with every event, e.g. `production`
+    pub environment: Option<String>,
+    /// Which host the event is from, the host name if unset
+    pub server_name: Option<String>,
+    /// Events sent to this backend, see [`EventKind`]
+    pub events: Option<Vec<EventKind>>,
+}
+
+/// Where events are sent and with which key, from a DSN
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Dsn {
+    pub envelope_url: String,
+    pub public_key: String,
+}
+
+impl Dsn {
+    pub fn parse(dsn: &str) -> Result<Self, String> {
+        let url = reqwest::Url::parse(dsn).map_err(|e| e.to_string())?;
+        if url.username().is_empty() {
+            return Err("it has no public key".to_string());
+        }
+        let host = url.host_str().ok_or("it has no host")?;
+        let path = url.path().trim_end_matches('/');
+        let (prefix, project) = path.rsplit_once('/').unwrap_or_default();
+        if project.is_empty() {
+            return Err("it has no project id".to_string());
+        }
+        let port = url
+            .port()
+            .map(|port| format!(":{port}"))
+            .unwrap_or_default();
+        Ok(Self {
+            envelope_url: format!(
+                "{}://{host}{port}{prefix}/api/{project}/envelope/",
+                url.scheme()
+            ),
+            public_key: url.username().to_string(),
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Sentry {
+    http: reqwest::Client,
+    config: SentryConfig,
+}
+
+impl Sentry {
+    pub fn new(http: reqwest::Client, config: &SentryConfig) -> Self {
+        Self {
+            http,
+            config: config.clone(),
+        }
+    }
+
+    /// Sends an event built by [`Sentry::event`]
+    pub async fn send(&self, event: Value) -> Result<(), DynsixError> {
+        let dsn = Dsn::parse(self.config.dsn.expose()).map_err(|message| DynsixError::Parse {
+            what: "notify.sentry.dsn".to_string(),
+            message,
+        })?;
+        // An envelope: its header, then the header and payload of each item
+        let payload = event.to_string();
+        let envelope = format!(
+            "{}\n{}\n{payload}\n",
+            json!({ "event_id": event["event_id"] }),
+            json!({ "type": "event", "length": payload.len() })
+        );
+        self.http
+            .post(&dsn.envelope_url)
+            .header("Content-Type", "application/x-sentry-envelope")
+            .header(
+                "X-Sentry-Auth",
+                format!(
+                    "Sentry sentry_version=7, sentry_client={}/{}, sentry_key={}",
+                    env!("CARGO_PKG_NAME"),
+                    env!("CARGO_PKG_VERSION"),
+                    dsn.public_key
+                ),
+            )
+            .body(envelope)
+            .send_logged()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map(|_| ())
+            .map_err(|source| DynsixError::Notify {
+                backend: "sentry",
+                source,
+            })
+    }
+
+    /// An event at `level` with `message` and `tags`, grouped by `fingerprint`
+    pub fn event(
+        &self,
+        level: &str,
+        kind: &str,
+        message: &str,
+        tags: &[(&str, &str)],
+        fingerprint: &[&str],
+    ) -> Value {
+        let tags: serde_json::Map<_, _> = tags
+            .iter()
+            .map(|(key, value)| (key.to_string(), Value::from(*value)))
+            .collect();
+        json!({
+            "event_id": event_id(),
+            "timestamp": humantime::format_rfc3339_seconds(SystemTime::now()).to_string(),
+            "platform": "native",
+            "level": level,
+            "logger": "dynsix",
+            "release": concat!(env!("CARGO_PKG_NAME"), "@", env!("CARGO_PKG_VERSION")),
+            "server_name": self.config.server_name.clone().or_else(host_name),
+            "environment": self.config.environment,
+            "exception": { "values": [{ "type": kind, "value": redact_text(message) }] },
+            "tags": tags,
+            "fingerprint": fingerprint,
+        })
+    }
+}
+
+impl Notifier for Sentry {
+    fn name(&self) -> &'static str {
+        "sentry"
+    }
+
+    fn default_events(&self) -> &'static [EventKind] {
+        &[EventKind::UpdateFailed]
+    }
+
+    fn notify<'a>(&'a self, event: &'a Event<'a>) -> BoxFuture<'a, Result<(), DynsixError>> {
+        Box::pin(async move {
+            let event = match event {
+                Event::UpdateFailed {
+                    service: Some(service),
+                    record: Some(record),
+                    error,
+                    consecutive_failures,
+                } => {
+                    let failures = consecutive_failures.to_string();
+                    self.event(
+                        "error",
+                        "UpdateFailed",
+                        error,
+                        &[
+                            ("service", service),
+                            ("record", record),
+                            ("consecutive_failures", &failures),
+                        ],
+                        &["update_failed", service],
+                    )
+                }
+                Event::UpdateFailed {
+                    error,
+                    consecutive_failures,
+                    ..
+                } => {
+                    let failures = consecutive_failures.to_string();
+                    self.event(
+                        "error",
+                        "RunFailed",
+                        error,
+                        &[("consecutive_failures", &failures)],
+                        &["run_failed"],
+                    )
+                }
+                event => match Notification::from_event(event) {
+                    Some(notification) => {
+                        let level = if notification.failure {
+                            "warning"
+                        } else {
+                            "info"
+                        };
+                        let message = format!("{}: {}", notification.title, notification.body);
+                        let kind = serde_json::to_value(event.kind()).unwrap_or_default();
+                        let kind = kind.as_str().unwrap_or_default();
+                        self.event(level, kind, &message, &[], &[kind, &notification.title])
+                    }
+                    None => return Ok(()),
+                },
+            };
+            self.send(event).await
+        })
+    }
+}
+
+/// The config panics are reported with, if any
+static PANICS: Mutex<Option<SentryConfig>> = Mutex::new(None);
+static PANIC_HOOK: Once = Once::new();
+
+/// Reports panics to `config` from now on, or stops if it is `None`. The
+/// panic is still printed as usual.
+pub fn report_panics(config: Option<&SentryConfig>) {
+    if let Ok(mut panics) = PANICS.lock() {
+        *panics = config.cloned();
+    }
+    if config.is_none() {
+        return;
+    }
+    PANIC_HOOK.call_once(|| {
+        let previous = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |info| {
+            previous(info);
+            let config = PANICS.lock().ok().and_then(|config| config.clone());
+            if let Some(config) = config {
+                report_panic(config, info);
+            }
+        }));
+    });
+}
+
+/// Sends the panic from a thread of its own, as the one panicking may be
+/// inside the runtime already
+fn report_panic(config: SentryConfig, info: &PanicHookInfo<'_>) {
+    let payload = info.payload();
+    let message = payload
+        .downcast_ref::<&str>()
+        .map(|message| message.to_string())
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "Box<dyn Any>".to_string());
+    let message = match info.location() {
+        Some(location) => format!("{message} at {location}"),
+        None => message,
+    };
+
+    let (done, finished) = mpsc::channel();
+    std::thread::spawn(move || {
+        let Ok(runtime) = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+        else {
+            return;
+        };
+        let sentry = Sentry::new(reqwest::Client::new(), &config);
+        let event = sentry.event("fatal", "panic", &message, &[], &["panic", &message]);
+        let _ = runtime.block_on(sentry.send(event));
+        let _ = done.send(());
+    });
+    let _ = finished.recv_timeout(PANIC_TIMEOUT);
+}
+
+/// 32 random hex digits
+fn event_id() -> String {
+    let random = || RandomState::new().build_hasher().finish();
+    format!("{:016x}{:016x}", random(), random())
+}
@@ -0,0 +1,70 @@
+//! Messages to a Slack channel through an incoming webhook
+
+use serde::Deserialize;
+use serde_json::json;
+
+use super::{BoxFuture, Event, EventKind, Notification, Notifier, MESSAGE_EVENTS};
+use crate::{http_log::SendLogged, DynsixError};
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct SlackConfig {
+    pub webhook_url: String,
+    /// Message text, `{title}` and `{body}` are replaced by the notification
+    #[serde(default = "default_template")]
+    pub template: String,
+    /// Events sent to this backend, see [`EventKind`]
+    pub events: Option<Vec<EventKind>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Slack {
+    http: reqwest::Client,
+    config: SlackConfig,
+}
+
+impl Slack {
+    pub fn new(http: reqwest::Client, config: &SlackConfig) -> Self {
+        Self {
+            http,
+            config: config.clone(),
+        }
+    }
+
+    pub async fn send(&self, notification: &Notification) -> Result<(), DynsixError> {
+        self.http
+            .post(&self.config.webhook_url)
+            .json(&json!({ "text": notification.render(&self.config.template) }))
+            .send_logged()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map(|_| ())
+            .map_err(|source| DynsixError::Notify {
+                backend: "slack",
+                source,
+            })
+    }
+}
+
+impl Notifier for Slack {
+    fn name(&self) -> &'static str {
+        "slack"
+    }
+
+    fn default_events(&self) -> &'static [EventKind] {
+        MESSAGE_EVENTS
+    }
+
+    fn notify<'a>(&'a self, event: &'a Event<'a>) -> BoxFuture<'a, Result<(), DynsixError>> {
+        Box::pin(async move {
+            match Notification::from_event(event) {
+                Some(notification) => self.send(&notification).await,
+                None => Ok(()),
+            }
+        })
+    }
+}
+
+fn default_template() -> String {
+    "*{title}*\n{body}".to_string()
+}
@@ -0,0 +1,83 @@
+//! Messages from a Telegram bot through the Bot API
+
+use serde::Deserialize;
+use serde_json::json;
+
+use super::{BoxFuture, Event, EventKind, Notification, Notifier, MESSAGE_EVENTS};
+use crate::{http_log::SendLogged, secret::Secret, DynsixError};
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct TelegramConfig {
+    /// Token handed out by @BotFather
+    pub bot_token: Secret,
+    /// Chat the bot writes to, a numeric id or `@channelname`
+    pub chat_id: String,
+    #[serde(default = "default_api_url")]
+    pub api_url: String,
+    /// Events sent to this backend, see [`EventKind`]
+    pub events: Option<Vec<EventKind>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Telegram {
+    http: reqwest::Client,
+    config: TelegramConfig,
+}
+
+impl Telegram {
+    pub fn new(http: reqwest::Client, config: &TelegramConfig) -> Self {
+        Self {
+            http,
+            config: config.clone(),
+        }
+    }
+
+    pub async fn send(&self, notification: &Notification) -> Result<(), DynsixError> {
+        let url = format!(
+            "{}/bot{}/sendMessage",
+            self.config.api_url.trim_end_matches('/'),
+            self.config.bot_token.expose()
+        );
+        let icon = if notification.failure { "⚠️ " } else { "" };
+
+        self.http
+            .post(url)
+            .json(&json!({
+                "chat_id": self.config.chat_id,
+                "text": format!("{icon}{}\n{}", notification.title, notification.body),
+            }))
+            .send_logged()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map(|_| ())
+            // The URL holds the token
+            .map_err(|source| DynsixError::Notify {
+                backend: "telegram",
+                source: source.without_url(),
+            })
+    }
+}
+
+impl Notifier for Telegram {
+    fn name(&self) -> &'static str {
+        "telegram"
+    }
+
+    fn default_events(&self) -> &'static [EventKind] {
+        MESSAGE_EVENTS
+    }
+
+    fn notify<'a>(&'a self, event: &'a Event<'a>) -> BoxFuture<'a, Result<(), DynsixError>> {
+        Box::pin(async move {
+            match Notification::from_event(event) {
+                Some(notification) => self.send(&notification).await,
+                None => Ok(()),
+            }
+        })
+    }
+}
+
+fn default_api_url() -> String {
+    "https://api.telegram.org".to_string()
+}
@@ -0,0 +1,81 @@
+//! Reviewable description of the changes a run would make
+
+use std::{net::Ipv6Addr, path::Path};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{provider::ProviderKind, DynsixError};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum PlannedAction {
+    Create,
+    Update,
+    /// The record differs but the service is in its `quiet_hours`
+    Defer,
+    NoOp,
+}
+
+/// The desired state of one service next to what its provider currently serves
+#[derive(Serialize, Deserialize, Debug)]
+pub struct PlannedChange {
+    pub service: String,
+    /// Plans written before providers existed only had Gandi services
+    #[serde(default)]
+    pub provider: ProviderKind,
+    pub fqdn: String,
+    pub name: String,
+    pub ttl: u32,
+    pub action: PlannedAction,
+    /// Values currently published, `None` if there is no record yet
+    pub current: Option<Vec<String>>,
+    pub desired: Ipv6Addr,
+}
+
+/// A record dynsix created for a service that is gone from the config, to be
+/// deleted with `prune`
+#[derive(Serialize, Deserialize, Debug)]
+pub struct PlannedDeletion {
+    /// The service that last changed it
+    pub service: String,
+    pub provider: ProviderKind,
+    pub fqdn: String,
+    pub name: String,
+    /// Values currently published
+    pub current: Vec<String>,
+}
+
+/// A service whose records could not be looked up, so nothing is planned for it
+#[derive(Serialize, Deserialize, Debug)]
+pub struct PlanFailure {
+    pub service: String,
+    pub error: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Plan {
+    pub public_ip: Ipv6Addr,
+    pub changes: Vec<PlannedChange>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub deletions: Vec<PlannedDeletion>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub failed: Vec<PlanFailure>,
+}
+
+impl Plan {
+    pub fn load(path: &Path) -> Result<Self, DynsixError> {
+        let raw = std::fs::read(path).map_err(|e| DynsixError::io(path, e))?;
+        serde_json::from_slice(&raw).map_err(|e| DynsixError::Parse {
+            what: format!("plan {}", path.display()),
+            message: e.to_string(),
+        })
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), DynsixError> {
+        let raw = serde_json::to_string_pretty(self).map_err(|e| DynsixError::Parse {
+            what: "plan".to_string(),
+            message: e.to_string(),
+        })?;
+        std::fs::write(path, raw).map_err(|e| DynsixError::io(path, e))
+    }
+}
@@ -0,0 +1,84 @@
+//! Switching the daemon to an unprivileged user once the sockets that may
+//! need root are open, see `daemon.user` and `daemon.group`
+
+use std::{ffi::CString, io};
+
+use dynsix::config::DaemonConfig;
+use tracing::{info, warn};
+
+/// Switches to the configured user and group, if any. Does nothing unless
+/// running as root.
+pub fn switch_user(config: &DaemonConfig) -> Result<(), Box<dyn std::error::Error>> {
+    if config.user.is_none() && config.group.is_none() {
+        return Ok(());
+    }
+    // SAFETY: geteuid has no preconditions
+    if unsafe { libc::geteuid() } != 0 {
+        warn!("Not running as root, ignoring daemon.user and daemon.group");
+        return Ok(());
+    }
+
+    let user = config.user.as_deref().map(lookup_user).transpose()?;
+    let gid = match (&config.group, &user) {
+        (Some(group), _) => lookup_group(group)?,
+        (None, Some((_, _, gid))) => *gid,
+        (None, None) => unreachable!("checked above"),
+    };
+
+    // SAFETY: the pointers are valid for the duration of the calls. The
+    // supplementary groups go first, root is required to change them.
+    unsafe {
+        match &user {
+            Some((name, _, _)) => check(libc::initgroups(name.as_ptr(), gid as _), "initgroups")?,
+            None => check(libc::setgroups(0, std::ptr::null()), "setgroups")?,
+        }
+        check(libc::setgid(gid), "setgid")?;
+        if let Some((_, uid, _)) = &user {
+            check(libc::setuid(*uid), "setuid")?;
+        }
+    }
+
+    info!(
+        "Running as {}:{}",
+        config.user.as_deref().unwrap_or("root"),
+        config
+            .group
+            .as_deref()
+            .map_or(gid.to_string(), str::to_string)
+    );
+    Ok(())
+}
+
+/// Name, uid and primary gid of `name`
+fn lookup_user(name: &str) -> Result<(CString, libc::uid_t, libc::gid_t), String> {
+    let c_name = CString::new(name).map_err(|_| format!("invalid user name '{name}'"))?;
+    // SAFETY: getpwnam returns null or a pointer to a static entry, which is
+    // read before anything else could look up users
+    let entry = unsafe { libc::getpwnam(c_name.as_ptr()) };
+    if entry.is_null() {
+        return Err(format!("daemon.user: no user named '{name}'"));
+    }
+    let (uid, gid) = unsafe { ((*entry).pw_uid, (*entry).pw_gid) };
+    Ok((c_name, uid, gid))
+}
+
+fn lookup_group(name: &str) -> Result<libc::gid_t, String> {
+    let c_name = CString::new(name).map_err(|_| format!("invalid group name '{name}'"))?;
+    // SAFETY: as for getpwnam
+    let entry = unsafe { libc::getgrnam(c_name.as_ptr()) };
+    if entry.is_null() {
+        return Err(format!("daemon.group: no group named '{name}'"));
+    }
+    Ok(unsafe { (*entry).gr_gid })
+}
+
+fn check(result: libc::c_int, call: &str) -> Result<(), String> {
+    if result == 0 {
+        Ok(())
+    } else {
+        Err(format!(
+            "dropping privileges failed in {call}: {}",
+            io::Error::last_os_error()
+        ))
+    }
+}
@@ -0,0 +1,116 @@
+//! Just enough of the Protocol Buffers wire format for the gRPC API of the
+//! daemon, whose messages are described in `proto/dynsix.proto`
+
+/// Builds a message field by field. Like proto3, empty strings are left out.
+#[derive(Debug, Default, Clone)]
+pub struct Encoder {
+    buffer: Vec<u8>,
+}
+
+impl Encoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn string(&mut self, field: u32, value: &str) -> &mut Self {
+        if !value.is_empty() {
+            self.bytes(field, value.as_bytes());
+        }
+        self
+    }
+
+    /// A string even if it is empty, as a value of a repeated field
+    pub fn repeated_string(&mut self, field: u32, value: &str) -> &mut Self {
+        self.bytes(field, value.as_bytes());
+        self
+    }
+
+    pub fn message(&mut self, field: u32, message: &Encoder) -> &mut Self {
+        self.bytes(field, &message.buffer);
+        self
+    }
+
+    fn bytes(&mut self, field: u32, value: &[u8]) {
+        varint(&mut self.buffer, u64::from(field) << 3 | 2);
+        varint(&mut self.buffer, value.len() as u64);
+        self.buffer.extend_from_slice(value);
+    }
+
+    pub fn finish(self) -> Vec<u8> {
+        self.buffer
+    }
+}
+
+fn varint(buffer: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buffer.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    buffer.push(value as u8);
+}
+
+/// The value of a field by its wire type
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Value<'a> {
+    Varint(u64),
+    Fixed64(u64),
+    /// Strings, bytes, embedded messages and packed repeated fields
+    Bytes(&'a [u8]),
+    Fixed32(u32),
+}
+
+impl<'a> Value<'a> {
+    pub fn as_str(&self) -> Option<&'a str> {
+        match self {
+            Self::Bytes(bytes) => std::str::from_utf8(bytes).ok(),
+            _ => None,
+        }
+    }
+}
+
+/// The fields of a message by number, in the order they were encoded
+pub fn decode(mut message: &[u8]) -> Result<Vec<(u32, Value<'_>)>, String> {
+    let mut fields = Vec::new();
+    while !message.is_empty() {
+        let key = read_varint(&mut message)?;
+        let field = u32::try_from(key >> 3).map_err(|_| "field number out of range")?;
+        let value = match key & 7 {
+            0 => Value::Varint(read_varint(&mut message)?),
+            1 => Value::Fixed64(u64::from_le_bytes(
+                take(&mut message, 8)?.try_into().unwrap(),
+            )),
+            2 => {
+                let length = read_varint(&mut message)?;
+                let length = usize::try_from(length).map_err(|_| "length out of range")?;
+                Value::Bytes(take(&mut message, length)?)
+            }
+            5 => Value::Fixed32(u32::from_le_bytes(
+                take(&mut message, 4)?.try_into().unwrap(),
+            )),
+            other => return Err(format!("unsupported wire type {other} of field {field}")),
+        };
+        fields.push((field, value));
+    }
+    Ok(fields)
+}
+
+fn read_varint(message: &mut &[u8]) -> Result<u64, String> {
+    let mut value = 0;
+    for shift in (0..64).step_by(7) {
+        let byte = take(message, 1)?[0];
+        value |= u64::from(byte & 0x7f) << shift;
+        if byte < 0x80 {
+            return Ok(value);
+        }
+    }
+    Err("varint is longer than 10 bytes".to_string())
+}
+
+fn take<'a>(message: &mut &'a [u8], length: usize) -> Result<&'a [u8], String> {
+    if message.len() < length {
+        return Err("message is truncated".to_string());
+    }
+    let (taken, rest) = message.split_at(length);
+    *message = rest;
+    Ok(taken)
+}
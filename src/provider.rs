@@ -0,0 +1,406 @@
+//! DNS providers publishing the records. Gandi LiveDNS, configured through the
+//! top level `token`, is the default; other providers are configured in their
+//! `[providers.*]` section and selected with the `provider` option of a service.
+
+use std::{
+    collections::HashMap,
+    fmt,
+    net::{IpAddr, Ipv6Addr},
+    ops::RangeInclusive,
+    sync::Arc,
+};
+
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+
+use crate::{idna, notify::BoxFuture, DynsixError};
+
+pub mod desec;
+pub mod dyndns2;
+pub mod hetzner;
+pub mod http;
+pub mod ovh;
+pub mod porkbun;
+pub mod route53;
+
+#[derive(
+    Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum ProviderKind {
+    #[default]
+    Gandi,
+    Route53,
+    Hetzner,
+    Desec,
+    Http,
+    Dyndns2,
+    Porkbun,
+    Ovh,
+}
+
+impl ProviderKind {
+    pub const ALL: [Self; 8] = [
+        Self::Gandi,
+        Self::Route53,
+        Self::Hetzner,
+        Self::Desec,
+        Self::Http,
+        Self::Dyndns2,
+        Self::Porkbun,
+        Self::Ovh,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Gandi => "gandi",
+            Self::Route53 => "route53",
+            Self::Hetzner => "hetzner",
+            Self::Desec => "desec",
+            Self::Http => "http",
+            Self::Dyndns2 => "dyndns2",
+            Self::Porkbun => "porkbun",
+            Self::Ovh => "ovh",
+        }
+    }
+
+    /// The TTLs the provider accepts. Providers that don't publish limits, or
+    /// ignore the TTL, get the range of RFC 2181.
+    pub fn ttl_range(self) -> RangeInclusive<u32> {
+        const RFC_2181: u32 = i32::MAX as u32;
+        match self {
+            Self::Gandi => 300..=2_592_000,
+            // The minimum is set per domain, 60 for dedyn.io dynDNS domains
+            // and 3600 for most others, so it is left to the API
+            Self::Desec => 0..=86_400,
+            Self::Porkbun => 600..=RFC_2181,
+            Self::Ovh => 60..=RFC_2181,
+            Self::Route53 | Self::Hetzner | Self::Http | Self::Dyndns2 => 0..=RFC_2181,
+        }
+    }
+}
+
+impl ProviderKind {
+    /// Whether the provider publishes records of other types than AAAA, see
+    /// [`Provider::set_records`]
+    pub fn any_record_type(self) -> bool {
+        self == Self::Gandi
+    }
+}
+
+impl fmt::Display for ProviderKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// The `[providers]` section of the config
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct ProvidersConfig {
+    pub route53: Option<route53::Route53Config>,
+    pub hetzner: Option<hetzner::HetznerConfig>,
+    pub desec: Option<desec::DesecConfig>,
+    pub http: Option<http::HttpConfig>,
+    pub dyndns2: Option<dyndns2::Dyndns2Config>,
+    pub porkbun: Option<porkbun::PorkbunConfig>,
+    pub ovh: Option<ovh::OvhConfig>,
+}
+
+impl ProvidersConfig {
+    /// Whether services can use `kind`, Gandi is always available
+    pub fn is_configured(&self, kind: ProviderKind) -> bool {
+        match kind {
+            ProviderKind::Gandi => true,
+            ProviderKind::Route53 => self.route53.is_some(),
+            ProviderKind::Hetzner => self.hetzner.is_some(),
+            ProviderKind::Desec => self.desec.is_some(),
+            ProviderKind::Http => self.http.is_some(),
+            ProviderKind::Dyndns2 => self.dyndns2.is_some(),
+            ProviderKind::Porkbun => self.porkbun.is_some(),
+            ProviderKind::Ovh => self.ovh.is_some(),
+        }
+    }
+
+    /// The configuration a service's own `credentials` stand for, as if they
+    /// were the `[providers.*]` section of its provider
+    pub fn for_service(
+        kind: ProviderKind,
+        credentials: &serde_json::Value,
+    ) -> Result<Self, String> {
+        if kind == ProviderKind::Gandi {
+            return Err("not supported for gandi, which uses the top level token".to_string());
+        }
+        serde_json::from_value(serde_json::json!({ kind.name(): credentials }))
+            .map_err(|e| e.to_string())
+    }
+
+    /// A client for every configured provider
+    pub fn build(&self, http: &reqwest::Client) -> Vec<(ProviderKind, Arc<dyn Provider>)> {
+        let mut providers: Vec<(ProviderKind, Arc<dyn Provider>)> = Vec::new();
+        if let Some(config) = &self.route53 {
+            providers.push((
+                ProviderKind::Route53,
+                Arc::new(route53::Route53::new(http.clone(), config)),
+            ));
+        }
+        if let Some(config) = &self.hetzner {
+            providers.push((
+                ProviderKind::Hetzner,
+                Arc::new(hetzner::Hetzner::new(http.clone(), config)),
+            ));
+        }
+        if let Some(config) = &self.desec {
+            providers.push((
+                ProviderKind::Desec,
+                Arc::new(desec::Desec::new(http.clone(), config)),
+            ));
+        }
+        if let Some(config) = &self.http {
+            providers.push((
+                ProviderKind::Http,
+                Arc::new(http::Http::new(http.clone(), config)),
+            ));
+        }
+        if let Some(config) = &self.dyndns2 {
+            providers.push((
+                ProviderKind::Dyndns2,
+                Arc::new(dyndns2::Dyndns2::new(http.clone(), config)),
+            ));
+        }
+        if let Some(config) = &self.porkbun {
+            providers.push((
+                ProviderKind::Porkbun,
+                Arc::new(porkbun::Porkbun::new(http.clone(), config)),
+            ));
+        }
+        if let Some(config) = &self.ovh {
+            providers.push((
+                ProviderKind::Ovh,
+                Arc::new(ovh::Ovh::new(http.clone(), config)),
+            ));
+        }
+        providers
+    }
+
+    pub(crate) fn validate(&self, problems: &mut Vec<String>) {
+        if let Some(route53) = &self.route53 {
+            if route53.access_key_id.is_some() != route53.secret_access_key.is_some() {
+                problems.push(
+                    "providers.route53 needs both access_key_id and secret_access_key, or neither to use the instance profile"
+                        .to_string(),
+                );
+            }
+            check_url("route53.endpoint", &route53.endpoint, problems);
+        }
+        if let Some(hetzner) = &self.hetzner {
+            if hetzner.token.is_blank() {
+                problems.push("providers.hetzner.token is empty".to_string());
+            }
+            check_url("hetzner.endpoint", &hetzner.endpoint, problems);
+        }
+        if let Some(desec) = &self.desec {
+            if desec.token.is_blank() {
+                problems.push("providers.desec.token is empty".to_string());
+            }
+            check_url("desec.endpoint", &desec.endpoint, problems);
+        }
+        if let Some(http) = &self.http {
+            if !http.url.contains("{ip}") {
+                problems.push(format!(
+                    "providers.http.url '{}' does not contain {{ip}}",
+                    http.url
+                ));
+            }
+            check_url("http.url", &http.url, problems);
+        }
+        if let Some(dyndns2) = &self.dyndns2 {
+            check_url("dyndns2.server", &dyndns2.server, problems);
+        }
+        if let Some(porkbun) = &self.porkbun {
+            check_url("porkbun.endpoint", &porkbun.endpoint, problems);
+        }
+        if let Some(ovh) = &self.ovh {
+            check_url("ovh.endpoint", &ovh.endpoint, problems);
+        }
+    }
+}
+
+/// Reports `providers.{option}` unless it is a valid URL
+fn check_url(option: &str, url: &str, problems: &mut Vec<String>) {
+    if let Err(e) = reqwest::Url::parse(url) {
+        problems.push(format!(
+            "providers.{option} '{url}' is not a valid URL: {e}"
+        ));
+    }
+}
+
+/// The values and TTL of a published AAAA record
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Record {
+    pub values: Vec<String>,
+    /// `None` if the provider doesn't tell, like those looked up in DNS
+    pub ttl: Option<u32>,
+}
+
+/// Access to the AAAA records of a DNS provider. Records are addressed like
+/// in the config, by the zone `fqdn` and the `name` within it, `@` being the
+/// zone apex.
+pub trait Provider: fmt::Debug + Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// The record, `None` if it does not exist
+    fn fetch_record<'a>(
+        &'a self,
+        fqdn: &'a str,
+        name: &'a str,
+    ) -> BoxFuture<'a, Result<Option<Record>, DynsixError>>;
+
+    fn create_record<'a>(
+        &'a self,
+        fqdn: &'a str,
+        name: &'a str,
+        ttl: u32,
+        ip: Ipv6Addr,
+    ) -> BoxFuture<'a, Result<(), DynsixError>>;
+
+    /// Replaces the values of an existing record
+    fn update_record<'a>(
+        &'a self,
+        fqdn: &'a str,
+        name: &'a str,
+        ttl: u32,
+        ip: Ipv6Addr,
+    ) -> BoxFuture<'a, Result<(), DynsixError>>;
+
+    fn delete_record<'a>(
+        &'a self,
+        fqdn: &'a str,
+        name: &'a str,
+    ) -> BoxFuture<'a, Result<(), DynsixError>>;
+
+    /// All AAAA records of the zone `fqdn` by their name in ASCII, `@` for
+    /// the apex, fetched at once. `None` if the provider can only look up
+    /// records one by one.
+    fn fetch_zone<'a>(
+        &'a self,
+        fqdn: &'a str,
+    ) -> BoxFuture<'a, Result<Option<HashMap<String, Record>>, DynsixError>> {
+        let _ = fqdn;
+        Box::pin(std::future::ready(Ok(None)))
+    }
+
+    /// The records of type `kind` other than AAAA, e.g. SSHFP, `None` if
+    /// there are none. Only providers for which
+    /// [`ProviderKind::any_record_type`] holds support this.
+    fn fetch_records<'a>(
+        &'a self,
+        fqdn: &'a str,
+        name: &'a str,
+        kind: &'a str,
+    ) -> BoxFuture<'a, Result<Option<Record>, DynsixError>> {
+        let _ = (fqdn, name);
+        Box::pin(std::future::ready(Err(unsupported(
+            self.name(),
+            "fetching",
+            kind,
+        ))))
+    }
+
+    /// Creates the records of type `kind` with `values` or replaces those
+    /// there are
+    fn set_records<'a>(
+        &'a self,
+        fqdn: &'a str,
+        name: &'a str,
+        kind: &'a str,
+        ttl: u32,
+        values: &'a [String],
+    ) -> BoxFuture<'a, Result<(), DynsixError>> {
+        let _ = (fqdn, name, ttl, values);
+        Box::pin(std::future::ready(Err(unsupported(
+            self.name(),
+            "setting",
+            kind,
+        ))))
+    }
+}
+
+fn unsupported(provider: &'static str, operation: &'static str, kind: &str) -> DynsixError {
+    DynsixError::Provider {
+        provider,
+        operation,
+        message: format!("{kind} records are not supported"),
+    }
+}
+
+/// Whether the records of other types than AAAA hold the same `values`, in
+/// any order, spacing and case, e.g. of the hex digits of SSHFP and TLSA
+pub fn same_values(values: &[String], expected: &[String]) -> bool {
+    let normalize = |values: &[String]| {
+        let mut values: Vec<_> = values
+            .iter()
+            .map(|value| {
+                value
+                    .split_whitespace()
+                    .collect::<Vec<_>>()
+                    .join(" ")
+                    .to_lowercase()
+            })
+            .collect();
+        values.sort();
+        values.dedup();
+        values
+    };
+    normalize(values) == normalize(expected)
+}
+
+/// `name.fqdn`, or just `fqdn` for the apex
+pub fn record_name(fqdn: &str, name: &str) -> String {
+    if name == "@" {
+        fqdn.to_string()
+    } else {
+        format!("{name}.{fqdn}")
+    }
+}
+
+/// [`record_name`] for people, with internationalized labels decoded
+pub fn display_name(fqdn: &str, name: &str) -> String {
+    idna::to_unicode(&record_name(fqdn, name))
+}
+
+/// Characters escaped in a record name used as a URL path segment. `*` is
+/// legal in paths but some APIs read it as a pattern; `@` is kept as the
+/// apex is spelled that way in their paths.
+const PATH_SEGMENT: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'_')
+    .remove(b'.')
+    .remove(b'@');
+
+/// `name` escaped for use in a URL path, e.g. `%2A` for a wildcard
+pub(crate) fn path_segment(name: &str) -> String {
+    utf8_percent_encode(name, PATH_SEGMENT).to_string()
+}
+
+/// The AAAA record as the resolver returns it, for providers that can only
+/// update records. It may lag behind an update by the TTL of the record,
+/// which is unknown.
+pub(crate) async fn resolve(record: &str) -> Option<Record> {
+    let addresses = match tokio::net::lookup_host((record, 0)).await {
+        Ok(addresses) => addresses,
+        Err(e) => {
+            debug!("Could not resolve {record}: {e}");
+            return None;
+        }
+    };
+
+    let values: Vec<_> = addresses
+        .filter_map(|address| match address.ip() {
+            IpAddr::V6(ip) => Some(ip.to_string()),
+            IpAddr::V4(_) => None,
+        })
+        .collect();
+    (!values.is_empty()).then_some(Record { values, ttl: None })
+}
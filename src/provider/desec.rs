@@ -0,0 +1,185 @@
+//! [deSEC](https://desec.io/) through its REST API, which replaces whole
+//! RRsets with a single PUT
+
+use std::net::Ipv6Addr;
+
+use reqwest::{header::HeaderValue, Method, RequestBuilder, StatusCode};
+use serde::{Deserialize, Serialize};
+
+use super::{path_segment, Provider, Record};
+use crate::{http_log::SendLogged, notify::BoxFuture, secret::Secret, DynsixError};
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct DesecConfig {
+    pub token: Secret,
+    #[serde(default = "default_endpoint")]
+    pub endpoint: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct RRset {
+    /// Empty for the apex
+    subname: String,
+    #[serde(rename = "type")]
+    kind: String,
+    ttl: u32,
+    records: Vec<String>,
+}
+
+#[derive(Debug)]
+pub struct Desec {
+    http: reqwest::Client,
+    config: DesecConfig,
+    authorization: HeaderValue,
+}
+
+impl Desec {
+    pub fn new(http: reqwest::Client, config: &DesecConfig) -> Self {
+        Self {
+            http,
+            config: config.clone(),
+            authorization: config.token.header("Token"),
+        }
+    }
+
+    fn request(&self, method: Method, path: &str) -> RequestBuilder {
+        self.http
+            .request(
+                method,
+                format!("{}{path}", self.config.endpoint.trim_end_matches('/')),
+            )
+            .header("Authorization", self.authorization.clone())
+    }
+
+    /// Replaces the RRset, creating it if needed
+    async fn put(
+        &self,
+        operation: &'static str,
+        fqdn: &str,
+        name: &str,
+        ttl: u32,
+        ip: Ipv6Addr,
+    ) -> Result<(), DynsixError> {
+        let rrsets = [RRset {
+            subname: subname(name).to_string(),
+            kind: "AAAA".to_string(),
+            ttl,
+            records: vec![ip.to_string()],
+        }];
+        let response = self
+            .request(Method::PUT, &format!("/domains/{fqdn}/rrsets/"))
+            .json(&rrsets)
+            .send_logged()
+            .await?;
+        check(operation, response).await.map(|_| ())
+    }
+}
+
+impl Provider for Desec {
+    fn name(&self) -> &'static str {
+        "desec"
+    }
+
+    fn fetch_record<'a>(
+        &'a self,
+        fqdn: &'a str,
+        name: &'a str,
+    ) -> BoxFuture<'a, Result<Option<Record>, DynsixError>> {
+        Box::pin(async move {
+            let response = self
+                .request(Method::GET, &rrset_path(fqdn, name))
+                .send_logged()
+                .await?;
+            if response.status() == StatusCode::NOT_FOUND {
+                return Ok(None);
+            }
+            let body = check("fetching", response).await?;
+            let rrset: RRset = serde_json::from_str(&body).map_err(|e| error("fetching", e))?;
+            Ok(Some(Record {
+                values: rrset.records,
+                ttl: Some(rrset.ttl),
+            }))
+        })
+    }
+
+    fn create_record<'a>(
+        &'a self,
+        fqdn: &'a str,
+        name: &'a str,
+        ttl: u32,
+        ip: Ipv6Addr,
+    ) -> BoxFuture<'a, Result<(), DynsixError>> {
+        Box::pin(self.put("setting", fqdn, name, ttl, ip))
+    }
+
+    fn update_record<'a>(
+        &'a self,
+        fqdn: &'a str,
+        name: &'a str,
+        ttl: u32,
+        ip: Ipv6Addr,
+    ) -> BoxFuture<'a, Result<(), DynsixError>> {
+        Box::pin(self.put("updating", fqdn, name, ttl, ip))
+    }
+
+    fn delete_record<'a>(
+        &'a self,
+        fqdn: &'a str,
+        name: &'a str,
+    ) -> BoxFuture<'a, Result<(), DynsixError>> {
+        Box::pin(async move {
+            let response = self
+                .request(Method::DELETE, &rrset_path(fqdn, name))
+                .send_logged()
+                .await?;
+            check("deleting", response).await.map(|_| ())
+        })
+    }
+}
+
+/// deSEC addresses the apex with an empty subname
+fn subname(name: &str) -> &str {
+    if name == "@" {
+        ""
+    } else {
+        name
+    }
+}
+
+/// In paths the apex is `@`, as an empty segment would not survive
+fn rrset_path(fqdn: &str, name: &str) -> String {
+    format!("/domains/{fqdn}/rrsets/{}/AAAA/", path_segment(name))
+}
+
+/// The body of a successful response, or the error deSEC reported
+async fn check(
+    operation: &'static str,
+    response: reqwest::Response,
+) -> Result<String, DynsixError> {
+    let status = response.status();
+    let body = response.text().await?;
+    if status.is_success() {
+        return Ok(body);
+    }
+
+    let json: serde_json::Value = serde_json::from_str(&body).unwrap_or_default();
+    let message = json
+        .get("detail")
+        .and_then(|detail| detail.as_str())
+        .map(str::to_string)
+        .unwrap_or_else(|| body.trim().to_string());
+    Err(error(operation, format!("{status}: {message}")))
+}
+
+fn error(operation: &'static str, message: impl ToString) -> DynsixError {
+    DynsixError::Provider {
+        provider: "desec",
+        operation,
+        message: message.to_string(),
+    }
+}
+
+fn default_endpoint() -> String {
+    "https://desec.io/api/v1".to_string()
+}
@@ -0,0 +1,140 @@
+//! The dyndns2 update protocol of dyndns.org, spoken by No-IP, Strato,
+//! Dynu and many others. Like with [`super::http`], the published record is
+//! looked up in DNS.
+
+use std::net::Ipv6Addr;
+
+use serde::Deserialize;
+
+use super::{record_name, resolve, Provider, Record};
+use crate::{http_log::SendLogged, notify::BoxFuture, secret::Secret, DynsixError};
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Dyndns2Config {
+    /// Base URL of the update server, e.g. `https://dynupdate.no-ip.com`
+    pub server: String,
+    pub username: String,
+    pub password: Secret,
+}
+
+#[derive(Debug)]
+pub struct Dyndns2 {
+    http: reqwest::Client,
+    config: Dyndns2Config,
+}
+
+impl Dyndns2 {
+    pub fn new(http: reqwest::Client, config: &Dyndns2Config) -> Self {
+        Self {
+            http,
+            config: config.clone(),
+        }
+    }
+
+    async fn update(
+        &self,
+        operation: &'static str,
+        fqdn: &str,
+        name: &str,
+        ip: Ipv6Addr,
+    ) -> Result<(), DynsixError> {
+        let response = self
+            .http
+            .get(format!(
+                "{}/nic/update",
+                self.config.server.trim_end_matches('/')
+            ))
+            .query(&[
+                ("hostname", record_name(fqdn, name)),
+                ("myip", ip.to_string()),
+            ])
+            .basic_auth(&self.config.username, Some(self.config.password.expose()))
+            .header(
+                "User-Agent",
+                concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION")),
+            )
+            .send_logged()
+            .await?;
+        let status = response.status();
+        let body = response.text().await?;
+
+        // The first word of the answer is the result code
+        let code = body.split_whitespace().next().unwrap_or_default();
+        match code {
+            "good" | "nochg" => Ok(()),
+            _ => Err(DynsixError::Provider {
+                provider: "dyndns2",
+                operation,
+                message: match describe(code) {
+                    Some(description) => format!("{code}: {description}"),
+                    None => format!("{status}: {}", body.trim()),
+                },
+            }),
+        }
+    }
+}
+
+impl Provider for Dyndns2 {
+    fn name(&self) -> &'static str {
+        "dyndns2"
+    }
+
+    fn fetch_record<'a>(
+        &'a self,
+        fqdn: &'a str,
+        name: &'a str,
+    ) -> BoxFuture<'a, Result<Option<Record>, DynsixError>> {
+        Box::pin(async move { Ok(resolve(&record_name(fqdn, name)).await) })
+    }
+
+    fn create_record<'a>(
+        &'a self,
+        fqdn: &'a str,
+        name: &'a str,
+        _ttl: u32,
+        ip: Ipv6Addr,
+    ) -> BoxFuture<'a, Result<(), DynsixError>> {
+        Box::pin(self.update("setting", fqdn, name, ip))
+    }
+
+    fn update_record<'a>(
+        &'a self,
+        fqdn: &'a str,
+        name: &'a str,
+        _ttl: u32,
+        ip: Ipv6Addr,
+    ) -> BoxFuture<'a, Result<(), DynsixError>> {
+        Box::pin(self.update("updating", fqdn, name, ip))
+    }
+
+    fn delete_record<'a>(
+        &'a self,
+        _fqdn: &'a str,
+        _name: &'a str,
+    ) -> BoxFuture<'a, Result<(), DynsixError>> {
+        Box::pin(async {
+            Err(DynsixError::Provider {
+                provider: "dyndns2",
+                operation: "deleting",
+                message: "the dyndns2 protocol can not delete records".to_string(),
+            })
+        })
+    }
+}
+
+/// Meaning of the error codes of the protocol
+fn describe(code: &str) -> Option<&'static str> {
+    Some(match code {
+        "badauth" => "username or password is wrong",
+        "!donator" => "the update needs a paid account",
+        "notfqdn" => "the hostname is not a fully qualified domain name",
+        "nohost" => "the hostname does not exist in this account",
+        "numhost" => "too many hosts in one update",
+        "abuse" => "the hostname is blocked for abuse",
+        "badagent" => "the user agent was rejected",
+        "dnserr" => "DNS error on the server",
+        "911" => "problem on the server, retry later",
+        _ => return None,
+    })
+}
@@ -0,0 +1,274 @@
+//! [Hetzner DNS](https://dns.hetzner.com/api-docs) through its REST API
+
+use std::{collections::HashMap, net::Ipv6Addr, sync::Mutex};
+
+use reqwest::{header::HeaderValue, Method, RequestBuilder};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use super::{Provider, Record};
+use crate::{http_log::SendLogged, notify::BoxFuture, secret::Secret, DynsixError};
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct HetznerConfig {
+    pub token: Secret,
+    #[serde(default = "default_endpoint")]
+    pub endpoint: String,
+}
+
+#[derive(Deserialize)]
+struct Zones {
+    zones: Vec<Zone>,
+}
+
+#[derive(Deserialize)]
+struct Zone {
+    id: String,
+    name: String,
+}
+
+#[derive(Deserialize)]
+struct Records {
+    records: Vec<HetznerRecord>,
+}
+
+#[derive(Deserialize)]
+struct HetznerRecord {
+    id: String,
+    #[serde(rename = "type")]
+    kind: String,
+    name: String,
+    value: String,
+    /// Unset if the record uses the default TTL of the zone
+    ttl: Option<u32>,
+}
+
+#[derive(Serialize)]
+struct RecordRequest<'a> {
+    zone_id: &'a str,
+    #[serde(rename = "type")]
+    kind: &'static str,
+    name: &'a str,
+    value: String,
+    ttl: u32,
+}
+
+#[derive(Debug)]
+pub struct Hetzner {
+    http: reqwest::Client,
+    config: HetznerConfig,
+    authorization: HeaderValue,
+    /// Zone ids by domain
+    zones: Mutex<HashMap<String, String>>,
+}
+
+impl Hetzner {
+    pub fn new(http: reqwest::Client, config: &HetznerConfig) -> Self {
+        Self {
+            http,
+            config: config.clone(),
+            authorization: config.token.header(""),
+            zones: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn request(&self, method: Method, path: &str) -> RequestBuilder {
+        self.http
+            .request(
+                method,
+                format!("{}{path}", self.config.endpoint.trim_end_matches('/')),
+            )
+            .header("Auth-API-Token", self.authorization.clone())
+    }
+
+    /// Sends the request and decodes the body of a successful response
+    async fn send<T: DeserializeOwned>(
+        operation: &'static str,
+        request: RequestBuilder,
+    ) -> Result<T, DynsixError> {
+        let response = request.send_logged().await?;
+        let status = response.status();
+        let body = response.text().await?;
+        if !status.is_success() {
+            return Err(error(operation, format!("{status}: {}", message(&body))));
+        }
+        // Deleting answers with an empty body
+        let body = if body.trim().is_empty() {
+            "null"
+        } else {
+            &body
+        };
+        serde_json::from_str(body).map_err(|e| error(operation, e))
+    }
+
+    async fn zone_id(&self, operation: &'static str, fqdn: &str) -> Result<String, DynsixError> {
+        if let Some(id) = self.zones.lock().unwrap().get(fqdn) {
+            return Ok(id.clone());
+        }
+
+        let zones: Zones = Self::send(
+            operation,
+            self.request(Method::GET, "/zones").query(&[("name", fqdn)]),
+        )
+        .await?;
+        let id = zones
+            .zones
+            .into_iter()
+            .find(|zone| zone.name.eq_ignore_ascii_case(fqdn))
+            .map(|zone| zone.id)
+            .ok_or_else(|| error(operation, format!("no zone named {fqdn}")))?;
+
+        self.zones
+            .lock()
+            .unwrap()
+            .insert(fqdn.to_string(), id.clone());
+        Ok(id)
+    }
+
+    /// The AAAA records `name` of the zone, Hetzner keeps one per value
+    async fn records(
+        &self,
+        operation: &'static str,
+        zone: &str,
+        name: &str,
+    ) -> Result<Vec<HetznerRecord>, DynsixError> {
+        let records: Records = Self::send(
+            operation,
+            self.request(Method::GET, "/records")
+                .query(&[("zone_id", zone)]),
+        )
+        .await?;
+        Ok(records
+            .records
+            .into_iter()
+            .filter(|record| record.kind == "AAAA" && record.name.eq_ignore_ascii_case(name))
+            .collect())
+    }
+
+    /// Makes `ip` the only value of the record, reusing an existing entry
+    async fn set(
+        &self,
+        operation: &'static str,
+        fqdn: &str,
+        name: &str,
+        ttl: u32,
+        ip: Ipv6Addr,
+    ) -> Result<(), DynsixError> {
+        let zone = self.zone_id(operation, fqdn).await?;
+        let mut existing = self.records(operation, &zone, name).await?.into_iter();
+        let body = RecordRequest {
+            zone_id: &zone,
+            kind: "AAAA",
+            name,
+            value: ip.to_string(),
+            ttl,
+        };
+
+        let request = match existing.next() {
+            Some(record) => self.request(Method::PUT, &format!("/records/{}", record.id)),
+            None => self.request(Method::POST, "/records"),
+        };
+        Self::send::<serde_json::Value>(operation, request.json(&body)).await?;
+
+        for record in existing {
+            Self::send::<serde_json::Value>(
+                operation,
+                self.request(Method::DELETE, &format!("/records/{}", record.id)),
+            )
+            .await?;
+        }
+        Ok(())
+    }
+}
+
+impl Provider for Hetzner {
+    fn name(&self) -> &'static str {
+        "hetzner"
+    }
+
+    fn fetch_record<'a>(
+        &'a self,
+        fqdn: &'a str,
+        name: &'a str,
+    ) -> BoxFuture<'a, Result<Option<Record>, DynsixError>> {
+        Box::pin(async move {
+            let zone = self.zone_id("fetching", fqdn).await?;
+            let records = self.records("fetching", &zone, name).await?;
+            if records.is_empty() {
+                return Ok(None);
+            }
+            Ok(Some(Record {
+                ttl: records[0].ttl,
+                values: records.into_iter().map(|record| record.value).collect(),
+            }))
+        })
+    }
+
+    fn create_record<'a>(
+        &'a self,
+        fqdn: &'a str,
+        name: &'a str,
+        ttl: u32,
+        ip: Ipv6Addr,
+    ) -> BoxFuture<'a, Result<(), DynsixError>> {
+        Box::pin(self.set("setting", fqdn, name, ttl, ip))
+    }
+
+    fn update_record<'a>(
+        &'a self,
+        fqdn: &'a str,
+        name: &'a str,
+        ttl: u32,
+        ip: Ipv6Addr,
+    ) -> BoxFuture<'a, Result<(), DynsixError>> {
+        Box::pin(self.set("updating", fqdn, name, ttl, ip))
+    }
+
+    fn delete_record<'a>(
+        &'a self,
+        fqdn: &'a str,
+        name: &'a str,
+    ) -> BoxFuture<'a, Result<(), DynsixError>> {
+        Box::pin(async move {
+            let zone = self.zone_id("deleting", fqdn).await?;
+            let records = self.records("deleting", &zone, name).await?;
+            if records.is_empty() {
+                return Err(error(
+                    "deleting",
+                    format!("{name} in {fqdn} has no AAAA record"),
+                ));
+            }
+            for record in records {
+                Self::send::<serde_json::Value>(
+                    "deleting",
+                    self.request(Method::DELETE, &format!("/records/{}", record.id)),
+                )
+                .await?;
+            }
+            Ok(())
+        })
+    }
+}
+
+fn error(operation: &'static str, message: impl ToString) -> DynsixError {
+    DynsixError::Provider {
+        provider: "hetzner",
+        operation,
+        message: message.to_string(),
+    }
+}
+
+/// The message of an error response, which is either `{"message": ...}` or
+/// `{"error": {"message": ...}}`
+fn message(body: &str) -> String {
+    let json: serde_json::Value = serde_json::from_str(body).unwrap_or_default();
+    json.pointer("/error/message")
+        .or_else(|| json.get("message"))
+        .and_then(|message| message.as_str())
+        .unwrap_or(body.trim())
+        .to_string()
+}
+
+fn default_endpoint() -> String {
+    "https://dns.hetzner.com/api/v1".to_string()
+}
@@ -0,0 +1,129 @@
+//! Simple DynDNS services like DuckDNS, which update a record with a single
+//! GET request carrying a token and the new address. These can not be asked
+//! for the published record, so it is looked up in DNS instead.
+
+use std::net::Ipv6Addr;
+
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+use serde::Deserialize;
+
+use super::{record_name, resolve, Provider, Record};
+use crate::{http_log::SendLogged, notify::BoxFuture, secret::Secret, DynsixError};
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct HttpConfig {
+    /// Requested to set a record, `{name}`, `{fqdn}`, `{token}` and `{ip}` are
+    /// replaced by the URL encoded values
+    pub url: String,
+    #[serde(default)]
+    pub token: Secret,
+    /// Text a successful response contains, any 2xx response counts if unset
+    pub success: Option<String>,
+}
+
+#[derive(Debug)]
+pub struct Http {
+    http: reqwest::Client,
+    config: HttpConfig,
+}
+
+impl Http {
+    pub fn new(http: reqwest::Client, config: &HttpConfig) -> Self {
+        Self {
+            http,
+            config: config.clone(),
+        }
+    }
+
+    fn url(&self, fqdn: &str, name: &str, ip: Ipv6Addr) -> String {
+        let encode = |value: &str| utf8_percent_encode(value, NON_ALPHANUMERIC).to_string();
+        self.config
+            .url
+            .replace("{name}", &encode(name))
+            .replace("{fqdn}", &encode(fqdn))
+            .replace("{token}", &encode(self.config.token.expose()))
+            .replace("{ip}", &encode(&ip.to_string()))
+    }
+
+    async fn set(
+        &self,
+        operation: &'static str,
+        fqdn: &str,
+        name: &str,
+        ip: Ipv6Addr,
+    ) -> Result<(), DynsixError> {
+        // The URL carries the token, so it is left out of errors
+        let response = self
+            .http
+            .get(self.url(fqdn, name, ip))
+            .send_logged()
+            .await
+            .map_err(reqwest::Error::without_url)?;
+        let status = response.status();
+        let body = response.text().await.map_err(reqwest::Error::without_url)?;
+
+        let succeeded = status.is_success()
+            && self
+                .config
+                .success
+                .as_ref()
+                .is_none_or(|success| body.contains(success.as_str()));
+        if !succeeded {
+            return Err(DynsixError::Provider {
+                provider: "http",
+                operation,
+                message: format!("{status}: {}", body.trim()),
+            });
+        }
+        Ok(())
+    }
+}
+
+impl Provider for Http {
+    fn name(&self) -> &'static str {
+        "http"
+    }
+
+    fn fetch_record<'a>(
+        &'a self,
+        fqdn: &'a str,
+        name: &'a str,
+    ) -> BoxFuture<'a, Result<Option<Record>, DynsixError>> {
+        Box::pin(async move { Ok(resolve(&record_name(fqdn, name)).await) })
+    }
+
+    fn create_record<'a>(
+        &'a self,
+        fqdn: &'a str,
+        name: &'a str,
+        _ttl: u32,
+        ip: Ipv6Addr,
+    ) -> BoxFuture<'a, Result<(), DynsixError>> {
+        Box::pin(self.set("setting", fqdn, name, ip))
+    }
+
+    fn update_record<'a>(
+        &'a self,
+        fqdn: &'a str,
+        name: &'a str,
+        _ttl: u32,
+        ip: Ipv6Addr,
+    ) -> BoxFuture<'a, Result<(), DynsixError>> {
+        Box::pin(self.set("updating", fqdn, name, ip))
+    }
+
+    fn delete_record<'a>(
+        &'a self,
+        _fqdn: &'a str,
+        _name: &'a str,
+    ) -> BoxFuture<'a, Result<(), DynsixError>> {
+        Box::pin(async {
+            Err(DynsixError::Provider {
+                provider: "http",
+                operation: "deleting",
+                message: "records can only be set through an update URL".to_string(),
+            })
+        })
+    }
+}
@@ -0,0 +1,342 @@
+//! [OVHcloud](https://api.ovh.com/) DNS zones through the signed OVH API.
+//! Changes to a zone only go live once it is refreshed.
+
+use std::{
+    net::Ipv6Addr,
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use openssl::sha::sha1;
+use reqwest::{header::HeaderValue, Method};
+use serde::{Deserialize, Serialize};
+
+use super::{Provider, Record};
+use crate::{
+    http_log::SendLogged,
+    notify::BoxFuture,
+    secret::{wipe, Secret},
+    DynsixError,
+};
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct OvhConfig {
+    pub application_key: String,
+    pub application_secret: Secret,
+    pub consumer_key: Secret,
+    /// API of the region the account belongs to, e.g.
+    /// `https://ca.api.ovh.com/1.0` for OVHcloud Canada
+    #[serde(default = "default_endpoint")]
+    pub endpoint: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct OvhRecord {
+    id: u64,
+    target: String,
+    ttl: u32,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct RecordRequest<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    field_type: Option<&'static str>,
+    sub_domain: &'a str,
+    target: String,
+    ttl: u32,
+}
+
+#[derive(Debug)]
+pub struct Ovh {
+    http: reqwest::Client,
+    config: OvhConfig,
+    consumer: HeaderValue,
+    /// Seconds the clock of the API is ahead of ours, timestamps in the
+    /// signature must match it
+    time_delta: Mutex<Option<i64>>,
+}
+
+impl Ovh {
+    pub fn new(http: reqwest::Client, config: &OvhConfig) -> Self {
+        Self {
+            http,
+            config: config.clone(),
+            consumer: config.consumer_key.header(""),
+            time_delta: Mutex::new(None),
+        }
+    }
+
+    /// Sends a signed request and returns the body of a successful response
+    async fn request(
+        &self,
+        operation: &'static str,
+        method: Method,
+        path: &str,
+        body: Option<String>,
+    ) -> Result<String, DynsixError> {
+        let url = format!("{}{path}", self.config.endpoint.trim_end_matches('/'));
+        let body = body.unwrap_or_default();
+        let timestamp = (now() + self.time_delta(operation).await?).to_string();
+        let mut signed = [
+            self.config.application_secret.expose(),
+            self.config.consumer_key.expose(),
+            method.as_str(),
+            &url,
+            &body,
+            &timestamp,
+        ]
+        .join("+")
+        .into_bytes();
+        let signature = format!("$1${}", hex(&sha1(&signed)));
+        wipe(&mut signed);
+
+        let mut request = self
+            .http
+            .request(method, &url)
+            .header("X-Ovh-Application", &self.config.application_key)
+            .header("X-Ovh-Consumer", self.consumer.clone())
+            .header("X-Ovh-Timestamp", timestamp)
+            .header("X-Ovh-Signature", signature);
+        if !body.is_empty() {
+            request = request
+                .header("Content-Type", "application/json")
+                .body(body);
+        }
+
+        let response = request.send_logged().await?;
+        let status = response.status();
+        let text = response.text().await?;
+        if !status.is_success() {
+            let json: serde_json::Value = serde_json::from_str(&text).unwrap_or_default();
+            let message = json
+                .get("message")
+                .and_then(|message| message.as_str())
+                .unwrap_or(text.trim());
+            return Err(error(operation, format!("{status}: {message}")));
+        }
+        Ok(text)
+    }
+
+    async fn time_delta(&self, operation: &'static str) -> Result<i64, DynsixError> {
+        if let Some(delta) = *self.time_delta.lock().unwrap() {
+            return Ok(delta);
+        }
+
+        let url = format!("{}/auth/time", self.config.endpoint.trim_end_matches('/'));
+        let server: i64 = self
+            .http
+            .get(url)
+            .send_logged()
+            .await?
+            .text()
+            .await?
+            .trim()
+            .parse()
+            .map_err(|e| error(operation, format!("invalid server time: {e}")))?;
+        let delta = server - now();
+        *self.time_delta.lock().unwrap() = Some(delta);
+        Ok(delta)
+    }
+
+    async fn records(
+        &self,
+        operation: &'static str,
+        fqdn: &str,
+        name: &str,
+    ) -> Result<Vec<OvhRecord>, DynsixError> {
+        let query: String = form_urlencoded::Serializer::new(String::new())
+            .append_pair("fieldType", "AAAA")
+            .append_pair("subDomain", sub_domain(name))
+            .finish();
+        let ids: Vec<u64> = parse(
+            operation,
+            &self
+                .request(
+                    operation,
+                    Method::GET,
+                    &format!("/domain/zone/{fqdn}/record?{query}"),
+                    None,
+                )
+                .await?,
+        )?;
+
+        let mut records = Vec::with_capacity(ids.len());
+        for id in ids {
+            let body = self
+                .request(
+                    operation,
+                    Method::GET,
+                    &format!("/domain/zone/{fqdn}/record/{id}"),
+                    None,
+                )
+                .await?;
+            records.push(parse(operation, &body)?);
+        }
+        Ok(records)
+    }
+
+    /// Publishes the pending changes of the zone
+    async fn refresh(&self, operation: &'static str, fqdn: &str) -> Result<(), DynsixError> {
+        self.request(
+            operation,
+            Method::POST,
+            &format!("/domain/zone/{fqdn}/refresh"),
+            None,
+        )
+        .await
+        .map(|_| ())
+    }
+
+    /// Makes `ip` the only value of the record, reusing an existing entry
+    async fn set(
+        &self,
+        operation: &'static str,
+        fqdn: &str,
+        name: &str,
+        ttl: u32,
+        ip: Ipv6Addr,
+    ) -> Result<(), DynsixError> {
+        let mut existing = self.records(operation, fqdn, name).await?.into_iter();
+        let mut body = RecordRequest {
+            field_type: None,
+            sub_domain: sub_domain(name),
+            target: ip.to_string(),
+            ttl,
+        };
+
+        let (method, path) = match existing.next() {
+            Some(record) => (
+                Method::PUT,
+                format!("/domain/zone/{fqdn}/record/{}", record.id),
+            ),
+            None => {
+                body.field_type = Some("AAAA");
+                (Method::POST, format!("/domain/zone/{fqdn}/record"))
+            }
+        };
+        let body = serde_json::to_string(&body).map_err(|e| error(operation, e))?;
+        self.request(operation, method, &path, Some(body)).await?;
+
+        for record in existing {
+            self.request(
+                operation,
+                Method::DELETE,
+                &format!("/domain/zone/{fqdn}/record/{}", record.id),
+                None,
+            )
+            .await?;
+        }
+        self.refresh(operation, fqdn).await
+    }
+}
+
+impl Provider for Ovh {
+    fn name(&self) -> &'static str {
+        "ovh"
+    }
+
+    fn fetch_record<'a>(
+        &'a self,
+        fqdn: &'a str,
+        name: &'a str,
+    ) -> BoxFuture<'a, Result<Option<Record>, DynsixError>> {
+        Box::pin(async move {
+            let records = self.records("fetching", fqdn, name).await?;
+            let Some(first) = records.first() else {
+                return Ok(None);
+            };
+            Ok(Some(Record {
+                ttl: Some(first.ttl),
+                values: records.into_iter().map(|record| record.target).collect(),
+            }))
+        })
+    }
+
+    fn create_record<'a>(
+        &'a self,
+        fqdn: &'a str,
+        name: &'a str,
+        ttl: u32,
+        ip: Ipv6Addr,
+    ) -> BoxFuture<'a, Result<(), DynsixError>> {
+        Box::pin(self.set("setting", fqdn, name, ttl, ip))
+    }
+
+    fn update_record<'a>(
+        &'a self,
+        fqdn: &'a str,
+        name: &'a str,
+        ttl: u32,
+        ip: Ipv6Addr,
+    ) -> BoxFuture<'a, Result<(), DynsixError>> {
+        Box::pin(self.set("updating", fqdn, name, ttl, ip))
+    }
+
+    fn delete_record<'a>(
+        &'a self,
+        fqdn: &'a str,
+        name: &'a str,
+    ) -> BoxFuture<'a, Result<(), DynsixError>> {
+        Box::pin(async move {
+            let records = self.records("deleting", fqdn, name).await?;
+            if records.is_empty() {
+                return Err(error(
+                    "deleting",
+                    format!("{name} in {fqdn} has no AAAA record"),
+                ));
+            }
+            for record in records {
+                self.request(
+                    "deleting",
+                    Method::DELETE,
+                    &format!("/domain/zone/{fqdn}/record/{}", record.id),
+                    None,
+                )
+                .await?;
+            }
+            self.refresh("deleting", fqdn).await
+        })
+    }
+}
+
+/// OVH addresses the apex with an empty subdomain
+fn sub_domain(name: &str) -> &str {
+    if name == "@" {
+        ""
+    } else {
+        name
+    }
+}
+
+fn parse<T: serde::de::DeserializeOwned>(
+    operation: &'static str,
+    body: &str,
+) -> Result<T, DynsixError> {
+    serde_json::from_str(body).map_err(|e| error(operation, e))
+}
+
+fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs() as i64
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+fn error(operation: &'static str, message: impl ToString) -> DynsixError {
+    DynsixError::Provider {
+        provider: "ovh",
+        operation,
+        message: message.to_string(),
+    }
+}
+
+fn default_endpoint() -> String {
+    "https://eu.api.ovh.com/1.0".to_string()
+}
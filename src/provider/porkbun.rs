@@ -0,0 +1,229 @@
+//! [Porkbun](https://porkbun.com/api/json/v3/documentation) through its JSON
+//! API, where every request is a POST carrying the API keys
+
+use std::net::Ipv6Addr;
+
+use serde::{Deserialize, Serialize};
+
+use super::{path_segment, Provider, Record};
+use crate::{http_log::SendLogged, notify::BoxFuture, secret::Secret, DynsixError};
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct PorkbunConfig {
+    pub api_key: Secret,
+    pub secret_api_key: Secret,
+    #[serde(default = "default_endpoint")]
+    pub endpoint: String,
+}
+
+#[derive(Serialize)]
+struct Body<'a> {
+    apikey: &'a str,
+    secretapikey: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    name: Option<&'a str>,
+    #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
+    kind: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    content: Option<String>,
+    /// Porkbun takes and returns numbers as strings
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ttl: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Response {
+    status: String,
+    message: Option<String>,
+    #[serde(default)]
+    records: Vec<PorkbunRecord>,
+}
+
+#[derive(Deserialize)]
+struct PorkbunRecord {
+    content: String,
+    ttl: String,
+}
+
+#[derive(Debug)]
+pub struct Porkbun {
+    http: reqwest::Client,
+    config: PorkbunConfig,
+}
+
+impl Porkbun {
+    pub fn new(http: reqwest::Client, config: &PorkbunConfig) -> Self {
+        Self {
+            http,
+            config: config.clone(),
+        }
+    }
+
+    fn body(&self) -> Body<'_> {
+        Body {
+            apikey: self.config.api_key.expose(),
+            secretapikey: self.config.secret_api_key.expose(),
+            name: None,
+            kind: None,
+            content: None,
+            ttl: None,
+        }
+    }
+
+    async fn call(
+        &self,
+        operation: &'static str,
+        path: &str,
+        body: Body<'_>,
+    ) -> Result<Response, DynsixError> {
+        let response = self
+            .http
+            .post(format!(
+                "{}{path}",
+                self.config.endpoint.trim_end_matches('/')
+            ))
+            .json(&body)
+            .send_logged()
+            .await?;
+        let status = response.status();
+        let text = response.text().await?;
+
+        let error = |message: String| DynsixError::Provider {
+            provider: "porkbun",
+            operation,
+            message,
+        };
+        let response: Response =
+            serde_json::from_str(&text).map_err(|_| error(format!("{status}: {}", text.trim())))?;
+        if response.status != "SUCCESS" {
+            return Err(error(response.message.unwrap_or(response.status)));
+        }
+        Ok(response)
+    }
+
+    /// Replaces the content of every AAAA record `name`
+    async fn edit(
+        &self,
+        operation: &'static str,
+        fqdn: &str,
+        name: &str,
+        ttl: u32,
+        ip: Ipv6Addr,
+    ) -> Result<(), DynsixError> {
+        let body = Body {
+            content: Some(ip.to_string()),
+            ttl: Some(ttl.to_string()),
+            ..self.body()
+        };
+        self.call(
+            operation,
+            &format!(
+                "/dns/editByNameType/{fqdn}/AAAA/{}",
+                path_segment(subdomain(name))
+            ),
+            body,
+        )
+        .await
+        .map(|_| ())
+    }
+}
+
+impl Provider for Porkbun {
+    fn name(&self) -> &'static str {
+        "porkbun"
+    }
+
+    fn fetch_record<'a>(
+        &'a self,
+        fqdn: &'a str,
+        name: &'a str,
+    ) -> BoxFuture<'a, Result<Option<Record>, DynsixError>> {
+        Box::pin(async move {
+            let response = self
+                .call(
+                    "fetching",
+                    &format!(
+                        "/dns/retrieveByNameType/{fqdn}/AAAA/{}",
+                        path_segment(subdomain(name))
+                    ),
+                    self.body(),
+                )
+                .await?;
+            let Some(first) = response.records.first() else {
+                return Ok(None);
+            };
+            Ok(Some(Record {
+                ttl: first.ttl.parse().ok(),
+                values: response
+                    .records
+                    .into_iter()
+                    .map(|record| record.content)
+                    .collect(),
+            }))
+        })
+    }
+
+    fn create_record<'a>(
+        &'a self,
+        fqdn: &'a str,
+        name: &'a str,
+        ttl: u32,
+        ip: Ipv6Addr,
+    ) -> BoxFuture<'a, Result<(), DynsixError>> {
+        Box::pin(async move {
+            let body = Body {
+                name: Some(subdomain(name)),
+                kind: Some("AAAA"),
+                content: Some(ip.to_string()),
+                ttl: Some(ttl.to_string()),
+                ..self.body()
+            };
+            self.call("setting", &format!("/dns/create/{fqdn}"), body)
+                .await
+                .map(|_| ())
+        })
+    }
+
+    fn update_record<'a>(
+        &'a self,
+        fqdn: &'a str,
+        name: &'a str,
+        ttl: u32,
+        ip: Ipv6Addr,
+    ) -> BoxFuture<'a, Result<(), DynsixError>> {
+        Box::pin(self.edit("updating", fqdn, name, ttl, ip))
+    }
+
+    fn delete_record<'a>(
+        &'a self,
+        fqdn: &'a str,
+        name: &'a str,
+    ) -> BoxFuture<'a, Result<(), DynsixError>> {
+        Box::pin(async move {
+            self.call(
+                "deleting",
+                &format!(
+                    "/dns/deleteByNameType/{fqdn}/AAAA/{}",
+                    path_segment(subdomain(name))
+                ),
+                self.body(),
+            )
+            .await
+            .map(|_| ())
+        })
+    }
+}
+
+/// Porkbun addresses the apex with an empty subdomain
+fn subdomain(name: &str) -> &str {
+    if name == "@" {
+        ""
+    } else {
+        name
+    }
+}
+
+fn default_endpoint() -> String {
+    "https://api.porkbun.com/api/json/v3".to_string()
+}
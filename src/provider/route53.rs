@@ -0,0 +1,498 @@
+//! [Amazon Route 53](https://aws.amazon.com/route53/) through its REST API,
+//! with requests signed by AWS Signature Version 4
+
+use std::{
+    collections::HashMap,
+    net::Ipv6Addr,
+    sync::Mutex,
+    time::{Duration, SystemTime},
+};
+
+use openssl::{hash::MessageDigest, pkey::PKey, sha::sha256, sign::Signer};
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use reqwest::Method;
+use serde::Deserialize;
+
+use super::{record_name, Provider, Record};
+use crate::{
+    http_log::SendLogged,
+    notify::BoxFuture,
+    secret::{wipe, Secret},
+    xml::{element, elements, escape, unescape},
+    DynsixError,
+};
+
+const API_VERSION: &str = "2013-04-01";
+/// Route 53 is a global service, requests are always signed for us-east-1
+const REGION: &str = "us-east-1";
+const SERVICE: &str = "route53";
+/// Instance metadata service, queried for instance profile credentials
+const METADATA_URL: &str = "http://169.254.169.254/latest";
+/// Instance profile credentials are renewed this long before they expire
+const CREDENTIALS_MARGIN: Duration = Duration::from_secs(300);
+
+/// Everything but the characters SigV4 leaves unencoded
+const QUERY_ENCODE: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'_')
+    .remove(b'.')
+    .remove(b'~');
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Route53Config {
+    /// Static credentials, the instance profile is used if unset
+    pub access_key_id: Option<String>,
+    pub secret_access_key: Option<Secret>,
+    #[serde(default = "default_endpoint")]
+    pub endpoint: String,
+}
+
+#[derive(Debug, Clone)]
+struct Credentials {
+    access_key_id: String,
+    secret_access_key: Secret,
+    session_token: Option<Secret>,
+    expires: Option<SystemTime>,
+}
+
+/// Answer of the instance metadata service
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct InstanceCredentials {
+    access_key_id: String,
+    secret_access_key: Secret,
+    token: Secret,
+    expiration: String,
+}
+
+#[derive(Debug)]
+pub struct Route53 {
+    http: reqwest::Client,
+    config: Route53Config,
+    /// Hosted zone ids by domain
+    zones: Mutex<HashMap<String, String>>,
+    credentials: Mutex<Option<Credentials>>,
+}
+
+impl Route53 {
+    pub fn new(http: reqwest::Client, config: &Route53Config) -> Self {
+        let credentials = match (&config.access_key_id, &config.secret_access_key) {
+            (Some(access_key_id), Some(secret_access_key)) => Some(Credentials {
+                access_key_id: access_key_id.clone(),
+                secret_access_key: secret_access_key.clone(),
+                session_token: None,
+                expires: None,
+            }),
+            _ => None,
+        };
+
+        Self {
+            http,
+            config: config.clone(),
+            zones: Mutex::new(HashMap::new()),
+            credentials: Mutex::new(credentials),
+        }
+    }
+
+    /// Sends a signed request and returns the body of a successful response
+    async fn request(
+        &self,
+        operation: &'static str,
+        method: Method,
+        path: &str,
+        query: &[(&str, &str)],
+        body: String,
+    ) -> Result<String, DynsixError> {
+        let credentials = self.credentials(operation).await?;
+
+        let mut query: Vec<_> = query
+            .iter()
+            .map(|(key, value)| {
+                format!(
+                    "{}={}",
+                    utf8_percent_encode(key, QUERY_ENCODE),
+                    utf8_percent_encode(value, QUERY_ENCODE)
+                )
+            })
+            .collect();
+        query.sort();
+        let query = query.join("&");
+
+        let base = self.config.endpoint.trim_end_matches('/');
+        let mut url = reqwest::Url::parse(&format!("{base}/{API_VERSION}{path}"))
+            .map_err(|e| error(operation, e))?;
+        if !query.is_empty() {
+            url.set_query(Some(&query));
+        }
+        let host = match url.port() {
+            Some(port) => format!("{}:{port}", url.host_str().unwrap_or_default()),
+            None => url.host_str().unwrap_or_default().to_string(),
+        };
+
+        let signed = sign(
+            &credentials,
+            method.as_str(),
+            url.path(),
+            &query,
+            &host,
+            &body,
+            SystemTime::now(),
+        )
+        .map_err(|e| error(operation, e))?;
+
+        let mut request = self
+            .http
+            .request(method, url)
+            .header("X-Amz-Date", signed.date)
+            .header("Authorization", signed.authorization)
+            .body(body);
+        if let Some(token) = &credentials.session_token {
+            request = request.header("X-Amz-Security-Token", token.header(""));
+        }
+
+        let response = request.send_logged().await?;
+        let status = response.status();
+        let text = response.text().await?;
+        if !status.is_success() {
+            let message = element(&text, "Message").unwrap_or(text.trim());
+            return Err(error(operation, format!("{status}: {}", unescape(message))));
+        }
+        Ok(text)
+    }
+
+    /// Static credentials, or those of the instance profile which are cached
+    /// until shortly before they expire
+    async fn credentials(&self, operation: &'static str) -> Result<Credentials, DynsixError> {
+        if let Some(credentials) = self.credentials.lock().unwrap().as_ref() {
+            let fresh = credentials
+                .expires
+                .is_none_or(|expires| expires > SystemTime::now() + CREDENTIALS_MARGIN);
+            if fresh {
+                return Ok(credentials.clone());
+            }
+        }
+
+        let credentials = self
+            .instance_credentials()
+            .await
+            .map_err(|e| error(operation, format!("no instance profile credentials: {e}")))?;
+        *self.credentials.lock().unwrap() = Some(credentials.clone());
+        Ok(credentials)
+    }
+
+    /// Asks the instance metadata service (IMDSv2) for the credentials of the
+    /// role of the instance profile
+    async fn instance_credentials(&self) -> Result<Credentials, Box<dyn std::error::Error>> {
+        let token = self
+            .http
+            .put(format!("{METADATA_URL}/api/token"))
+            .header("X-aws-ec2-metadata-token-ttl-seconds", "60")
+            .send_logged()
+            .await?
+            .error_for_status()?
+            .text()
+            .await?;
+
+        let roles_url = format!("{METADATA_URL}/meta-data/iam/security-credentials/");
+        let roles = self
+            .http
+            .get(&roles_url)
+            .header("X-aws-ec2-metadata-token", &token)
+            .send_logged()
+            .await?
+            .error_for_status()?
+            .text()
+            .await?;
+        let role = roles.lines().next().ok_or("the instance has no role")?;
+
+        let credentials: InstanceCredentials = self
+            .http
+            .get(format!("{roles_url}{role}"))
+            .header("X-aws-ec2-metadata-token", &token)
+            .send_logged()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        Ok(Credentials {
+            access_key_id: credentials.access_key_id,
+            secret_access_key: credentials.secret_access_key,
+            session_token: Some(credentials.token),
+            expires: Some(humantime::parse_rfc3339(&credentials.expiration)?),
+        })
+    }
+
+    /// Id of the public hosted zone named `fqdn`
+    async fn zone_id(&self, operation: &'static str, fqdn: &str) -> Result<String, DynsixError> {
+        if let Some(id) = self.zones.lock().unwrap().get(fqdn) {
+            return Ok(id.clone());
+        }
+
+        let body = self
+            .request(
+                operation,
+                Method::GET,
+                "/hostedzonesbyname",
+                &[("dnsname", fqdn)],
+                String::new(),
+            )
+            .await?;
+        let id = elements(&body, "HostedZone")
+            .into_iter()
+            .filter(|zone| element(zone, "PrivateZone") != Some("true"))
+            .find(|zone| element(zone, "Name").is_some_and(|name| same_name(name, fqdn)))
+            .and_then(|zone| element(zone, "Id"))
+            .map(|id| id.trim_start_matches("/hostedzone/").to_string())
+            .ok_or_else(|| error(operation, format!("no public hosted zone named {fqdn}")))?;
+
+        self.zones
+            .lock()
+            .unwrap()
+            .insert(fqdn.to_string(), id.clone());
+        Ok(id)
+    }
+
+    async fn fetch(
+        &self,
+        operation: &'static str,
+        zone: &str,
+        record: &str,
+    ) -> Result<Option<Record>, DynsixError> {
+        // Lists record sets starting at the name, which may be a later one
+        let body = self
+            .request(
+                operation,
+                Method::GET,
+                &format!("/hostedzone/{zone}/rrset"),
+                &[("name", record), ("type", "AAAA"), ("maxitems", "1")],
+                String::new(),
+            )
+            .await?;
+
+        let Some(set) = element(&body, "ResourceRecordSet") else {
+            return Ok(None);
+        };
+        let matches = element(set, "Name").is_some_and(|name| same_name(name, record))
+            && element(set, "Type") == Some("AAAA");
+        if !matches {
+            return Ok(None);
+        }
+
+        Ok(Some(Record {
+            values: elements(set, "Value").into_iter().map(unescape).collect(),
+            ttl: element(set, "TTL").and_then(|ttl| ttl.parse().ok()),
+        }))
+    }
+
+    async fn change(
+        &self,
+        operation: &'static str,
+        zone: &str,
+        action: &str,
+        record: &str,
+        record_set: &Record,
+    ) -> Result<(), DynsixError> {
+        let values: String = record_set
+            .values
+            .iter()
+            .map(|value| {
+                format!(
+                    "<ResourceRecord><Value>{}</Value></ResourceRecord>",
+                    escape(value)
+                )
+            })
+            .collect();
+        let body = format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<ChangeResourceRecordSetsRequest xmlns="https://route53.amazonaws.com/doc/{API_VERSION}/"><ChangeBatch><Comment>dynsix</Comment><Changes><Change><Action>{action}</Action><ResourceRecordSet><Name>{name}.</Name><Type>AAAA</Type><TTL>{ttl}</TTL><ResourceRecords>{values}</ResourceRecords></ResourceRecordSet></Change></Changes></ChangeBatch></ChangeResourceRecordSetsRequest>"#,
+            name = escape(record),
+            ttl = record_set.ttl.unwrap_or_default(),
+        );
+
+        self.request(
+            operation,
+            Method::POST,
+            &format!("/hostedzone/{zone}/rrset"),
+            &[],
+            body,
+        )
+        .await
+        .map(|_| ())
+    }
+
+    /// Creates or replaces the record, Route 53 does not distinguish the two
+    async fn upsert(
+        &self,
+        operation: &'static str,
+        fqdn: &str,
+        name: &str,
+        ttl: u32,
+        ip: Ipv6Addr,
+    ) -> Result<(), DynsixError> {
+        let zone = self.zone_id(operation, fqdn).await?;
+        let record = Record {
+            values: vec![ip.to_string()],
+            ttl: Some(ttl),
+        };
+        self.change(
+            operation,
+            &zone,
+            "UPSERT",
+            &record_name(fqdn, name),
+            &record,
+        )
+        .await
+    }
+}
+
+impl Provider for Route53 {
+    fn name(&self) -> &'static str {
+        "route53"
+    }
+
+    fn fetch_record<'a>(
+        &'a self,
+        fqdn: &'a str,
+        name: &'a str,
+    ) -> BoxFuture<'a, Result<Option<Record>, DynsixError>> {
+        Box::pin(async move {
+            let zone = self.zone_id("fetching", fqdn).await?;
+            self.fetch("fetching", &zone, &record_name(fqdn, name))
+                .await
+        })
+    }
+
+    fn create_record<'a>(
+        &'a self,
+        fqdn: &'a str,
+        name: &'a str,
+        ttl: u32,
+        ip: Ipv6Addr,
+    ) -> BoxFuture<'a, Result<(), DynsixError>> {
+        Box::pin(self.upsert("setting", fqdn, name, ttl, ip))
+    }
+
+    fn update_record<'a>(
+        &'a self,
+        fqdn: &'a str,
+        name: &'a str,
+        ttl: u32,
+        ip: Ipv6Addr,
+    ) -> BoxFuture<'a, Result<(), DynsixError>> {
+        Box::pin(self.upsert("updating", fqdn, name, ttl, ip))
+    }
+
+    fn delete_record<'a>(
+        &'a self,
+        fqdn: &'a str,
+        name: &'a str,
+    ) -> BoxFuture<'a, Result<(), DynsixError>> {
+        Box::pin(async move {
+            // Route 53 only deletes a record set given exactly as it is
+            let zone = self.zone_id("deleting", fqdn).await?;
+            let record = record_name(fqdn, name);
+            let current = self
+                .fetch("deleting", &zone, &record)
+                .await?
+                .ok_or_else(|| error("deleting", format!("{record} has no AAAA record")))?;
+            self.change("deleting", &zone, "DELETE", &record, &current)
+                .await
+        })
+    }
+}
+
+struct Signed {
+    date: String,
+    authorization: String,
+}
+
+/// Signs a request with AWS Signature Version 4, covering the host and date
+/// headers. `query` must already be canonical, i.e. sorted and encoded.
+fn sign(
+    credentials: &Credentials,
+    method: &str,
+    path: &str,
+    query: &str,
+    host: &str,
+    body: &str,
+    now: SystemTime,
+) -> Result<Signed, openssl::error::ErrorStack> {
+    let timestamp: String = humantime::format_rfc3339_seconds(now)
+        .to_string()
+        .chars()
+        .filter(|c| *c != '-' && *c != ':')
+        .collect();
+    let day = &timestamp[..8];
+    let scope = format!("{day}/{REGION}/{SERVICE}/aws4_request");
+
+    let mut headers = vec![("host", host), ("x-amz-date", &timestamp)];
+    if let Some(token) = &credentials.session_token {
+        headers.push(("x-amz-security-token", token.expose()));
+    }
+    let canonical_headers: String = headers
+        .iter()
+        .map(|(name, value)| format!("{name}:{}\n", value.trim()))
+        .collect();
+    let signed_headers = headers
+        .iter()
+        .map(|(name, _)| *name)
+        .collect::<Vec<_>>()
+        .join(";");
+
+    let canonical_request = format!(
+        "{method}\n{path}\n{query}\n{canonical_headers}\n{signed_headers}\n{}",
+        hex(&sha256(body.as_bytes()))
+    );
+    let string_to_sign = format!(
+        "AWS4-HMAC-SHA256\n{timestamp}\n{scope}\n{}",
+        hex(&sha256(canonical_request.as_bytes()))
+    );
+
+    let mut secret = format!("AWS4{}", credentials.secret_access_key.expose()).into_bytes();
+    let key = hmac(&secret, day.as_bytes());
+    wipe(&mut secret);
+    let mut key = key?;
+    for part in [REGION, SERVICE, "aws4_request"] {
+        key = hmac(&key, part.as_bytes())?;
+    }
+    let signature = hex(&hmac(&key, string_to_sign.as_bytes())?);
+
+    Ok(Signed {
+        authorization: format!(
+            "AWS4-HMAC-SHA256 Credential={}/{scope}, SignedHeaders={signed_headers}, Signature={signature}",
+            credentials.access_key_id
+        ),
+        date: timestamp,
+    })
+}
+
+fn hmac(key: &[u8], data: &[u8]) -> Result<Vec<u8>, openssl::error::ErrorStack> {
+    let key = PKey::hmac(key)?;
+    let mut signer = Signer::new(MessageDigest::sha256(), &key)?;
+    signer.sign_oneshot_to_vec(data)
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+fn error(operation: &'static str, message: impl ToString) -> DynsixError {
+    DynsixError::Provider {
+        provider: "route53",
+        operation,
+        message: message.to_string(),
+    }
+}
+
+/// Whether the name returned by Route 53, e.g. `www.example.com.`, is `name`
+fn same_name(returned: &str, name: &str) -> bool {
+    returned
+        .trim_end_matches('.')
+        .replace("\\052", "*")
+        .eq_ignore_ascii_case(name.trim_end_matches('.'))
+}
+
+fn default_endpoint() -> String {
+    "https://route53.amazonaws.com".to_string()
+}
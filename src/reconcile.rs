@@ -0,0 +1,1458 @@
+//! Bringing published records in line with the configured services
+
+use std::{
+    collections::{HashMap, HashSet},
+    net::Ipv6Addr,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Instant, SystemTime},
+};
+
+use serde::{
+    de::{value::StrDeserializer, IntoDeserializer},
+    Deserialize,
+};
+use tokio::{sync::Semaphore, task::JoinSet};
+use tracing::{debug, error, field, info, info_span, warn, Instrument, Span};
+
+use crate::{
+    audit::{AuditLog, Mutation, Operation},
+    config::ServiceConfig,
+    gandi, idna,
+    plan::{Plan, PlanFailure, PlannedAction, PlannedChange, PlannedDeletion},
+    provider::{display_name, same_values, Provider, ProviderKind, ProvidersConfig, Record},
+    report::{Action, Reconciled, RunReport, ServiceReport},
+    secret::Secret,
+    srv, sshfp,
+    state::{self, ManagedRecord},
+    DynsixError,
+};
+
+/// Creates and updates the AAAA records of services through the [`Provider`]
+/// each of them is configured with
+#[derive(Debug, Clone)]
+pub struct Reconciler {
+    providers: HashMap<ProviderKind, Arc<dyn Provider>>,
+    /// Providers of services with their own credentials, by service name
+    services: HashMap<String, Arc<dyn Provider>>,
+    /// Why services have no provider, e.g. as their credentials are wrong.
+    /// They fail on their own while the others are reconciled.
+    unusable: HashMap<String, String>,
+    /// Named Gandi token used by a service, shown in its logs
+    accounts: HashMap<String, String>,
+    /// Where every change to a record is appended
+    audit: Option<Arc<AuditLog>>,
+    /// Whether records are read again right before they are changed
+    check_races: bool,
+    /// Whether the changes to a domain are undone if one of them fails
+    transactional: bool,
+    /// Permits for services being reconciled at the same time
+    limit: Arc<Semaphore>,
+    /// Further permits for the services of some providers
+    provider_limits: HashMap<ProviderKind, Arc<Semaphore>>,
+    /// Requests made through providers, for the summary of a run
+    api_calls: Arc<AtomicU64>,
+    /// The AAAA records of the zones listed at the start of a run, by the
+    /// provider they were fetched with and the zone
+    zones: Arc<Mutex<HashMap<ZoneKey, HashMap<String, Record>>>>,
+    /// Records created by dynsix, those no service manages any more are
+    /// deleted
+    owned: Vec<ManagedRecord>,
+}
+
+/// A zone as listed through one provider: the address of the provider
+/// instance, as services with their own credentials have their own, and
+/// the zone in its ASCII form
+type ZoneKey = (usize, String);
+
+/// The `[concurrency]` section of the config
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct ConcurrencyConfig {
+    /// Services reconciled at the same time
+    #[serde(default = "default_max_concurrent_updates")]
+    pub max_concurrent_updates: usize,
+    /// Lower limits for the services of a provider
+    #[serde(default, deserialize_with = "deserialize_per_provider")]
+    pub per_provider: HashMap<ProviderKind, usize>,
+}
+
+/// Reads a table keyed by provider name, which TOML can't give as enum keys
+fn deserialize_per_provider<'de, D>(
+    deserializer: D,
+) -> Result<HashMap<ProviderKind, usize>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    HashMap::<String, usize>::deserialize(deserializer)?
+        .into_iter()
+        .map(|(provider, limit)| {
+            let provider: StrDeserializer<D::Error> = provider.as_str().into_deserializer();
+            Ok((ProviderKind::deserialize(provider)?, limit))
+        })
+        .collect()
+}
+
+impl Default for ConcurrencyConfig {
+    fn default() -> Self {
+        Self {
+            max_concurrent_updates: default_max_concurrent_updates(),
+            per_provider: HashMap::new(),
+        }
+    }
+}
+
+impl ConcurrencyConfig {
+    pub(crate) fn validate(&self, problems: &mut Vec<String>) {
+        if self.max_concurrent_updates == 0 {
+            problems.push("concurrency.max_concurrent_updates must be at least 1".to_string());
+        }
+        let mut providers: Vec<_> = self.per_provider.iter().collect();
+        providers.sort();
+        for (provider, limit) in providers {
+            if *limit == 0 {
+                problems.push(format!(
+                    "concurrency.per_provider.{provider} must be at least 1"
+                ));
+            }
+        }
+    }
+}
+
+fn default_max_concurrent_updates() -> usize {
+    1
+}
+
+/// A change made during a run, kept to undo it
+#[derive(Debug)]
+struct Undo {
+    service: String,
+    provider: ProviderKind,
+    fqdn: String,
+    name: String,
+    /// The record before the change, `None` if it was created
+    previous: Option<Record>,
+}
+
+impl Reconciler {
+    /// Reconciles services using the default provider through `client`
+    pub fn new(client: gandi::Client) -> Self {
+        Self {
+            providers: HashMap::new(),
+            services: HashMap::new(),
+            unusable: HashMap::new(),
+            accounts: HashMap::new(),
+            audit: None,
+            check_races: false,
+            transactional: false,
+            limit: Arc::new(Semaphore::new(default_max_concurrent_updates())),
+            provider_limits: HashMap::new(),
+            api_calls: Arc::new(AtomicU64::new(0)),
+            zones: Arc::new(Mutex::new(HashMap::new())),
+            owned: Vec::new(),
+        }
+        .with_provider(ProviderKind::Gandi, client)
+    }
+
+    pub fn with_provider<P>(mut self, kind: ProviderKind, provider: P) -> Self
+    where
+        P: Provider + 'static,
+    {
+        self.providers.insert(kind, Arc::new(provider));
+        self
+    }
+
+    /// Appends every record created, updated or deleted to `audit`, whether
+    /// the provider accepted the change or not
+    pub fn with_audit(mut self, audit: AuditLog) -> Self {
+        self.audit = Some(Arc::new(audit));
+        self
+    }
+
+    /// Reads every record again right before changing it and fails with
+    /// [`DynsixError::RaceDetected`] if it is no longer what was read first
+    pub fn with_race_check(mut self, check_races: bool) -> Self {
+        self.check_races = check_races;
+        self
+    }
+
+    /// Treats the services of a domain as a unit: if one of them fails, the
+    /// records changed for the others are restored to what they were
+    pub fn with_transactions(mut self, transactional: bool) -> Self {
+        self.transactional = transactional;
+        self
+    }
+
+    /// Deletes the records of `owned` that none of the services reconciled
+    /// manages any more, e.g. as their service was removed from the config.
+    /// Plans list them as deletions.
+    pub fn with_pruning(mut self, owned: impl IntoIterator<Item = ManagedRecord>) -> Self {
+        self.owned = owned.into_iter().collect();
+        self
+    }
+
+    /// Reconciles up to `max_concurrent_updates` services at the same time,
+    /// and fewer of a provider limited in `per_provider`
+    pub fn with_concurrency(mut self, config: &ConcurrencyConfig) -> Self {
+        self.limit = Arc::new(Semaphore::new(config.max_concurrent_updates.max(1)));
+        self.provider_limits = config
+            .per_provider
+            .iter()
+            .map(|(provider, limit)| (*provider, Arc::new(Semaphore::new((*limit).max(1)))))
+            .collect();
+        self
+    }
+
+    /// Adds every provider configured in `[providers]`
+    pub fn with_providers(mut self, http: reqwest::Client, config: &ProvidersConfig) -> Self {
+        self.providers.extend(config.build(&http));
+        self
+    }
+
+    /// Adds a provider for every service with its own `credentials`. Services
+    /// with the same provider and credentials share one instance, services
+    /// with credentials that can't be used fail when they are reconciled.
+    pub fn with_service_credentials(
+        mut self,
+        http: reqwest::Client,
+        services: &HashMap<String, ServiceConfig>,
+    ) -> Self {
+        let mut shared: HashMap<(ProviderKind, String), Arc<dyn Provider>> = HashMap::new();
+        for (name, service) in services {
+            let Some(credentials) = &service.credentials else {
+                continue;
+            };
+
+            let key = (service.provider, credentials.to_string());
+            let provider = match shared.get(&key) {
+                Some(provider) => provider.clone(),
+                None => {
+                    let config = match ProvidersConfig::for_service(service.provider, credentials) {
+                        Ok(config) => config,
+                        Err(e) => {
+                            self.unusable
+                                .insert(name.clone(), format!("credentials: {e}"));
+                            continue;
+                        }
+                    };
+                    let (_, provider) = config
+                        .build(&http)
+                        .pop()
+                        .expect("credentials configure exactly one provider");
+                    shared.insert(key, provider.clone());
+                    provider
+                }
+            };
+            self.services.insert(name.clone(), provider);
+        }
+        self
+    }
+
+    /// Adds a Gandi client like `client` for every named token of `[tokens]`
+    /// that services refer to with `token_ref`. Services referring to a token
+    /// that does not exist fail when they are reconciled.
+    pub fn with_gandi_tokens(
+        mut self,
+        client: &gandi::Client,
+        tokens: &HashMap<String, Secret>,
+        services: &HashMap<String, ServiceConfig>,
+    ) -> Self {
+        let mut clients: HashMap<&str, Arc<dyn Provider>> = HashMap::new();
+        for (name, service) in services {
+            let Some(token_ref) = &service.token_ref else {
+                continue;
+            };
+            let Some(token) = tokens.get(token_ref) else {
+                self.unusable.insert(
+                    name.clone(),
+                    format!("token_ref '{token_ref}' is not in [tokens]"),
+                );
+                continue;
+            };
+
+            let client = clients
+                .entry(token_ref)
+                .or_insert_with(|| Arc::new(client.with_token(token)));
+            self.services.insert(name.clone(), client.clone());
+            self.accounts.insert(name.clone(), token_ref.clone());
+        }
+        self
+    }
+
+    /// [`Self::provider`] for a request about to be made, which is counted
+    fn provider_for_request(
+        &self,
+        service: &str,
+        kind: ProviderKind,
+    ) -> Result<&dyn Provider, DynsixError> {
+        let provider = self.provider(service, kind)?;
+        self.api_calls.fetch_add(1, Ordering::Relaxed);
+        Ok(provider)
+    }
+
+    /// The provider of the service, either its own or the shared one of `kind`
+    fn provider(&self, service: &str, kind: ProviderKind) -> Result<&dyn Provider, DynsixError> {
+        if let Some(problem) = self.unusable.get(service) {
+            return Err(DynsixError::InvalidService {
+                service: service.to_string(),
+                message: problem.clone(),
+            });
+        }
+        if let Some(provider) = self.services.get(service) {
+            return Ok(provider.as_ref());
+        }
+        self.providers
+            .get(&kind)
+            .map(|provider| provider.as_ref())
+            .ok_or(DynsixError::ProviderNotConfigured {
+                provider: kind.name(),
+            })
+    }
+
+    /// Reconciles every service for which `selects` returns true. Errors are
+    /// contained to their service so the remaining records still get updated.
+    pub async fn reconcile<F>(
+        &self,
+        services: &HashMap<String, ServiceConfig>,
+        public_ip: Ipv6Addr,
+        selects: F,
+    ) -> RunReport
+    where
+        F: Fn(&str) -> bool,
+    {
+        let run = async {
+            let mut report = RunReport::new(public_ip);
+            let calls = self.api_calls.load(Ordering::Relaxed);
+            // Services of the same provider and credentials are started one
+            // after another, so they take turns on the same client
+            let mut selected: Vec<_> = services
+                .iter()
+                .filter(|(name, _)| {
+                    let selected = selects(name);
+                    if !selected {
+                        debug!(service = %name, "Skipped, not selected");
+                    }
+                    selected
+                })
+                .collect();
+            selected.sort_by_cached_key(|(name, service)| {
+                (
+                    service.provider,
+                    service.credentials.as_ref().map(|c| c.to_string()),
+                    name.to_string(),
+                )
+            });
+            let orphans = self.orphans(services, &selects);
+            self.fetch_zones(
+                selected
+                    .iter()
+                    .filter(|(_, service)| service.enabled)
+                    .map(|(name, service)| (name.as_str(), service.provider, service.fqdn.as_str()))
+                    .chain(orphans.iter().map(|record| {
+                        (
+                            record.service.as_str(),
+                            record.provider,
+                            record.fqdn.as_str(),
+                        )
+                    })),
+            )
+            .await;
+            let this = Arc::new(self.clone());
+            let mut tasks = JoinSet::new();
+            for (index, (name, service)) in selected.iter().enumerate() {
+                let (this, name, service) = (this.clone(), name.to_string(), (*service).clone());
+                let task = async move {
+                    // The provider first, so services waiting for it leave
+                    // the overall permits to others
+                    let _provider = match this.provider_limits.get(&service.provider) {
+                        Some(limit) => Some(limit.clone().acquire_owned().await),
+                        None => None,
+                    };
+                    let _permit = this.limit.clone().acquire_owned().await;
+                    let mut journal = Vec::new();
+                    let report = this
+                        .reconcile_service_journaled(&name, &service, public_ip, &mut journal)
+                        .await;
+                    (index, report, journal)
+                };
+                tasks.spawn(task.instrument(Span::current()));
+            }
+            let mut done = Vec::new();
+            while let Some(result) = tasks.join_next().await {
+                match result {
+                    Ok((index, report, journal)) => {
+                        done.push((index, report, selected[index].1.fqdn.as_str(), journal))
+                    }
+                    Err(e) => std::panic::resume_unwind(e.into_panic()),
+                }
+            }
+            // In the order they were started, which changes are undone in
+            done.sort_by_key(|(index, ..)| *index);
+            let mut done: Vec<_> = done
+                .into_iter()
+                .map(|(_, report, fqdn, journal)| (report, fqdn, journal))
+                .collect();
+            if self.transactional {
+                self.roll_back_failed_domains(&mut done).await;
+            }
+            for (service_report, _, _) in done {
+                report.push(service_report);
+            }
+            for record in orphans {
+                self.prune(record, &mut report).await;
+            }
+            self.zones.lock().unwrap().clear();
+            report.summary.api_calls = self.api_calls.load(Ordering::Relaxed) - calls;
+            report.finish();
+            info!("Finished: {}", report.summary);
+            report
+        };
+
+        run.instrument(info_span!("reconcile", %public_ip)).await
+    }
+
+    pub async fn reconcile_service(
+        &self,
+        name: &str,
+        service: &ServiceConfig,
+        public_ip: Ipv6Addr,
+    ) -> ServiceReport {
+        self.reconcile_service_journaled(name, service, public_ip, &mut Vec::new())
+            .await
+    }
+
+    /// Publishes SSHFP records with the fingerprints of the `ssh_host_keys`
+    /// of the service next to each of its AAAA records, where they differ.
+    /// Returns the names of the records that were changed.
+    pub async fn publish_sshfp(
+        &self,
+        name: &str,
+        service: &ServiceConfig,
+    ) -> Result<Vec<String>, DynsixError> {
+        let values = sshfp::values(&service.ssh_host_keys)?;
+        let mut changed = Vec::new();
+        for record in service.record_names() {
+            let published = self
+                .publish_records(
+                    name,
+                    service.provider,
+                    &service.fqdn,
+                    record,
+                    sshfp::RECORD_TYPE,
+                    service.ttl,
+                    &values,
+                )
+                .await?;
+            if published {
+                changed.push(display_name(&service.fqdn, record));
+            }
+        }
+        Ok(changed)
+    }
+
+    /// Publishes the SRV records of the service where they differ. Returns
+    /// the names of the records that were changed.
+    pub async fn publish_srv(
+        &self,
+        name: &str,
+        service: &ServiceConfig,
+    ) -> Result<Vec<String>, DynsixError> {
+        let mut changed = Vec::new();
+        for (record, values) in srv::rrsets(service) {
+            let published = self
+                .publish_records(
+                    name,
+                    service.provider,
+                    &service.fqdn,
+                    record,
+                    srv::RECORD_TYPE,
+                    service.ttl,
+                    &values,
+                )
+                .await?;
+            if published {
+                changed.push(display_name(&service.fqdn, record));
+            }
+        }
+        Ok(changed)
+    }
+
+    /// Makes the records of type `kind`, other than AAAA, hold `values`
+    /// unless they already do. Returns whether they were changed.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn publish_records(
+        &self,
+        service: &str,
+        provider: ProviderKind,
+        fqdn: &str,
+        name: &str,
+        kind: &str,
+        ttl: u32,
+        values: &[String],
+    ) -> Result<bool, DynsixError> {
+        let (fqdn, name) = (idna::to_ascii(fqdn)?, idna::to_ascii(name)?);
+        let published = self
+            .provider_for_request(service, provider)?
+            .fetch_records(&fqdn, &name, kind)
+            .await?;
+        if published.is_some_and(|published| {
+            published.ttl.is_none_or(|published| published == ttl)
+                && same_values(&published.values, values)
+        }) {
+            return Ok(false);
+        }
+        self.provider_for_request(service, provider)?
+            .set_records(&fqdn, &name, kind, ttl, values)
+            .await?;
+        Ok(true)
+    }
+
+    /// [`Self::reconcile_service`], adding the changes made to `journal`
+    async fn reconcile_service_journaled(
+        &self,
+        name: &str,
+        service: &ServiceConfig,
+        public_ip: Ipv6Addr,
+        journal: &mut Vec<Undo>,
+    ) -> ServiceReport {
+        let started = Instant::now();
+        let span = self.service_span(name, &service.fqdn, &service.record_names().join(","));
+        if !service.enabled {
+            span.in_scope(|| info!("Skipped, disabled"));
+            return ServiceReport::new(
+                name.to_string(),
+                display_names(service),
+                service.address(name, public_ip).unwrap_or(public_ip),
+                Ok(Reconciled {
+                    action: Action::Skipped,
+                    old: None,
+                }),
+                started.elapsed(),
+            );
+        }
+        let service_ip = match service.address(name, public_ip) {
+            Ok(service_ip) => service_ip,
+            Err(e) => {
+                span.in_scope(|| error!("{e}"));
+                // The suffix is unknown, so is the address
+                return ServiceReport::new(
+                    name.to_string(),
+                    display_names(service),
+                    public_ip,
+                    Err(e),
+                    started.elapsed(),
+                );
+            }
+        };
+        span.record("new", field::display(service_ip));
+
+        let result = self
+            .reconcile_record(name, service, service_ip, journal)
+            .instrument(span.clone())
+            .await;
+        if let Err(e) = &result {
+            span.in_scope(|| error!("{e}"));
+        }
+
+        ServiceReport::new(
+            name.to_string(),
+            display_names(service),
+            service_ip,
+            result,
+            started.elapsed(),
+        )
+    }
+
+    /// Deletes a record no service manages any more, adding it to the
+    /// deleted records of `report` unless that fails
+    async fn prune(&self, record: ManagedRecord, report: &mut RunReport) {
+        let span = self.service_span(&record.service, &record.fqdn, &record.name);
+        let result = async {
+            let current = self
+                .current_record(&record.service, record.provider, &record.fqdn, &record.name)
+                .await?;
+            let Some(current) = current else {
+                info!("Record of a removed service is gone already");
+                return Ok(());
+            };
+            Span::current().record("old", field::debug(&current.values));
+            self.check_race(
+                &record.service,
+                record.provider,
+                &record.fqdn,
+                &record.name,
+                Some(&current.values),
+            )
+            .await?;
+            self.delete_record(&record.service, record.provider, &record.fqdn, &record.name)
+                .await?;
+            info!("Deleted the record of a removed service");
+            Ok::<_, DynsixError>(())
+        }
+        .instrument(span.clone())
+        .await;
+        match result {
+            Ok(()) => report.deleted.push(record),
+            Err(e) => span.in_scope(|| error!("Failed to delete the record: {e}")),
+        }
+    }
+
+    /// Fetches the records of all names of the service before changing any,
+    /// so that either all of them are brought to `service_ip` or, if one can
+    /// not be looked up, none is touched. Changes made are added to `journal`.
+    async fn reconcile_record(
+        &self,
+        name: &str,
+        service: &ServiceConfig,
+        service_ip: Ipv6Addr,
+        journal: &mut Vec<Undo>,
+    ) -> Result<Reconciled, DynsixError> {
+        let mut current = Vec::new();
+        for record in service.record_names() {
+            let published = self
+                .current_record(name, service.provider, &service.fqdn, record)
+                .await?;
+            current.push((record, published));
+        }
+
+        let now = SystemTime::now();
+        let mut reconciled = Reconciled {
+            action: Action::Unchanged,
+            old: None,
+        };
+        for (record, published) in current {
+            let action = match &published {
+                None => {
+                    debug!(name = record, "No AAAA record found");
+                    self.check_race(name, service.provider, &service.fqdn, record, None)
+                        .await?;
+                    self.create_record(
+                        name,
+                        service.provider,
+                        &service.fqdn,
+                        record,
+                        service.ttl,
+                        &service_ip,
+                    )
+                    .await?;
+                    journal.push(Undo {
+                        service: name.to_string(),
+                        provider: service.provider,
+                        fqdn: service.fqdn.clone(),
+                        name: record.to_string(),
+                        previous: None,
+                    });
+                    Action::Created
+                }
+                Some(current @ Record { values, .. }) => {
+                    Span::current().record("old", field::debug(values));
+                    info!(name = record, "Found an existing AAAA record");
+                    match decide(current, &service_ip, service, now) {
+                        Action::Deferred => {
+                            info!(
+                                name = record,
+                                "Record differs, deferred for the quiet hours"
+                            );
+                            Action::Deferred
+                        }
+                        Action::Updated => {
+                            debug!(name = record, "Record differs");
+                            self.check_race(
+                                name,
+                                service.provider,
+                                &service.fqdn,
+                                record,
+                                Some(values),
+                            )
+                            .await?;
+                            self.update_record(
+                                name,
+                                service.provider,
+                                &service.fqdn,
+                                record,
+                                service.ttl,
+                                &service_ip,
+                            )
+                            .await?;
+                            journal.push(Undo {
+                                service: name.to_string(),
+                                provider: service.provider,
+                                fqdn: service.fqdn.clone(),
+                                name: record.to_string(),
+                                previous: published.clone(),
+                            });
+                            Action::Updated
+                        }
+                        _ => {
+                            info!(
+                                name = record,
+                                "Record was already set to the correct address"
+                            );
+                            Action::Unchanged
+                        }
+                    }
+                }
+            };
+
+            // An update outweighs a creation, which outweighs a deferred
+            // change, which outweighs no change
+            if rank(action) > rank(reconciled.action) {
+                reconciled.action = action;
+            }
+            if reconciled.old.is_none() {
+                reconciled.old = published.map(|record| record.values);
+            }
+        }
+        Ok(reconciled)
+    }
+
+    /// Undoes the changes of every domain in which a service failed, in the
+    /// reverse order they were made. Services whose changes were undone are
+    /// reported as failed along with the services that caused it.
+    async fn roll_back_failed_domains(&self, done: &mut [(ServiceReport, &str, Vec<Undo>)]) {
+        let mut failed: HashMap<String, Vec<String>> = HashMap::new();
+        for (report, fqdn, _) in done.iter() {
+            if report.action == Action::Failed {
+                failed
+                    .entry(fqdn.to_string())
+                    .or_default()
+                    .push(format!("'{}'", report.service));
+            }
+        }
+
+        for (report, fqdn, journal) in done.iter_mut().rev() {
+            let Some(failed) = failed.get(*fqdn) else {
+                continue;
+            };
+            if journal.is_empty() {
+                continue;
+            }
+            let span = info_span!("rollback", service = %report.service);
+            let mut message = match &report.error {
+                Some(error) => format!("{error}, changes made before were undone"),
+                None => format!(
+                    "changes were undone as {} of {fqdn} failed",
+                    failed.join(", ")
+                ),
+            };
+            for undo in journal.drain(..).rev() {
+                if let Err(e) = self.undo(&undo).instrument(span.clone()).await {
+                    let record = display_name(&undo.fqdn, &undo.name);
+                    message.push_str(&format!("; restoring {record} failed: {e}"));
+                }
+            }
+            span.in_scope(|| warn!("{message}"));
+            report.action = Action::Failed;
+            report.error = Some(message);
+        }
+    }
+
+    /// Brings a record back to what it was before a change
+    async fn undo(&self, undo: &Undo) -> Result<(), DynsixError> {
+        let Some(previous) = &undo.previous else {
+            return self
+                .delete_record(&undo.service, undo.provider, &undo.fqdn, &undo.name)
+                .await;
+        };
+        // Providers write a single address
+        match previous.values.as_slice() {
+            [value] => match Ipv6Addr::from_str(value.trim()) {
+                Ok(ip) => {
+                    self.update_record(
+                        &undo.service,
+                        undo.provider,
+                        &undo.fqdn,
+                        &undo.name,
+                        // Providers that don't tell the TTL ignore it
+                        previous.ttl.unwrap_or_default(),
+                        &ip,
+                    )
+                    .await
+                }
+                Err(_) => Err(unrestorable(undo)),
+            },
+            _ => Err(unrestorable(undo)),
+        }
+    }
+
+    /// Computes the desired state of the selected services next to what their
+    /// providers currently serve, without changing anything. Services that
+    /// can't be planned are listed in [`Plan::failed`] next to the others.
+    pub async fn plan<F>(
+        &self,
+        services: &HashMap<String, ServiceConfig>,
+        public_ip: Ipv6Addr,
+        selects: F,
+    ) -> Plan
+    where
+        F: Fn(&str) -> bool,
+    {
+        let orphans = self.orphans(services, &selects);
+        self.fetch_zones(
+            services
+                .iter()
+                .filter(|(name, service)| selects(name) && service.enabled)
+                .map(|(name, service)| (name.as_str(), service.provider, service.fqdn.as_str()))
+                .chain(orphans.iter().map(|record| {
+                    (
+                        record.service.as_str(),
+                        record.provider,
+                        record.fqdn.as_str(),
+                    )
+                })),
+        )
+        .await;
+
+        let mut changes = Vec::new();
+        let mut deletions = Vec::new();
+        let mut failed = Vec::new();
+        for (name, service) in services {
+            if !selects(name) || !service.enabled {
+                continue;
+            }
+
+            match self.plan_service(name, service, public_ip).await {
+                Ok(planned) => changes.extend(planned),
+                Err(e) => {
+                    error!(service = %name, "{e}");
+                    failed.push(PlanFailure {
+                        service: name.clone(),
+                        error: e.to_string(),
+                    });
+                }
+            }
+        }
+        for record in orphans {
+            let current = self
+                .current_record(&record.service, record.provider, &record.fqdn, &record.name)
+                .await;
+            match current {
+                Ok(Some(current)) => deletions.push(PlannedDeletion {
+                    service: record.service,
+                    provider: record.provider,
+                    fqdn: record.fqdn,
+                    name: record.name,
+                    current: current.values,
+                }),
+                Ok(None) => {}
+                Err(e) => {
+                    error!(service = %record.service, "{e}");
+                    failed.push(PlanFailure {
+                        service: record.service,
+                        error: e.to_string(),
+                    });
+                }
+            }
+        }
+        self.zones.lock().unwrap().clear();
+        changes.sort_by(|a, b| a.service.cmp(&b.service));
+        failed.sort_by(|a, b| a.service.cmp(&b.service));
+
+        Plan {
+            public_ip,
+            changes,
+            deletions,
+            failed,
+        }
+    }
+
+    /// The changes to the records of one service, none if one of them can't
+    /// be looked up
+    async fn plan_service(
+        &self,
+        name: &str,
+        service: &ServiceConfig,
+        public_ip: Ipv6Addr,
+    ) -> Result<Vec<PlannedChange>, DynsixError> {
+        let desired = service.address(name, public_ip)?;
+        let now = SystemTime::now();
+        let mut changes = Vec::new();
+        for record in service.record_names() {
+            let current = self
+                .current_record(name, service.provider, &service.fqdn, record)
+                .await?;
+            let action = match &current {
+                None => PlannedAction::Create,
+                Some(current) => match decide(current, &desired, service, now) {
+                    Action::Updated => PlannedAction::Update,
+                    Action::Deferred => PlannedAction::Defer,
+                    _ => PlannedAction::NoOp,
+                },
+            };
+            let current = current.map(|record| record.values);
+
+            changes.push(PlannedChange {
+                service: name.to_string(),
+                provider: service.provider,
+                fqdn: service.fqdn.clone(),
+                name: record.to_string(),
+                ttl: service.ttl,
+                action,
+                current,
+                desired,
+            });
+        }
+        Ok(changes)
+    }
+
+    /// Executes the changes of a plan for which `selects` returns true. The
+    /// plan may have been made elsewhere or a while ago, so all of its
+    /// records are read first and nothing is changed unless they still hold
+    /// what the plan saw.
+    pub async fn apply<F>(&self, plan: Plan, selects: F) -> Result<RunReport, DynsixError>
+    where
+        F: Fn(&str) -> bool,
+    {
+        let mut report = RunReport::new(plan.public_ip);
+        let calls = self.api_calls.load(Ordering::Relaxed);
+        self.check_stale(&plan, &selects).await?;
+        for change in plan.changes {
+            if !selects(&change.service) {
+                continue;
+            }
+
+            let started = Instant::now();
+            let span = self.service_span(&change.service, &change.fqdn, &change.name);
+            span.record("new", field::display(change.desired));
+            if let Some(current) = &change.current {
+                span.record("old", field::debug(current));
+            }
+
+            let result = match change.action {
+                PlannedAction::NoOp => Ok(Reconciled {
+                    action: Action::Unchanged,
+                    old: change.current,
+                }),
+                PlannedAction::Defer => Ok(Reconciled {
+                    action: Action::Deferred,
+                    old: change.current,
+                }),
+                PlannedAction::Create => async {
+                    self.check_race(
+                        &change.service,
+                        change.provider,
+                        &change.fqdn,
+                        &change.name,
+                        None,
+                    )
+                    .await?;
+                    self.create_record(
+                        &change.service,
+                        change.provider,
+                        &change.fqdn,
+                        &change.name,
+                        change.ttl,
+                        &change.desired,
+                    )
+                    .await
+                }
+                .instrument(span.clone())
+                .await
+                .map(|_| Reconciled {
+                    action: Action::Created,
+                    old: None,
+                }),
+                PlannedAction::Update => async {
+                    self.check_race(
+                        &change.service,
+                        change.provider,
+                        &change.fqdn,
+                        &change.name,
+                        change.current.as_deref(),
+                    )
+                    .await?;
+                    self.update_record(
+                        &change.service,
+                        change.provider,
+                        &change.fqdn,
+                        &change.name,
+                        change.ttl,
+                        &change.desired,
+                    )
+                    .await
+                }
+                .instrument(span.clone())
+                .await
+                .map(|_| Reconciled {
+                    action: Action::Updated,
+                    old: change.current,
+                }),
+            };
+            if let Err(e) = &result {
+                span.in_scope(|| error!("{e}"));
+            }
+            report.push(ServiceReport::new(
+                change.service,
+                display_name(&change.fqdn, &change.name),
+                change.desired,
+                result,
+                started.elapsed(),
+            ));
+        }
+        for deletion in plan.deletions {
+            if !selects(&deletion.service) {
+                continue;
+            }
+            let span = self.service_span(&deletion.service, &deletion.fqdn, &deletion.name);
+            span.record("old", field::debug(&deletion.current));
+            let result = async {
+                self.check_race(
+                    &deletion.service,
+                    deletion.provider,
+                    &deletion.fqdn,
+                    &deletion.name,
+                    Some(&deletion.current),
+                )
+                .await?;
+                self.delete_record(
+                    &deletion.service,
+                    deletion.provider,
+                    &deletion.fqdn,
+                    &deletion.name,
+                )
+                .await
+            }
+            .instrument(span.clone())
+            .await;
+            match result {
+                Ok(()) => report.deleted.push(ManagedRecord {
+                    provider: deletion.provider,
+                    fqdn: deletion.fqdn,
+                    name: deletion.name,
+                    service: deletion.service,
+                    address: None,
+                }),
+                Err(e) => span.in_scope(|| error!("Failed to delete the record: {e}")),
+            }
+        }
+        report.summary.api_calls = self.api_calls.load(Ordering::Relaxed) - calls;
+        report.finish();
+        info!("Finished: {}", report.summary);
+
+        Ok(report)
+    }
+
+    /// The AAAA record, `None` if it does not exist. Like the other record
+    /// operations it goes through the own provider of `service` if it has
+    /// credentials, or the shared one of `provider` otherwise, with
+    /// internationalized names in their ASCII form.
+    pub async fn fetch_record(
+        &self,
+        service: &str,
+        provider: ProviderKind,
+        fqdn: &str,
+        name: &str,
+    ) -> Result<Option<Record>, DynsixError> {
+        self.provider_for_request(service, provider)?
+            .fetch_record(&idna::to_ascii(fqdn)?, &idna::to_ascii(name)?)
+            .await
+    }
+
+    /// Fails with [`DynsixError::StalePlan`] unless every record of the
+    /// selected changes and deletions of `plan` still holds the values it
+    /// was planned with, in any order
+    async fn check_stale<F>(&self, plan: &Plan, selects: F) -> Result<(), DynsixError>
+    where
+        F: Fn(&str) -> bool,
+    {
+        let planned: Vec<_> = plan
+            .changes
+            .iter()
+            .map(|change| {
+                let record = (&change.service, change.provider, &change.fqdn, &change.name);
+                (record, change.current.as_deref())
+            })
+            .chain(plan.deletions.iter().map(|deletion| {
+                let record = (
+                    &deletion.service,
+                    deletion.provider,
+                    &deletion.fqdn,
+                    &deletion.name,
+                );
+                (record, Some(deletion.current.as_slice()))
+            }))
+            .filter(|((service, ..), _)| selects(service))
+            .collect();
+        self.fetch_zones(
+            planned.iter().map(|((service, provider, fqdn, _), _)| {
+                (service.as_str(), *provider, fqdn.as_str())
+            }),
+        )
+        .await;
+
+        let mut changed = Vec::new();
+        let mut result = Ok(());
+        for ((service, provider, fqdn, name), expected) in planned {
+            match self.current_record(service, provider, fqdn, name).await {
+                Ok(found) => {
+                    let found = found.map(|record| record.values);
+                    if normalize(found.as_deref()) != normalize(expected) {
+                        changed.push(display_name(fqdn, name));
+                    }
+                }
+                Err(e) => {
+                    result = Err(e);
+                    break;
+                }
+            }
+        }
+        self.zones.lock().unwrap().clear();
+        result?;
+        if changed.is_empty() {
+            Ok(())
+        } else {
+            changed.sort();
+            changed.dedup();
+            Err(DynsixError::StalePlan { changed })
+        }
+    }
+
+    /// The AAAA record as listed with its zone at the start of the run, or
+    /// fetched on its own if the provider can't list the zone or listing it
+    /// failed
+    async fn current_record(
+        &self,
+        service: &str,
+        provider: ProviderKind,
+        fqdn: &str,
+        name: &str,
+    ) -> Result<Option<Record>, DynsixError> {
+        let key = (
+            provider_address(self.provider(service, provider)?),
+            idna::to_ascii(fqdn)?,
+        );
+        if let Some(zone) = self.zones.lock().unwrap().get(&key) {
+            return Ok(zone.get(&idna::to_ascii(name)?).cloned());
+        }
+        self.fetch_record(service, provider, fqdn, name).await
+    }
+
+    /// Lists the zones of `records`, each given by its service, provider and
+    /// zone, once per provider and zone, so that the records in them are not
+    /// fetched one by one. Zones that can't be listed are left out.
+    async fn fetch_zones<'a>(
+        &self,
+        records: impl IntoIterator<Item = (&'a str, ProviderKind, &'a str)>,
+    ) {
+        let mut listed = HashSet::new();
+        for (service, kind, fqdn) in records {
+            let (Ok(provider), Ok(fqdn)) = (self.provider(service, kind), idna::to_ascii(fqdn))
+            else {
+                continue;
+            };
+            let key = (provider_address(provider), fqdn);
+            if !listed.insert(key.clone()) {
+                continue;
+            }
+            match provider.fetch_zone(&key.1).await {
+                Ok(Some(zone)) => {
+                    self.api_calls.fetch_add(1, Ordering::Relaxed);
+                    debug!(fqdn = %key.1, "Listed {} AAAA records", zone.len());
+                    self.zones.lock().unwrap().insert(key, zone);
+                }
+                Ok(None) => {}
+                Err(e) => {
+                    self.api_calls.fetch_add(1, Ordering::Relaxed);
+                    warn!(fqdn = %key.1, "{e}, fetching its records one by one");
+                }
+            }
+        }
+    }
+
+    /// The records of [`Self::with_pruning`] that none of `services`
+    /// manages, of services for which `selects` returns true
+    fn orphans<F>(
+        &self,
+        services: &HashMap<String, ServiceConfig>,
+        selects: F,
+    ) -> Vec<ManagedRecord>
+    where
+        F: Fn(&str) -> bool,
+    {
+        let mut orphans = state::orphans(&self.owned, services);
+        orphans.retain(|record| selects(&record.service));
+        orphans
+    }
+
+    /// With race checks, reads the record again and fails unless it still
+    /// holds `expected`, the values read before deciding to change it, or
+    /// still does not exist if `None`
+    async fn check_race(
+        &self,
+        service: &str,
+        provider: ProviderKind,
+        fqdn: &str,
+        name: &str,
+        expected: Option<&[String]>,
+    ) -> Result<(), DynsixError> {
+        if !self.check_races {
+            return Ok(());
+        }
+        let found = self.fetch_record(service, provider, fqdn, name).await?;
+        let found = found.as_ref().map(|record| record.values.as_slice());
+        if normalize(found) == normalize(expected) {
+            return Ok(());
+        }
+        let describe = |values: Option<&[String]>| match values {
+            Some(values) => values.join(", "),
+            None => "nothing".to_string(),
+        };
+        Err(DynsixError::RaceDetected {
+            record: display_name(fqdn, name),
+            expected: describe(expected),
+            found: describe(found),
+        })
+    }
+
+    pub async fn create_record(
+        &self,
+        service: &str,
+        provider: ProviderKind,
+        fqdn: &str,
+        name: &str,
+        ttl: u32,
+        ip: &Ipv6Addr,
+    ) -> Result<(), DynsixError> {
+        let result = self
+            .provider_for_request(service, provider)?
+            .create_record(&idna::to_ascii(fqdn)?, &idna::to_ascii(name)?, ttl, *ip)
+            .await;
+        self.audit(
+            Operation::Create,
+            service,
+            provider,
+            fqdn,
+            name,
+            Some((ttl, *ip)),
+            &result,
+        );
+        result?;
+        info!(%fqdn, %name, "Successfully set AAAA record");
+        Ok(())
+    }
+
+    pub async fn update_record(
+        &self,
+        service: &str,
+        provider: ProviderKind,
+        fqdn: &str,
+        name: &str,
+        ttl: u32,
+        ip: &Ipv6Addr,
+    ) -> Result<(), DynsixError> {
+        let result = self
+            .provider_for_request(service, provider)?
+            .update_record(&idna::to_ascii(fqdn)?, &idna::to_ascii(name)?, ttl, *ip)
+            .await;
+        self.audit(
+            Operation::Update,
+            service,
+            provider,
+            fqdn,
+            name,
+            Some((ttl, *ip)),
+            &result,
+        );
+        result?;
+        info!(%fqdn, %name, "Successfully updated AAAA record");
+        Ok(())
+    }
+
+    pub async fn delete_record(
+        &self,
+        service: &str,
+        provider: ProviderKind,
+        fqdn: &str,
+        name: &str,
+    ) -> Result<(), DynsixError> {
+        let result = self
+            .provider_for_request(service, provider)?
+            .delete_record(&idna::to_ascii(fqdn)?, &idna::to_ascii(name)?)
+            .await;
+        self.audit(
+            Operation::Delete,
+            service,
+            provider,
+            fqdn,
+            name,
+            None,
+            &result,
+        );
+        result
+    }
+
+    /// Appends a change to the audit log. The change has been made already,
+    /// so failing to record it is only logged.
+    #[allow(clippy::too_many_arguments)]
+    fn audit(
+        &self,
+        operation: Operation,
+        service: &str,
+        provider: ProviderKind,
+        fqdn: &str,
+        name: &str,
+        written: Option<(u32, Ipv6Addr)>,
+        result: &Result<(), DynsixError>,
+    ) {
+        let Some(audit) = &self.audit else {
+            return;
+        };
+        let mutation = Mutation {
+            operation,
+            service,
+            provider: provider.name(),
+            account: self.accounts.get(service).map(String::as_str),
+            fqdn,
+            name,
+            ttl: written.map(|(ttl, _)| ttl),
+            value: written.map(|(_, ip)| ip),
+            error: result.as_ref().err().map(ToString::to_string),
+        };
+        if let Err(e) = audit.append(&mutation) {
+            error!(%fqdn, %name, "Failed to write the audit log: {e}");
+        }
+    }
+
+    /// Span carrying the identity of a service, `old` and `new` are recorded
+    /// once they are known
+    fn service_span(&self, service: &str, fqdn: &str, name: &str) -> Span {
+        let span = info_span!(
+            "service",
+            service,
+            fqdn,
+            name,
+            account = field::Empty,
+            old = field::Empty,
+            new = field::Empty
+        );
+        if let Some(account) = self.accounts.get(service) {
+            span.record("account", account.as_str());
+        }
+        span
+    }
+}
+
+/// When a published record counts as up to date, set with `compare` on a
+/// service
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Compare {
+    /// The record holds the address of the service and nothing else, other
+    /// values are removed
+    #[default]
+    Exact,
+    /// The address of the service is one of the values. Other values are
+    /// left alone until the record needs an update, which replaces them.
+    Contains,
+}
+
+/// Tells provider instances apart, including those of services with their
+/// own credentials
+fn provider_address(provider: &dyn Provider) -> usize {
+    provider as *const dyn Provider as *const () as usize
+}
+
+/// The full names of the records of `service`, comma separated
+fn display_names(service: &ServiceConfig) -> String {
+    service
+        .record_names()
+        .iter()
+        .map(|record| display_name(&service.fqdn, record))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// The error for a previous record that can not be written back
+fn unrestorable(undo: &Undo) -> DynsixError {
+    DynsixError::Provider {
+        provider: undo.provider.name(),
+        operation: "restoring",
+        message: format!(
+            "{} held {:?}, only a single address can be written",
+            display_name(&undo.fqdn, &undo.name),
+            undo.previous.as_ref().map(|record| &record.values)
+        ),
+    }
+}
+
+/// How much an action of one record counts for the service
+fn rank(action: Action) -> u8 {
+    match action {
+        Action::Unchanged | Action::Skipped => 0,
+        Action::Deferred => 1,
+        Action::Created => 2,
+        Action::Updated => 3,
+        Action::Failed => 4,
+    }
+}
+
+/// What a run does with a published record: update it if it differs from
+/// `ip`, or with `low_ttl` from the TTL of `service`, but defer that to the
+/// end of the `quiet_hours` unless the record holds no address at all
+fn decide(published: &Record, ip: &Ipv6Addr, service: &ServiceConfig, now: SystemTime) -> Action {
+    // The TTL only counts if it changes with low_ttl, and if the provider
+    // tells it
+    let ttl_differs =
+        service.low_ttl.is_some() && published.ttl.is_some_and(|ttl| ttl != service.ttl);
+    let quiet = service
+        .quiet_hours
+        .as_ref()
+        .is_some_and(|quiet_hours| quiet_hours.contains(now));
+    if record_matches(&published.values, ip, service.compare) && !ttl_differs {
+        Action::Unchanged
+    } else if quiet && !is_broken(&published.values) {
+        Action::Deferred
+    } else {
+        Action::Updated
+    }
+}
+
+/// The values of a record in a form to compare: sorted, with addresses
+/// parsed so that `2001:DB8::1` and `2001:db8:0::1` are the same, and
+/// anything else trimmed
+fn normalize(values: Option<&[String]>) -> Option<Vec<String>> {
+    values.map(|values| {
+        let mut values: Vec<_> = values
+            .iter()
+            .map(|value| {
+                let value = value.trim();
+                Ipv6Addr::from_str(value).map_or_else(|_| value.to_string(), |ip| ip.to_string())
+            })
+            .collect();
+        values.sort_unstable();
+        values
+    })
+}
+
+/// Whether no published value is an address at all, which is fixed even
+/// in the quiet hours
+fn is_broken(values: &[String]) -> bool {
+    !values
+        .iter()
+        .any(|value| Ipv6Addr::from_str(value.trim()).is_ok())
+}
+
+/// Whether the published `values` are up to date with `ip`. Values are
+/// compared as addresses, so any textual form matches; a value that is not
+/// an address needs the record to be rewritten.
+pub fn record_matches(values: &[String], ip: &Ipv6Addr, compare: Compare) -> bool {
+    let mut published = HashSet::new();
+    for value in values {
+        match Ipv6Addr::from_str(value.trim()) {
+            Ok(address) => {
+                published.insert(address);
+            }
+            Err(e) => {
+                warn!("Published value '{value}' is not an IPv6 address: {e}");
+                return false;
+            }
+        }
+    }
+    match compare {
+        Compare::Exact => published.len() == 1 && published.contains(ip),
+        Compare::Contains => published.contains(ip),
+    }
+}
@@ -0,0 +1,225 @@
+use std::{
+    fmt,
+    net::Ipv6Addr,
+    process::ExitCode,
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{state::ManagedRecord, DynsixError};
+
+/// Some, but not all services failed
+pub const EXIT_PARTIAL_FAILURE: u8 = 1;
+/// Nothing could be reconciled, including setup errors
+pub const EXIT_TOTAL_FAILURE: u8 = 2;
+/// Another invocation was running and `--wait` was not given
+pub const EXIT_LOCKED: u8 = 3;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    Unchanged,
+    Created,
+    Updated,
+    /// The record differs but the service is in its `quiet_hours`
+    Deferred,
+    /// The service is disabled with `enabled = false`
+    Skipped,
+    Failed,
+}
+
+/// What reconciling a single service did, as returned by the reconcile step
+#[derive(Debug)]
+pub struct Reconciled {
+    pub action: Action,
+    /// Values of the record before the run, `None` if there was no record
+    pub old: Option<Vec<String>>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ServiceReport {
+    pub service: String,
+    pub record: String,
+    pub action: Action,
+    pub old: Option<Vec<String>>,
+    pub new: Ipv6Addr,
+    pub error: Option<String>,
+    /// See [`DynsixError::class`]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error_class: Option<&'static str>,
+    pub duration_ms: u128,
+}
+
+impl ServiceReport {
+    pub fn new(
+        service: String,
+        record: String,
+        new: Ipv6Addr,
+        result: Result<Reconciled, DynsixError>,
+        duration: Duration,
+    ) -> Self {
+        let (action, old, error, error_class) = match result {
+            Ok(reconciled) => (reconciled.action, reconciled.old, None, None),
+            Err(e) => (Action::Failed, None, Some(e.to_string()), Some(e.class())),
+        };
+
+        Self {
+            service,
+            record,
+            action,
+            old,
+            new,
+            error,
+            error_class,
+            duration_ms: duration.as_millis(),
+        }
+    }
+}
+
+/// How the services of one or more runs ended and what it took
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(default)]
+pub struct Summary {
+    pub runs: u64,
+    pub unchanged: u64,
+    pub created: u64,
+    pub updated: u64,
+    pub deferred: u64,
+    pub skipped: u64,
+    pub failed: u64,
+    /// Records of removed services deleted with `prune`
+    pub deleted: u64,
+    /// Requests for records made to providers, not counting retries
+    pub api_calls: u64,
+    pub duration_ms: u64,
+}
+
+impl Summary {
+    /// Adds the counts of `other`, e.g. of another run
+    pub fn add(&mut self, other: &Summary) {
+        self.runs += other.runs;
+        self.unchanged += other.unchanged;
+        self.created += other.created;
+        self.updated += other.updated;
+        self.deferred += other.deferred;
+        self.skipped += other.skipped;
+        self.failed += other.failed;
+        self.deleted += other.deleted;
+        self.api_calls += other.api_calls;
+        self.duration_ms += other.duration_ms;
+    }
+
+    fn count(&mut self, action: Action) {
+        let count = match action {
+            Action::Unchanged => &mut self.unchanged,
+            Action::Created => &mut self.created,
+            Action::Updated => &mut self.updated,
+            Action::Deferred => &mut self.deferred,
+            Action::Skipped => &mut self.skipped,
+            Action::Failed => &mut self.failed,
+        };
+        *count += 1;
+    }
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} unchanged, {} updated, {} created",
+            self.unchanged, self.updated, self.created
+        )?;
+        if self.deferred > 0 {
+            write!(f, ", {} deferred", self.deferred)?;
+        }
+        if self.skipped > 0 {
+            write!(f, ", {} skipped", self.skipped)?;
+        }
+        if self.deleted > 0 {
+            write!(f, ", {} deleted", self.deleted)?;
+        }
+        write!(
+            f,
+            ", {} failed in {} with {} API calls",
+            self.failed,
+            humantime::format_duration(Duration::from_millis(self.duration_ms)),
+            self.api_calls
+        )
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct RunReport {
+    pub public_ip: Ipv6Addr,
+    pub services: Vec<ServiceReport>,
+    /// Records dynsix no longer manages, deleted by the run or found
+    /// deleted already
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub deleted: Vec<ManagedRecord>,
+    pub duration_ms: u128,
+    /// Counted by [`RunReport::finish`], except for the API calls
+    pub summary: Summary,
+
+    #[serde(skip)]
+    started: Instant,
+}
+
+impl RunReport {
+    pub fn new(public_ip: Ipv6Addr) -> Self {
+        Self {
+            public_ip,
+            services: Vec::new(),
+            deleted: Vec::new(),
+            duration_ms: 0,
+            summary: Summary::default(),
+            started: Instant::now(),
+        }
+    }
+
+    pub fn push(&mut self, service: ServiceReport) {
+        self.services.push(service);
+    }
+
+    pub fn finish(&mut self) {
+        self.services.sort_by(|a, b| a.service.cmp(&b.service));
+        self.duration_ms = self.started.elapsed().as_millis();
+
+        let mut summary = Summary {
+            runs: 1,
+            deleted: self.deleted.len().try_into().unwrap_or(u64::MAX),
+            api_calls: self.summary.api_calls,
+            duration_ms: self.duration_ms.try_into().unwrap_or(u64::MAX),
+            ..Summary::default()
+        };
+        for service in &self.services {
+            summary.count(service.action);
+        }
+        self.summary = summary;
+    }
+
+    pub fn failures(&self) -> impl Iterator<Item = &ServiceReport> {
+        self.services
+            .iter()
+            .filter(|service| service.action == Action::Failed)
+    }
+
+    /// 0 if everything succeeded, 1 on partial and 2 on total failure.
+    /// Skipped services do not count either way.
+    pub fn exit_code(&self) -> ExitCode {
+        let failed = self.failures().count();
+        let attempted = self
+            .services
+            .iter()
+            .filter(|service| service.action != Action::Skipped)
+            .count();
+
+        if failed == 0 {
+            ExitCode::SUCCESS
+        } else if failed == attempted {
+            ExitCode::from(EXIT_TOTAL_FAILURE)
+        } else {
+            ExitCode::from(EXIT_PARTIAL_FAILURE)
+        }
+    }
+}
@@ -0,0 +1,146 @@
+//! Retrying failed requests with exponential backoff. The `[retry]` section
+//! has a policy per class of endpoint: the query server is cheap to ask
+//! again, changes to records are only repeated once their outcome is known.
+
+use std::{fmt::Display, future::Future, time::Duration};
+
+use serde::Deserialize;
+use tracing::warn;
+
+use crate::config::deserialize_duration;
+
+/// The `[retry]` section of the config
+#[derive(Deserialize, Debug, Clone, Copy)]
+#[serde(deny_unknown_fields)]
+pub struct RetryConfig {
+    /// Asking the query server for the public address
+    #[serde(default = "default_query")]
+    pub query: RetryPolicy,
+    /// Requests to the Gandi API
+    #[serde(default = "default_api")]
+    pub api: RetryPolicy,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        Self {
+            query: default_query(),
+            api: default_api(),
+        }
+    }
+}
+
+impl RetryConfig {
+    pub(crate) fn validate(&self, problems: &mut Vec<String>) {
+        for (name, policy) in [("query", self.query), ("api", self.api)] {
+            if policy.attempts == 0 {
+                problems.push(format!(
+                    "retry.{name}.attempts must be at least 1, which disables retries"
+                ));
+            }
+            if policy.max_delay < policy.delay {
+                problems.push(format!(
+                    "retry.{name}.max_delay is shorter than retry.{name}.delay"
+                ));
+            }
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct RetryPolicy {
+    /// Tries in total, including the first
+    #[serde(default = "default_attempts")]
+    pub attempts: u32,
+    /// Wait before the first retry, doubled for every further one
+    #[serde(default = "default_delay", deserialize_with = "deserialize_duration")]
+    pub delay: Duration,
+    /// Upper bound of the wait between tries
+    #[serde(
+        default = "default_max_delay",
+        deserialize_with = "deserialize_duration"
+    )]
+    pub max_delay: Duration,
+}
+
+impl RetryPolicy {
+    /// Tries once only
+    pub const NEVER: Self = Self {
+        attempts: 1,
+        delay: Duration::ZERO,
+        max_delay: Duration::ZERO,
+    };
+
+    /// The wait before retry number `retry`, counting from 1
+    pub fn delay(&self, retry: u32) -> Duration {
+        let factor = 2u32.saturating_pow(retry.saturating_sub(1));
+        self.delay.saturating_mul(factor).min(self.max_delay)
+    }
+
+    /// Runs `attempt` until it succeeds, fails with an error that is not
+    /// `retryable` or the attempts run out
+    pub async fn run<T, E, F, Fut>(
+        &self,
+        what: &str,
+        mut attempt: F,
+        retryable: impl Fn(&E) -> bool,
+    ) -> Result<T, E>
+    where
+        E: Display,
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+    {
+        let mut retry = 0;
+        loop {
+            let error = match attempt().await {
+                Ok(value) => return Ok(value),
+                Err(error) => error,
+            };
+            retry += 1;
+            if retry >= self.attempts || !retryable(&error) {
+                return Err(error);
+            }
+            self.wait(what, retry, &error).await;
+        }
+    }
+
+    /// Sleeps before retry number `retry` after `error`
+    pub async fn wait(&self, what: &str, retry: u32, error: &impl Display) {
+        let delay = self.delay(retry);
+        warn!(
+            "{what} failed, trying again in {} ({retry}/{}): {error}",
+            humantime::format_duration(delay),
+            self.attempts - 1
+        );
+        tokio::time::sleep(delay).await;
+    }
+}
+
+fn default_query() -> RetryPolicy {
+    RetryPolicy {
+        attempts: 4,
+        delay: Duration::from_secs(1),
+        max_delay: default_max_delay(),
+    }
+}
+
+fn default_api() -> RetryPolicy {
+    RetryPolicy {
+        attempts: 2,
+        delay: Duration::from_secs(5),
+        max_delay: default_max_delay(),
+    }
+}
+
+fn default_attempts() -> u32 {
+    3
+}
+
+fn default_delay() -> Duration {
+    Duration::from_secs(1)
+}
+
+fn default_max_delay() -> Duration {
+    Duration::from_secs(30)
+}
@@ -0,0 +1,459 @@
+//! A full run: reconciling the services and everything around it, i.e.
+//! notifications, the state file and metrics
+
+use std::{
+    borrow::Cow,
+    collections::{BTreeSet, HashMap, HashSet},
+    net::Ipv6Addr,
+    path::Path,
+    time::{Duration, Instant, SystemTime},
+};
+
+use dynsix::{
+    audit::AuditLog,
+    config::PrefixCheck,
+    discovery,
+    drift::{self, Drift},
+    gandi, http_client,
+    ip::{
+        dns_address, get_public_ip, interface_address, local_addresses, prefix_mismatch,
+        query_client, read_prefix_file, upnp_address, Source,
+    },
+    lock::RunLock,
+    metrics::{self, statsd::Statsd},
+    notify::Notifiers,
+    report::{Action, RunReport},
+    state::State,
+    ttl, vault, Config, DynsixError, Reconciler, ServiceConfig,
+};
+use tracing::{debug, info, warn};
+
+pub struct Runner {
+    config: Config,
+    notifiers: Notifiers,
+    state: State,
+    wait_for_lock: bool,
+    /// When the token fetched from `[vault]` has to be fetched again
+    token_expires: Option<Instant>,
+    /// Changes made outside dynsix that were notified about already
+    drifted: HashSet<Drift>,
+}
+
+impl Runner {
+    pub fn new(config: Config) -> Result<Self, DynsixError> {
+        Ok(Self {
+            notifiers: Notifiers::new(http_client::client(&config.http)?, &config.notify),
+            state: load_state(&config),
+            config,
+            wait_for_lock: false,
+            token_expires: None,
+            drifted: HashSet::new(),
+        })
+    }
+
+    /// Whether a run waits for another process holding `lock_file` instead
+    /// of failing with [`DynsixError::Locked`]
+    pub fn wait_for_lock(mut self, wait: bool) -> Self {
+        self.wait_for_lock = wait;
+        self
+    }
+
+    /// Switches to a new config, keeping the state unless it moved to another file
+    pub fn reload(&mut self, config: Config) -> Result<(), DynsixError> {
+        let notifiers = Notifiers::new(http_client::client(&config.http)?, &config.notify);
+        if config.state_file != self.config.state_file {
+            self.state = load_state(&config);
+        }
+        self.notifiers = notifiers;
+        self.config = config;
+        self.token_expires = None;
+        Ok(())
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    pub fn state(&self) -> &State {
+        &self.state
+    }
+
+    /// Reconciles the services for which `selects` returns true, using `prefix`
+    /// instead of asking the query server if given
+    pub async fn run<F>(
+        &mut self,
+        selects: F,
+        prefix: Option<Ipv6Addr>,
+    ) -> Result<RunReport, Box<dyn std::error::Error>>
+    where
+        F: Fn(&str) -> bool,
+    {
+        let _lock = match &self.config.lock_file {
+            Some(path) => Some(lock(path, self.wait_for_lock).await?),
+            None => None,
+        };
+        self.notifiers.started().await;
+
+        let result = match self.refresh_token().await {
+            Ok(()) => self.reconcile(selects, prefix).await,
+            Err(e) => Err(e.into()),
+        };
+        self.state.record_run(result.as_ref().ok());
+        let error_class = result.as_ref().err().map(|e| {
+            e.downcast_ref::<DynsixError>()
+                .map_or("other", DynsixError::class)
+        });
+        if let Some(class) = error_class {
+            self.state.count_error(class);
+        }
+        self.notifiers
+            .finished(result.as_ref().map_err(|e| e.to_string()), &self.state)
+            .await;
+
+        let config = &self.config;
+        if let Some(path) = &config.state_file {
+            if let Err(e) = self.state.save(path) {
+                warn!("{e}");
+            }
+        }
+        if let Ok(report) = &result {
+            if let Err(e) = record_history(config, report) {
+                warn!("{e}");
+            }
+        }
+        if let Some(path) = &config.metrics.textfile {
+            if let Err(e) = metrics::textfile::write(path, result.as_ref().ok(), &self.state) {
+                warn!("{e}");
+            }
+        }
+        if let Some(statsd) = &config.metrics.statsd {
+            let sent = Statsd::new(statsd)
+                .send(
+                    result.as_ref().map_err(|_| error_class.unwrap_or("other")),
+                    &self.state,
+                )
+                .await;
+            if let Err(e) = sent {
+                warn!("{e}");
+            }
+        }
+
+        result
+    }
+
+    /// Compares the records written by dynsix with what their providers
+    /// publish and notifies about those that someone else changed, once per
+    /// change
+    pub async fn check_drift(&mut self) -> Result<Vec<Drift>, Box<dyn std::error::Error>> {
+        self.refresh_token().await?;
+        let reconciler = build_reconciler(&self.config)?;
+        let services = services(&self.config).await?;
+
+        let drifts = drift::detect(&reconciler, &self.state, &services).await;
+        for drift in &drifts {
+            if self.drifted.contains(drift) {
+                continue;
+            }
+            match &drift.found {
+                Some(found) => warn!(
+                    record = %drift.record,
+                    "Changed outside dynsix from {} to {}",
+                    drift.written,
+                    found.join(", ")
+                ),
+                None => warn!(record = %drift.record, "Deleted outside dynsix"),
+            }
+            self.notifiers.drifted(drift).await;
+        }
+        self.drifted = drifts.iter().cloned().collect();
+        Ok(drifts)
+    }
+
+    /// Fetches the token from `[vault]` unless the last one is still valid
+    async fn refresh_token(&mut self) -> Result<(), DynsixError> {
+        if self
+            .token_expires
+            .is_some_and(|expires| Instant::now() < expires)
+        {
+            return Ok(());
+        }
+        if let Some(valid) = fetch_vault_token(&mut self.config).await? {
+            self.token_expires = Some(Instant::now() + valid);
+        }
+        Ok(())
+    }
+
+    async fn reconcile<F>(
+        &mut self,
+        selects: F,
+        prefix: Option<Ipv6Addr>,
+    ) -> Result<RunReport, Box<dyn std::error::Error>>
+    where
+        F: Fn(&str) -> bool,
+    {
+        let mut reconciler = build_reconciler(&self.config)?;
+        if self.config.prune {
+            reconciler = reconciler.with_pruning(self.state.managed.iter().cloned());
+        }
+        let public_ip = resolve_public_ip(&self.config, prefix).await?;
+        let mut services = services(&self.config).await?;
+        if services.values().any(|service| service.low_ttl.is_some()) {
+            ttl::lower(services.to_mut(), &self.state, SystemTime::now());
+        }
+
+        let report = reconciler.reconcile(&services, public_ip, selects).await;
+        self.state.track(&report, &services);
+        publish_other_records(&reconciler, &services, &report).await;
+        Ok(report)
+    }
+}
+
+/// Publishes the SSHFP and SRV records of the services reconciled in
+/// `report`. A failure is logged, the AAAA records being what the run is
+/// about.
+async fn publish_other_records(
+    reconciler: &Reconciler,
+    services: &HashMap<String, ServiceConfig>,
+    report: &RunReport,
+) {
+    let reconciled: BTreeSet<_> = report
+        .services
+        .iter()
+        .filter(|service| service.action != Action::Skipped)
+        .map(|service| service.service.as_str())
+        .collect();
+    for name in reconciled {
+        let Some(service) = services.get(name) else {
+            continue;
+        };
+        if !service.ssh_host_keys.is_empty() {
+            match reconciler.publish_sshfp(name, service).await {
+                Ok(changed) => {
+                    for record in changed {
+                        info!(service = name, "Published the SSHFP records of {record}");
+                    }
+                }
+                Err(e) => warn!(service = name, "Failed to publish SSHFP records: {e}"),
+            }
+        }
+        if !service.srv.is_empty() {
+            match reconciler.publish_srv(name, service).await {
+                Ok(changed) => {
+                    for record in changed {
+                        info!(service = name, "Published the SRV records {record}");
+                    }
+                }
+                Err(e) => warn!(service = name, "Failed to publish SRV records: {e}"),
+            }
+        }
+    }
+}
+
+/// Keeps track of the records that `report` of changes made outside of a
+/// run, e.g. by `apply` or `delete`, created, updated or deleted, like a run
+/// does: in the state file, without which gc, prune and drift detection
+/// wouldn't know of them, and in the history.
+pub fn record_changes(
+    config: &Config,
+    report: &RunReport,
+    services: &HashMap<String, ServiceConfig>,
+) -> Result<(), DynsixError> {
+    if let Some(path) = &config.state_file {
+        let mut state = State::load(path)?;
+        state.track(report, services);
+        state.save(path)?;
+    }
+    record_history(config, report)
+}
+
+/// Adds the changes of `report` to `history_file`
+#[cfg(feature = "history")]
+fn record_history(config: &Config, report: &RunReport) -> Result<(), DynsixError> {
+    match &config.history_file {
+        Some(path) => dynsix::history::History::open(path)?.record(report),
+        None => Ok(()),
+    }
+}
+
+/// Config validation rejects `history_file` without the history feature
+#[cfg(not(feature = "history"))]
+fn record_history(_config: &Config, _report: &RunReport) -> Result<(), DynsixError> {
+    Ok(())
+}
+
+/// Takes the run lock, waiting on a blocking thread if `wait` is set
+pub async fn lock(path: &Path, wait: bool) -> Result<RunLock, DynsixError> {
+    let owned = path.to_path_buf();
+    let lock = if wait {
+        tokio::task::spawn_blocking(move || RunLock::acquire(&owned, true))
+            .await
+            .expect("lock task panicked")?
+    } else {
+        RunLock::acquire(&owned, false)?
+    };
+    lock.ok_or_else(|| DynsixError::Locked {
+        path: path.to_path_buf(),
+    })
+}
+
+fn load_state(config: &Config) -> State {
+    match &config.state_file {
+        Some(path) => State::load(path).unwrap_or_else(|e| {
+            warn!("{e}");
+            State::default()
+        }),
+        None => State::default(),
+    }
+}
+
+/// Replaces the token with the one kept in `[vault]`, if configured, and
+/// returns how long it may be used
+pub async fn fetch_vault_token(config: &mut Config) -> Result<Option<Duration>, DynsixError> {
+    let Some(vault) = &config.vault else {
+        return Ok(None);
+    };
+    let (token, valid) = vault::fetch_token(&http_client::client(&config.http)?, vault).await?;
+    debug!(
+        "Fetched the token from vault, valid for {}",
+        humantime::format_duration(valid)
+    );
+    config.token = token;
+    Ok(Some(valid))
+}
+
+pub fn build_reconciler(config: &Config) -> Result<Reconciler, DynsixError> {
+    let http = http_client::client(&config.http)?;
+    let gandi = gandi_client(config)?;
+    let reconciler = Reconciler::new(gandi.clone())
+        .with_providers(http.clone(), &config.providers)
+        .with_service_credentials(http, &config.services)
+        .with_gandi_tokens(&gandi, &config.tokens, &config.services)
+        .with_race_check(config.check_races)
+        .with_transactions(config.transactional)
+        .with_concurrency(&config.concurrency);
+    Ok(match AuditLog::new(&config.audit) {
+        Some(audit) => reconciler.with_audit(audit),
+        None => reconciler,
+    })
+}
+
+/// A client for the Gandi API with the top level token
+pub fn gandi_client(config: &Config) -> Result<gandi::Client, DynsixError> {
+    let client = gandi::Client::new(http_client::client(&config.http)?, &config.token)
+        .with_retry(config.retry.api);
+    Ok(match http_client::ipv4_fallback_client(&config.http)? {
+        Some(ipv4) => client.with_ipv4_fallback(ipv4),
+        None => client,
+    })
+}
+
+/// The configured services along with those found by `[discovery]`
+pub async fn services(
+    config: &Config,
+) -> Result<Cow<'_, HashMap<String, ServiceConfig>>, DynsixError> {
+    if !config.discovery.is_enabled() {
+        return Ok(Cow::Borrowed(&config.services));
+    }
+
+    let client = gandi_client(config)?;
+    let discovered = discovery::discover(&client, &config.discovery, &config.services).await?;
+    debug!("Discovered {} records to manage", discovered.len());
+
+    let mut services = config.services.clone();
+    services.extend(discovered);
+    Ok(Cow::Owned(services))
+}
+
+/// Resolves the public ip, unless it was handed to us, e.g. on the command line
+pub async fn resolve_public_ip(
+    config: &Config,
+    prefix: Option<Ipv6Addr>,
+) -> Result<Ipv6Addr, Box<dyn std::error::Error>> {
+    if let Some(ip) = prefix {
+        debug!("Using given prefix: {ip}");
+        return Ok(ip);
+    }
+    match &config.source {
+        Some(Source::File(path)) => {
+            let ip = read_prefix_file(path)?;
+            debug!("Read prefix {ip} from {}", path.display());
+            return Ok(ip);
+        }
+        Some(Source::Interface(interface)) => {
+            let ip = interface_address(interface)?;
+            debug!("Got address {ip} of {interface}");
+            return Ok(ip);
+        }
+        Some(Source::Dns(source)) => {
+            let ip = config
+                .retry
+                .query
+                .run(
+                    "Looking up the public ip",
+                    || dns_address(source, &config.http),
+                    |_| true,
+                )
+                .await?;
+            debug!("Got public ip {ip} from {}", source.server);
+            return Ok(check_prefix(config, ip)?);
+        }
+        Some(Source::Upnp(source)) => {
+            let ip = config
+                .retry
+                .query
+                .run(
+                    "Asking the gateway for the public ip",
+                    || upnp_address(source, &config.http),
+                    |_| true,
+                )
+                .await?;
+            debug!("Got public ip {ip} from the UPnP gateway");
+            return Ok(ip);
+        }
+        None => {}
+    }
+
+    let client = query_client(&config.http)?;
+    let ip = config
+        .retry
+        .query
+        .run(
+            "Querying the public ip",
+            || get_public_ip(&client, &config.query_server),
+            |_| true,
+        )
+        .await?;
+    debug!("Got public ip: {ip}");
+    Ok(check_prefix(config, ip)?)
+}
+
+/// Applies `prefix_check` to the address a server saw us come from
+fn check_prefix(config: &Config, ip: Ipv6Addr) -> Result<Ipv6Addr, DynsixError> {
+    if config.prefix_check == PrefixCheck::Off {
+        return Ok(ip);
+    }
+    let local = match local_addresses() {
+        Ok(local) => local,
+        Err(e) => {
+            debug!("Not comparing the public ip with local addresses: {e}");
+            return Ok(ip);
+        }
+    };
+    match prefix_mismatch(ip, &local) {
+        None => Ok(ip),
+        Some(reason) if config.prefix_check == PrefixCheck::Refuse => {
+            Err(DynsixError::PrefixMismatch {
+                detected: ip,
+                reason,
+            })
+        }
+        Some(reason) => {
+            warn!(
+                detected = %ip,
+                "The public ip isn't in the /64 of a local interface, records may not reach \
+                 this network: {reason}"
+            );
+            Ok(ip)
+        }
+    }
+}
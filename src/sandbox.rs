@@ -0,0 +1,398 @@
+//! Confining the daemon with `daemon.sandbox`: Landlock limits the files it
+//! can reach to the system directories, its config and the directories it
+//! writes to, and a seccomp filter denies the system calls it never needs.
+//! Both need Linux, older kernels without Landlock only get a warning.
+
+use std::{
+    ffi::CString,
+    io,
+    os::unix::ffi::OsStrExt,
+    path::{Path, PathBuf},
+};
+
+use dynsix::{
+    config::{
+        source_paths, Config, DaemonConfig, PrefixCheck, PREFIX_TOKEN_FILE_ENV, TOKEN_FILE_ENV,
+    },
+    ip::Source,
+};
+use tracing::{info, warn};
+
+/// Read and execute, e.g. libraries, certificates, resolv.conf and sops
+const SYSTEM_PATHS: &[&str] = &[
+    "/usr",
+    "/bin",
+    "/sbin",
+    "/lib",
+    "/lib32",
+    "/lib64",
+    "/etc",
+    "/nix/store",
+];
+
+// linux/landlock.h
+const LANDLOCK_CREATE_RULESET_VERSION: u32 = 1;
+const LANDLOCK_RULE_PATH_BENEATH: libc::c_int = 1;
+const ACCESS_EXECUTE: u64 = 1 << 0;
+const ACCESS_WRITE_FILE: u64 = 1 << 1;
+const ACCESS_READ_FILE: u64 = 1 << 2;
+const ACCESS_READ_DIR: u64 = 1 << 3;
+const ACCESS_REMOVE_FILE: u64 = 1 << 5;
+const ACCESS_MAKE_REG: u64 = 1 << 8;
+const ACCESS_MAKE_SOCK: u64 = 1 << 9;
+const ACCESS_TRUNCATE: u64 = 1 << 14;
+/// Rights that only apply to files, rules for files may not grant others
+const FILE_ACCESS: u64 = ACCESS_EXECUTE | ACCESS_WRITE_FILE | ACCESS_READ_FILE | ACCESS_TRUNCATE;
+const READ: u64 = ACCESS_EXECUTE | ACCESS_READ_FILE | ACCESS_READ_DIR;
+/// Creating, replacing and removing the state file, lock file, metrics and
+/// control socket
+const WRITE: u64 = READ
+    | ACCESS_WRITE_FILE
+    | ACCESS_REMOVE_FILE
+    | ACCESS_MAKE_REG
+    | ACCESS_MAKE_SOCK
+    | ACCESS_TRUNCATE;
+
+/// Denied with EPERM: debugging other processes, changing users, namespaces,
+/// mounts, kernel modules and keys, and the like
+const DENIED_SYSCALLS: &[libc::c_long] = &[
+    libc::SYS_ptrace,
+    libc::SYS_process_vm_readv,
+    libc::SYS_process_vm_writev,
+    libc::SYS_setuid,
+    libc::SYS_setgid,
+    libc::SYS_setreuid,
+    libc::SYS_setregid,
+    libc::SYS_setresuid,
+    libc::SYS_setresgid,
+    libc::SYS_setgroups,
+    libc::SYS_unshare,
+    libc::SYS_setns,
+    libc::SYS_mount,
+    libc::SYS_umount2,
+    libc::SYS_pivot_root,
+    libc::SYS_chroot,
+    libc::SYS_swapon,
+    libc::SYS_swapoff,
+    libc::SYS_reboot,
+    libc::SYS_kexec_load,
+    libc::SYS_kexec_file_load,
+    libc::SYS_init_module,
+    libc::SYS_finit_module,
+    libc::SYS_delete_module,
+    libc::SYS_bpf,
+    libc::SYS_perf_event_open,
+    libc::SYS_keyctl,
+    libc::SYS_add_key,
+    libc::SYS_request_key,
+    libc::SYS_userfaultfd,
+    libc::SYS_open_by_handle_at,
+    libc::SYS_acct,
+    libc::SYS_settimeofday,
+    libc::SYS_clock_settime,
+];
+
+// linux/audit.h, the architecture the filter was written for
+#[cfg(target_arch = "x86_64")]
+const AUDIT_ARCH: u32 = 0xc000_003e;
+#[cfg(target_arch = "aarch64")]
+const AUDIT_ARCH: u32 = 0xc000_00b7;
+/// Set in the numbers of the x32 system calls on x86_64
+const X32_SYSCALL_BIT: u32 = 0x4000_0000;
+
+// linux/seccomp.h
+const SECCOMP_SET_MODE_FILTER: libc::c_uint = 1;
+const SECCOMP_FILTER_FLAG_TSYNC: libc::c_uint = 1;
+
+#[repr(C)]
+struct RulesetAttr {
+    handled_access_fs: u64,
+}
+
+#[repr(C, packed)]
+struct PathBeneathAttr {
+    allowed_access: u64,
+    parent_fd: libc::c_int,
+}
+
+/// Restricts the files the process can access. Landlock only confines the
+/// calling thread and those it starts later, so this has to run before the
+/// runtime starts its threads.
+pub fn restrict_paths(
+    config: &Config,
+    config_path: &Path,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if !config.daemon.sandbox {
+        return Ok(());
+    }
+    // SAFETY: querying the version takes no pointers
+    let abi = unsafe {
+        libc::syscall(
+            libc::SYS_landlock_create_ruleset,
+            std::ptr::null::<RulesetAttr>(),
+            0,
+            LANDLOCK_CREATE_RULESET_VERSION,
+        )
+    };
+    if abi < 1 {
+        warn!(
+            "Landlock is not available ({}), file access is not restricted",
+            io::Error::last_os_error()
+        );
+        return Ok(());
+    }
+    // Rights of newer ABIs are only known to the kernels supporting them
+    let handled = match abi {
+        1 => (1 << 13) - 1,
+        2 => (1 << 14) - 1,
+        _ => (1 << 15) - 1,
+    };
+
+    let attr = RulesetAttr {
+        handled_access_fs: handled,
+    };
+    // SAFETY: attr is valid and its size is passed along
+    let ruleset = unsafe {
+        libc::syscall(
+            libc::SYS_landlock_create_ruleset,
+            &attr,
+            std::mem::size_of::<RulesetAttr>(),
+            0,
+        )
+    };
+    if ruleset < 0 {
+        return Err(format!(
+            "creating the Landlock ruleset failed: {}",
+            io::Error::last_os_error()
+        )
+        .into());
+    }
+    let ruleset = ruleset as libc::c_int;
+
+    let result = add_rules(ruleset, config, config_path, handled).and_then(|()| {
+        // SAFETY: plain calls on a ruleset fd owned by this function
+        unsafe {
+            check(
+                libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0),
+                "PR_SET_NO_NEW_PRIVS",
+            )?;
+            check(
+                libc::syscall(libc::SYS_landlock_restrict_self, ruleset, 0) as libc::c_int,
+                "landlock_restrict_self",
+            )
+        }
+    });
+    // SAFETY: the fd is not used afterwards
+    unsafe { libc::close(ruleset) };
+    result?;
+
+    info!("Restricted file access with Landlock ABI {abi}");
+    Ok(())
+}
+
+/// Denies [`DENIED_SYSCALLS`] to all threads. Changing users is among them,
+/// so this runs after `daemon.user` is switched to.
+#[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+pub fn restrict_syscalls(config: &DaemonConfig) -> Result<(), Box<dyn std::error::Error>> {
+    if !config.sandbox {
+        return Ok(());
+    }
+    let deny = libc::SECCOMP_RET_ERRNO | libc::EPERM as u32;
+    let load = |offset| statement(libc::BPF_LD | libc::BPF_W | libc::BPF_ABS, offset);
+    let jump_if = |op, value, jt, jf| libc::sock_filter {
+        code: (libc::BPF_JMP | op | libc::BPF_K) as u16,
+        jt,
+        jf,
+        k: value,
+    };
+    let ret = |value| statement(libc::BPF_RET | libc::BPF_K, value);
+
+    // seccomp_data starts with the syscall number followed by the architecture
+    let mut filter = vec![
+        load(4),
+        jump_if(libc::BPF_JEQ, AUDIT_ARCH, 1, 0),
+        ret(deny),
+        load(0),
+        jump_if(libc::BPF_JGE, X32_SYSCALL_BIT, 0, 1),
+        ret(deny),
+    ];
+    for &syscall in DENIED_SYSCALLS {
+        filter.push(jump_if(libc::BPF_JEQ, syscall as u32, 0, 1));
+        filter.push(ret(deny));
+    }
+    filter.push(ret(libc::SECCOMP_RET_ALLOW));
+
+    let program = libc::sock_fprog {
+        len: filter.len() as libc::c_ushort,
+        filter: filter.as_mut_ptr(),
+    };
+    // SAFETY: program points to the filter, which outlives the calls. The
+    // kernel copies it.
+    unsafe {
+        check(
+            libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0),
+            "PR_SET_NO_NEW_PRIVS",
+        )?;
+        check(
+            libc::syscall(
+                libc::SYS_seccomp,
+                SECCOMP_SET_MODE_FILTER,
+                SECCOMP_FILTER_FLAG_TSYNC,
+                &program,
+            ) as libc::c_int,
+            "seccomp",
+        )?;
+    }
+    info!("Restricted system calls with seccomp");
+    Ok(())
+}
+
+#[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+pub fn restrict_syscalls(config: &DaemonConfig) -> Result<(), Box<dyn std::error::Error>> {
+    if config.sandbox {
+        warn!("The seccomp filter is not available on this architecture");
+    }
+    Ok(())
+}
+
+fn statement(code: u32, k: u32) -> libc::sock_filter {
+    libc::sock_filter {
+        code: code as u16,
+        jt: 0,
+        jf: 0,
+        k,
+    }
+}
+
+fn add_rules(
+    ruleset: libc::c_int,
+    config: &Config,
+    config_path: &Path,
+    handled: u64,
+) -> Result<(), String> {
+    let mut read: Vec<PathBuf> = SYSTEM_PATHS.iter().map(PathBuf::from).collect();
+    read.push(PathBuf::from("/dev/urandom"));
+    read.extend(source_paths(config_path));
+    read.extend(config.daemon.sandbox_paths.iter().cloned());
+    read.extend(config.http.ca_file.iter().cloned());
+    for service in config.services.values() {
+        read.extend(service.ssh_host_keys.iter().cloned());
+    }
+    // MAC addresses of interfaces, /sys/class/net only links to devices
+    if config
+        .services
+        .values()
+        .any(|service| service.suffix.interface().is_some())
+    {
+        read.push(PathBuf::from("/sys"));
+    }
+    match &config.source {
+        // Routers may replace the prefix file rather than write to it
+        Some(Source::File(path)) => read.extend(path.parent().map(Path::to_path_buf)),
+        // /proc/net links there
+        Some(Source::Interface(_)) => read.push(PathBuf::from("/proc/self/net")),
+        // For comparing the public address with the local ones
+        Some(Source::Dns(_)) | None if config.prefix_check != PrefixCheck::Off => {
+            read.push(PathBuf::from("/proc/self/net"))
+        }
+        Some(Source::Dns(_) | Source::Upnp(_)) | None => {}
+    }
+    if let Some(dir) = std::env::var_os("CREDENTIALS_DIRECTORY") {
+        read.push(PathBuf::from(dir));
+    }
+    for var in [TOKEN_FILE_ENV, PREFIX_TOKEN_FILE_ENV, "SOPS_AGE_KEY_FILE"] {
+        if let Some(path) = std::env::var_os(var) {
+            read.push(PathBuf::from(path));
+        }
+    }
+
+    // Files are replaced through a temporary file in the same directory
+    let mut write = vec![PathBuf::from("/dev/null")];
+    write.extend(
+        [
+            config.state_file.as_deref(),
+            config.history_file.as_deref(),
+            config.audit.path.as_deref(),
+            config.lock_file.as_deref(),
+            config.metrics.textfile.as_deref(),
+            config.daemon.control_socket.as_deref(),
+        ]
+        .into_iter()
+        .flatten()
+        .map(|path| match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+            _ => PathBuf::from("."),
+        }),
+    );
+
+    for (paths, access) in [(read, READ), (write, WRITE)] {
+        for path in paths {
+            if !path.exists() {
+                // System directories differ between distributions
+                if access == WRITE {
+                    warn!(
+                        "Sandbox: {} does not exist, writing there will fail",
+                        path.display()
+                    );
+                }
+                continue;
+            }
+            add_rule(ruleset, &path, access & handled)?;
+        }
+    }
+    Ok(())
+}
+
+fn add_rule(ruleset: libc::c_int, path: &Path, access: u64) -> Result<(), String> {
+    let c_path = CString::new(path.as_os_str().as_bytes())
+        .map_err(|_| format!("sandbox: invalid path {}", path.display()))?;
+    // SAFETY: c_path is a valid C string
+    let fd = unsafe { libc::open(c_path.as_ptr(), libc::O_PATH | libc::O_CLOEXEC) };
+    if fd < 0 {
+        return Err(format!(
+            "sandbox: {}: {}",
+            path.display(),
+            io::Error::last_os_error()
+        ));
+    }
+    let access = if path.is_dir() {
+        access
+    } else {
+        access & FILE_ACCESS
+    };
+    let attr = PathBeneathAttr {
+        allowed_access: access,
+        parent_fd: fd,
+    };
+    // SAFETY: attr is valid for the call, fd is closed afterwards
+    let result = unsafe {
+        let result = libc::syscall(
+            libc::SYS_landlock_add_rule,
+            ruleset,
+            LANDLOCK_RULE_PATH_BENEATH,
+            &attr,
+            0,
+        );
+        libc::close(fd);
+        result
+    };
+    if result < 0 {
+        return Err(format!(
+            "sandbox: adding a rule for {} failed: {}",
+            path.display(),
+            io::Error::last_os_error()
+        ));
+    }
+    Ok(())
+}
+
+fn check(result: libc::c_int, call: &str) -> Result<(), String> {
+    if result == 0 {
+        Ok(())
+    } else {
+        Err(format!(
+            "sandbox: {call} failed: {}",
+            io::Error::last_os_error()
+        ))
+    }
+}
@@ -0,0 +1,245 @@
+//! Cron expressions for services that are reconciled on their own schedule
+//! by `dynsix daemon`, e.g. `*/5 * * * *`, or that describe a window such as
+//! the quiet hours `* 8-17 * * 1-5`. Times are evaluated in UTC.
+
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+    str::FromStr,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Deserializer};
+
+use crate::config::ServiceConfig;
+
+/// How far ahead [`Schedule::next_after`] looks before giving up, enough for
+/// anything but expressions such as `0 0 30 2 *` that never match
+const SEARCH_LIMIT: u64 = 5 * 366 * 86400;
+/// How far ahead [`Schedule::end_after`] looks for the end of a window
+const WINDOW_LIMIT: u64 = 31 * 86400;
+
+/// A five field cron expression: minute, hour, day of month, month and day
+/// of week (0 or 7 is Sunday). Fields accept `*`, numbers, ranges `a-b`,
+/// steps `*/n` or `a-b/n` and comma separated lists of those. `@hourly`,
+/// `@daily`, `@weekly`, `@monthly` and `@yearly` are understood as well.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Schedule {
+    expression: String,
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    /// Like cron, a day matches if either the day of month or the day of week
+    /// matches when both are restricted
+    days_restricted: bool,
+    weekdays_restricted: bool,
+}
+
+impl Schedule {
+    /// The first time strictly after `time` matching the expression, at the
+    /// start of a minute
+    pub fn next_after(&self, time: SystemTime) -> Option<SystemTime> {
+        let now = time.duration_since(UNIX_EPOCH).ok()?.as_secs();
+        let mut t = (now / 60 + 1) * 60;
+
+        while t < now + SEARCH_LIMIT {
+            let days = t / 86400;
+            let (month, day) = month_and_day(days);
+            if !matches(self.months, month) || !self.matches_day(day, (days + 4) % 7) {
+                t = (days + 1) * 86400;
+                continue;
+            }
+            if !matches(self.hours, t % 86400 / 3600) {
+                t = (t / 3600 + 1) * 3600;
+                continue;
+            }
+            if !matches(self.minutes, t % 3600 / 60) {
+                t += 60;
+                continue;
+            }
+            return Some(UNIX_EPOCH + Duration::from_secs(t));
+        }
+        None
+    }
+
+    /// Whether the minute `time` falls into matches the expression
+    pub fn contains(&self, time: SystemTime) -> bool {
+        let Ok(since_epoch) = time.duration_since(UNIX_EPOCH) else {
+            return false;
+        };
+        let t = since_epoch.as_secs();
+        let days = t / 86400;
+        let (month, day) = month_and_day(days);
+        matches(self.months, month)
+            && self.matches_day(day, (days + 4) % 7)
+            && matches(self.hours, t % 86400 / 3600)
+            && matches(self.minutes, t % 3600 / 60)
+    }
+
+    /// The start of the first minute after `time` that does not match, where
+    /// the window `time` is in ends. `None` if it does not end within a month.
+    pub fn end_after(&self, time: SystemTime) -> Option<SystemTime> {
+        let now = time.duration_since(UNIX_EPOCH).ok()?.as_secs();
+        let mut t = (now / 60 + 1) * 60;
+        while t < now + WINDOW_LIMIT {
+            let minute = UNIX_EPOCH + Duration::from_secs(t);
+            if !self.contains(minute) {
+                return Some(minute);
+            }
+            t += 60;
+        }
+        None
+    }
+
+    fn matches_day(&self, day: u64, weekday: u64) -> bool {
+        let day = matches(self.days, day);
+        let weekday = matches(self.weekdays, weekday);
+        if self.days_restricted && self.weekdays_restricted {
+            day || weekday
+        } else {
+            day && weekday
+        }
+    }
+}
+
+impl FromStr for Schedule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let expanded = match s.trim() {
+            "@hourly" => "0 * * * *",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@weekly" => "0 0 * * 0",
+            "@monthly" => "0 0 1 * *",
+            "@yearly" | "@annually" => "0 0 1 1 *",
+            other => other,
+        };
+        let fields: Vec<_> = expanded.split_whitespace().collect();
+        let [minutes, hours, days, months, weekdays] = fields[..] else {
+            return Err(format!(
+                "invalid schedule '{s}': expected 5 fields, found {}",
+                fields.len()
+            ));
+        };
+
+        let field = |spec: &str, name: &str, min: u64, max: u64| {
+            parse_field(spec, min, max).map_err(|e| format!("invalid schedule '{s}': {name} {e}"))
+        };
+        let mut weekday_bits = field(weekdays, "day of week", 0, 7)?;
+        if weekday_bits & 1 << 7 != 0 {
+            weekday_bits |= 1;
+        }
+
+        Ok(Self {
+            expression: s.trim().to_string(),
+            minutes: field(minutes, "minute", 0, 59)?,
+            hours: field(hours, "hour", 0, 23)?,
+            days: field(days, "day of month", 1, 31)?,
+            months: field(months, "month", 1, 12)?,
+            weekdays: weekday_bits,
+            days_restricted: !days.starts_with('*'),
+            weekdays_restricted: !weekdays.starts_with('*'),
+        })
+    }
+}
+
+impl fmt::Display for Schedule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.expression)
+    }
+}
+
+impl<'de> Deserialize<'de> for Schedule {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+fn matches(bits: u64, value: u64) -> bool {
+    bits & 1 << value != 0
+}
+
+/// Parses one field into a bit set of the allowed values
+fn parse_field(spec: &str, min: u64, max: u64) -> Result<u64, String> {
+    let number = |value: &str| {
+        value
+            .parse::<u64>()
+            .ok()
+            .filter(|value| (min..=max).contains(value))
+            .ok_or_else(|| format!("'{value}' is not a number from {min} to {max}"))
+    };
+
+    let mut bits = 0;
+    for part in spec.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (
+                range,
+                step.parse::<u64>()
+                    .ok()
+                    .filter(|step| *step > 0)
+                    .ok_or_else(|| format!("has an invalid step '{step}'"))?,
+            ),
+            None => (part, 1),
+        };
+        let (start, end) = match range {
+            "*" => (min, max),
+            _ => match range.split_once('-') {
+                Some((start, end)) => (number(start)?, number(end)?),
+                None if step > 1 => (number(range)?, max),
+                None => (number(range)?, number(range)?),
+            },
+        };
+        if start > end {
+            return Err(format!("has a backwards range '{range}'"));
+        }
+        for value in (start..=end).step_by(step as usize) {
+            bits |= 1 << value;
+        }
+    }
+
+    Ok(bits)
+}
+
+/// Month and day of month of a day counted from the Unix epoch, see
+/// <http://howardhinnant.github.io/date_algorithms.html#civil_from_days>
+fn month_and_day(days: u64) -> (u64, u64) {
+    let z = days + 719_468;
+    let day_of_era = z % 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    (month, day)
+}
+
+/// Whether the daemon reconciles the service `name` when it wakes up: if its
+/// own `schedule` or `interval` is in `due`, or if it follows the interval of
+/// the daemon and `interval_due`. Records found by `[discovery]` are not
+/// among the configured `services` and always follow the interval.
+pub fn is_due(
+    name: &str,
+    services: &HashMap<String, ServiceConfig>,
+    due: &HashSet<String>,
+    interval_due: bool,
+) -> bool {
+    if due.contains(name) {
+        return true;
+    }
+    interval_due
+        && services
+            .get(name)
+            .is_none_or(|service| service.schedule.is_none() && service.interval.is_none())
+}
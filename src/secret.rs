@@ -0,0 +1,97 @@
+//! Credentials that must not end up in logs or error messages, nor linger in
+//! memory after use, where core dumps and swap could expose them
+
+use std::{
+    fmt,
+    sync::atomic::{compiler_fence, Ordering},
+};
+
+use reqwest::header::HeaderValue;
+use serde::Deserialize;
+
+/// A token or password. Its `Debug` output is redacted, the value is only
+/// available through [`Secret::expose`] and is overwritten when dropped.
+#[derive(Deserialize, Clone, Default, PartialEq, Eq)]
+#[serde(transparent)]
+pub struct Secret(String);
+
+impl Secret {
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+
+    /// Empty or only whitespace, which no API accepts
+    pub fn is_blank(&self) -> bool {
+        self.0.trim().is_empty()
+    }
+
+    /// The trimmed `raw`, e.g. the contents of a secret file, which is wiped
+    /// instead of left behind in memory
+    pub(crate) fn trimmed(mut raw: String) -> Self {
+        let secret = Self::from(raw.trim());
+        wipe_string(&mut raw);
+        secret
+    }
+
+    /// `<scheme> <secret>` as a header value that is redacted when requests
+    /// are logged. A value that is not allowed in a header is sent empty, so
+    /// the API rejects it as unauthorized. Clients build it once and reuse it
+    /// rather than copying the secret for every request.
+    pub(crate) fn header(&self, scheme: &str) -> HeaderValue {
+        let mut raw = Vec::with_capacity(scheme.len() + 1 + self.0.len());
+        if !scheme.is_empty() {
+            raw.extend_from_slice(scheme.as_bytes());
+            raw.push(b' ');
+        }
+        raw.extend_from_slice(self.0.as_bytes());
+        let mut value = HeaderValue::from_bytes(&raw).unwrap_or(HeaderValue::from_static(""));
+        wipe(&mut raw);
+        value.set_sensitive(true);
+        value
+    }
+}
+
+impl Drop for Secret {
+    fn drop(&mut self) {
+        wipe_string(&mut self.0);
+    }
+}
+
+/// Overwrites `bytes` with zeroes in a way the compiler does not optimize
+/// away, for temporary copies of secrets
+pub(crate) fn wipe(bytes: &mut [u8]) {
+    for byte in bytes.iter_mut() {
+        // SAFETY: byte is a valid, aligned reference
+        unsafe { std::ptr::write_volatile(byte, 0) };
+    }
+    compiler_fence(Ordering::SeqCst);
+}
+
+fn wipe_string(string: &mut str) {
+    // SAFETY: zeroes are valid UTF-8
+    wipe(unsafe { string.as_bytes_mut() });
+}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("\"[redacted]\"")
+    }
+}
+
+impl From<String> for Secret {
+    fn from(value: String) -> Self {
+        Self(value)
+    }
+}
+
+impl From<&str> for Secret {
+    fn from(value: &str) -> Self {
+        Self(value.to_string())
+    }
+}
+
+impl From<&Secret> for Secret {
+    fn from(value: &Secret) -> Self {
+        value.clone()
+    }
+}
@@ -0,0 +1,79 @@
+//! Configs encrypted with [SOPS](https://github.com/getsops/sops), e.g. with
+//! age keys, so they can be kept in a public repository. The `sops` binary
+//! decrypts them to memory, the plain config is never written to disk.
+
+use std::{
+    io::ErrorKind,
+    path::Path,
+    process::{Command, Stdio},
+};
+
+use crate::{config::ConfigFormat, DynsixError};
+
+/// Name of the systemd credential holding the age key, passed to sops as
+/// `SOPS_AGE_KEY_FILE` unless that is set already
+pub const AGE_KEY_CREDENTIAL: &str = "age-key";
+
+/// Marks values encrypted by sops
+const ENCRYPTED_MARKER: &[u8] = b"ENC[AES256_GCM,";
+
+/// Whether `raw` was encrypted by sops, which adds its metadata under a
+/// top-level `sops` key
+pub fn is_encrypted(raw: &[u8]) -> bool {
+    let has_metadata = contains(raw, b"\"sops\":")
+        || raw
+            .split(|&byte| byte == b'\n')
+            .any(|line| line.starts_with(b"sops:"));
+    has_metadata && contains(raw, ENCRYPTED_MARKER)
+}
+
+/// The decrypted contents of `path`. Sops has no TOML support, TOML configs
+/// are encrypted as binary files.
+pub fn decrypt(path: &Path, format: ConfigFormat) -> Result<Vec<u8>, DynsixError> {
+    let file_type = match format {
+        ConfigFormat::Toml => "binary",
+        ConfigFormat::Yaml => "yaml",
+        ConfigFormat::Json => "json",
+    };
+
+    let mut command = Command::new("sops");
+    command
+        .args([
+            "--decrypt",
+            "--input-type",
+            file_type,
+            "--output-type",
+            file_type,
+        ])
+        .arg(path)
+        .stdin(Stdio::null());
+    if std::env::var_os("SOPS_AGE_KEY_FILE").is_none() {
+        if let Some(dir) = std::env::var_os("CREDENTIALS_DIRECTORY") {
+            let key = Path::new(&dir).join(AGE_KEY_CREDENTIAL);
+            if key.is_file() {
+                command.env("SOPS_AGE_KEY_FILE", key);
+            }
+        }
+    }
+
+    let output = command.output().map_err(|e| DynsixError::Sops {
+        path: path.to_path_buf(),
+        message: match e.kind() {
+            ErrorKind::NotFound => "sops is not installed or not in PATH".to_string(),
+            _ => e.to_string(),
+        },
+    })?;
+    if !output.status.success() {
+        return Err(DynsixError::Sops {
+            path: path.to_path_buf(),
+            message: String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        });
+    }
+    Ok(output.stdout)
+}
+
+fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+    haystack
+        .windows(needle.len())
+        .any(|window| window == needle)
+}
@@ -0,0 +1,271 @@
+//! Just enough of the SQLite C API for the history database, linked against
+//! the system's libsqlite3
+
+use std::{
+    ffi::{c_char, c_int, c_void, CStr, CString},
+    os::unix::ffi::OsStrExt,
+    path::Path,
+    ptr,
+};
+
+const SQLITE_OK: c_int = 0;
+const SQLITE_ROW: c_int = 100;
+const SQLITE_DONE: c_int = 101;
+const SQLITE_NULL: c_int = 5;
+const SQLITE_OPEN_READWRITE: c_int = 0x2;
+const SQLITE_OPEN_CREATE: c_int = 0x4;
+const SQLITE_OPEN_FULLMUTEX: c_int = 0x10000;
+/// Makes SQLite copy bound text right away
+const SQLITE_TRANSIENT: isize = -1;
+
+#[repr(C)]
+struct Sqlite3 {
+    _private: [u8; 0],
+}
+
+#[repr(C)]
+struct Sqlite3Stmt {
+    _private: [u8; 0],
+}
+
+#[link(name = "sqlite3")]
+extern "C" {
+    fn sqlite3_open_v2(
+        filename: *const c_char,
+        db: *mut *mut Sqlite3,
+        flags: c_int,
+        vfs: *const c_char,
+    ) -> c_int;
+    fn sqlite3_close_v2(db: *mut Sqlite3) -> c_int;
+    fn sqlite3_errmsg(db: *mut Sqlite3) -> *const c_char;
+    fn sqlite3_busy_timeout(db: *mut Sqlite3, milliseconds: c_int) -> c_int;
+    fn sqlite3_exec(
+        db: *mut Sqlite3,
+        sql: *const c_char,
+        callback: *const c_void,
+        argument: *mut c_void,
+        error: *mut *mut c_char,
+    ) -> c_int;
+    fn sqlite3_prepare_v2(
+        db: *mut Sqlite3,
+        sql: *const c_char,
+        length: c_int,
+        statement: *mut *mut Sqlite3Stmt,
+        tail: *mut *const c_char,
+    ) -> c_int;
+    fn sqlite3_bind_text(
+        statement: *mut Sqlite3Stmt,
+        index: c_int,
+        text: *const c_char,
+        length: c_int,
+        destructor: isize,
+    ) -> c_int;
+    fn sqlite3_bind_int64(statement: *mut Sqlite3Stmt, index: c_int, value: i64) -> c_int;
+    fn sqlite3_bind_null(statement: *mut Sqlite3Stmt, index: c_int) -> c_int;
+    fn sqlite3_step(statement: *mut Sqlite3Stmt) -> c_int;
+    fn sqlite3_column_type(statement: *mut Sqlite3Stmt, column: c_int) -> c_int;
+    fn sqlite3_column_text(statement: *mut Sqlite3Stmt, column: c_int) -> *const u8;
+    fn sqlite3_column_bytes(statement: *mut Sqlite3Stmt, column: c_int) -> c_int;
+    fn sqlite3_column_int64(statement: *mut Sqlite3Stmt, column: c_int) -> i64;
+    fn sqlite3_finalize(statement: *mut Sqlite3Stmt) -> c_int;
+}
+
+/// A value bound to a parameter of a statement
+pub(crate) enum Value<'a> {
+    Integer(i64),
+    Text(&'a str),
+    Null,
+}
+
+/// An open database connection
+pub(crate) struct Connection {
+    db: *mut Sqlite3,
+}
+
+// SAFETY: the connection is opened in serialized mode, where SQLite
+// synchronizes all use of it
+unsafe impl Send for Connection {}
+unsafe impl Sync for Connection {}
+
+impl Connection {
+    /// Opens the database at `path`, creating it if needed
+    pub(crate) fn open(path: &Path) -> Result<Self, String> {
+        let filename = CString::new(path.as_os_str().as_bytes())
+            .map_err(|_| "path contains a null byte".to_string())?;
+        let mut db = ptr::null_mut();
+        // SAFETY: filename is a valid C string and db a valid out pointer.
+        // A handle is returned even on most errors and has to be closed.
+        let result = unsafe {
+            sqlite3_open_v2(
+                filename.as_ptr(),
+                &mut db,
+                SQLITE_OPEN_READWRITE | SQLITE_OPEN_CREATE | SQLITE_OPEN_FULLMUTEX,
+                ptr::null(),
+            )
+        };
+        if db.is_null() {
+            return Err("out of memory".to_string());
+        }
+        let connection = Self { db };
+        if result != SQLITE_OK {
+            return Err(connection.error());
+        }
+        // Other processes, e.g. `history` next to the daemon, hold the lock
+        // only briefly
+        // SAFETY: db is an open connection
+        unsafe { sqlite3_busy_timeout(db, 5000) };
+        Ok(connection)
+    }
+
+    /// Runs one or more statements without parameters
+    pub(crate) fn execute_batch(&self, sql: &str) -> Result<(), String> {
+        let sql = CString::new(sql).map_err(|_| "SQL contains a null byte".to_string())?;
+        // SAFETY: db is open and sql a valid C string, no callback is used
+        let result = unsafe {
+            sqlite3_exec(
+                self.db,
+                sql.as_ptr(),
+                ptr::null(),
+                ptr::null_mut(),
+                ptr::null_mut(),
+            )
+        };
+        self.check(result)
+    }
+
+    /// Runs a statement, binding `parameters` to `?1`, `?2`, ...
+    pub(crate) fn execute(&self, sql: &str, parameters: &[Value]) -> Result<(), String> {
+        self.prepare(sql, parameters)?.step()?;
+        Ok(())
+    }
+
+    /// Runs a query and maps every row with `row`
+    pub(crate) fn query<T>(
+        &self,
+        sql: &str,
+        parameters: &[Value],
+        mut row: impl FnMut(&Row) -> T,
+    ) -> Result<Vec<T>, String> {
+        let statement = self.prepare(sql, parameters)?;
+        let mut rows = Vec::new();
+        while statement.step()? {
+            rows.push(row(&Row {
+                statement: &statement,
+            }));
+        }
+        Ok(rows)
+    }
+
+    fn prepare(&self, sql: &str, parameters: &[Value]) -> Result<Statement<'_>, String> {
+        let length = c_int::try_from(sql.len()).map_err(|_| "SQL too long".to_string())?;
+        let mut statement = ptr::null_mut();
+        // SAFETY: sql is valid for length bytes, statement a valid out pointer
+        let result = unsafe {
+            sqlite3_prepare_v2(
+                self.db,
+                sql.as_ptr().cast(),
+                length,
+                &mut statement,
+                ptr::null_mut(),
+            )
+        };
+        self.check(result)?;
+        let statement = Statement {
+            connection: self,
+            statement,
+        };
+
+        for (index, parameter) in (1..).zip(parameters) {
+            // SAFETY: the statement is valid and text is copied by SQLite
+            let result = unsafe {
+                match parameter {
+                    Value::Integer(value) => sqlite3_bind_int64(statement.statement, index, *value),
+                    Value::Text(text) => sqlite3_bind_text(
+                        statement.statement,
+                        index,
+                        text.as_ptr().cast(),
+                        c_int::try_from(text.len()).map_err(|_| "text too long".to_string())?,
+                        SQLITE_TRANSIENT,
+                    ),
+                    Value::Null => sqlite3_bind_null(statement.statement, index),
+                }
+            };
+            self.check(result)?;
+        }
+        Ok(statement)
+    }
+
+    fn check(&self, result: c_int) -> Result<(), String> {
+        match result {
+            SQLITE_OK => Ok(()),
+            _ => Err(self.error()),
+        }
+    }
+
+    fn error(&self) -> String {
+        // SAFETY: errmsg returns a valid C string owned by the connection
+        unsafe { CStr::from_ptr(sqlite3_errmsg(self.db)) }
+            .to_string_lossy()
+            .into_owned()
+    }
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        // SAFETY: all statements borrow the connection and are finalized
+        unsafe { sqlite3_close_v2(self.db) };
+    }
+}
+
+struct Statement<'a> {
+    connection: &'a Connection,
+    statement: *mut Sqlite3Stmt,
+}
+
+impl Statement<'_> {
+    /// Advances to the next row, false once the statement is done
+    fn step(&self) -> Result<bool, String> {
+        // SAFETY: the statement is valid
+        match unsafe { sqlite3_step(self.statement) } {
+            SQLITE_ROW => Ok(true),
+            SQLITE_DONE => Ok(false),
+            _ => Err(self.connection.error()),
+        }
+    }
+}
+
+impl Drop for Statement<'_> {
+    fn drop(&mut self) {
+        // SAFETY: the statement is valid and not used afterwards
+        unsafe { sqlite3_finalize(self.statement) };
+    }
+}
+
+/// The current row of a query
+pub(crate) struct Row<'a> {
+    statement: &'a Statement<'a>,
+}
+
+impl Row<'_> {
+    pub(crate) fn integer(&self, column: c_int) -> i64 {
+        // SAFETY: the statement is on a row
+        unsafe { sqlite3_column_int64(self.statement.statement, column) }
+    }
+
+    pub(crate) fn text(&self, column: c_int) -> Option<String> {
+        // SAFETY: the statement is on a row, the text is valid until the
+        // next step and copied before
+        unsafe {
+            if sqlite3_column_type(self.statement.statement, column) == SQLITE_NULL {
+                return None;
+            }
+            let text = sqlite3_column_text(self.statement.statement, column);
+            let length = sqlite3_column_bytes(self.statement.statement, column);
+            if text.is_null() {
+                return Some(String::new());
+            }
+            let bytes = std::slice::from_raw_parts(text, length as usize);
+            Some(String::from_utf8_lossy(bytes).into_owned())
+        }
+    }
+}
@@ -0,0 +1,61 @@
+//! SRV records (RFC 2782) of a service pointing at its host, e.g. for game
+//! servers or SIP
+
+use std::collections::BTreeMap;
+
+use serde::Deserialize;
+
+use crate::{config::ServiceConfig, provider::record_name};
+
+pub const RECORD_TYPE: &str = "SRV";
+
+/// One target of an SRV record, records of the same `name` being published
+/// together
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct SrvRecord {
+    /// Name within the domain of the service, e.g. `_minecraft._tcp`
+    pub name: String,
+    #[serde(default)]
+    pub priority: u16,
+    #[serde(default)]
+    pub weight: u16,
+    pub port: u16,
+    /// Host the record points at, the first record of the service unless set
+    pub target: Option<String>,
+}
+
+/// The values of the SRV records of `service` by their name
+pub fn rrsets(service: &ServiceConfig) -> BTreeMap<&str, Vec<String>> {
+    let host = record_name(&service.fqdn, service.record_names()[0]);
+    let mut rrsets: BTreeMap<_, Vec<_>> = BTreeMap::new();
+    for record in &service.srv {
+        let target = record.target.as_deref().unwrap_or(&host);
+        rrsets
+            .entry(record.name.as_str())
+            .or_default()
+            .push(format!(
+                "{} {} {} {}.",
+                record.priority,
+                record.weight,
+                record.port,
+                target.trim_end_matches('.')
+            ));
+    }
+    rrsets
+}
+
+/// What is wrong with `record`, if anything
+pub fn problem(record: &SrvRecord) -> Option<String> {
+    let labels: Vec<_> = record.name.split('.').collect();
+    if labels.len() < 2 || !labels[0].starts_with('_') || !labels[1].starts_with('_') {
+        return Some(format!(
+            "srv name '{}' should start with _service._proto, e.g. _sip._udp",
+            record.name
+        ));
+    }
+    if record.port == 0 {
+        return Some(format!("srv '{}' has port 0", record.name));
+    }
+    None
+}
@@ -0,0 +1,56 @@
+//! SSHFP records (RFC 4255) with the fingerprints of the host keys of a
+//! service, for SSH clients with `VerifyHostKeyDNS`
+
+use std::path::PathBuf;
+
+use openssl::sha::sha256;
+
+use crate::DynsixError;
+
+pub const RECORD_TYPE: &str = "SSHFP";
+
+/// Fingerprint type of SHA-256, RFC 6594
+const SHA256: u8 = 2;
+
+/// The algorithm number of a key type, RFC 4255, 6594, 7479 and 8709
+fn algorithm(key_type: &str) -> Option<u8> {
+    match key_type {
+        "ssh-rsa" => Some(1),
+        "ssh-dss" => Some(2),
+        _ if key_type.starts_with("ecdsa-sha2-") => Some(3),
+        "ssh-ed25519" => Some(4),
+        "ssh-ed448" => Some(6),
+        _ => None,
+    }
+}
+
+/// The SSHFP value with the SHA-256 fingerprint of a public key in the
+/// format of `ssh_host_*_key.pub`, e.g. `4 2 4e6f...`
+pub fn value(public_key: &str) -> Result<String, String> {
+    let mut fields = public_key.split_whitespace();
+    let (Some(key_type), Some(key)) = (fields.next(), fields.next()) else {
+        return Err("expected a key type and the key".to_string());
+    };
+    let algorithm = algorithm(key_type).ok_or_else(|| format!("unknown key type {key_type}"))?;
+    let key = base64::decode(key).map_err(|e| format!("invalid key: {e}"))?;
+    let fingerprint: String = sha256(&key)
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect();
+    Ok(format!("{algorithm} {SHA256} {fingerprint}"))
+}
+
+/// The values for the keys in `paths`, sorted
+pub fn values(paths: &[PathBuf]) -> Result<Vec<String>, DynsixError> {
+    let mut values = Vec::new();
+    for path in paths {
+        let raw = std::fs::read_to_string(path).map_err(|e| DynsixError::io(path, e))?;
+        values.push(value(&raw).map_err(|message| DynsixError::Parse {
+            what: format!("host key {}", path.display()),
+            message,
+        })?);
+    }
+    values.sort();
+    values.dedup();
+    Ok(values)
+}
@@ -0,0 +1,255 @@
+//! Information carried over from one run to the next, kept in the JSON file
+//! configured as `state_file`
+
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    net::Ipv6Addr,
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    provider::{display_name, ProviderKind},
+    report::{Action, RunReport, ServiceReport, Summary},
+    DynsixError, ServiceConfig,
+};
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct State {
+    /// Runs in a row in which at least one service failed
+    #[serde(default)]
+    pub consecutive_failures: u32,
+
+    /// Unix time at which the record of a service was last created or updated
+    #[serde(default)]
+    pub last_changed: HashMap<String, u64>,
+
+    /// Unix time at which the record of a service was last found or made to
+    /// hold its address
+    #[serde(default)]
+    pub last_succeeded: HashMap<String, u64>,
+
+    /// Unix time at which the address of a service last changed, for
+    /// `low_ttl`
+    #[serde(default)]
+    pub address_changed: HashMap<String, u64>,
+
+    /// Every record dynsix created or updated, for `gc` and drift detection
+    #[serde(default)]
+    pub managed: BTreeSet<ManagedRecord>,
+
+    /// The last run of every service, for `dynsix status`
+    #[serde(default)]
+    pub last_attempt: HashMap<String, Attempt>,
+
+    /// The summaries of all runs added up, runs that failed as a whole only
+    /// count as runs
+    #[serde(default)]
+    pub totals: Summary,
+
+    /// Errors of services and failed runs so far, by class
+    #[serde(default)]
+    pub errors: BTreeMap<String, u64>,
+}
+
+/// The outcome of reconciling a service
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Attempt {
+    /// Unix time of the run
+    pub time: u64,
+    pub record: String,
+    pub action: Action,
+    /// The address the record should hold
+    pub address: Ipv6Addr,
+    /// The address the record holds as far as dynsix knows, carried over
+    /// from earlier runs when this one did not change the record
+    pub published: Option<Ipv6Addr>,
+    pub error: Option<String>,
+}
+
+/// A record created or updated by dynsix
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ManagedRecord {
+    pub provider: ProviderKind,
+    pub fqdn: String,
+    /// Name within `fqdn`, as in the service config
+    pub name: String,
+    /// The service that last changed it
+    pub service: String,
+    /// The address it was set to, `None` in state files written before
+    /// addresses were kept
+    #[serde(default)]
+    pub address: Option<Ipv6Addr>,
+}
+
+impl ManagedRecord {
+    /// Whether `service` manages this record
+    pub fn is_managed_by(&self, service: &ServiceConfig) -> bool {
+        service.provider == self.provider
+            && service.fqdn == self.fqdn
+            && service.record_names().contains(&self.name.as_str())
+    }
+
+    /// The full name, e.g. `www.example.com`
+    pub fn display_name(&self) -> String {
+        display_name(&self.fqdn, &self.name)
+    }
+}
+
+impl State {
+    /// Loads the state, a missing file is the state before the first run
+    pub fn load(path: &Path) -> Result<Self, DynsixError> {
+        let raw = match std::fs::read(path) {
+            Ok(raw) => raw,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => return Err(DynsixError::io(path, e)),
+        };
+        serde_json::from_slice(&raw).map_err(|e| DynsixError::Parse {
+            what: format!("state {}", path.display()),
+            message: e.to_string(),
+        })
+    }
+
+    /// Writes to a temporary file first, so an interrupted write never leaves
+    /// a truncated state behind
+    pub fn save(&self, path: &Path) -> Result<(), DynsixError> {
+        if let Some(parent) = path
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+        {
+            std::fs::create_dir_all(parent).map_err(|e| DynsixError::io(parent, e))?;
+        }
+
+        let temporary = path.with_extension("tmp");
+        let raw = serde_json::to_string_pretty(self).map_err(|e| DynsixError::Parse {
+            what: "state".to_string(),
+            message: e.to_string(),
+        })?;
+        std::fs::write(&temporary, raw).map_err(|e| DynsixError::io(&temporary, e))?;
+        std::fs::rename(&temporary, path).map_err(|e| DynsixError::io(path, e))
+    }
+
+    /// Accounts for a run, `report` is `None` if the run failed as a whole
+    pub fn record_run(&mut self, report: Option<&RunReport>) {
+        let Some(report) = report else {
+            self.consecutive_failures += 1;
+            self.totals.runs += 1;
+            return;
+        };
+        self.totals.add(&report.summary);
+
+        if report.failures().next().is_none() {
+            self.consecutive_failures = 0;
+        } else {
+            self.consecutive_failures += 1;
+        }
+
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        for service in &report.services {
+            if matches!(service.action, Action::Created | Action::Updated) {
+                self.last_changed.insert(service.service.clone(), now);
+            }
+            if matches!(
+                service.action,
+                Action::Unchanged | Action::Created | Action::Updated
+            ) {
+                self.last_succeeded.insert(service.service.clone(), now);
+            }
+            if let Some(class) = service.error_class {
+                self.count_error(class);
+            }
+            if address_changed(service) {
+                self.address_changed.insert(service.service.clone(), now);
+            }
+            let published = match service.action {
+                Action::Unchanged | Action::Created | Action::Updated => Some(service.new),
+                Action::Deferred | Action::Skipped | Action::Failed => self
+                    .last_attempt
+                    .get(&service.service)
+                    .and_then(|attempt| attempt.published),
+            };
+            self.last_attempt.insert(
+                service.service.clone(),
+                Attempt {
+                    time: now,
+                    record: service.record.clone(),
+                    action: service.action,
+                    address: service.new,
+                    published,
+                    error: service.error.clone(),
+                },
+            );
+        }
+    }
+
+    /// Counts an error of `class`, for runs that failed as a whole
+    pub fn count_error(&mut self, class: &str) {
+        *self.errors.entry(class.to_string()).or_default() += 1;
+    }
+
+    /// Remembers the records of `services` that `report` created or updated,
+    /// and forgets those it deleted
+    pub fn track(&mut self, report: &RunReport, services: &HashMap<String, ServiceConfig>) {
+        for deleted in &report.deleted {
+            // Deletions from a plan don't know the address written
+            self.managed.retain(|record| {
+                (record.provider, &record.fqdn, &record.name)
+                    != (deleted.provider, &deleted.fqdn, &deleted.name)
+            });
+        }
+        for service in &report.services {
+            if !matches!(service.action, Action::Created | Action::Updated) {
+                continue;
+            }
+            let Some(config) = services.get(&service.service) else {
+                continue;
+            };
+            for name in config.record_names() {
+                // Another service may have changed the record before
+                self.managed.retain(|record| {
+                    (record.provider, record.fqdn.as_str(), record.name.as_str())
+                        != (config.provider, config.fqdn.as_str(), name)
+                });
+                self.managed.insert(ManagedRecord {
+                    provider: config.provider,
+                    fqdn: config.fqdn.clone(),
+                    name: name.to_string(),
+                    service: service.service.clone(),
+                    address: Some(service.new),
+                });
+            }
+        }
+    }
+}
+
+/// The records of `managed` that none of `services` manages, e.g. as their
+/// service was removed from the config
+pub fn orphans<'a>(
+    managed: impl IntoIterator<Item = &'a ManagedRecord>,
+    services: &HashMap<String, ServiceConfig>,
+) -> Vec<ManagedRecord> {
+    managed
+        .into_iter()
+        .filter(|record| {
+            !services
+                .values()
+                .any(|service| record.is_managed_by(service))
+        })
+        .cloned()
+        .collect()
+}
+
+/// Whether the run gave the record of `service` another address, rather
+/// than e.g. only another TTL
+pub fn address_changed(service: &ServiceReport) -> bool {
+    let same = service.old.as_ref().is_some_and(|old| {
+        old.iter()
+            .any(|value| value.trim().parse::<Ipv6Addr>() == Ok(service.new))
+    });
+    matches!(service.action, Action::Created | Action::Updated) && !same
+}
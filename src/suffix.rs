@@ -0,0 +1,423 @@
+//! Host suffixes, the lower 64 bits of the published addresses
+
+use std::{net::Ipv6Addr, path::PathBuf};
+
+use serde::Deserialize;
+
+use crate::{
+    audit::host_name,
+    ip::merge_bits,
+    merge_ips,
+    secret::{wipe, Secret},
+    DynsixError,
+};
+
+/// How often the kernel retries when a stable privacy identifier is
+/// reserved, `net.ipv6.conf.*.idgen_retries`
+const IDGEN_RETRIES: u8 = 3;
+
+/// The interface identifier of a service, configured as `suffix = "::1"`,
+/// `suffix = "::1:2:3:4/56"`,
+/// `suffix = { eui64_from = "aa:bb:cc:dd:ee:ff" }`,
+/// `suffix = { stable_privacy = { secret = "...", mac_from = "eth0" } }` or
+/// `suffix = { hash_of = "service", seed = "..." }`
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(try_from = "RawSuffix")]
+pub enum Suffix {
+    /// A fixed suffix, e.g. `::1`
+    Address(Ipv6Addr),
+    /// A fixed suffix after the first `length` bits of the prefix instead of
+    /// 64, e.g. `::1:2:3:4/56`
+    Split { suffix: Ipv6Addr, length: u8 },
+    /// The modified EUI-64 identifier SLAAC derives from a MAC address
+    Eui64(Mac),
+    /// The RFC 7217 identifier Linux generates with `addr_gen_mode = 2`,
+    /// which changes with the prefix. `secret` is the `stable_secret` of
+    /// the interface, an IPv6 address.
+    StablePrivacy { secret: Secret, mac: Mac },
+    /// Derived from the name of the service or the host, so a fleet needs
+    /// no suffixes assigned by hand. Another `seed` gives other suffixes.
+    Hashed { of: HashOf, seed: String },
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum HashOf {
+    Service,
+    Hostname,
+}
+
+/// Where the MAC address comes from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Mac {
+    Address([u8; 6]),
+    /// Of a local interface, at the time of the run
+    Interface(String),
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawSuffix {
+    Address(String),
+    Eui64 {
+        eui64_from: String,
+    },
+    StablePrivacy {
+        stable_privacy: RawStablePrivacy,
+    },
+    Hashed {
+        hash_of: HashOf,
+        #[serde(default)]
+        seed: String,
+    },
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawStablePrivacy {
+    secret: Secret,
+    mac_from: String,
+}
+
+impl TryFrom<RawSuffix> for Suffix {
+    type Error = String;
+
+    fn try_from(raw: RawSuffix) -> Result<Self, Self::Error> {
+        match raw {
+            RawSuffix::Address(raw) => {
+                let (address, length) = match raw.split_once('/') {
+                    Some((address, length)) => (address, Some(length)),
+                    None => (raw.as_str(), None),
+                };
+                let suffix = address
+                    .parse()
+                    .map_err(|e| format!("invalid suffix '{raw}': {e}"))?;
+                let length = match length.map(str::parse::<u8>) {
+                    None => return Ok(Suffix::Address(suffix)),
+                    Some(Ok(length)) if length <= 128 => length,
+                    Some(_) => {
+                        return Err(format!(
+                            "invalid suffix '{raw}': the length must be between 0 and 128"
+                        ))
+                    }
+                };
+                Ok(match length {
+                    64 => Suffix::Address(suffix),
+                    length => Suffix::Split { suffix, length },
+                })
+            }
+            RawSuffix::Eui64 { eui64_from } => {
+                Mac::parse(&eui64_from).map(Suffix::Eui64).ok_or_else(|| {
+                    format!(
+                        "eui64_from '{eui64_from}' is neither a MAC address nor an interface name"
+                    )
+                })
+            }
+            RawSuffix::StablePrivacy { stable_privacy } => {
+                if parse_secret(&stable_privacy.secret).is_none() {
+                    return Err(
+                        "stable_privacy.secret is not an IPv6 address like the kernel's \
+                         stable_secret"
+                            .to_string(),
+                    );
+                }
+                let mac = Mac::parse(&stable_privacy.mac_from).ok_or_else(|| {
+                    format!(
+                        "stable_privacy.mac_from '{}' is neither a MAC address nor an interface \
+                         name",
+                        stable_privacy.mac_from
+                    )
+                })?;
+                Ok(Suffix::StablePrivacy {
+                    secret: stable_privacy.secret,
+                    mac,
+                })
+            }
+            RawSuffix::Hashed { hash_of, seed } => Ok(Suffix::Hashed { of: hash_of, seed }),
+        }
+    }
+}
+
+impl Suffix {
+    /// The address of the host within the /64 of `prefix` for `service`
+    pub fn address(&self, service: &str, prefix: Ipv6Addr) -> Result<Ipv6Addr, DynsixError> {
+        let suffix = match self {
+            Suffix::Address(suffix) => *suffix,
+            Suffix::Split { suffix, length } => return Ok(merge_bits(prefix, *suffix, *length)),
+            Suffix::Eui64(mac) => eui64(mac.resolve(false)?),
+            Suffix::StablePrivacy { secret, mac } => {
+                let secret = parse_secret(secret).ok_or_else(|| DynsixError::Parse {
+                    what: "stable_privacy.secret".to_string(),
+                    message: "not an IPv6 address".to_string(),
+                })?;
+                stable_privacy(secret, prefix, mac.resolve(true)?).ok_or_else(|| {
+                    DynsixError::Parse {
+                        what: format!("stable privacy address in {prefix}/64"),
+                        message: "only reserved identifiers were generated".to_string(),
+                    }
+                })?
+            }
+            Suffix::Hashed {
+                of: HashOf::Service,
+                seed,
+            } => hashed(seed, service),
+            Suffix::Hashed {
+                of: HashOf::Hostname,
+                seed,
+            } => {
+                let host = host_name().ok_or_else(|| DynsixError::Parse {
+                    what: "host name".to_string(),
+                    message: "gethostname failed".to_string(),
+                })?;
+                hashed(seed, &host)
+            }
+        };
+        Ok(merge_ips(prefix, suffix))
+    }
+
+    /// The local interface whose MAC address is used, if any
+    pub fn interface(&self) -> Option<&str> {
+        match self {
+            Suffix::Address(_) | Suffix::Split { .. } | Suffix::Hashed { .. } => None,
+            Suffix::Eui64(mac) | Suffix::StablePrivacy { mac, .. } => match mac {
+                Mac::Address(_) => None,
+                Mac::Interface(interface) => Some(interface),
+            },
+        }
+    }
+}
+
+impl Mac {
+    /// Parses a MAC address, anything else that could be the name of an
+    /// interface is one
+    fn parse(raw: &str) -> Option<Self> {
+        if let Some(mac) = parse_mac(raw) {
+            Some(Mac::Address(mac))
+        } else if is_interface_name(raw) {
+            Some(Mac::Interface(raw.to_string()))
+        } else {
+            None
+        }
+    }
+
+    /// The address of an interface is either its current one, which SLAAC
+    /// uses, or the `permanent` one of the hardware
+    fn resolve(&self, permanent: bool) -> Result<[u8; 6], DynsixError> {
+        match self {
+            Mac::Address(mac) => Ok(*mac),
+            Mac::Interface(interface) if permanent => permanent_mac(interface),
+            Mac::Interface(interface) => interface_mac(interface),
+        }
+    }
+}
+
+/// The interface identifier SLAAC forms from `mac` (RFC 4291 appendix A):
+/// `ff:fe` in the middle and the universal/local bit flipped
+pub fn eui64(mac: [u8; 6]) -> Ipv6Addr {
+    let [a, b, c, d, e, f] = mac;
+    Ipv6Addr::from([0, 0, 0, 0, 0, 0, 0, 0, a ^ 0x02, b, c, 0xff, 0xfe, d, e, f])
+}
+
+/// The first 64 bits of SHA-256 over `seed` and `input` as a suffix. A zero
+/// byte separates them, so seed `a` with `bc` differs from `ab` with `c`.
+pub fn hashed(seed: &str, input: &str) -> Ipv6Addr {
+    let mut hasher = openssl::sha::Sha256::new();
+    hasher.update(seed.as_bytes());
+    hasher.update(&[0]);
+    hasher.update(input.as_bytes());
+    let digest = hasher.finish();
+
+    let mut octets = [0u8; 16];
+    octets[8..].copy_from_slice(&digest[..8]);
+    Ipv6Addr::from(octets)
+}
+
+/// The interface identifier `ipv6_generate_stable_address` of Linux forms
+/// for the /64 of `prefix`: a single SHA-1 block transform over the secret,
+/// the prefix and the permanent MAC address, which is all zeroes for e.g.
+/// veth devices, skipping reserved identifiers. The kernel
+/// stores the digest words in host byte order, so this matches the kernel of
+/// a machine with the same endianness. `None` if every retry was reserved.
+pub fn stable_privacy(secret: Ipv6Addr, prefix: Ipv6Addr, mac: [u8; 6]) -> Option<Ipv6Addr> {
+    for dad_count in 0..=IDGEN_RETRIES {
+        // struct { in6_addr secret; __be32 prefix[2]; u8 hwaddr[32]; u8 dad_count; }
+        let mut block = [0u8; 64];
+        block[..16].copy_from_slice(&secret.octets());
+        block[16..24].copy_from_slice(&prefix.octets()[..8]);
+        block[24..30].copy_from_slice(&mac);
+        block[56] = dad_count;
+        let digest = sha1_transform(&block);
+        wipe(&mut block);
+
+        let mut octets = [0u8; 16];
+        octets[8..12].copy_from_slice(&digest[0].to_ne_bytes());
+        octets[12..].copy_from_slice(&digest[1].to_ne_bytes());
+        if !is_reserved(&octets) {
+            return Some(Ipv6Addr::from(octets));
+        }
+    }
+    None
+}
+
+/// `ipv6_reserved_interfaceid`: the subnet-router anycast identifier and
+/// the ranges of RFC 5453
+fn is_reserved(octets: &[u8; 16]) -> bool {
+    let high = u32::from_be_bytes(octets[8..12].try_into().unwrap());
+    let low = u32::from_be_bytes(octets[12..].try_into().unwrap());
+    (high | low) == 0
+        || (high == 0x0200_5eff && low & 0xfe00_0000 == 0xfe00_0000)
+        || (high == 0xfdff_ffff && low & 0xffff_ff80 == 0xffff_ff80)
+}
+
+/// One SHA-1 compression of `block` from the initial state, without the
+/// padding a full hash appends
+fn sha1_transform(block: &[u8; 64]) -> [u32; 5] {
+    let mut w = [0u32; 80];
+    for (word, chunk) in w.iter_mut().zip(block.chunks_exact(4)) {
+        *word = u32::from_be_bytes(chunk.try_into().unwrap());
+    }
+    for i in 16..80 {
+        w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+    }
+
+    let initial = [
+        0x6745_2301u32,
+        0xefcd_ab89,
+        0x98ba_dcfe,
+        0x1032_5476,
+        0xc3d2_e1f0,
+    ];
+    let [mut a, mut b, mut c, mut d, mut e] = initial;
+    for (i, word) in w.iter().enumerate() {
+        let (f, k) = match i {
+            0..=19 => ((b & c) | (!b & d), 0x5a82_7999),
+            20..=39 => (b ^ c ^ d, 0x6ed9_eba1),
+            40..=59 => ((b & c) | (b & d) | (c & d), 0x8f1b_bcdc),
+            _ => (b ^ c ^ d, 0xca62_c1d6),
+        };
+        let temp = a
+            .rotate_left(5)
+            .wrapping_add(f)
+            .wrapping_add(e)
+            .wrapping_add(k)
+            .wrapping_add(*word);
+        e = d;
+        d = c;
+        c = b.rotate_left(30);
+        b = a;
+        a = temp;
+    }
+
+    let mut digest = initial;
+    for (word, value) in digest.iter_mut().zip([a, b, c, d, e]) {
+        *word = word.wrapping_add(value);
+    }
+    digest
+}
+
+fn parse_secret(secret: &Secret) -> Option<Ipv6Addr> {
+    secret.expose().trim().parse().ok()
+}
+
+/// Parses `aa:bb:cc:dd:ee:ff` or `aa-bb-cc-dd-ee-ff`
+fn parse_mac(raw: &str) -> Option<[u8; 6]> {
+    let mut mac = [0; 6];
+    let mut octets = raw.split([':', '-']);
+    for octet in &mut mac {
+        let part = octets.next()?;
+        if part.len() != 2 {
+            return None;
+        }
+        *octet = u8::from_str_radix(part, 16).ok()?;
+    }
+    octets.next().is_none().then_some(mac)
+}
+
+/// What Linux accepts as the name of a network interface
+fn is_interface_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= 15
+        && !name.contains(|c: char| c == '/' || c == ':' || c.is_whitespace())
+}
+
+fn interface_mac(interface: &str) -> Result<[u8; 6], DynsixError> {
+    let path = PathBuf::from(format!("/sys/class/net/{interface}/address"));
+    let raw = std::fs::read_to_string(&path).map_err(|e| DynsixError::io(&path, e))?;
+    parse_mac(raw.trim()).ok_or_else(|| DynsixError::Parse {
+        what: format!("MAC address of {interface}"),
+        message: format!("'{}' is not a MAC address", raw.trim()),
+    })
+}
+
+/// The permanent MAC address of `interface`, as `ethtool -P` shows it
+#[cfg(target_os = "linux")]
+fn permanent_mac(interface: &str) -> Result<[u8; 6], DynsixError> {
+    // linux/ethtool.h
+    const ETHTOOL_GPERMADDR: u32 = 0x20;
+    #[repr(C)]
+    struct PermAddr {
+        cmd: u32,
+        size: u32,
+        data: [u8; 32],
+    }
+
+    let error = |source| DynsixError::Interface {
+        interface: interface.to_string(),
+        source,
+    };
+    let mut request = PermAddr {
+        cmd: ETHTOOL_GPERMADDR,
+        size: 32,
+        data: [0; 32],
+    };
+    // The name has to fit into ifr_name with its terminating zero
+    if !is_interface_name(interface) {
+        return Err(error(std::io::ErrorKind::InvalidInput.into()));
+    }
+    // SAFETY: ifreq is plain data, for which zeroes are valid
+    let mut ifreq: libc::ifreq = unsafe { std::mem::zeroed() };
+    for (target, byte) in ifreq.ifr_name.iter_mut().zip(interface.bytes()) {
+        *target = byte as libc::c_char;
+    }
+    ifreq.ifr_ifru.ifru_data = &mut request as *mut PermAddr as *mut libc::c_char;
+
+    // SAFETY: plain system calls, ifreq and request outlive the ioctl
+    let result = unsafe {
+        let socket = libc::socket(libc::AF_INET, libc::SOCK_DGRAM | libc::SOCK_CLOEXEC, 0);
+        if socket < 0 {
+            return Err(error(std::io::Error::last_os_error()));
+        }
+        let result = libc::ioctl(socket, libc::SIOCETHTOOL, &mut ifreq);
+        let result = (result >= 0)
+            .then_some(())
+            .ok_or_else(std::io::Error::last_os_error);
+        libc::close(socket);
+        result
+    };
+    result.map_err(error)?;
+
+    request.data[..6]
+        .try_into()
+        .ok()
+        .filter(|_| request.size == 6)
+        .ok_or_else(|| {
+            error(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!(
+                    "hardware address of {} bytes is no MAC address",
+                    request.size
+                ),
+            ))
+        })
+}
+
+#[cfg(not(target_os = "linux"))]
+fn permanent_mac(interface: &str) -> Result<[u8; 6], DynsixError> {
+    Err(DynsixError::Interface {
+        interface: interface.to_string(),
+        source: std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "reading the permanent MAC address is only supported on Linux",
+        ),
+    })
+}
@@ -0,0 +1,147 @@
+use std::io::IsTerminal;
+
+use dynsix::{
+    plan::{Plan, PlannedAction},
+    provider::display_name,
+};
+
+/// ANSI color codes, empty if colors are disabled
+pub struct Colors {
+    pub red: &'static str,
+    pub green: &'static str,
+    pub yellow: &'static str,
+    pub reset: &'static str,
+}
+
+impl Colors {
+    /// Colors are only used if stdout is a terminal
+    pub fn stdout() -> Self {
+        Self::new(std::io::stdout().is_terminal())
+    }
+
+    pub fn new(enabled: bool) -> Self {
+        if enabled {
+            Self {
+                red: "\x1b[31m",
+                green: "\x1b[32m",
+                yellow: "\x1b[33m",
+                reset: "\x1b[0m",
+            }
+        } else {
+            Self {
+                red: "",
+                green: "",
+                yellow: "",
+                reset: "",
+            }
+        }
+    }
+}
+
+/// Lines of a table with aligned columns, the first row being the header
+pub fn table<const N: usize>(rows: &[[String; N]]) -> Vec<String> {
+    let mut widths = [0; N];
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.len());
+        }
+    }
+    rows.iter()
+        .map(|row| {
+            row.iter()
+                .zip(widths)
+                .map(|(cell, width)| format!("{cell:width$}"))
+                .collect::<Vec<_>>()
+                .join("  ")
+                .trim_end()
+                .to_string()
+        })
+        .collect()
+}
+
+/// Terraform style diff, colored if stdout is a terminal
+pub fn render_plan(plan: &Plan) -> String {
+    let colors = Colors::stdout();
+    let mut out = String::new();
+
+    for change in &plan.changes {
+        let (symbol, color) = match change.action {
+            PlannedAction::Create => ("+", colors.green),
+            PlannedAction::Update => ("~", colors.yellow),
+            PlannedAction::Defer | PlannedAction::NoOp => (" ", ""),
+        };
+        out.push_str(&format!(
+            "{color}{symbol} {}{} ({} AAAA, ttl {})\n",
+            change.service,
+            colors.reset,
+            display_name(&change.fqdn, &change.name),
+            change.ttl
+        ));
+
+        match change.action {
+            PlannedAction::NoOp => {
+                out.push_str(&format!("    {} (unchanged)\n", change.desired));
+            }
+            PlannedAction::Defer => {
+                out.push_str(&format!(
+                    "    {} (deferred for the quiet hours)\n",
+                    change.desired
+                ));
+            }
+            _ => {
+                for value in change.current.iter().flatten() {
+                    out.push_str(&format!("  {}- {value}{}\n", colors.red, colors.reset));
+                }
+                out.push_str(&format!(
+                    "  {}+ {}{}\n",
+                    colors.green, change.desired, colors.reset
+                ));
+            }
+        }
+    }
+
+    for deletion in &plan.deletions {
+        out.push_str(&format!(
+            "{}- {}{} ({} AAAA, removed from the config)\n",
+            colors.red,
+            deletion.service,
+            colors.reset,
+            display_name(&deletion.fqdn, &deletion.name)
+        ));
+        for value in &deletion.current {
+            out.push_str(&format!("  {}- {value}{}\n", colors.red, colors.reset));
+        }
+    }
+
+    for failure in &plan.failed {
+        out.push_str(&format!(
+            "{}! {}{} ({})\n",
+            colors.red, failure.service, colors.reset, failure.error
+        ));
+    }
+
+    let count = |action| {
+        plan.changes
+            .iter()
+            .filter(|change| change.action == action)
+            .count()
+    };
+    out.push_str(&format!(
+        "\nPlan: {} to create, {} to update, {} unchanged",
+        count(PlannedAction::Create),
+        count(PlannedAction::Update),
+        count(PlannedAction::NoOp)
+    ));
+    if count(PlannedAction::Defer) > 0 {
+        out.push_str(&format!(", {} deferred", count(PlannedAction::Defer)));
+    }
+    if !plan.deletions.is_empty() {
+        out.push_str(&format!(", {} to delete", plan.deletions.len()));
+    }
+    if !plan.failed.is_empty() {
+        out.push_str(&format!(", {} failed", plan.failed.len()));
+    }
+    out.push('\n');
+
+    out
+}
@@ -0,0 +1,24 @@
+//! TLSA records (RFC 6698) for DANE, with the hash of the public key of a
+//! certificate
+
+use openssl::{sha::sha256, x509::X509};
+
+pub const RECORD_TYPE: &str = "TLSA";
+/// TTL of records in a domain without services to take it from
+pub const DEFAULT_TTL: u32 = 3600;
+
+/// The TLSA value of the first certificate in `pem`, e.g. a `fullchain.pem`:
+/// usage DANE-EE (3), selector SPKI (1) and matching type SHA-256 (1). It
+/// only changes with the key, so renewals reusing it leave it alone.
+pub fn value(pem: &[u8]) -> Result<String, String> {
+    let certificate = X509::from_pem(pem).map_err(|e| format!("no certificate: {e}"))?;
+    let key = certificate
+        .public_key()
+        .and_then(|key| key.public_key_to_der())
+        .map_err(|e| format!("unreadable public key: {e}"))?;
+    let hash: String = sha256(&key)
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect();
+    Ok(format!("3 1 1 {hash}"))
+}
@@ -0,0 +1,79 @@
+//! Publishing a short TTL only while the address of a service is about to
+//! change or just changed, set with `[services.*.low_ttl]`. Resolvers then
+//! pick up a new prefix quickly without the record running a tiny TTL all
+//! the time.
+
+use std::{
+    collections::HashMap,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use serde::Deserialize;
+
+use crate::{config::deserialize_duration, schedule::Schedule, state::State, ServiceConfig};
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct LowTtl {
+    /// The TTL published instead of the one of the service
+    pub ttl: u32,
+    /// Cron expression (UTC) of when the prefix is expected to change, e.g.
+    /// the forced reconnect of the ISP
+    pub expected_change: Option<Schedule>,
+    /// How long before an expected change the TTL is lowered
+    #[serde(default = "default_before", deserialize_with = "deserialize_duration")]
+    pub before: Duration,
+    /// How long the address has to stay the same, after it changed or was
+    /// expected to, before the normal TTL is restored
+    #[serde(
+        default = "default_stable_for",
+        deserialize_with = "deserialize_duration"
+    )]
+    pub stable_for: Duration,
+}
+
+impl LowTtl {
+    /// Whether the TTL is lowered at `now`, given when the address of the
+    /// service last changed
+    pub fn applies(&self, now: SystemTime, address_changed: Option<SystemTime>) -> bool {
+        if address_changed.is_some_and(|changed| changed + self.stable_for > now) {
+            return true;
+        }
+        let Some(expected) = &self.expected_change else {
+            return false;
+        };
+        // The next change is close, or the last one has not settled yet
+        let upcoming = expected
+            .next_after(now)
+            .is_some_and(|change| change <= now + self.before);
+        let recent = now
+            .checked_sub(self.stable_for)
+            .and_then(|since| expected.next_after(since))
+            .is_some_and(|change| change <= now);
+        upcoming || recent
+    }
+}
+
+fn default_before() -> Duration {
+    Duration::from_secs(3600)
+}
+
+fn default_stable_for() -> Duration {
+    Duration::from_secs(3600)
+}
+
+/// Replaces the TTL of the services with a `low_ttl` that applies at `now`
+pub fn lower(services: &mut HashMap<String, ServiceConfig>, state: &State, now: SystemTime) {
+    for (name, service) in services {
+        let Some(low_ttl) = &service.low_ttl else {
+            continue;
+        };
+        let changed = state
+            .address_changed
+            .get(name)
+            .map(|&time| UNIX_EPOCH + Duration::from_secs(time));
+        if low_ttl.applies(now, changed) {
+            service.ttl = low_ttl.ttl;
+        }
+    }
+}
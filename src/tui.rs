@@ -0,0 +1,313 @@
+//! `dynsix tui`: a live view of a running daemon through its control socket,
+//! showing every service with its published and desired address and the
+//! latest log lines. Drawn with plain ANSI escapes on a terminal in raw mode.
+
+use std::{
+    io::{IsTerminal, Read, Write},
+    os::fd::AsRawFd,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use dynsix::report::Summary;
+use serde_json::Value;
+use tokio::sync::mpsc;
+
+use crate::{control, daemon::Status, term::Colors};
+
+/// How often the status and log lines are fetched again
+const REFRESH: Duration = Duration::from_secs(2);
+
+const HELP: &str = "↑/↓ or j/k select  r reconcile the service  a reconcile all  q quit";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Key {
+    Up,
+    Down,
+    Reconcile,
+    ReconcileAll,
+    Quit,
+}
+
+/// The keys in what was read from the terminal, arrows being sent as
+/// `ESC [ A` and `ESC [ B`
+fn keys(bytes: &[u8]) -> Vec<Key> {
+    let mut keys = Vec::new();
+    let mut bytes = bytes.iter();
+    while let Some(byte) = bytes.next() {
+        keys.push(match byte {
+            b'\x1b' => match (bytes.next(), bytes.next()) {
+                (Some(b'['), Some(b'A')) => Key::Up,
+                (Some(b'['), Some(b'B')) => Key::Down,
+                _ => continue,
+            },
+            b'k' => Key::Up,
+            b'j' => Key::Down,
+            b'r' => Key::Reconcile,
+            b'a' => Key::ReconcileAll,
+            // Ctrl-C doesn't raise SIGINT in raw mode
+            b'q' | b'\x03' => Key::Quit,
+            _ => continue,
+        });
+    }
+    keys
+}
+
+/// Raw mode on the alternate screen, restored when dropped
+struct Terminal {
+    saved: libc::termios,
+}
+
+impl Terminal {
+    fn enter() -> std::io::Result<Self> {
+        let fd = std::io::stdin().as_raw_fd();
+        let mut saved = unsafe { std::mem::zeroed::<libc::termios>() };
+        if unsafe { libc::tcgetattr(fd, &mut saved) } != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        let mut raw = saved;
+        raw.c_lflag &= !(libc::ICANON | libc::ECHO | libc::ISIG | libc::IEXTEN);
+        raw.c_iflag &= !(libc::IXON | libc::ICRNL);
+        raw.c_cc[libc::VMIN] = 1;
+        raw.c_cc[libc::VTIME] = 0;
+        if unsafe { libc::tcsetattr(fd, libc::TCSANOW, &raw) } != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+
+        print!("\x1b[?1049h\x1b[?25l");
+        std::io::stdout().flush()?;
+        Ok(Self { saved })
+    }
+
+    /// Columns and rows, 80x24 if unknown
+    fn size() -> (usize, usize) {
+        let mut size = unsafe { std::mem::zeroed::<libc::winsize>() };
+        let fd = std::io::stdout().as_raw_fd();
+        match unsafe { libc::ioctl(fd, libc::TIOCGWINSZ, &mut size) } {
+            0 if size.ws_col > 0 && size.ws_row > 0 => {
+                (usize::from(size.ws_col), usize::from(size.ws_row))
+            }
+            _ => (80, 24),
+        }
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        print!("\x1b[?25h\x1b[?1049l");
+        let _ = std::io::stdout().flush();
+        unsafe { libc::tcsetattr(std::io::stdin().as_raw_fd(), libc::TCSANOW, &self.saved) };
+    }
+}
+
+/// What is shown, fetched from the daemon
+#[derive(Default)]
+struct View {
+    status: Status,
+    logs: Vec<String>,
+    /// Index of the selected service
+    selected: usize,
+    /// Outcome of the last command or why fetching failed
+    message: String,
+}
+
+impl View {
+    fn services(&self) -> Vec<&String> {
+        self.status.services.keys().collect()
+    }
+
+    fn render(&self, socket: &Path, width: usize, height: usize) -> String {
+        let colors = Colors::new(true);
+        let mut lines = Vec::new();
+        lines.push(match &self.status.last_run {
+            Some(run) => format!(
+                "dynsix on {}  last run {}  address {}{}",
+                socket.display(),
+                run.finished_at,
+                run.public_ip
+                    .map_or_else(|| "unknown".to_string(), |ip| ip.to_string()),
+                run.error
+                    .as_ref()
+                    .map(|e| format!("  failed: {e}"))
+                    .unwrap_or_default()
+            ),
+            None => format!("dynsix on {}  no run yet", socket.display()),
+        });
+        lines.push(String::new());
+
+        let mut rows = vec![[
+            "SERVICE".to_string(),
+            "RECORD".to_string(),
+            "PUBLISHED".to_string(),
+            "DESIRED".to_string(),
+            "LAST CHANGE".to_string(),
+            "RESULT".to_string(),
+        ]];
+        let mut colored = vec![""];
+        for (name, service) in &self.status.services {
+            let action = serde_json::to_value(service.action).unwrap_or_default();
+            let action = action.as_str().unwrap_or_default();
+            rows.push([
+                name.clone(),
+                service.record.clone(),
+                service
+                    .published
+                    .map_or_else(|| "-".to_string(), |ip| ip.to_string()),
+                service.address.to_string(),
+                service
+                    .last_change
+                    .clone()
+                    .unwrap_or_else(|| "-".to_string()),
+                match &service.error {
+                    Some(error) => format!("{action}: {error}"),
+                    None => action.to_string(),
+                },
+            ]);
+            colored.push(if service.error.is_some() {
+                colors.red
+            } else if service.published != Some(service.address) {
+                colors.yellow
+            } else {
+                ""
+            });
+        }
+        for (index, line) in crate::term::table(&rows).into_iter().enumerate() {
+            let line = truncate(&line, width);
+            let reverse = if index > 0 && index - 1 == self.selected {
+                "\x1b[7m"
+            } else {
+                ""
+            };
+            lines.push(format!("{reverse}{}{line}{}", colored[index], colors.reset));
+        }
+
+        lines.push(String::new());
+        lines.push(format!(
+            "{} runs: {}",
+            self.status.totals.runs, self.status.totals
+        ));
+        lines.push(String::new());
+        // The rest of the screen, but for the message and help at the bottom
+        let room = height.saturating_sub(lines.len() + 2);
+        let skip = self.logs.len().saturating_sub(room);
+        for log in &self.logs[skip..] {
+            lines.push(truncate(log, width));
+        }
+        while lines.len() < height.saturating_sub(2) {
+            lines.push(String::new());
+        }
+        lines.push(truncate(&self.message, width));
+        lines.push(truncate(HELP, width));
+
+        format!("\x1b[H\x1b[2J{}", lines.join("\r\n"))
+    }
+}
+
+fn truncate(line: &str, width: usize) -> String {
+    line.chars().take(width).collect()
+}
+
+/// Fetches the status and log lines of the daemon
+async fn refresh(socket: &Path, view: &mut View) {
+    let status = control::send(socket, "status").await;
+    let logs = control::send(socket, "logs").await;
+    match (status, logs) {
+        (Ok(status), Ok(logs)) => {
+            match serde_json::from_value::<Status>(status) {
+                Ok(status) => view.status = status,
+                Err(e) => view.message = format!("Unexpected status: {e}"),
+            }
+            view.logs = serde_json::from_value(logs["lines"].clone()).unwrap_or_default();
+            let count = view.status.services.len();
+            view.selected = view.selected.min(count.saturating_sub(1));
+        }
+        (Err(e), _) | (_, Err(e)) => {
+            view.message = format!("{}: {e}, is the daemon running?", socket.display())
+        }
+    }
+}
+
+/// The answer to `reconcile` as a line for the view
+fn reconciled(what: &str, response: std::io::Result<Value>) -> String {
+    match response {
+        Ok(response) => match response.get("error").and_then(Value::as_str) {
+            Some(error) => format!("Reconciling {what} failed: {error}"),
+            None => match serde_json::from_value::<Summary>(response["summary"].clone()) {
+                Ok(summary) => format!("Reconciled {what}: {summary}"),
+                Err(_) => format!("Reconciled {what}"),
+            },
+        },
+        Err(e) => format!("Reconciling {what} failed: {e}"),
+    }
+}
+
+pub async fn run(socket: PathBuf) -> Result<(), Box<dyn std::error::Error>> {
+    if !std::io::stdin().is_terminal() || !std::io::stdout().is_terminal() {
+        return Err("tui needs a terminal".into());
+    }
+    // Fails right away if the daemon isn't running
+    control::send(&socket, "status")
+        .await
+        .map_err(|e| format!("{}: {e}, is the daemon running?", socket.display()))?;
+
+    let terminal = Terminal::enter()?;
+    let (key_sender, mut key_receiver) = mpsc::unbounded_channel();
+    // Blocks on the terminal, so it gets a thread of its own, ending with the
+    // process
+    std::thread::spawn(move || {
+        let mut buffer = [0; 32];
+        while let Ok(read @ 1..) = std::io::stdin().read(&mut buffer) {
+            for key in keys(&buffer[..read]) {
+                if key_sender.send(key).is_err() {
+                    return;
+                }
+            }
+        }
+    });
+    let (done_sender, mut done_receiver) = mpsc::unbounded_channel();
+
+    let mut view = View::default();
+    let mut ticks = tokio::time::interval(REFRESH);
+    loop {
+        tokio::select! {
+            _ = ticks.tick() => refresh(&socket, &mut view).await,
+            Some(message) = done_receiver.recv() => {
+                view.message = message;
+                refresh(&socket, &mut view).await;
+            }
+            key = key_receiver.recv() => {
+                let services = view.services();
+                match key {
+                    None | Some(Key::Quit) => break,
+                    Some(Key::Up) => view.selected = view.selected.saturating_sub(1),
+                    Some(Key::Down) => {
+                        view.selected = (view.selected + 1).min(services.len().saturating_sub(1))
+                    }
+                    Some(key @ (Key::Reconcile | Key::ReconcileAll)) => {
+                        let (what, command) = match (key, services.get(view.selected)) {
+                            (Key::Reconcile, Some(name)) => {
+                                (name.to_string(), format!("reconcile {name}"))
+                            }
+                            (Key::Reconcile, None) => continue,
+                            _ => ("all services".to_string(), "reconcile".to_string()),
+                        };
+                        view.message = format!("Reconciling {what}...");
+                        let socket = socket.clone();
+                        let done = done_sender.clone();
+                        tokio::spawn(async move {
+                            let response = control::send(&socket, &command).await;
+                            let _ = done.send(reconciled(&what, response));
+                        });
+                    }
+                }
+            }
+        }
+
+        let (width, height) = Terminal::size();
+        print!("{}", view.render(&socket, width, height));
+        std::io::stdout().flush()?;
+    }
+
+    drop(terminal);
+    Ok(())
+}
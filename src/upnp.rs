@@ -0,0 +1,164 @@
+//! Asks the Internet Gateway Device of the local network for our address
+//! over UPnP. SSDP finds the gateway, whose description names the control
+//! URL of its WAN connection, which answers SOAP calls.
+
+use std::{
+    net::{Ipv4Addr, Ipv6Addr},
+    time::Duration,
+};
+
+use reqwest::Url;
+use tokio::net::UdpSocket;
+
+use crate::{
+    http_log::SendLogged,
+    xml::{element, elements, unescape},
+};
+
+const SSDP_ADDRESS: (Ipv4Addr, u16) = (Ipv4Addr::new(239, 255, 255, 250), 1900);
+/// How long gateways may take to answer a search, in seconds
+const SSDP_MX: u64 = 2;
+
+const SEARCH_TARGETS: [&str; 2] = [
+    "urn:schemas-upnp-org:device:InternetGatewayDevice:2",
+    "urn:schemas-upnp-org:device:InternetGatewayDevice:1",
+];
+
+/// Services of the WAN connection, the preferred first
+const SERVICE_TYPES: [&str; 3] = [
+    "urn:schemas-upnp-org:service:WANIPConnection:2",
+    "urn:schemas-upnp-org:service:WANIPConnection:1",
+    "urn:schemas-upnp-org:service:WANPPPConnection:1",
+];
+
+/// Actions answering with an IPv6 prefix or address, and the argument
+/// holding it. IGD only has `GetExternalIPAddress`, which most gateways
+/// answer with their IPv4 address; a FRITZ!Box has actions of its own.
+const ACTIONS: [(&str, &str); 3] = [
+    ("X_AVM_DE_GetIPv6Prefix", "NewIPv6Prefix"),
+    ("X_AVM_DE_GetExternalIPv6Address", "NewExternalIPv6Address"),
+    ("GetExternalIPAddress", "NewExternalIPAddress"),
+];
+
+/// An `M-SEARCH` for devices of type `target`
+pub fn search_request(target: &str) -> String {
+    format!(
+        "M-SEARCH * HTTP/1.1\r\n\
+         HOST: 239.255.255.250:1900\r\n\
+         MAN: \"ssdp:discover\"\r\n\
+         MX: {SSDP_MX}\r\n\
+         ST: {target}\r\n\r\n"
+    )
+}
+
+/// The `LOCATION` header of an answer to a search, the URL of the device
+/// description
+pub fn location(response: &str) -> Option<&str> {
+    let mut lines = response.lines();
+    if !lines.next()?.starts_with("HTTP/1.1 200") {
+        return None;
+    }
+    lines
+        .filter_map(|line| line.split_once(':'))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("location"))
+        .map(|(_, value)| value.trim())
+}
+
+/// Searches the local network for a gateway, returning the location of the
+/// first to answer
+pub async fn discover() -> Result<String, String> {
+    let search = async {
+        let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).await?;
+        for target in SEARCH_TARGETS {
+            socket
+                .send_to(search_request(target).as_bytes(), SSDP_ADDRESS)
+                .await?;
+        }
+        let mut buffer = [0; 2048];
+        loop {
+            let (received, _) = socket.recv_from(&mut buffer).await?;
+            if let Some(location) = location(&String::from_utf8_lossy(&buffer[..received])) {
+                return Ok::<_, std::io::Error>(location.to_string());
+            }
+        }
+    };
+    match tokio::time::timeout(Duration::from_secs(SSDP_MX + 1), search).await {
+        Ok(result) => result.map_err(|e| format!("searching for a gateway failed: {e}")),
+        Err(_) => Err("no gateway answered the search".to_string()),
+    }
+}
+
+/// The type and control URL of the WAN connection in the description of a
+/// gateway, relative URLs resolved against `URLBase` or `location`
+pub fn control_url(description: &str, location: &Url) -> Option<(&'static str, Url)> {
+    let base = element(description, "URLBase")
+        .and_then(|base| Url::parse(base.trim()).ok())
+        .unwrap_or_else(|| location.clone());
+    let services = elements(description, "service");
+    SERVICE_TYPES.into_iter().find_map(|service_type| {
+        let service = services
+            .iter()
+            .find(|service| element(service, "serviceType").map(str::trim) == Some(service_type))?;
+        let url = unescape(element(service, "controlURL")?.trim());
+        Some((service_type, base.join(&url).ok()?))
+    })
+}
+
+fn envelope(service_type: &str, action: &str) -> String {
+    format!(
+        "<?xml version=\"1.0\"?>\n\
+         <s:Envelope xmlns:s=\"http://schemas.xmlsoap.org/soap/envelope/\" \
+         s:encodingStyle=\"http://schemas.xmlsoap.org/soap/encoding/\">\
+         <s:Body><u:{action} xmlns:u=\"{service_type}\"/></s:Body></s:Envelope>"
+    )
+}
+
+/// Asks the gateway described at `location` for an IPv6 prefix or address,
+/// trying each of the actions gateways may answer that with
+pub async fn external_address(
+    client: &reqwest::Client,
+    location: &str,
+) -> Result<Ipv6Addr, String> {
+    let url = Url::parse(location).map_err(|e| format!("invalid location {location}: {e}"))?;
+    let description = client
+        .get(url.clone())
+        .send_logged()
+        .await
+        .and_then(reqwest::Response::error_for_status)
+        .map_err(|e| format!("failed to fetch the description at {location}: {e}"))?
+        .text()
+        .await
+        .map_err(|e| format!("failed to fetch the description at {location}: {e}"))?;
+    let (service_type, control) = control_url(&description, &url)
+        .ok_or_else(|| format!("the gateway at {location} has no WAN connection service"))?;
+
+    let mut problems = Vec::new();
+    for (action, argument) in ACTIONS {
+        let response = client
+            .post(control.clone())
+            .header("Content-Type", "text/xml; charset=\"utf-8\"")
+            .header("SOAPAction", format!("\"{service_type}#{action}\""))
+            .body(envelope(service_type, action))
+            .send_logged()
+            .await
+            .map_err(|e| format!("{action} failed: {e}"))?;
+        let status = response.status();
+        let body = response.text().await.unwrap_or_default();
+        if !status.is_success() {
+            let error = element(&body, "errorDescription").unwrap_or("fault");
+            problems.push(format!("{action}: {status} {error}"));
+            continue;
+        }
+        match element(&body, argument).map(str::trim) {
+            Some(value) => match value.parse::<Ipv6Addr>() {
+                Ok(address) if !address.is_unspecified() => return Ok(address),
+                _ => problems.push(format!("{action}: '{value}' is no IPv6 address")),
+            },
+            None => problems.push(format!("{action}: no {argument} in the answer")),
+        }
+    }
+    Err(format!(
+        "the gateway at {location} gave no IPv6 address ({})",
+        problems.join(", ")
+    ))
+}
@@ -0,0 +1,206 @@
+//! The Gandi token kept in [HashiCorp Vault](https://developer.hashicorp.com/vault)
+//! instead of the config. It is fetched before the first run and again once
+//! its lease or `refresh` runs out, so it can be rotated centrally.
+
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{config::deserialize_duration, http_log::SendLogged, secret::Secret, DynsixError};
+
+/// The `[vault]` section of the config
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct VaultConfig {
+    /// e.g. `https://vault.example.com:8200`
+    pub address: String,
+    pub auth: VaultAuth,
+    /// API path of the secret without `/v1`, e.g. `secret/data/dynsix` for
+    /// the KV version 2 engine mounted at `secret`
+    pub path: String,
+    /// Field of the secret holding the token
+    #[serde(default = "default_field")]
+    pub field: String,
+    /// Vault Enterprise namespace
+    pub namespace: Option<String>,
+    /// The token is fetched again after this long at most, earlier if its
+    /// lease ends
+    #[serde(default = "default_refresh", deserialize_with = "deserialize_duration")]
+    pub refresh: Duration,
+}
+
+/// How dynsix logs in to Vault
+#[derive(Deserialize, Debug, Clone)]
+#[serde(tag = "method", rename_all = "snake_case")]
+pub enum VaultAuth {
+    /// A Vault token, e.g. a periodic one
+    Token { token: Secret },
+    /// The AppRole auth method
+    Approle {
+        role_id: String,
+        secret_id: Secret,
+        /// Path the auth method is mounted at
+        #[serde(default = "default_approle_mount")]
+        mount: String,
+    },
+}
+
+#[derive(Serialize)]
+struct LoginRequest<'a> {
+    role_id: &'a str,
+    secret_id: &'a str,
+}
+
+#[derive(Deserialize)]
+struct LoginResponse {
+    auth: LoginAuth,
+}
+
+#[derive(Deserialize)]
+struct LoginAuth {
+    client_token: Secret,
+}
+
+#[derive(Deserialize)]
+struct SecretResponse {
+    #[serde(default)]
+    lease_duration: u64,
+    data: Value,
+}
+
+impl VaultConfig {
+    pub(crate) fn validate(&self, problems: &mut Vec<String>) {
+        if let Err(e) = reqwest::Url::parse(&self.address) {
+            problems.push(format!(
+                "vault.address '{}' is not a valid URL: {e}",
+                self.address
+            ));
+        }
+        if self.path.trim_matches('/').is_empty() {
+            problems.push("vault.path is empty".to_string());
+        }
+        if self.field.is_empty() {
+            problems.push("vault.field is empty".to_string());
+        }
+        if self.refresh.is_zero() {
+            problems.push("vault.refresh must not be zero".to_string());
+        }
+        match &self.auth {
+            VaultAuth::Token { token } if token.is_blank() => {
+                problems.push("vault.auth.token is empty".to_string())
+            }
+            VaultAuth::Approle { role_id, .. } if role_id.is_empty() => {
+                problems.push("vault.auth.role_id is empty".to_string())
+            }
+            _ => {}
+        }
+    }
+}
+
+/// The Gandi token and how long it may be used
+pub async fn fetch_token(
+    http: &reqwest::Client,
+    config: &VaultConfig,
+) -> Result<(Secret, Duration), DynsixError> {
+    let client_token = match &config.auth {
+        VaultAuth::Token { token } => token.clone(),
+        VaultAuth::Approle {
+            role_id,
+            secret_id,
+            mount,
+        } => {
+            let request = http
+                .post(url(
+                    config,
+                    &format!("auth/{}/login", mount.trim_matches('/')),
+                ))
+                .json(&LoginRequest {
+                    role_id,
+                    secret_id: secret_id.expose(),
+                });
+            let response: LoginResponse = send(config, request).await?;
+            response.auth.client_token
+        }
+    };
+
+    let request = http
+        .get(url(config, config.path.trim_matches('/')))
+        .header("X-Vault-Token", client_token.header(""));
+    let response: SecretResponse = send(config, request).await?;
+
+    // KV version 2 nests the fields of the secret in another data object
+    let fields = match response.data.get("data") {
+        Some(Value::Object(fields)) => fields,
+        _ => response
+            .data
+            .as_object()
+            .ok_or_else(|| error(config, format!("{} holds no key/value secret", config.path)))?,
+    };
+    let token = fields
+        .get(&config.field)
+        .and_then(Value::as_str)
+        .ok_or_else(|| {
+            error(
+                config,
+                format!("{} has no field {}", config.path, config.field),
+            )
+        })?;
+
+    let valid = match response.lease_duration {
+        0 => config.refresh,
+        lease => config.refresh.min(Duration::from_secs(lease)),
+    };
+    Ok((Secret::from(token), valid))
+}
+
+fn url(config: &VaultConfig, path: &str) -> String {
+    format!("{}/v1/{path}", config.address.trim_end_matches('/'))
+}
+
+/// Sends the request and decodes a successful answer, or the errors Vault
+/// reported
+async fn send<T: serde::de::DeserializeOwned>(
+    config: &VaultConfig,
+    mut request: reqwest::RequestBuilder,
+) -> Result<T, DynsixError> {
+    if let Some(namespace) = &config.namespace {
+        request = request.header("X-Vault-Namespace", namespace);
+    }
+    let response = request.send_logged().await?;
+    let status = response.status();
+    let text = response.text().await?;
+
+    if !status.is_success() {
+        let errors: Vec<String> = serde_json::from_str::<Value>(&text)
+            .ok()
+            .and_then(|json| serde_json::from_value(json["errors"].clone()).ok())
+            .unwrap_or_default();
+        let message = if errors.is_empty() {
+            text.trim().to_string()
+        } else {
+            errors.join(", ")
+        };
+        return Err(error(config, format!("{status}: {message}")));
+    }
+    serde_json::from_str(&text).map_err(|e| error(config, e))
+}
+
+fn error(config: &VaultConfig, message: impl ToString) -> DynsixError {
+    DynsixError::Vault {
+        address: config.address.clone(),
+        message: message.to_string(),
+    }
+}
+
+fn default_field() -> String {
+    "token".to_string()
+}
+
+fn default_refresh() -> Duration {
+    Duration::from_secs(3600)
+}
+
+fn default_approle_mount() -> String {
+    "approle".to_string()
+}
@@ -0,0 +1,75 @@
+//! What this build is and what it can do, for `--version` and `dynsix
+//! features`
+
+use std::fmt::Write as _;
+
+use crate::{config::SCHEMA_VERSION, provider::ProviderKind};
+
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+/// The abbreviated commit the binary was built from, `unknown` outside git
+pub const GIT_COMMIT: &str = env!("DYNSIX_GIT_COMMIT");
+/// `YYYY-MM-DD`, from `SOURCE_DATE_EPOCH` if it was set
+pub const BUILD_DATE: &str = env!("DYNSIX_BUILD_DATE");
+
+/// Where the prefix can come from
+pub const IP_SOURCES: &[&str] = &["query_server", "file", "interface", "dns", "upnp"];
+pub const NOTIFIERS: &[&str] = &[
+    "discord",
+    "email",
+    "healthchecks",
+    "matrix",
+    "mqtt",
+    "ntfy",
+    "sentry",
+    "slack",
+    "telegram",
+];
+pub const METRICS: &[&str] = &["textfile", "statsd"];
+pub const CONFIG_FORMATS: &[&str] = &["toml", "yaml", "json", "sops"];
+/// Ways to control a running daemon
+pub const APIS: &[&str] = &["http", "grpc", "control_socket"];
+
+/// The cargo features the binary was built with
+pub fn cargo_features() -> Vec<&'static str> {
+    env!("DYNSIX_FEATURES")
+        .split(',')
+        .filter(|feature| !feature.is_empty())
+        .collect()
+}
+
+pub fn providers() -> Vec<&'static str> {
+    ProviderKind::ALL.iter().map(|kind| kind.name()).collect()
+}
+
+/// Every kind of component that is compiled in, by kind
+pub fn features() -> Vec<(&'static str, Vec<&'static str>)> {
+    vec![
+        ("ip sources", IP_SOURCES.to_vec()),
+        ("providers", providers()),
+        ("notifiers", NOTIFIERS.to_vec()),
+        ("metrics", METRICS.to_vec()),
+        ("config formats", CONFIG_FORMATS.to_vec()),
+        ("apis", APIS.to_vec()),
+        ("cargo features", cargo_features()),
+    ]
+}
+
+/// The output of `--version`: the version, then how it was built
+pub fn long_version(name: &str) -> String {
+    let features = cargo_features();
+    let mut out = format!("{name} {VERSION}\n");
+    let _ = writeln!(out, "commit:         {GIT_COMMIT}");
+    let _ = writeln!(out, "built:          {BUILD_DATE}");
+    let _ = writeln!(out, "config schema:  {SCHEMA_VERSION}");
+    let _ = writeln!(
+        out,
+        "features:       {}",
+        if features.is_empty() {
+            "none".to_string()
+        } else {
+            features.join(", ")
+        }
+    );
+    let _ = write!(out, "providers:      {}", providers().join(", "));
+    out
+}
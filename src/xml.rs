@@ -0,0 +1,39 @@
+//! Just enough XML for the Route 53 API and UPnP gateways
+
+/// Contents of every `<tag>` element, which must not contain elements of the
+/// same name. Enough for flat documents like the responses of the Route 53
+/// API, without namespace prefixes on `tag`.
+pub fn elements<'a>(xml: &'a str, tag: &str) -> Vec<&'a str> {
+    let open = format!("<{tag}>");
+    let close = format!("</{tag}>");
+
+    let mut found = Vec::new();
+    let mut rest = xml;
+    while let Some(start) = rest.find(&open) {
+        let content = &rest[start + open.len()..];
+        let Some(end) = content.find(&close) else {
+            break;
+        };
+        found.push(&content[..end]);
+        rest = &content[end + close.len()..];
+    }
+    found
+}
+
+pub fn element<'a>(xml: &'a str, tag: &str) -> Option<&'a str> {
+    elements(xml, tag).into_iter().next()
+}
+
+pub fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+pub fn unescape(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
@@ -0,0 +1,249 @@
+//! A small YAML reader covering the subset needed for configuration files:
+//! block mappings and sequences, flow sequences of scalars, plain and quoted
+//! scalars and comments. Anchors, tags and multi-line scalars are not supported.
+
+use serde_json::{Map, Number, Value};
+
+struct Line {
+    number: usize,
+    indent: usize,
+    text: String,
+}
+
+pub fn from_str(input: &str) -> Result<Value, String> {
+    let mut lines = Vec::new();
+    for (index, raw) in input.lines().enumerate() {
+        let text = strip_comment(raw).trim_end();
+        let trimmed = text.trim_start();
+        if trimmed.is_empty() || trimmed == "---" {
+            continue;
+        }
+        if text.starts_with('\t') {
+            return Err(format!(
+                "line {}: tabs are not allowed for indentation",
+                index + 1
+            ));
+        }
+        lines.push(Line {
+            number: index + 1,
+            indent: text.len() - trimmed.len(),
+            text: trimmed.to_string(),
+        });
+    }
+
+    if lines.is_empty() {
+        return Ok(Value::Object(Map::new()));
+    }
+
+    let mut pos = 0;
+    let indent = lines[0].indent;
+    let value = parse_block(&mut lines, &mut pos, indent)?;
+    match lines.get(pos) {
+        Some(line) => Err(format!("line {}: unexpected indentation", line.number)),
+        None => Ok(value),
+    }
+}
+
+fn parse_block(lines: &mut [Line], pos: &mut usize, indent: usize) -> Result<Value, String> {
+    if is_sequence_item(&lines[*pos].text) {
+        parse_sequence(lines, pos, indent)
+    } else {
+        parse_mapping(lines, pos, indent)
+    }
+}
+
+fn parse_mapping(lines: &mut [Line], pos: &mut usize, indent: usize) -> Result<Value, String> {
+    let mut map = Map::new();
+
+    while let Some(line) = lines.get(*pos) {
+        if line.indent < indent {
+            break;
+        }
+        if line.indent > indent || is_sequence_item(&line.text) {
+            return Err(format!("line {}: unexpected indentation", line.number));
+        }
+
+        let number = line.number;
+        let (key, rest) =
+            split_key(&line.text).ok_or_else(|| format!("line {number}: expected `key: value`"))?;
+        let key = parse_scalar_string(key);
+        *pos += 1;
+
+        let value = if !rest.is_empty() {
+            parse_inline(rest, number)?
+        } else {
+            match lines.get(*pos) {
+                Some(next) if next.indent > indent => {
+                    let next_indent = next.indent;
+                    parse_block(lines, pos, next_indent)?
+                }
+                // Sequences may share the indentation of their parent key
+                Some(next) if next.indent == indent && is_sequence_item(&next.text) => {
+                    parse_sequence(lines, pos, indent)?
+                }
+                _ => Value::Null,
+            }
+        };
+
+        if map.insert(key.clone(), value).is_some() {
+            return Err(format!("line {number}: duplicate key `{key}`"));
+        }
+    }
+
+    Ok(Value::Object(map))
+}
+
+fn parse_sequence(lines: &mut [Line], pos: &mut usize, indent: usize) -> Result<Value, String> {
+    let mut items = Vec::new();
+
+    while let Some(line) = lines.get(*pos) {
+        if line.indent != indent || !is_sequence_item(&line.text) {
+            break;
+        }
+
+        let number = line.number;
+        let rest = line.text[1..].trim_start().to_string();
+        if rest.is_empty() {
+            *pos += 1;
+            match lines.get(*pos) {
+                Some(next) if next.indent > indent => {
+                    let next_indent = next.indent;
+                    items.push(parse_block(lines, pos, next_indent)?);
+                }
+                _ => items.push(Value::Null),
+            }
+        } else if split_key(&rest).is_some() {
+            // `- key: value` starts a mapping indented to the position of `key`
+            let item_indent = indent + (line.text.len() - rest.len());
+            lines[*pos].indent = item_indent;
+            lines[*pos].text = rest;
+            items.push(parse_mapping(lines, pos, item_indent)?);
+        } else {
+            items.push(parse_inline(&rest, number)?);
+            *pos += 1;
+        }
+    }
+
+    Ok(Value::Array(items))
+}
+
+fn parse_inline(text: &str, number: usize) -> Result<Value, String> {
+    if let Some(inner) = text.strip_prefix('[') {
+        let inner = inner
+            .strip_suffix(']')
+            .ok_or_else(|| format!("line {number}: unterminated flow sequence"))?;
+        if inner.trim().is_empty() {
+            return Ok(Value::Array(Vec::new()));
+        }
+        return Ok(Value::Array(
+            inner
+                .split(',')
+                .map(|item| parse_scalar(item.trim()))
+                .collect(),
+        ));
+    }
+    if text == "{}" {
+        return Ok(Value::Object(Map::new()));
+    }
+    if text.starts_with('{') {
+        return Err(format!("line {number}: flow mappings are not supported"));
+    }
+
+    Ok(parse_scalar(text))
+}
+
+fn parse_scalar(text: &str) -> Value {
+    if is_quoted(text) {
+        return Value::String(parse_scalar_string(text));
+    }
+
+    match text {
+        "~" | "null" | "Null" | "NULL" => return Value::Null,
+        "true" | "True" | "TRUE" => return Value::Bool(true),
+        "false" | "False" | "FALSE" => return Value::Bool(false),
+        _ => {}
+    }
+
+    if let Ok(int) = text.parse::<i64>() {
+        return Value::Number(int.into());
+    }
+    if let Some(float) = text.parse::<f64>().ok().and_then(Number::from_f64) {
+        return Value::Number(float);
+    }
+
+    Value::String(text.to_string())
+}
+
+fn parse_scalar_string(text: &str) -> String {
+    if text.len() >= 2 && text.starts_with('\'') && text.ends_with('\'') {
+        text[1..text.len() - 1].replace("''", "'")
+    } else if text.len() >= 2 && text.starts_with('"') && text.ends_with('"') {
+        unescape_double_quoted(&text[1..text.len() - 1])
+    } else {
+        text.to_string()
+    }
+}
+
+fn unescape_double_quoted(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => out.push('\n'),
+            Some('t') => out.push('\t'),
+            Some('0') => out.push('\0'),
+            Some(other) => out.push(other),
+            None => out.push('\\'),
+        }
+    }
+    out
+}
+
+fn is_quoted(text: &str) -> bool {
+    text.len() >= 2
+        && ((text.starts_with('"') && text.ends_with('"'))
+            || (text.starts_with('\'') && text.ends_with('\'')))
+}
+
+fn is_sequence_item(text: &str) -> bool {
+    text == "-" || text.starts_with("- ")
+}
+
+/// Splits `key: value` at the first `:` outside of quotes that is followed by
+/// whitespace or the end of the line
+fn split_key(text: &str) -> Option<(&str, &str)> {
+    let mut quote = None;
+    for (index, c) in text.char_indices() {
+        match (quote, c) {
+            (None, '"' | '\'') => quote = Some(c),
+            (Some(q), c) if c == q => quote = None,
+            (None, ':') => {
+                let rest = &text[index + 1..];
+                if rest.is_empty() || rest.starts_with(' ') {
+                    return Some((text[..index].trim(), rest.trim()));
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+fn strip_comment(line: &str) -> &str {
+    let mut quote = None;
+    let mut previous = ' ';
+    for (index, c) in line.char_indices() {
+        match (quote, c) {
+            (None, '"' | '\'') => quote = Some(c),
+            (Some(q), c) if c == q => quote = None,
+            (None, '#') if previous.is_whitespace() => return &line[..index],
+            _ => {}
+        }
+        previous = c;
+    }
+    line
+}
@@ -0,0 +1,152 @@
+//! Copies of whole Gandi zones, written by `dynsix backup` and pushed back
+//! with `dynsix restore`, e.g. before trying out another suffix scheme
+
+use std::{
+    collections::{BTreeSet, HashMap},
+    net::Ipv6Addr,
+    path::Path,
+    time::{Duration, SystemTime},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::ServiceConfig,
+    gandi::{self, GandiListResponse, GandiRecord, GandiResponse},
+    idna,
+    provider::{display_name, same_values, ProviderKind},
+    report::{Action, Reconciled, RunReport, ServiceReport},
+    state::ManagedRecord,
+    DynsixError,
+};
+
+/// All records of a domain at one point in time
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ZoneBackup {
+    /// The domain the records were fetched from, in its ASCII form
+    pub domain: String,
+    /// RFC 3339 time the backup was taken
+    pub created: String,
+    pub records: Vec<GandiRecord>,
+}
+
+impl ZoneBackup {
+    /// Fetches every record of `domain`
+    pub async fn fetch(client: &gandi::Client, domain: &str) -> Result<Self, DynsixError> {
+        let domain = idna::to_ascii(domain)?;
+        Ok(Self {
+            records: records(client, &domain).await?,
+            domain,
+            created: humantime::format_rfc3339_seconds(SystemTime::now()).to_string(),
+        })
+    }
+
+    pub fn load(path: &Path) -> Result<Self, DynsixError> {
+        let raw = std::fs::read(path).map_err(|e| DynsixError::io(path, e))?;
+        serde_json::from_slice(&raw).map_err(|e| DynsixError::Parse {
+            what: format!("backup {}", path.display()),
+            message: e.to_string(),
+        })
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), DynsixError> {
+        let raw = serde_json::to_string_pretty(self).map_err(|e| DynsixError::Parse {
+            what: "backup".to_string(),
+            message: e.to_string(),
+        })?;
+        std::fs::write(path, raw).map_err(|e| DynsixError::io(path, e))
+    }
+
+    /// Replaces all records of `domain` with those of the backup. Records
+    /// that were added since are removed.
+    pub async fn restore(&self, client: &gandi::Client, domain: &str) -> Result<(), DynsixError> {
+        let domain = idna::to_ascii(domain)?;
+        match client.replace_records(&domain, &self.records).await? {
+            GandiResponse::Error(e) => Err(DynsixError::gandi("restoring", Some(&domain), e)),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// The records `domain` has now, e.g. to show what a restore replaces
+pub async fn records(
+    client: &gandi::Client,
+    domain: &str,
+) -> Result<Vec<GandiRecord>, DynsixError> {
+    let domain = idna::to_ascii(domain)?;
+    match client.list_records(&domain).await? {
+        GandiListResponse::List(records) => Ok(records),
+        GandiListResponse::Error(e) => Err(DynsixError::gandi("listing records", Some(&domain), e)),
+    }
+}
+
+/// The AAAA records that replacing `current` with `restored` creates,
+/// updates or deletes in `domain`, as a report for the history and the state
+/// file. A record counts for the service of `services` publishing it, or
+/// for `restore` if there is none.
+pub fn restore_report(
+    domain: &str,
+    current: &[GandiRecord],
+    restored: &[GandiRecord],
+    services: &HashMap<String, ServiceConfig>,
+) -> RunReport {
+    let aaaa = |records: &[GandiRecord]| -> HashMap<String, (u32, Vec<String>)> {
+        records
+            .iter()
+            .filter(|record| record.rrset_type == "AAAA")
+            .map(|record| {
+                let values = (record.rrset_ttl, record.rrset_values.clone());
+                (record.rrset_name.clone(), values)
+            })
+            .collect()
+    };
+    let (current, restored) = (aaaa(current), aaaa(restored));
+    let ascii = |name: &str| idna::to_ascii(name).unwrap_or_else(|_| name.to_string());
+    let address = |values: &[String]| {
+        values
+            .iter()
+            .find_map(|value| value.trim().parse::<Ipv6Addr>().ok())
+    };
+
+    let mut report = RunReport::new(Ipv6Addr::UNSPECIFIED);
+    let names: BTreeSet<_> = current.keys().chain(restored.keys()).collect();
+    for name in names {
+        let (service, fqdn) = services
+            .iter()
+            .find(|(_, service)| {
+                ascii(&service.fqdn) == ascii(domain)
+                    && service.record_names().contains(&name.as_str())
+            })
+            .map_or(("restore", domain), |(service_name, service)| {
+                (service_name.as_str(), service.fqdn.as_str())
+            });
+        let (action, old, new) = match (current.get(name), restored.get(name)) {
+            (None, Some((_, new))) => (Action::Created, None, new),
+            (Some((old_ttl, old)), Some((new_ttl, new)))
+                if old_ttl != new_ttl || !same_values(old, new) =>
+            {
+                (Action::Updated, Some(old.clone()), new)
+            }
+            (Some((_, old)), None) => {
+                report.deleted.push(ManagedRecord {
+                    provider: ProviderKind::Gandi,
+                    fqdn: fqdn.to_string(),
+                    name: name.clone(),
+                    service: service.to_string(),
+                    address: address(old),
+                });
+                continue;
+            }
+            _ => continue,
+        };
+        report.push(ServiceReport::new(
+            service.to_string(),
+            display_name(fqdn, name),
+            address(new).unwrap_or(Ipv6Addr::UNSPECIFIED),
+            Ok(Reconciled { action, old }),
+            Duration::ZERO,
+        ));
+    }
+    report.finish();
+    report
+}
@@ -0,0 +1,121 @@
+use std::{io::Write, path::PathBuf};
+
+use dynsix::audit::{self, Actor, AuditConfig, AuditLog, Entry, Mutation, Operation};
+
+fn config(name: &str, max_size: u64) -> (PathBuf, AuditConfig) {
+    let dir = std::env::temp_dir().join(format!("dynsix-audit-{name}-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    let config = AuditConfig {
+        path: Some(dir.join("audit.jsonl")),
+        max_size,
+        keep: 2,
+    };
+    (dir, config)
+}
+
+fn log(config: &AuditConfig) -> AuditLog {
+    AuditLog::new(config).unwrap().with_actor(Actor {
+        user: "dynsix".to_string(),
+        uid: 990,
+        sudo_user: None,
+        host: "router".to_string(),
+        pid: 42,
+        command: "dynsix run".to_string(),
+    })
+}
+
+fn mutation(name: &str) -> Mutation<'_> {
+    Mutation {
+        operation: Operation::Update,
+        service: "web",
+        provider: "gandi",
+        account: None,
+        fqdn: "example.com",
+        name,
+        ttl: Some(300),
+        value: Some("2001:db8::80".parse().unwrap()),
+        error: None,
+    }
+}
+
+fn entries(path: &PathBuf) -> Vec<Entry> {
+    std::fs::read_to_string(path)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect()
+}
+
+#[test]
+fn chains_entries() {
+    let (dir, config) = config("chain", 1 << 20);
+    let log = log(&config);
+    log.append(&mutation("www")).unwrap();
+    log.append(&Mutation {
+        operation: Operation::Delete,
+        ttl: None,
+        value: None,
+        error: Some("record not found".to_string()),
+        ..mutation("old")
+    })
+    .unwrap();
+
+    let path = config.path.clone().unwrap();
+    let written = entries(&path);
+    let verified = audit::verify(&config);
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(verified.unwrap(), 2);
+    assert_eq!(written[0].prev, "0".repeat(64));
+    assert_eq!(written[0].actor.user, "dynsix");
+    assert_eq!(written[0].value.as_deref(), Some("2001:db8::80"));
+    assert_eq!(written[1].operation, Operation::Delete);
+    assert_eq!(written[1].outcome, "failed");
+    assert_ne!(written[1].prev, written[0].prev);
+}
+
+#[test]
+fn detects_changed_and_removed_entries() {
+    let (dir, config) = config("tamper", 1 << 20);
+    let log = log(&config);
+    for name in ["a", "b", "c"] {
+        log.append(&mutation(name)).unwrap();
+    }
+    let path = config.path.clone().unwrap();
+    let original = std::fs::read_to_string(&path).unwrap();
+
+    std::fs::write(&path, original.replace("\"name\":\"b\"", "\"name\":\"x\"")).unwrap();
+    let changed = audit::verify(&config).unwrap_err().to_string();
+
+    let lines: Vec<_> = original.lines().collect();
+    let mut file = std::fs::File::create(&path).unwrap();
+    writeln!(file, "{}\n{}", lines[0], lines[2]).unwrap();
+    drop(file);
+    let removed = audit::verify(&config).unwrap_err().to_string();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert!(changed.contains("line 2: hash does not match"), "{changed}");
+    assert!(removed.contains("line 2: prev does not match"), "{removed}");
+}
+
+#[test]
+fn continues_the_chain_across_rotation() {
+    let (dir, config) = config("rotate", 1);
+    let log = log(&config);
+    for name in ["a", "b", "c", "d"] {
+        log.append(&mutation(name)).unwrap();
+    }
+    let path = config.path.clone().unwrap();
+    let current = entries(&path);
+    let rotated = entries(&dir.join("audit.jsonl.1"));
+    let oldest = entries(&dir.join("audit.jsonl.2"));
+    let dropped = dir.join("audit.jsonl.3").exists();
+    let verified = audit::verify(&config);
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(current[0].name, "d");
+    assert_eq!(rotated[0].name, "c");
+    assert_eq!(oldest[0].name, "b");
+    assert!(!dropped);
+    assert_eq!(verified.unwrap(), 3);
+}
@@ -0,0 +1,62 @@
+mod common;
+
+use common::MockServer;
+use dynsix::{
+    http_client::{self, HttpConfig, LocalAddress},
+    ip::get_public_ip,
+};
+
+fn bound_to(interface: &str) -> HttpConfig {
+    HttpConfig {
+        bind_interface: Some(interface.to_string()),
+        query_local_address: LocalAddress::Any,
+        ..Default::default()
+    }
+}
+
+#[tokio::test]
+async fn connects_through_the_interface() {
+    let server = MockServer::start().await;
+    server.route("GET", "/", 200, r#"{"ip": "2001:db8::5"}"#);
+
+    let client = http_client::client(&bound_to("lo")).unwrap();
+    let ip = get_public_ip(&client, &format!("{}/", server.url()))
+        .await
+        .unwrap();
+
+    assert_eq!(ip, "2001:db8::5".parse::<std::net::Ipv6Addr>().unwrap());
+    // Requests reach the server in origin form, not as to a proxy
+    assert_eq!(server.requests()[0].path, "/");
+}
+
+#[tokio::test]
+async fn fails_without_the_interface() {
+    let server = MockServer::start().await;
+    server.route("GET", "/", 200, r#"{"ip": "2001:db8::5"}"#);
+
+    let client = http_client::client(&bound_to("dynsix-none0")).unwrap();
+    let result = get_public_ip(&client, &format!("{}/", server.url())).await;
+
+    assert!(result.is_err());
+    assert!(server.requests().is_empty());
+}
+
+#[test]
+fn bind_address_has_to_match_the_families() {
+    let config: dynsix::Config = toml::from_str(
+        r#"
+        token = "secret"
+
+        [http]
+        bind_address = "192.0.2.1"
+        "#,
+    )
+    .unwrap();
+    let problems = config.validate();
+    assert!(
+        problems
+            .iter()
+            .any(|problem| problem.contains("query_local_address")),
+        "{problems:?}"
+    );
+}
@@ -0,0 +1,133 @@
+mod common;
+
+use std::collections::HashMap;
+
+use common::MockServer;
+use dynsix::{
+    check::{Check, Outcome, Status},
+    gandi, Reconciler, ServiceConfig,
+};
+
+const NOT_FOUND: &str = r#"{"code": 404, "message": "Record not found", "object": "HTTPNotFound", "cause": "Not Found"}"#;
+
+fn services() -> HashMap<String, ServiceConfig> {
+    let service = |name: &str| {
+        toml::from_str(&format!(
+            r#"
+            suffix = "::1:2:3:4"
+            name = "{name}"
+            fqdn = "example.com"
+            ttl = 600
+            "#
+        ))
+        .unwrap()
+    };
+    HashMap::from([
+        ("mail".to_string(), service("mail")),
+        ("web".to_string(), service("www")),
+    ])
+}
+
+fn reconciler(server: &MockServer) -> Reconciler {
+    Reconciler::new(gandi::Client::with_base_url(
+        reqwest::Client::new(),
+        "secret-token",
+        server.url(),
+    ))
+}
+
+#[tokio::test]
+async fn is_ok_when_every_record_is_up_to_date() {
+    let server = MockServer::start().await;
+    for name in ["www", "mail"] {
+        server.route(
+            "GET",
+            &format!("/livedns/domains/example.com/records/{name}/AAAA"),
+            200,
+            r#"{"rrset_values": ["2001:db8:aa:bb:1:2:3:4"], "rrset_ttl": 600}"#,
+        );
+    }
+
+    let check = Check::run(
+        &reconciler(&server),
+        &services(),
+        "2001:db8:aa:bb::1".parse().unwrap(),
+    )
+    .await;
+    assert_eq!(check.status(), Status::Ok);
+    assert_eq!(
+        check.nagios(),
+        "DYNSIX OK - 2 records up to date | records=2;;;0 up_to_date=2;;;0 wrong=0;;;0 \
+         missing=0;;;0 unchecked=0;;;0"
+    );
+    // Only reads
+    assert!(server
+        .requests()
+        .iter()
+        .all(|request| request.method == "GET"));
+}
+
+#[tokio::test]
+async fn is_critical_when_records_are_wrong_or_missing() {
+    let server = MockServer::start().await;
+    server.route(
+        "GET",
+        "/livedns/domains/example.com/records/www/AAAA",
+        200,
+        r#"{"rrset_values": ["2001:db8::dead"], "rrset_ttl": 600}"#,
+    );
+    server.route(
+        "GET",
+        "/livedns/domains/example.com/records/mail/AAAA",
+        404,
+        NOT_FOUND,
+    );
+
+    let check = Check::run(
+        &reconciler(&server),
+        &services(),
+        "2001:db8:aa:bb::1".parse().unwrap(),
+    )
+    .await;
+    assert_eq!(check.status(), Status::Critical);
+    assert_eq!(check.records[0].outcome, Outcome::Missing);
+    assert_eq!(
+        check.records[1].outcome,
+        Outcome::Wrong(vec!["2001:db8::dead".to_string()])
+    );
+    assert_eq!(
+        check.nagios(),
+        "DYNSIX CRITICAL - 2 records, 1 wrong, 1 missing | records=2;;;0 up_to_date=0;;;0 \
+         wrong=1;;;0 missing=1;;;0 unchecked=0;;;0\n\
+         mail.example.com (mail) is missing, should be 2001:db8:aa:bb:1:2:3:4\n\
+         www.example.com (web) holds 2001:db8::dead instead of 2001:db8:aa:bb:1:2:3:4"
+    );
+}
+
+#[tokio::test]
+async fn warns_about_records_that_could_not_be_checked() {
+    let server = MockServer::start().await;
+    server.route(
+        "GET",
+        "/livedns/domains/example.com/records/www/AAAA",
+        200,
+        r#"{"rrset_values": ["2001:db8:aa:bb:1:2:3:4"], "rrset_ttl": 600}"#,
+    );
+    server.route(
+        "GET",
+        "/livedns/domains/example.com/records/mail/AAAA",
+        500,
+        r#"{"code": 500, "message": "Internal error", "object": "Error", "cause": "Oops"}"#,
+    );
+
+    let check = Check::run(
+        &reconciler(&server),
+        &services(),
+        "2001:db8:aa:bb::1".parse().unwrap(),
+    )
+    .await;
+    assert_eq!(check.status(), Status::Warning);
+    assert!(matches!(check.records[0].outcome, Outcome::Unchecked(_)));
+
+    assert_eq!(Check::default().status(), Status::Unknown);
+}
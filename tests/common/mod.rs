@@ -0,0 +1,229 @@
+//! A tiny HTTP server answering canned responses, standing in for the Gandi API
+//! and the other providers, and the fixtures the provider tests share
+
+#![allow(dead_code)]
+
+use std::{
+    net::Ipv6Addr,
+    sync::{Arc, Mutex},
+};
+
+use dynsix::{
+    gandi,
+    provider::ProvidersConfig,
+    report::{Action, ServiceReport},
+    Reconciler, ServiceConfig,
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+
+#[derive(Debug, Clone)]
+pub struct Request {
+    pub method: String,
+    pub path: String,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+
+impl Request {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Route {
+    method: String,
+    path: String,
+    status: u16,
+    body: String,
+}
+
+#[derive(Default)]
+struct State {
+    routes: Vec<Route>,
+    requests: Vec<Request>,
+}
+
+pub struct MockServer {
+    url: String,
+    state: Arc<Mutex<State>>,
+}
+
+impl MockServer {
+    pub async fn start() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let state = Arc::new(Mutex::new(State::default()));
+
+        let server_state = state.clone();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(handle(stream, server_state.clone()));
+            }
+        });
+
+        Self { url, state }
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Answers requests to `method path` with `status` and the JSON `body`.
+    /// Later routes take precedence over earlier ones.
+    pub fn route(&self, method: &str, path: &str, status: u16, body: &str) {
+        self.state.lock().unwrap().routes.push(Route {
+            method: method.to_string(),
+            path: path.to_string(),
+            status,
+            body: body.to_string(),
+        });
+    }
+
+    pub fn requests(&self) -> Vec<Request> {
+        self.state.lock().unwrap().requests.clone()
+    }
+
+    pub fn requests_to(&self, method: &str) -> Vec<Request> {
+        self.requests()
+            .into_iter()
+            .filter(|request| request.method == method)
+            .collect()
+    }
+}
+
+/// The address the provider tests publish before the change to [`public_ip`]
+pub const OLD_ADDRESS: &str = "2001:db8:ff:ff:1:2:3:4";
+
+pub fn public_ip() -> Ipv6Addr {
+    "2001:db8:aa:bb::1".parse().unwrap()
+}
+
+/// A service publishing `www.<fqdn>` with the suffix `::1:2:3:4`
+pub fn service(provider: &str, fqdn: &str, ttl: u32) -> ServiceConfig {
+    toml::from_str(&format!(
+        r#"
+        suffix = "::1:2:3:4"
+        name = "www"
+        fqdn = "{fqdn}"
+        ttl = {ttl}
+        provider = "{provider}"
+        "#
+    ))
+    .unwrap()
+}
+
+/// A reconciler with the `[providers]` of the TOML `providers` and no
+/// usable Gandi token
+pub fn reconciler(providers: &str) -> Reconciler {
+    let providers: ProvidersConfig = toml::from_str(providers).unwrap();
+    Reconciler::new(gandi::Client::new(reqwest::Client::new(), "unused"))
+        .with_providers(reqwest::Client::new(), &providers)
+}
+
+/// Reconciles `service` as `web` and checks that it succeeded with `action`
+pub async fn reconcile(
+    reconciler: &Reconciler,
+    service: &ServiceConfig,
+    action: Action,
+) -> ServiceReport {
+    let report = reconciler
+        .reconcile_service("web", service, public_ip())
+        .await;
+    assert_eq!(report.error, None);
+    assert_eq!(report.action, action);
+    report
+}
+
+/// Reconciles `service` and checks that it replaced [`OLD_ADDRESS`]
+pub async fn assert_updates(reconciler: &Reconciler, service: &ServiceConfig) {
+    let report = reconcile(reconciler, service, Action::Updated).await;
+    assert_eq!(report.old, Some(vec![OLD_ADDRESS.to_string()]));
+}
+
+/// Reconciles `service` and checks that it failed with an error mentioning
+/// `message`
+pub async fn assert_fails_with(reconciler: &Reconciler, service: &ServiceConfig, message: &str) {
+    let report = reconciler
+        .reconcile_service("web", service, public_ip())
+        .await;
+    assert_eq!(report.action, Action::Failed);
+    let error = report.error.unwrap();
+    assert!(error.contains(message), "{error}");
+}
+
+async fn handle(mut stream: TcpStream, state: Arc<Mutex<State>>) {
+    let mut raw = Vec::new();
+    let mut buffer = [0; 4096];
+    let header_end = loop {
+        let read = stream.read(&mut buffer).await.unwrap();
+        if read == 0 {
+            return;
+        }
+        raw.extend_from_slice(&buffer[..read]);
+        if let Some(end) = raw.windows(4).position(|window| window == b"\r\n\r\n") {
+            break end + 4;
+        }
+    };
+
+    let head = String::from_utf8_lossy(&raw[..header_end]).to_string();
+    let mut lines = head.lines();
+    let mut request_line = lines.next().unwrap().split_whitespace();
+    let method = request_line.next().unwrap().to_string();
+    let path = request_line.next().unwrap().to_string();
+    let headers: Vec<(String, String)> = lines
+        .filter_map(|line| line.split_once(':'))
+        .map(|(key, value)| (key.trim().to_string(), value.trim().to_string()))
+        .collect();
+
+    let content_length = headers
+        .iter()
+        .find(|(key, _)| key.eq_ignore_ascii_case("content-length"))
+        .and_then(|(_, value)| value.parse::<usize>().ok())
+        .unwrap_or(0);
+    while raw.len() < header_end + content_length {
+        let read = stream.read(&mut buffer).await.unwrap();
+        if read == 0 {
+            break;
+        }
+        raw.extend_from_slice(&buffer[..read]);
+    }
+    let body = String::from_utf8_lossy(&raw[header_end..]).to_string();
+
+    let route = {
+        let mut state = state.lock().unwrap();
+        state.requests.push(Request {
+            method: method.clone(),
+            path: path.clone(),
+            headers,
+            body,
+        });
+        state
+            .routes
+            .iter()
+            .rev()
+            .find(|route| route.method == method && route.path == path)
+            .cloned()
+    };
+
+    let (status, body) = match route {
+        Some(route) => (route.status, route.body),
+        None => (
+            404,
+            r#"{"code": 404, "message": "Unknown route", "object": "HTTPNotFound", "cause": "Not Found"}"#
+                .to_string(),
+        ),
+    };
+    let response = format!(
+        "HTTP/1.1 {status} Mock\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    );
+    let _ = stream.write_all(response.as_bytes()).await;
+    let _ = stream.shutdown().await;
+}
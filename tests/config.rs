@@ -0,0 +1,448 @@
+use dynsix::{
+    config::{self, ColorMode, Config, LogLevel, Timestamps},
+    provider::ProviderKind,
+    suffix::Suffix,
+};
+
+fn config(raw: &str) -> Config {
+    toml::from_str(raw).unwrap()
+}
+
+#[test]
+fn token_ref_must_name_a_token() {
+    let config = config(
+        r#"
+        [tokens]
+        work = "work-token"
+
+        [services.work]
+        suffix = "::1"
+        name = "www"
+        fqdn = "example.com"
+        ttl = 600
+        token_ref = "work"
+
+        [services.typo]
+        suffix = "::1"
+        name = "www"
+        fqdn = "example.org"
+        ttl = 600
+        token_ref = "wrok"
+        "#,
+    );
+
+    // No top level token is needed when every Gandi service has its own
+    assert_eq!(
+        config.validate(),
+        ["service 'typo': token_ref 'wrok' is not in [tokens]"]
+    );
+}
+
+#[test]
+fn explains_wildcard_and_apex_names() {
+    let config = config(
+        r#"
+        token = "secret"
+
+        [services.apex]
+        suffix = "::1"
+        names = ["@", "*", "*.lab"]
+        fqdn = "example.com"
+        ttl = 600
+
+        [services.bad]
+        suffix = "::1"
+        names = ["www.*", "@.home", "vpn.example.org"]
+        fqdn = "example.org"
+        ttl = 600
+        "#,
+    );
+
+    assert_eq!(
+        config.validate(),
+        [
+            r#"service 'bad': name 'www.*': "*" is only a wildcard as the whole leftmost label, e.g. "*" or "*.lab""#,
+            r#"service 'bad': name '@.home': "@" is the apex of example.org and stands alone"#,
+            r#"service 'bad': name 'vpn.example.org' includes the domain, it is relative to example.org ("@" for the apex)"#,
+        ]
+    );
+}
+
+#[test]
+fn checks_names_against_dns_rules() {
+    let config = config(
+        r#"
+        token = "secret"
+
+        [services.labels]
+        suffix = "::1"
+        names = ["-www", "home_lab", "my host", "_acme-challenge"]
+        fqdn = "example..com"
+        ttl = 600
+
+        [services.long]
+        suffix = "::1"
+        name = "this-label-is-longer-than-the-sixty-three-characters-dns-allows-for-one"
+        fqdn = "example.com."
+        ttl = 600
+        "#,
+    );
+
+    assert_eq!(
+        config.validate(),
+        [
+            "service 'labels': name '-www' has label '-www' starting or ending with '-'",
+            "service 'labels': name 'my host' has label 'my host', labels may only contain letters, digits, '-' and '_'",
+            "service 'labels': fqdn 'example..com' has an empty label",
+            "service 'long': name 'this-label-is-longer-than-the-sixty-three-characters-dns-allows-for-one' has label 'this-label-is-longer-than-the-sixty-three-characters-dns-allows-for-one' longer than 63 characters",
+        ]
+    );
+}
+
+#[test]
+fn identifies_both_services_managing_a_record() {
+    let config = config(
+        r#"
+        token = "secret"
+
+        [services.a]
+        suffix = "::1"
+        name = "WWW"
+        fqdn = "Example.com."
+        ttl = 600
+
+        [services.b]
+        suffix = "::2"
+        name = "www"
+        fqdn = "example.com"
+        ttl = 600
+        "#,
+    );
+
+    assert_eq!(
+        config.validate(),
+        ["services 'a' and 'b' both manage the AAAA record www.example.com"]
+    );
+}
+
+#[test]
+fn services_have_their_own_interval_or_schedule() {
+    let config = config(
+        r#"
+        token = "secret"
+
+        [services.vpn]
+        suffix = "::1"
+        name = "vpn"
+        fqdn = "example.com"
+        ttl = 600
+        interval = "1m"
+
+        [services.both]
+        suffix = "::2"
+        name = "www"
+        fqdn = "example.com"
+        ttl = 600
+        interval = "1h"
+        schedule = "0 * * * *"
+        "#,
+    );
+
+    assert_eq!(
+        config.services["vpn"].interval,
+        Some(std::time::Duration::from_secs(60))
+    );
+    assert_eq!(
+        config.validate(),
+        ["service 'both': set either interval or schedule, not both"]
+    );
+}
+
+#[test]
+fn redacts_tokens_in_debug_output() {
+    let config = config(
+        r#"
+        token = "secret-gandi-token"
+
+        [tokens]
+        work = "secret-work-token"
+
+        [providers.hetzner]
+        token = "secret-hetzner-token"
+        "#,
+    );
+
+    let debug = format!("{config:?}");
+    assert!(!debug.contains("secret-"), "{debug}");
+    assert_eq!(config.token.expose(), "secret-gandi-token");
+}
+
+#[test]
+fn strict_permissions_refuse_readable_configs() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = std::env::temp_dir().join(format!("dynsix-permissions-test-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("config.toml");
+    std::fs::write(&path, "token = \"secret\"\nstrict_permissions = true\n").unwrap();
+
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o644)).unwrap();
+    let error = Config::load(&path).unwrap_err().to_string();
+    assert!(error.contains("readable by everyone"), "{error}");
+
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600)).unwrap();
+    assert!(Config::load(&path).is_ok());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn warns_about_readable_configs() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = std::env::temp_dir().join(format!("dynsix-readable-test-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("config.toml");
+    std::fs::write(&path, "token = \"secret\"\n").unwrap();
+
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o644)).unwrap();
+    let readable = Config::load(&path).unwrap();
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600)).unwrap();
+    let private = Config::load(&path).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(
+        readable.warnings,
+        [format!(
+            "{} holds secrets and is readable by everyone (mode 644), restrict it with chmod 600",
+            path.display()
+        )]
+    );
+    assert!(private.warnings.is_empty());
+}
+
+#[test]
+fn only_the_query_server_is_reached_over_ipv6_by_default() {
+    use dynsix::http_client::LocalAddress;
+
+    let defaults = config("token = \"secret\"\n");
+    assert_eq!(defaults.http.query_local_address, LocalAddress::Ipv6);
+    assert_eq!(defaults.http.api_local_address, LocalAddress::Any);
+
+    let overridden = config(
+        r#"
+        token = "secret"
+
+        [http]
+        query_local_address = "any"
+        api_local_address = "ipv4"
+        "#,
+    );
+    assert_eq!(overridden.http.query_local_address, LocalAddress::Any);
+    assert_eq!(overridden.http.api_local_address, LocalAddress::Ipv4);
+}
+
+#[test]
+fn subnets_of_a_delegated_prefix() {
+    let config = config(
+        r#"
+        token = "secret"
+
+        [services.vlan]
+        suffix = "::1"
+        name = "vlan"
+        fqdn = "example.com"
+        ttl = 600
+        subnet = 0x12
+        delegated_length = 56
+
+        [services.wide]
+        suffix = "::2"
+        name = "wide"
+        fqdn = "example.com"
+        ttl = 600
+        subnet = 0x100
+        delegated_length = 56
+        "#,
+    );
+
+    // The host asking the query server sits in subnet 0x01
+    let detected = "2001:db8:1:201::abcd".parse().unwrap();
+    assert_eq!(
+        config.services["vlan"].address("vlan", detected).unwrap(),
+        "2001:db8:1:212::1".parse::<std::net::Ipv6Addr>().unwrap()
+    );
+    assert_eq!(
+        config.validate(),
+        ["service 'wide': subnet 0x100 does not fit into the 8 bits after a /56"]
+    );
+}
+
+#[test]
+fn ttls_are_checked_against_the_provider() {
+    let raw = r#"
+        token = "secret"
+
+        [providers.desec]
+        token = "desec-token"
+
+        [services.gandi]
+        suffix = "::1"
+        name = "www"
+        fqdn = "example.com"
+        ttl = 60
+
+        [services.desec]
+        suffix = "::1"
+        name = "www"
+        fqdn = "example.org"
+        ttl = 172800
+        provider = "desec"
+        "#;
+
+    assert_eq!(
+        config(raw).validate(),
+        [
+            "service 'desec': ttl 172800 is outside of the range 0..=86400 desec accepts",
+            "service 'gandi': ttl 60 is outside of the range 300..=2592000 gandi accepts",
+        ]
+    );
+
+    // Loading keeps them as written, so that validation reports them
+    let dir = std::env::temp_dir().join(format!("dynsix-ttl-test-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("config.toml");
+    std::fs::write(&path, raw).unwrap();
+    let mut loaded = Config::load(&path).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(loaded.validate().len(), 2);
+
+    // Runs move them to the nearest accepted TTL instead
+    assert_eq!(
+        loaded.clamp_ttls(),
+        [
+            "service 'desec': ttl 172800 is outside of the range 0..=86400 desec accepts, using 86400",
+            "service 'gandi': ttl 60 is outside of the range 300..=2592000 gandi accepts, using 300",
+        ]
+    );
+    assert_eq!(loaded.services["gandi"].ttl, 300);
+    assert_eq!(loaded.services["desec"].ttl, 86400);
+    assert!(loaded.validate().is_empty(), "{:?}", loaded.validate());
+}
+
+#[test]
+fn names_with_several_labels() {
+    let config = config(
+        r#"
+        token = "secret"
+
+        [services.nested]
+        suffix = "::1"
+        names = ["host.internal", "*.lab.internal"]
+        fqdn = "example.com"
+        ttl = 600
+
+        [services.zone]
+        suffix = "::2"
+        name = "host"
+        fqdn = "internal.example.com"
+        ttl = 600
+        "#,
+    );
+
+    assert_eq!(
+        config.validate(),
+        ["services 'nested' and 'zone' both manage the AAAA record host.internal.example.com"]
+    );
+}
+
+#[test]
+fn concurrency_limits_are_at_least_one() {
+    let config = config(
+        r#"
+        token = "secret"
+
+        [concurrency]
+        max_concurrent_updates = 0
+        per_provider = { gandi = 2, route53 = 0 }
+        "#,
+    );
+
+    assert_eq!(config.concurrency.per_provider.len(), 2);
+    assert_eq!(
+        config.validate(),
+        [
+            "concurrency.max_concurrent_updates must be at least 1",
+            "concurrency.per_provider.route53 must be at least 1",
+        ]
+    );
+}
+
+#[test]
+fn grpc_api_needs_a_token() {
+    let config = config(
+        r#"
+        token = "secret"
+
+        [daemon]
+        grpc_listen = "127.0.0.1:8054"
+        "#,
+    );
+
+    assert_eq!(
+        config.validate(),
+        ["daemon.grpc_listen requires daemon.grpc_token"]
+    );
+}
+
+#[test]
+fn log_formatting() {
+    let config = config(
+        r#"
+        token = "secret"
+
+        [log]
+        level = "info"
+        levels = { reqwest = "off", "dynsix::reconcile" = "debug" }
+        timestamps = "none"
+        color = "never"
+        "#,
+    );
+
+    assert_eq!(config.log.level, Some(LogLevel::Info));
+    assert_eq!(config.log.levels["reqwest"], LogLevel::Off);
+    assert_eq!(config.log.levels["dynsix::reconcile"], LogLevel::Debug);
+    assert_eq!(config.log.timestamps, Timestamps::None);
+    assert_eq!(config.log.color, ColorMode::Never);
+
+    let error = toml::from_str::<Config>("[log]\nlevel = \"loud\"").unwrap_err();
+    assert!(
+        error.to_string().contains("unknown variant `loud`"),
+        "{error}"
+    );
+}
+
+#[test]
+fn example_fills_in_the_placeholders() {
+    let suffix = "::1:2:3:4".parse().unwrap();
+    let rendered = config::example(r#"to"ken\"#, "bücher.example", &suffix);
+    let example = config(&rendered);
+
+    assert_eq!(example.token.expose(), r#"to"ken\"#);
+    let service = &example.services["your_service"];
+    assert_eq!(service.fqdn, "bücher.example");
+    assert_eq!(service.suffix, Suffix::Address(suffix));
+}
+
+#[test]
+fn example_matches_the_defaults() {
+    let rendered = config::example("token", "example.com", &"::1".parse().unwrap());
+    let defaults = config(r#"token = "token""#);
+
+    assert!(rendered.contains(&format!(
+        "LoadCredential={}:<file>",
+        config::TOKEN_CREDENTIAL
+    )));
+    assert!(rendered.contains(&format!("# query_server = \"{}\"", defaults.query_server)));
+    let ttl = ProviderKind::Gandi.ttl_range();
+    assert!(rendered.contains(&format!("Gandi accepts {} to {}", ttl.start(), ttl.end())));
+}
@@ -0,0 +1,52 @@
+use std::sync::Mutex;
+
+use dynsix::Config;
+
+/// The tests change the environment of the whole process
+static ENV: Mutex<()> = Mutex::new(());
+
+#[test]
+fn token_comes_from_systemd_credential() {
+    let _env = ENV.lock().unwrap();
+    let dir = std::env::temp_dir().join(format!("dynsix-credentials-test-{}", std::process::id()));
+    let credentials = dir.join("credentials");
+    std::fs::create_dir_all(&credentials).unwrap();
+    let path = dir.join("config.toml");
+    std::fs::write(&path, "token = \"from-config\"\n").unwrap();
+
+    std::env::set_var("CREDENTIALS_DIRECTORY", &credentials);
+    assert_eq!(Config::load(&path).unwrap().token.expose(), "from-config");
+
+    std::fs::write(credentials.join("gandi-token"), "from-credential\n").unwrap();
+    assert_eq!(
+        Config::load(&path).unwrap().token.expose(),
+        "from-credential"
+    );
+
+    std::env::remove_var("CREDENTIALS_DIRECTORY");
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn secrets_come_from_files_named_in_the_environment() {
+    let _env = ENV.lock().unwrap();
+    let dir = std::env::temp_dir().join(format!("dynsix-secret-files-test-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("config.toml");
+    std::fs::write(&path, "token = \"from-config\"\n").unwrap();
+    std::fs::write(dir.join("token"), "from-file\n").unwrap();
+    std::fs::write(dir.join("prefix-token"), "push-secret").unwrap();
+
+    std::env::set_var("DYNSIX_TOKEN_FILE", dir.join("token"));
+    std::env::set_var("DYNSIX_PREFIX_TOKEN_FILE", dir.join("prefix-token"));
+    let config = Config::load(&path).unwrap();
+    std::env::set_var("DYNSIX_TOKEN_FILE", dir.join("missing"));
+    let missing = Config::load(&path);
+    std::env::remove_var("DYNSIX_TOKEN_FILE");
+    std::env::remove_var("DYNSIX_PREFIX_TOKEN_FILE");
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(config.token.expose(), "from-file");
+    assert_eq!(config.daemon.prefix_token.unwrap().expose(), "push-secret");
+    assert!(missing.unwrap_err().to_string().contains("missing"));
+}
@@ -0,0 +1,52 @@
+mod common;
+
+use common::{assert_fails_with, reconcile, MockServer};
+use dynsix::{report::Action, Reconciler, ServiceConfig};
+
+const RRSET_PATH: &str = "/domains/example.com/rrsets/www/AAAA/";
+
+fn service() -> ServiceConfig {
+    common::service("desec", "example.com", 60)
+}
+
+fn reconciler(server: &MockServer) -> Reconciler {
+    common::reconciler(&format!(
+        r#"
+        [desec]
+        token = "secret-token"
+        endpoint = "{}"
+        "#,
+        server.url()
+    ))
+}
+
+#[tokio::test]
+async fn puts_rrset_for_missing_record() {
+    let server = MockServer::start().await;
+    server.route("GET", RRSET_PATH, 404, r#"{"detail": "Not found."}"#);
+    server.route("PUT", "/domains/example.com/rrsets/", 200, "[]");
+
+    reconcile(&reconciler(&server), &service(), Action::Created).await;
+
+    let puts = server.requests_to("PUT");
+    assert_eq!(puts.len(), 1);
+    assert_eq!(puts[0].header("Authorization"), Some("Token secret-token"));
+    let body: serde_json::Value = serde_json::from_str(&puts[0].body).unwrap();
+    assert_eq!(
+        body,
+        serde_json::json!([{
+            "subname": "www",
+            "type": "AAAA",
+            "ttl": 60,
+            "records": ["2001:db8:aa:bb:1:2:3:4"]
+        }])
+    );
+}
+
+#[tokio::test]
+async fn reports_api_errors() {
+    let server = MockServer::start().await;
+    server.route("GET", RRSET_PATH, 401, r#"{"detail": "Invalid token."}"#);
+
+    assert_fails_with(&reconciler(&server), &service(), "Invalid token.").await;
+}
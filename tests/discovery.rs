@@ -0,0 +1,62 @@
+mod common;
+
+use std::collections::HashMap;
+
+use common::MockServer;
+use dynsix::{
+    discovery::{discover, DiscoveryConfig},
+    gandi,
+    suffix::Suffix,
+    ServiceConfig,
+};
+
+#[tokio::test]
+async fn finds_matching_records_and_keeps_their_suffix() {
+    let server = MockServer::start().await;
+    server.route(
+        "GET",
+        "/livedns/domains",
+        200,
+        r#"[{"fqdn": "example.com"}, {"fqdn": "example.org"}]"#,
+    );
+    server.route(
+        "GET",
+        "/livedns/domains/example.com/records",
+        200,
+        r#"[
+            {"rrset_name": "nas-dyn", "rrset_type": "AAAA", "rrset_ttl": 300, "rrset_values": ["2001:db8:1:2:aa:bb:cc:dd"]},
+            {"rrset_name": "nas-dyn", "rrset_type": "A", "rrset_ttl": 300, "rrset_values": ["192.0.2.1"]},
+            {"rrset_name": "www", "rrset_type": "AAAA", "rrset_ttl": 300, "rrset_values": ["2001:db8:1:2::1"]},
+            {"rrset_name": "pi-dyn", "rrset_type": "AAAA", "rrset_ttl": 600, "rrset_values": ["2001:db8:1:2::5"]}
+        ]"#,
+    );
+    server.route("GET", "/livedns/domains/example.org/records", 200, "[]");
+
+    let config = DiscoveryConfig {
+        names: vec!["*-dyn".to_string()],
+        domains: Vec::new(),
+    };
+    let configured: HashMap<String, ServiceConfig> = toml::from_str(
+        r#"
+        [pi]
+        suffix = "::5"
+        name = "pi-dyn"
+        fqdn = "example.com"
+        ttl = 600
+        "#,
+    )
+    .unwrap();
+    let client = gandi::Client::with_base_url(reqwest::Client::new(), "token", server.url());
+
+    let discovered = discover(&client, &config, &configured).await.unwrap();
+
+    assert_eq!(discovered.len(), 1);
+    let service = &discovered["nas-dyn.example.com"];
+    assert_eq!(service.name, "nas-dyn");
+    assert_eq!(service.fqdn, "example.com");
+    assert_eq!(service.ttl, 300);
+    assert_eq!(
+        service.suffix,
+        Suffix::Address("::aa:bb:cc:dd".parse().unwrap())
+    );
+}
@@ -0,0 +1,96 @@
+use std::{net::Ipv6Addr, time::Duration};
+
+use dynsix::{
+    dns::{lookup_aaaa, parse_aaaa, query},
+    ip::{DnsSource, Source},
+    Config,
+};
+use tokio::net::UdpSocket;
+
+/// The answer OpenDNS gives, the name of the record being a pointer to the
+/// question
+fn answer(query: &[u8], address: Ipv6Addr) -> Vec<u8> {
+    let mut answer = query.to_vec();
+    answer[2] |= 0x80;
+    answer[7] = 1;
+    answer.extend_from_slice(&[0xc0, 12, 0, 28, 0, 1, 0, 0, 0, 0, 0, 16]);
+    answer.extend_from_slice(&address.octets());
+    answer
+}
+
+#[test]
+fn parses_the_address_in_the_answer() {
+    let message = query(0x1234, "myip.opendns.com").unwrap();
+    assert_eq!(
+        &message[12..],
+        b"\x04myip\x07opendns\x03com\x00\x00\x1c\x00\x01"
+    );
+
+    let address: Ipv6Addr = "2001:db8:1:2::1".parse().unwrap();
+    let answer = answer(&message, address);
+    assert_eq!(parse_aaaa(&answer, 0x1234), Ok(address));
+    assert!(parse_aaaa(&answer, 0x4321).is_err());
+    assert!(parse_aaaa(&answer[..answer.len() - 1], 0x1234).is_err());
+
+    // NXDOMAIN
+    let mut failed = message.clone();
+    failed[2] |= 0x80;
+    failed[3] |= 3;
+    assert_eq!(
+        parse_aaaa(&failed, 0x1234),
+        Err("the server answered NXDOMAIN (3)".to_string())
+    );
+    assert!(query(1, "bad..name").is_err());
+}
+
+#[test]
+fn is_configured_with_opendns_by_default() {
+    let config: Config = toml::from_str("source = { dns = {} }").unwrap();
+    assert_eq!(
+        config.source,
+        Some(Source::Dns(DnsSource {
+            name: "myip.opendns.com".to_string(),
+            server: "resolver1.opendns.com".to_string(),
+        }))
+    );
+}
+
+#[tokio::test]
+async fn asks_the_server_over_udp() {
+    let server = UdpSocket::bind("[::1]:0").await.unwrap();
+    let port = server.local_addr().unwrap().port();
+    tokio::spawn(async move {
+        let mut buffer = [0; 512];
+        let (received, from) = server.recv_from(&mut buffer).await.unwrap();
+        let std::net::SocketAddr::V6(from) = from else {
+            unreachable!("bound to ::1")
+        };
+        server
+            .send_to(&answer(&buffer[..received], *from.ip()), from)
+            .await
+            .unwrap();
+    });
+
+    let address = lookup_aaaa(
+        &format!("[::1]:{port}"),
+        "myip.opendns.com",
+        Duration::from_secs(5),
+        None,
+    )
+    .await
+    .unwrap();
+    assert_eq!(address, Ipv6Addr::LOCALHOST);
+
+    // Nobody answers on the port now
+    let error = lookup_aaaa(
+        &format!("[::1]:{port}"),
+        "myip.opendns.com",
+        Duration::from_millis(200),
+        None,
+    )
+    .await
+    .unwrap_err();
+    assert!(error
+        .to_string()
+        .starts_with("failed to look up myip.opendns.com at [::1]:"));
+}
@@ -0,0 +1,128 @@
+mod common;
+
+use std::collections::HashMap;
+
+use common::MockServer;
+use dynsix::{
+    drift::{self, Drift},
+    gandi,
+    notify::{Event, Notification},
+    report::RunReport,
+    state::State,
+    Reconciler, ServiceConfig,
+};
+
+const RECORD_PATH: &str = "/livedns/domains/example.com/records/www/AAAA";
+const NOT_FOUND: &str = r#"{"code": 404, "message": "Record not found", "object": "HTTPNotFound", "cause": "Not Found"}"#;
+const WRITTEN: &str = r#"{"rrset_values": ["2001:db8:aa:bb:1:2:3:4"], "rrset_ttl": 600}"#;
+
+fn services() -> HashMap<String, ServiceConfig> {
+    let service = toml::from_str(
+        r#"
+        suffix = "::1:2:3:4"
+        name = "www"
+        fqdn = "example.com"
+        ttl = 600
+        "#,
+    )
+    .unwrap();
+    HashMap::from([("web".to_string(), service)])
+}
+
+/// A reconciler and the state after it created the record of `web`
+async fn written(server: &MockServer) -> (Reconciler, State) {
+    server.route("GET", RECORD_PATH, 404, NOT_FOUND);
+    server.route(
+        "POST",
+        RECORD_PATH,
+        201,
+        r#"{"message": "DNS Record Created"}"#,
+    );
+    let reconciler = Reconciler::new(gandi::Client::with_base_url(
+        reqwest::Client::new(),
+        "secret-token",
+        server.url(),
+    ));
+
+    let services = services();
+    let mut report = RunReport::new("2001:db8:aa:bb::1".parse().unwrap());
+    report.push(
+        reconciler
+            .reconcile_service("web", &services["web"], report.public_ip)
+            .await,
+    );
+    let mut state = State::default();
+    state.track(&report, &services);
+    assert_eq!(state.managed.len(), 1);
+    (reconciler, state)
+}
+
+#[tokio::test]
+async fn records_holding_what_was_written_have_not_drifted() {
+    let server = MockServer::start().await;
+    let (reconciler, state) = written(&server).await;
+    server.route("GET", RECORD_PATH, 200, WRITTEN);
+
+    assert_eq!(drift::detect(&reconciler, &state, &services()).await, []);
+}
+
+#[tokio::test]
+async fn detects_records_changed_or_deleted_by_someone_else() {
+    let server = MockServer::start().await;
+    let (reconciler, state) = written(&server).await;
+
+    server.route(
+        "GET",
+        RECORD_PATH,
+        200,
+        r#"{"rrset_values": ["2001:db8::dead"], "rrset_ttl": 600}"#,
+    );
+    let changed = drift::detect(&reconciler, &state, &services()).await;
+    server.route("GET", RECORD_PATH, 404, NOT_FOUND);
+    let deleted = drift::detect(&reconciler, &state, &services()).await;
+
+    let drift = Drift {
+        service: "web".to_string(),
+        record: "www.example.com".to_string(),
+        written: "2001:db8:aa:bb:1:2:3:4".parse().unwrap(),
+        found: Some(vec!["2001:db8::dead".to_string()]),
+    };
+    let gone = Drift {
+        found: None,
+        ..drift.clone()
+    };
+    assert_eq!(changed, [drift]);
+    assert_eq!(deleted, [gone]);
+}
+
+#[tokio::test]
+async fn records_of_removed_services_are_not_checked() {
+    let server = MockServer::start().await;
+    let (reconciler, state) = written(&server).await;
+    let requests = server.requests().len();
+
+    assert_eq!(
+        drift::detect(&reconciler, &state, &HashMap::new()).await,
+        []
+    );
+    assert_eq!(server.requests().len(), requests);
+}
+
+#[test]
+fn notification_names_both_values() {
+    let found = ["2001:db8::dead".to_string()];
+    let notification = Notification::from_event(&Event::RecordDrifted {
+        service: "web",
+        record: "www.example.com",
+        written: "2001:db8::1".parse().unwrap(),
+        found: Some(&found),
+    })
+    .unwrap();
+
+    assert_eq!(notification.title, "www.example.com changed outside dynsix");
+    assert_eq!(
+        notification.body,
+        "Set to 2001:db8::1 by dynsix, now 2001:db8::dead"
+    );
+    assert!(notification.failure);
+}
@@ -0,0 +1,56 @@
+mod common;
+
+use common::{assert_fails_with, reconcile, MockServer};
+use dynsix::{report::Action, Reconciler, ServiceConfig};
+
+const UPDATE_PATH: &str =
+    "/nic/update?hostname=www.example.invalid&myip=2001%3Adb8%3Aaa%3Abb%3A1%3A2%3A3%3A4";
+
+fn service() -> ServiceConfig {
+    // .invalid never resolves, so the record always looks missing
+    common::service("dyndns2", "example.invalid", 60)
+}
+
+fn reconciler(server: &MockServer) -> Reconciler {
+    common::reconciler(&format!(
+        r#"
+        [dyndns2]
+        server = "{}"
+        username = "user"
+        password = "pass"
+        "#,
+        server.url()
+    ))
+}
+
+#[tokio::test]
+async fn accepts_good_and_nochg() {
+    for answer in [
+        "good 2001:db8:aa:bb:1:2:3:4",
+        "nochg 2001:db8:aa:bb:1:2:3:4",
+    ] {
+        let server = MockServer::start().await;
+        server.route("GET", UPDATE_PATH, 200, answer);
+
+        reconcile(&reconciler(&server), &service(), Action::Created).await;
+
+        let requests = server.requests_to("GET");
+        assert_eq!(
+            requests[0].header("Authorization"),
+            Some("Basic dXNlcjpwYXNz")
+        );
+    }
+}
+
+#[tokio::test]
+async fn explains_error_codes() {
+    let server = MockServer::start().await;
+    server.route("GET", UPDATE_PATH, 200, "badauth");
+
+    assert_fails_with(
+        &reconciler(&server),
+        &service(),
+        "badauth: username or password is wrong",
+    )
+    .await;
+}
@@ -0,0 +1,61 @@
+mod common;
+
+use common::MockServer;
+use dynsix::{gandi, DynsixError};
+
+const RECORD_PATH: &str = "/livedns/domains/example.com/records/www/AAAA";
+
+fn client(server: &MockServer) -> gandi::Client {
+    gandi::Client::with_base_url(reqwest::Client::new(), "secret-token", server.url())
+}
+
+#[tokio::test]
+async fn keeps_the_status_and_body_of_unexpected_responses() {
+    let server = MockServer::start().await;
+    // An error in a shape Gandi did not use before
+    server.route(
+        "GET",
+        RECORD_PATH,
+        400,
+        r#"{"status": "error", "errors": [{"location": "body", "name": "rrset_ttl"}]}"#,
+    );
+
+    let error = client(&server)
+        .get_record("example.com", "www")
+        .await
+        .unwrap_err();
+    match &error {
+        DynsixError::GandiParse {
+            operation,
+            status,
+            body,
+            ..
+        } => {
+            assert_eq!(*operation, "fetching");
+            assert_eq!(*status, 400);
+            assert!(body.contains(r#""name": "rrset_ttl""#), "{body}");
+        }
+        other => panic!("unexpected error {other}"),
+    }
+}
+
+#[tokio::test]
+async fn error_bodies_are_not_taken_for_success() {
+    let server = MockServer::start().await;
+    // Has a message like the answer to a successful change
+    server.route(
+        "PUT",
+        RECORD_PATH,
+        500,
+        r#"{"message": "Internal Server Error"}"#,
+    );
+
+    let error = client(&server)
+        .update_record("example.com", "www", 300, &"2001:db8::1".parse().unwrap())
+        .await
+        .unwrap_err();
+    assert!(
+        matches!(error, DynsixError::GandiParse { status: 500, .. }),
+        "{error}"
+    );
+}
@@ -0,0 +1,136 @@
+mod common;
+
+use std::collections::HashMap;
+
+use common::{assert_updates, public_ip, reconcile, MockServer, OLD_ADDRESS};
+use dynsix::{report::Action, Reconciler, ServiceConfig};
+
+const ZONES_PATH: &str = "/zones?name=example.com";
+const RECORDS_PATH: &str = "/records?zone_id=Z1";
+const ZONES: &str = r#"{"zones": [{"id": "Z1", "name": "example.com", "ttl": 86400}]}"#;
+
+fn service() -> ServiceConfig {
+    common::service("hetzner", "example.com", 600)
+}
+
+fn reconciler(server: &MockServer) -> Reconciler {
+    common::reconciler(&format!(
+        r#"
+        [hetzner]
+        token = "secret-token"
+        endpoint = "{}"
+        "#,
+        server.url()
+    ))
+}
+
+#[tokio::test]
+async fn creates_missing_record() {
+    let server = MockServer::start().await;
+    server.route("GET", ZONES_PATH, 200, ZONES);
+    server.route(
+        "GET",
+        RECORDS_PATH,
+        200,
+        r#"{"records": [{"id": "R0", "type": "A", "name": "www", "value": "192.0.2.1", "zone_id": "Z1"}]}"#,
+    );
+    server.route("POST", "/records", 200, r#"{"record": {"id": "R1"}}"#);
+
+    reconcile(&reconciler(&server), &service(), Action::Created).await;
+
+    let posts = server.requests_to("POST");
+    assert_eq!(posts.len(), 1);
+    assert_eq!(posts[0].header("Auth-API-Token"), Some("secret-token"));
+    let body: serde_json::Value = serde_json::from_str(&posts[0].body).unwrap();
+    assert_eq!(
+        body,
+        serde_json::json!({
+            "zone_id": "Z1",
+            "type": "AAAA",
+            "name": "www",
+            "value": "2001:db8:aa:bb:1:2:3:4",
+            "ttl": 600
+        })
+    );
+}
+
+#[tokio::test]
+async fn updates_record_in_place() {
+    let server = MockServer::start().await;
+    server.route("GET", ZONES_PATH, 200, ZONES);
+    server.route(
+        "GET",
+        RECORDS_PATH,
+        200,
+        &format!(
+            r#"{{"records": [{{"id": "R1", "type": "AAAA", "name": "www", "value": "{OLD_ADDRESS}", "ttl": 600, "zone_id": "Z1"}}]}}"#
+        ),
+    );
+    server.route("PUT", "/records/R1", 200, r#"{"record": {"id": "R1"}}"#);
+
+    assert_updates(&reconciler(&server), &service()).await;
+
+    assert_eq!(server.requests_to("PUT").len(), 1);
+    assert!(server.requests_to("POST").is_empty());
+}
+
+#[tokio::test]
+async fn records_without_a_ttl_keep_low_ttl_services_unchanged() {
+    let server = MockServer::start().await;
+    server.route("GET", ZONES_PATH, 200, ZONES);
+    // Records without a TTL of their own use that of the zone
+    server.route(
+        "GET",
+        RECORDS_PATH,
+        200,
+        r#"{"records": [{"id": "R1", "type": "AAAA", "name": "www", "value": "2001:db8:aa:bb:1:2:3:4", "zone_id": "Z1"}]}"#,
+    );
+    let service = ServiceConfig {
+        low_ttl: Some(toml::from_str("ttl = 60").unwrap()),
+        ..service()
+    };
+
+    reconcile(&reconciler(&server), &service, Action::Unchanged).await;
+
+    assert!(server.requests_to("PUT").is_empty());
+}
+
+#[tokio::test]
+async fn services_use_their_own_credentials() {
+    let server = MockServer::start().await;
+    server.route("GET", ZONES_PATH, 200, ZONES);
+    server.route("GET", RECORDS_PATH, 200, r#"{"records": []}"#);
+    server.route("POST", "/records", 200, r#"{"record": {"id": "R1"}}"#);
+
+    let mut services = HashMap::new();
+    services.insert("shared".to_string(), service());
+    for (name, token) in [("other", "other-token"), ("another", "other-token")] {
+        let mut service = service();
+        service.credentials = Some(serde_json::json!({
+            "token": token,
+            "endpoint": server.url(),
+        }));
+        services.insert(name.to_string(), service);
+    }
+
+    let report = reconciler(&server)
+        .with_service_credentials(reqwest::Client::new(), &services)
+        .reconcile(&services, public_ip(), |_| true)
+        .await;
+
+    assert_eq!(report.exit_code(), std::process::ExitCode::SUCCESS);
+    let mut tokens: Vec<_> = server
+        .requests_to("POST")
+        .iter()
+        .map(|request| request.header("Auth-API-Token").unwrap().to_string())
+        .collect();
+    tokens.sort();
+    assert_eq!(tokens, ["other-token", "other-token", "secret-token"]);
+    // The zone id is cached per provider, which both other services share
+    let zone_lookups = server
+        .requests_to("GET")
+        .iter()
+        .filter(|request| request.path == ZONES_PATH)
+        .count();
+    assert_eq!(zone_lookups, 2);
+}
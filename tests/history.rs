@@ -0,0 +1,116 @@
+#![cfg(feature = "history")]
+
+use std::{net::Ipv6Addr, time::Duration};
+
+use dynsix::{
+    history::History,
+    provider::ProviderKind,
+    report::{Action, Reconciled, RunReport, ServiceReport},
+    state::ManagedRecord,
+    DynsixError,
+};
+
+fn report() -> RunReport {
+    let mut report = RunReport::new("2001:db8:aa:bb::1".parse().unwrap());
+    report.push(ServiceReport::new(
+        "web".to_string(),
+        "www.example.com".to_string(),
+        "2001:db8:aa:bb::80".parse().unwrap(),
+        Ok(Reconciled {
+            action: Action::Updated,
+            old: Some(vec!["2001:db8:cc:dd::80".to_string()]),
+        }),
+        Duration::from_millis(120),
+    ));
+    report.push(ServiceReport::new(
+        "mail".to_string(),
+        "mail.example.com".to_string(),
+        "2001:db8:aa:bb::25".parse().unwrap(),
+        Ok(Reconciled {
+            action: Action::Unchanged,
+            old: Some(vec!["2001:db8:aa:bb::25".to_string()]),
+        }),
+        Duration::ZERO,
+    ));
+    report.push(ServiceReport::new(
+        "vpn".to_string(),
+        "vpn.example.com".to_string(),
+        "2001:db8:aa:bb::443".parse().unwrap(),
+        Err(DynsixError::ProviderNotConfigured {
+            provider: "hetzner",
+        }),
+        Duration::from_millis(3),
+    ));
+    report.finish();
+    report
+}
+
+#[test]
+fn keeps_changes_across_opens() {
+    let dir = std::env::temp_dir().join(format!("dynsix-history-test-{}", std::process::id()));
+    let path = dir.join("history.sqlite");
+
+    History::open(&path).unwrap().record(&report()).unwrap();
+    let changes = History::open(&path).unwrap().changes().unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    let summary: Vec<_> = changes
+        .iter()
+        .map(|change| {
+            (
+                change.service.as_str(),
+                change.old.as_deref(),
+                change.new.as_str(),
+                change.outcome.as_str(),
+                change.latency_ms,
+            )
+        })
+        .collect();
+    assert_eq!(
+        summary,
+        [
+            ("vpn", None, "2001:db8:aa:bb::443", "failed", 3),
+            (
+                "web",
+                Some("2001:db8:cc:dd::80"),
+                "2001:db8:aa:bb::80",
+                "updated",
+                120
+            ),
+        ]
+    );
+    assert_eq!(
+        changes[0].error.as_deref(),
+        Some("provider hetzner is not configured")
+    );
+    assert!(changes[0].time > 0);
+}
+
+#[test]
+fn keeps_deleted_records() {
+    let dir = std::env::temp_dir().join(format!(
+        "dynsix-history-deleted-test-{}",
+        std::process::id()
+    ));
+    let path = dir.join("history.sqlite");
+    let mut report = RunReport::new(Ipv6Addr::UNSPECIFIED);
+    report.deleted.push(ManagedRecord {
+        provider: ProviderKind::Gandi,
+        fqdn: "example.com".to_string(),
+        name: "old".to_string(),
+        service: "web".to_string(),
+        address: Some("2001:db8:cc:dd::80".parse().unwrap()),
+    });
+    report.finish();
+
+    History::open(&path).unwrap().record(&report).unwrap();
+    let changes = History::open(&path).unwrap().changes().unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(changes.len(), 1);
+    assert_eq!(changes[0].service, "web");
+    assert_eq!(changes[0].record, "old.example.com");
+    assert_eq!(changes[0].old.as_deref(), Some("2001:db8:cc:dd::80"));
+    assert_eq!(changes[0].new, "");
+    assert_eq!(changes[0].outcome, "deleted");
+}
@@ -0,0 +1,67 @@
+use std::collections::HashMap;
+
+use dynsix::hook::{event, Event};
+
+fn env<'a>(vars: &'a [(&str, &str)]) -> impl Fn(&str) -> Option<String> + 'a {
+    let vars: HashMap<_, _> = vars.iter().copied().collect();
+    move |name| vars.get(name).map(|value| value.to_string())
+}
+
+#[test]
+fn dhcpcd_delegated_prefix() {
+    let vars = [
+        ("reason", "BOUND6"),
+        ("interface", "wan0"),
+        ("new_dhcp6_ia_pd1_prefix1", "2001:db8:1::"),
+        ("new_dhcp6_ia_pd1_prefix1_length", "56"),
+    ];
+    assert_eq!(
+        event(env(&vars)),
+        Ok(Event::Up {
+            interface: "wan0".to_string(),
+            prefix: Some("2001:db8:1::/56".to_string()),
+        })
+    );
+
+    let vars = [
+        ("reason", "DELEGATED6"),
+        ("interface", "lan0"),
+        (
+            "new_delegated_dhcp6_prefix",
+            "2001:db8:1:100::/64 2001:db8:1:200::/64",
+        ),
+    ];
+    assert_eq!(
+        event(env(&vars)),
+        Ok(Event::Up {
+            interface: "lan0".to_string(),
+            prefix: Some("2001:db8:1:100::/64".to_string()),
+        })
+    );
+}
+
+#[test]
+fn dhcpcd_events_without_an_ipv6_prefix_are_ignored() {
+    let vars = [("reason", "BOUND"), ("interface", "wan0")];
+    assert_eq!(
+        event(env(&vars)),
+        Ok(Event::Ignored {
+            interface: "wan0".to_string(),
+            reason: "BOUND".to_string(),
+        })
+    );
+}
+
+#[test]
+fn pppd_leaves_the_prefix_to_detection() {
+    let vars = [("IFNAME", "ppp0"), ("LLLOCAL", "fe80::1")];
+    assert_eq!(
+        event(env(&vars)),
+        Ok(Event::Up {
+            interface: "ppp0".to_string(),
+            prefix: None,
+        })
+    );
+
+    assert!(event(env(&[])).is_err());
+}
@@ -0,0 +1,42 @@
+mod common;
+
+use common::{assert_fails_with, reconcile, MockServer};
+use dynsix::{report::Action, Reconciler, ServiceConfig};
+
+const UPDATE_PATH: &str =
+    "/update?domains=www&token=secret&ipv6=2001%3Adb8%3Aaa%3Abb%3A1%3A2%3A3%3A4";
+
+fn service() -> ServiceConfig {
+    // .invalid never resolves, so the record always looks missing
+    common::service("http", "example.invalid", 60)
+}
+
+fn reconciler(server: &MockServer) -> Reconciler {
+    common::reconciler(&format!(
+        r#"
+        [http]
+        url = "{}/update?domains={{name}}&token={{token}}&ipv6={{ip}}"
+        token = "secret"
+        success = "OK"
+        "#,
+        server.url()
+    ))
+}
+
+#[tokio::test]
+async fn requests_update_url() {
+    let server = MockServer::start().await;
+    server.route("GET", UPDATE_PATH, 200, "OK");
+
+    reconcile(&reconciler(&server), &service(), Action::Created).await;
+
+    assert_eq!(server.requests_to("GET").len(), 1);
+}
+
+#[tokio::test]
+async fn fails_without_success_text() {
+    let server = MockServer::start().await;
+    server.route("GET", UPDATE_PATH, 200, "KO");
+
+    assert_fails_with(&reconciler(&server), &service(), "KO").await;
+}
@@ -0,0 +1,55 @@
+mod common;
+
+use common::MockServer;
+use dynsix::{gandi, http_log, report::Action, Reconciler, ServiceConfig};
+
+#[tokio::test]
+async fn logged_responses_are_still_read() {
+    http_log::enable();
+    let server = MockServer::start().await;
+    server.route(
+        "GET",
+        "/livedns/domains/example.com/records/www/AAAA",
+        200,
+        r#"{"rrset_values": ["2001:db8:aa:bb:1:2:3:4"], "rrset_ttl": 600}"#,
+    );
+
+    let service: ServiceConfig = toml::from_str(
+        r#"
+        suffix = "::1:2:3:4"
+        name = "www"
+        fqdn = "example.com"
+        ttl = 600
+        "#,
+    )
+    .unwrap();
+    let report = Reconciler::new(gandi::Client::with_base_url(
+        reqwest::Client::new(),
+        "secret-token",
+        server.url(),
+    ))
+    .reconcile_service("web", &service, "2001:db8:aa:bb::1".parse().unwrap())
+    .await;
+
+    assert_eq!(report.action, Action::Unchanged);
+    assert_eq!(report.error, None);
+}
+
+#[test]
+fn masks_credentials_in_urls() {
+    assert_eq!(
+        http_log::redact_text(
+            "error sending request for url (https://api.telegram.org/bot123456:ABC-DEF/sendMessage)"
+        ),
+        "error sending request for url (https://api.telegram.org/bot[redacted]/sendMessage)"
+    );
+    assert_eq!(
+        http_log::redact_text("GET https://example.com/update?hostname=www&password=hunter2"),
+        "GET https://example.com/update?hostname=www&password=[redacted]"
+    );
+    // Paths that merely start with bot are left alone
+    assert_eq!(
+        http_log::redact_text("GET https://example.com/bottles/1"),
+        "GET https://example.com/bottles/1"
+    );
+}
@@ -0,0 +1,21 @@
+use dynsix::idna::{to_ascii, to_unicode};
+
+#[test]
+fn encodes_unicode_labels() {
+    assert_eq!(to_ascii("bücher.example").unwrap(), "xn--bcher-kva.example");
+    assert_eq!(to_ascii("MÜNCHEN.de").unwrap(), "xn--mnchen-3ya.de");
+    assert_eq!(to_ascii("例え.テスト").unwrap(), "xn--r8jz45g.xn--zckzah");
+    assert_eq!(to_ascii("*.Lab").unwrap(), "*.Lab");
+    assert_eq!(to_ascii("@").unwrap(), "@");
+}
+
+#[test]
+fn decodes_punycode_labels() {
+    assert_eq!(
+        to_unicode("www.xn--bcher-kva.example"),
+        "www.bücher.example"
+    );
+    assert_eq!(to_unicode("XN--R8JZ45G.xn--zckzah"), "例え.テスト");
+    // Not valid punycode, shown as it is
+    assert_eq!(to_unicode("xn--!.example"), "xn--!.example");
+}
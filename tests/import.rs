@@ -0,0 +1,190 @@
+use dynsix::{
+    import::{ddclient, inadyn, Format},
+    provider::ProviderKind,
+    Config,
+};
+
+const DDCLIENT: &str = r#"
+# Global settings
+daemon=300
+ssl=yes
+usev6=ifv6, ifv6=eth0
+
+protocol=dyndns2, \
+server=dynupdate.no-ip.com, \
+login=me, password='p#ss, word'
+home.example.net, vpn.example.net
+
+protocol=gandi, zone=example.com, password=first, ttl=60 www.example.com
+protocol=gandi, zone=example.com, password=second example.com
+protocol=cloudflare, zone=example.org, login=token, password=x host.example.org
+"#;
+
+#[test]
+fn parses_ddclient_settings() {
+    let hosts = ddclient::parse(DDCLIENT).unwrap();
+    let names: Vec<_> = hosts.iter().map(|host| host.host.as_str()).collect();
+    assert_eq!(
+        names,
+        [
+            "home.example.net",
+            "vpn.example.net",
+            "www.example.com",
+            "example.com",
+            "host.example.org"
+        ]
+    );
+
+    // Quoted values keep their commas and #, continued lines are joined
+    assert_eq!(hosts[0].settings["password"], "p#ss, word");
+    assert_eq!(hosts[1].settings["server"], "dynupdate.no-ip.com");
+    // Settings on a host line only apply to its hosts
+    assert_eq!(hosts[2].settings["password"], "first");
+    assert_eq!(hosts[2].settings["ifv6"], "eth0");
+    assert!(!hosts[3].settings.contains_key("ttl"));
+
+    assert!(ddclient::parse("password='open host.example.com")
+        .unwrap_err()
+        .starts_with("line 1: unterminated quote"));
+}
+
+#[test]
+fn converts_ddclient_configs() {
+    let imported = ddclient::convert(DDCLIENT).unwrap();
+    assert_eq!(imported.token.as_deref(), Some("first"));
+    assert_eq!(
+        imported.tokens,
+        [("gandi2".to_string(), "second".to_string())]
+    );
+    assert_eq!(imported.interface.as_deref(), Some("eth0"));
+    assert_eq!(imported.providers.len(), 1);
+    assert_eq!(imported.providers[0].0, ProviderKind::Dyndns2);
+    assert_eq!(
+        imported.providers[0].1[0],
+        ("server", "https://dynupdate.no-ip.com".to_string())
+    );
+    assert_eq!(imported.notes.len(), 1);
+    assert!(imported.notes[0].contains("cloudflare"));
+
+    let config: Config = toml::from_str(&imported.to_toml("ddclient.conf")).unwrap();
+    assert_eq!(config.services.len(), 4);
+    let www = &config.services["www.example.com"];
+    assert_eq!(www.name, "www");
+    assert_eq!(www.fqdn, "example.com");
+    // Raised to the minimum of Gandi
+    assert_eq!(www.ttl, 300);
+    let apex = &config.services["example.com"];
+    assert_eq!(apex.name, "@");
+    assert_eq!(apex.token_ref.as_deref(), Some("gandi2"));
+    let home = &config.services["home.example.net"];
+    assert_eq!(home.provider, ProviderKind::Dyndns2);
+    assert_eq!(home.name, "home");
+    assert_eq!(home.fqdn, "example.net");
+    assert_eq!(config.validate(), Vec::<String>::new());
+}
+
+#[test]
+fn keeps_differing_credentials_with_the_service() {
+    let imported = ddclient::convert(
+        "protocol=hetzner, zone=example.com, password=one a.example.com\n\
+         protocol=hetzner, zone=example.com, password=two b.example.com\n",
+    )
+    .unwrap();
+    assert_eq!(imported.providers[0].1, [("token", "one".to_string())]);
+    assert_eq!(imported.services[0].credentials, None);
+    assert_eq!(
+        imported.services[1].credentials,
+        Some(vec![("token", "two".to_string())])
+    );
+
+    let config: Config = toml::from_str(&imported.to_toml("ddclient.conf")).unwrap();
+    assert!(config.services["b.example.com"].credentials.is_some());
+}
+
+#[test]
+fn rejects_configs_without_convertible_hosts() {
+    let path = std::env::temp_dir().join(format!("dynsix-import-{}.conf", std::process::id()));
+    std::fs::write(&path, "protocol=cloudflare, password=x host.example.org\n").unwrap();
+    let error = Format::Ddclient.import(&path).unwrap_err();
+    std::fs::remove_file(&path).unwrap();
+    assert!(error.to_string().contains("no host that can be converted"));
+}
+
+const INADYN: &str = r#"
+period = 300
+iface = eth0
+
+provider default@no-ip.com:1 {
+    username = "me"
+    password = "secret"   # comment
+    hostname = { "home.example.net", "nas.example.net" }
+}
+
+provider default@dedyn.io {
+    password = token
+    hostname = myhost.dedyn.io
+}
+
+provider default@cloudflare.com {
+    username = example.org
+    password = x
+    hostname = host.example.org
+}
+
+custom myddns {
+    password = p
+    ddns-server = "update.example.com"
+    ddns-path = "/update?secret=%p&host=%h&ip=%i"
+    hostname = "box.example.info"
+}
+"#;
+
+#[test]
+fn parses_inadyn_blocks() {
+    let config = inadyn::parse(INADYN).unwrap();
+    assert_eq!(config.settings["iface"], ["eth0"]);
+    assert_eq!(config.blocks.len(), 4);
+    assert_eq!(config.blocks[0].name, "default@no-ip.com:1");
+    assert_eq!(
+        config.blocks[0].settings["hostname"],
+        ["home.example.net", "nas.example.net"]
+    );
+    assert_eq!(config.blocks[0].settings["password"], ["secret"]);
+    assert!(config.blocks[3].custom);
+
+    assert_eq!(
+        inadyn::parse("provider default@no-ip.com {\n hostname = a.example.com\n").unwrap_err(),
+        "line 1: provider default@no-ip.com is not closed"
+    );
+}
+
+#[test]
+fn converts_inadyn_configs() {
+    let imported = inadyn::convert(INADYN).unwrap();
+    assert_eq!(imported.interface.as_deref(), Some("eth0"));
+    assert_eq!(imported.notes.len(), 1);
+    assert!(imported.notes[0].starts_with("default@cloudflare.com"));
+
+    let config: Config = toml::from_str(&imported.to_toml("inadyn.conf")).unwrap();
+    assert_eq!(config.services.len(), 4);
+    let nas = &config.services["nas.example.net"];
+    assert_eq!(nas.provider, ProviderKind::Dyndns2);
+    assert_eq!(
+        (nas.name.as_str(), nas.fqdn.as_str()),
+        ("nas", "example.net")
+    );
+    let desec = &config.services["myhost.dedyn.io"];
+    assert_eq!(desec.provider, ProviderKind::Desec);
+    assert_eq!(desec.name, "@");
+    // The default, dedyn.io domains accept TTLs down to 60
+    assert_eq!(desec.ttl, 600);
+    assert_eq!(
+        config.services["box.example.info"].provider,
+        ProviderKind::Http
+    );
+    assert_eq!(
+        config.providers.http.as_ref().unwrap().url,
+        "https://update.example.com/update?secret={token}&host={name}.{fqdn}&ip={ip}"
+    );
+    assert_eq!(config.validate(), Vec::<String>::new());
+}
@@ -0,0 +1,49 @@
+mod common;
+
+use common::{public_ip, MockServer};
+use dynsix::{gandi, report::Action, Reconciler, ServiceConfig};
+
+const RECORD_PATH: &str = "/livedns/domains/example.com/records/www/AAAA";
+const RECORD: &str = r#"{"rrset_name": "www", "rrset_type": "AAAA", "rrset_ttl": 600, "rrset_values": ["2001:db8:aa:bb:1:2:3:4"]}"#;
+
+/// A client that can't connect anywhere, like one over a broken IPv6 route
+fn unreachable() -> reqwest::Client {
+    reqwest::Client::builder()
+        .proxy(reqwest::Proxy::all("http://127.0.0.1:1").unwrap())
+        .build()
+        .unwrap()
+}
+
+fn service() -> ServiceConfig {
+    common::service("gandi", "example.com", 600)
+}
+
+#[tokio::test]
+async fn retries_with_the_fallback_client() {
+    let server = MockServer::start().await;
+    server.route("GET", RECORD_PATH, 200, RECORD);
+
+    let client = gandi::Client::with_base_url(unreachable(), "secret-token", server.url())
+        .with_ipv4_fallback(reqwest::Client::new());
+    let report = Reconciler::new(client)
+        .reconcile_service("web", &service(), public_ip())
+        .await;
+
+    assert_eq!(report.error, None);
+    assert_eq!(report.action, Action::Unchanged);
+    assert_eq!(server.requests().len(), 1);
+}
+
+#[tokio::test]
+async fn fails_without_fallback() {
+    let server = MockServer::start().await;
+    server.route("GET", RECORD_PATH, 200, RECORD);
+
+    let client = gandi::Client::with_base_url(unreachable(), "secret-token", server.url());
+    let report = Reconciler::new(client)
+        .reconcile_service("web", &service(), public_ip())
+        .await;
+
+    assert!(report.error.is_some());
+    assert!(server.requests().is_empty());
+}
@@ -0,0 +1,14 @@
+use dynsix::lock::RunLock;
+
+#[test]
+fn lock_is_exclusive_until_dropped() {
+    let path = std::env::temp_dir().join(format!("dynsix-lock-test-{}", std::process::id()));
+
+    let held = RunLock::acquire(&path, false).unwrap();
+    assert!(held.is_some());
+    assert!(RunLock::acquire(&path, false).unwrap().is_none());
+
+    drop(held);
+    assert!(RunLock::acquire(&path, false).unwrap().is_some());
+    std::fs::remove_file(&path).unwrap();
+}
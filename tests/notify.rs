@@ -0,0 +1,165 @@
+mod common;
+
+use std::time::Duration;
+
+use common::MockServer;
+use dynsix::{
+    notify::{sentry::Dsn, Notifiers, NotifyConfig},
+    report::{Action, Reconciled, RunReport, ServiceReport},
+    state::State,
+    DynsixError,
+};
+
+fn report() -> RunReport {
+    let mut report = RunReport::new("2001:db8:aa:bb::1".parse().unwrap());
+    report.push(ServiceReport::new(
+        "web".to_string(),
+        "www.example.com".to_string(),
+        "2001:db8:aa:bb:1:2:3:4".parse().unwrap(),
+        Ok(Reconciled {
+            action: Action::Updated,
+            old: Some(vec!["2001:db8:cc:dd:1:2:3:4".to_string()]),
+        }),
+        Duration::ZERO,
+    ));
+    report.push(ServiceReport::new(
+        "mail".to_string(),
+        "mail.example.com".to_string(),
+        "2001:db8:aa:bb::25".parse().unwrap(),
+        Ok(Reconciled {
+            action: Action::Unchanged,
+            old: Some(vec!["2001:db8:aa:bb::25".to_string()]),
+        }),
+        Duration::ZERO,
+    ));
+    report.finish();
+    report
+}
+
+#[tokio::test]
+async fn ntfy_receives_changed_records() {
+    let server = MockServer::start().await;
+    server.route("POST", "/dynsix", 200, "{}");
+    let config: NotifyConfig = toml::from_str(&format!(
+        r#"
+        [ntfy]
+        server = "{}"
+        topic = "dynsix"
+        token = "tk_secret"
+        priority = "high"
+        "#,
+        server.url()
+    ))
+    .unwrap();
+
+    Notifiers::new(reqwest::Client::new(), &config)
+        .finished(Ok(&report()), &State::default())
+        .await;
+
+    let posts = server.requests_to("POST");
+    assert_eq!(posts.len(), 1);
+    assert_eq!(posts[0].header("Title"), Some("www.example.com updated"));
+    assert_eq!(posts[0].header("Priority"), Some("high"));
+    assert_eq!(posts[0].header("Authorization"), Some("Bearer tk_secret"));
+    assert_eq!(
+        posts[0].body,
+        "Changed from 2001:db8:cc:dd:1:2:3:4 to 2001:db8:aa:bb:1:2:3:4"
+    );
+}
+
+#[tokio::test]
+async fn events_are_routed_to_subscribed_backends_only() {
+    let server = MockServer::start().await;
+    server.route("POST", "/changes", 200, "{}");
+    server.route("POST", "/failures", 200, "{}");
+    let config: NotifyConfig = toml::from_str(&format!(
+        r#"
+        [ntfy]
+        server = "{url}"
+        topic = "failures"
+        events = ["update_failed"]
+
+        [slack]
+        webhook_url = "{url}/changes"
+        events = ["record_updated", "prefix_changed"]
+        "#,
+        url = server.url()
+    ))
+    .unwrap();
+
+    Notifiers::new(reqwest::Client::new(), &config)
+        .finished(Ok(&report()), &State::default())
+        .await;
+
+    let paths: Vec<_> = server
+        .requests_to("POST")
+        .into_iter()
+        .map(|request| request.path)
+        .collect();
+    // One message about the updated record, one about the prefix it moved from
+    assert_eq!(paths, ["/changes", "/changes"]);
+}
+
+#[tokio::test]
+async fn sentry_receives_failures_with_their_service() {
+    let server = MockServer::start().await;
+    server.route("POST", "/sentry/api/42/envelope/", 200, "{}");
+    let config: NotifyConfig = toml::from_str(&format!(
+        r#"
+        [sentry]
+        dsn = "{}/sentry/42"
+        server_name = "site-1"
+        "#,
+        server.url().replacen("://", "://public-key@", 1)
+    ))
+    .unwrap();
+
+    let mut failed = RunReport::new("2001:db8:aa:bb::1".parse().unwrap());
+    failed.push(ServiceReport::new(
+        "web".to_string(),
+        "www.example.com".to_string(),
+        "2001:db8:aa:bb::1".parse().unwrap(),
+        Err(DynsixError::Provider {
+            provider: "http",
+            operation: "updating",
+            message: "https://example.com/update?host=www&token=secret returned 500".to_string(),
+        }),
+        Duration::ZERO,
+    ));
+    failed.finish();
+    Notifiers::new(reqwest::Client::new(), &config)
+        .finished(Ok(&failed), &State::default())
+        .await;
+
+    let posts = server.requests_to("POST");
+    assert_eq!(posts.len(), 1);
+    assert!(posts[0]
+        .header("X-Sentry-Auth")
+        .unwrap()
+        .contains("sentry_key=public-key"));
+    let lines: Vec<_> = posts[0].body.lines().collect();
+    assert_eq!(lines.len(), 3);
+    let event: serde_json::Value = serde_json::from_str(lines[2]).unwrap();
+    assert_eq!(event["level"], "error");
+    assert_eq!(event["server_name"], "site-1");
+    assert_eq!(event["tags"]["service"], "web");
+    assert_eq!(event["tags"]["record"], "www.example.com");
+    assert_eq!(
+        event["exception"]["values"][0]["value"],
+        "http API error while updating record: \
+         https://example.com/update?host=www&token=[redacted] returned 500"
+    );
+}
+
+#[test]
+fn sentry_dsn_gives_the_envelope_endpoint() {
+    assert_eq!(
+        Dsn::parse("https://abc@o1.ingest.sentry.io/42").unwrap(),
+        Dsn {
+            envelope_url: "https://o1.ingest.sentry.io/api/42/envelope/".to_string(),
+            public_key: "abc".to_string(),
+        }
+    );
+    assert!(Dsn::parse("https://o1.ingest.sentry.io/42").is_err());
+    assert!(Dsn::parse("https://abc@o1.ingest.sentry.io/").is_err());
+}
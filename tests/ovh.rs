@@ -0,0 +1,80 @@
+mod common;
+
+use common::{assert_updates, reconcile, MockServer, OLD_ADDRESS};
+use dynsix::{report::Action, Reconciler, ServiceConfig};
+
+const LIST_PATH: &str = "/domain/zone/example.com/record?fieldType=AAAA&subDomain=www";
+
+fn service() -> ServiceConfig {
+    common::service("ovh", "example.com", 600)
+}
+
+fn reconciler(server: &MockServer) -> Reconciler {
+    common::reconciler(&format!(
+        r#"
+        [ovh]
+        application_key = "app-key"
+        application_secret = "app-secret"
+        consumer_key = "consumer-key"
+        endpoint = "{}"
+        "#,
+        server.url()
+    ))
+}
+
+#[tokio::test]
+async fn creates_record_and_refreshes_zone() {
+    let server = MockServer::start().await;
+    server.route("GET", "/auth/time", 200, "1700000000");
+    server.route("GET", LIST_PATH, 200, "[]");
+    server.route(
+        "POST",
+        "/domain/zone/example.com/record",
+        200,
+        r#"{"id": 1}"#,
+    );
+    server.route("POST", "/domain/zone/example.com/refresh", 200, "null");
+
+    reconcile(&reconciler(&server), &service(), Action::Created).await;
+
+    let posts = server.requests_to("POST");
+    assert_eq!(posts.len(), 2);
+    assert_eq!(posts[1].path, "/domain/zone/example.com/refresh");
+
+    let create = &posts[0];
+    assert_eq!(create.header("X-Ovh-Application"), Some("app-key"));
+    assert_eq!(create.header("X-Ovh-Consumer"), Some("consumer-key"));
+    assert!(create.header("X-Ovh-Signature").unwrap().starts_with("$1$"));
+    let body: serde_json::Value = serde_json::from_str(&create.body).unwrap();
+    assert_eq!(
+        body,
+        serde_json::json!({
+            "fieldType": "AAAA",
+            "subDomain": "www",
+            "target": "2001:db8:aa:bb:1:2:3:4",
+            "ttl": 600
+        })
+    );
+}
+
+#[tokio::test]
+async fn updates_existing_record() {
+    let server = MockServer::start().await;
+    server.route("GET", "/auth/time", 200, "1700000000");
+    server.route("GET", LIST_PATH, 200, "[42]");
+    server.route(
+        "GET",
+        "/domain/zone/example.com/record/42",
+        200,
+        &format!(
+            r#"{{"id": 42, "zone": "example.com", "fieldType": "AAAA", "subDomain": "www", "target": "{OLD_ADDRESS}", "ttl": 600}}"#
+        ),
+    );
+    server.route("PUT", "/domain/zone/example.com/record/42", 200, "null");
+    server.route("POST", "/domain/zone/example.com/refresh", 200, "null");
+
+    assert_updates(&reconciler(&server), &service()).await;
+
+    assert_eq!(server.requests_to("PUT").len(), 1);
+    assert_eq!(server.requests_to("POST").len(), 1);
+}
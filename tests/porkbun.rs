@@ -0,0 +1,90 @@
+mod common;
+
+use common::{assert_fails_with, assert_updates, reconcile, MockServer, OLD_ADDRESS};
+use dynsix::{report::Action, Reconciler, ServiceConfig};
+
+const RETRIEVE_PATH: &str = "/dns/retrieveByNameType/example.com/AAAA/www";
+
+fn service() -> ServiceConfig {
+    common::service("porkbun", "example.com", 600)
+}
+
+fn reconciler(server: &MockServer) -> Reconciler {
+    common::reconciler(&format!(
+        r#"
+        [porkbun]
+        api_key = "pk1_key"
+        secret_api_key = "sk1_secret"
+        endpoint = "{}"
+        "#,
+        server.url()
+    ))
+}
+
+#[tokio::test]
+async fn creates_missing_record() {
+    let server = MockServer::start().await;
+    server.route(
+        "POST",
+        RETRIEVE_PATH,
+        200,
+        r#"{"status": "SUCCESS", "records": []}"#,
+    );
+    server.route(
+        "POST",
+        "/dns/create/example.com",
+        200,
+        r#"{"status": "SUCCESS", "id": 106926659}"#,
+    );
+
+    reconcile(&reconciler(&server), &service(), Action::Created).await;
+
+    let posts = server.requests_to("POST");
+    assert_eq!(posts.len(), 2);
+    let body: serde_json::Value = serde_json::from_str(&posts[1].body).unwrap();
+    assert_eq!(
+        body,
+        serde_json::json!({
+            "apikey": "pk1_key",
+            "secretapikey": "sk1_secret",
+            "name": "www",
+            "type": "AAAA",
+            "content": "2001:db8:aa:bb:1:2:3:4",
+            "ttl": "600"
+        })
+    );
+}
+
+#[tokio::test]
+async fn edits_differing_record() {
+    let server = MockServer::start().await;
+    server.route(
+        "POST",
+        RETRIEVE_PATH,
+        200,
+        &format!(
+            r#"{{"status": "SUCCESS", "records": [{{"id": "1", "name": "www.example.com", "type": "AAAA", "content": "{OLD_ADDRESS}", "ttl": "600", "prio": "0", "notes": ""}}]}}"#
+        ),
+    );
+    server.route(
+        "POST",
+        "/dns/editByNameType/example.com/AAAA/www",
+        200,
+        r#"{"status": "SUCCESS"}"#,
+    );
+
+    assert_updates(&reconciler(&server), &service()).await;
+}
+
+#[tokio::test]
+async fn reports_api_errors() {
+    let server = MockServer::start().await;
+    server.route(
+        "POST",
+        RETRIEVE_PATH,
+        200,
+        r#"{"status": "ERROR", "message": "Invalid API key. (002)"}"#,
+    );
+
+    assert_fails_with(&reconciler(&server), &service(), "Invalid API key.").await;
+}
@@ -0,0 +1,58 @@
+use dynsix::protobuf::{decode, Encoder, Value};
+
+#[test]
+fn encodes_strings_and_messages() {
+    let mut inner = Encoder::new();
+    inner.string(1, "testing");
+    assert_eq!(
+        inner.clone().finish(),
+        [0x0a, 0x07, b't', b'e', b's', b't', b'i', b'n', b'g']
+    );
+
+    let mut message = Encoder::new();
+    message
+        .string(1, "")
+        .repeated_string(2, "")
+        .message(3, &inner);
+    assert_eq!(
+        message.finish(),
+        [0x12, 0x00, 0x1a, 0x09, 0x0a, 0x07, b't', b'e', b's', b't', b'i', b'n', b'g']
+    );
+}
+
+#[test]
+fn decodes_every_wire_type() {
+    // 1: varint 150, 2: "web", 3: fixed64 1, 4: fixed32 2
+    let message = [
+        0x08, 0x96, 0x01, 0x12, 0x03, b'w', b'e', b'b', 0x19, 1, 0, 0, 0, 0, 0, 0, 0, 0x25, 2, 0,
+        0, 0,
+    ];
+    let fields = decode(&message).unwrap();
+    assert_eq!(
+        fields,
+        [
+            (1, Value::Varint(150)),
+            (2, Value::Bytes(b"web")),
+            (3, Value::Fixed64(1)),
+            (4, Value::Fixed32(2)),
+        ]
+    );
+    assert_eq!(fields[1].1.as_str(), Some("web"));
+    assert_eq!(fields[0].1.as_str(), None);
+}
+
+#[test]
+fn rejects_broken_messages() {
+    assert_eq!(
+        decode(&[0x12, 0x05, b'w']),
+        Err("message is truncated".to_string())
+    );
+    assert_eq!(
+        decode(&[0x08, 0x96]),
+        Err("message is truncated".to_string())
+    );
+    assert_eq!(
+        decode(&[0x0b]),
+        Err("unsupported wire type 3 of field 1".to_string())
+    );
+}
@@ -0,0 +1,49 @@
+mod common;
+
+use common::MockServer;
+use dynsix::{
+    http_client::HttpConfig,
+    ip::{get_public_ip, query_client},
+};
+
+fn http_config(proxy: &str, no_proxy: &[&str]) -> HttpConfig {
+    HttpConfig {
+        proxy: Some(proxy.to_string()),
+        no_proxy: no_proxy.iter().map(|host| host.to_string()).collect(),
+        ..Default::default()
+    }
+}
+
+#[tokio::test]
+async fn queries_through_the_proxy() {
+    let proxy = MockServer::start().await;
+    proxy.route(
+        "GET",
+        "http://ip.example.test/",
+        200,
+        r#"{"ip": "2001:db8::1"}"#,
+    );
+
+    let client = query_client(&http_config(proxy.url(), &[])).unwrap();
+    let ip = get_public_ip(&client, "http://ip.example.test/")
+        .await
+        .unwrap();
+
+    assert_eq!(ip, "2001:db8::1".parse::<std::net::Ipv6Addr>().unwrap());
+    assert_eq!(proxy.requests().len(), 1);
+}
+
+#[tokio::test]
+async fn no_proxy_hosts_are_reached_directly() {
+    let server = MockServer::start().await;
+    server.route("GET", "/", 200, r#"{"ip": "2001:db8::2"}"#);
+
+    // Nothing listens on the discard port, only a bypassed request succeeds
+    let client =
+        dynsix::http_client::client(&http_config("http://127.0.0.1:9", &["127.0.0.1"])).unwrap();
+    let ip = get_public_ip(&client, &format!("{}/", server.url()))
+        .await
+        .unwrap();
+
+    assert_eq!(ip, "2001:db8::2".parse::<std::net::Ipv6Addr>().unwrap());
+}
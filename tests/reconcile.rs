@@ -0,0 +1,827 @@
+mod common;
+
+use std::{
+    collections::HashMap,
+    net::Ipv6Addr,
+    process::ExitCode,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use common::MockServer;
+use dynsix::{
+    gandi,
+    plan::{Plan, PlannedAction},
+    provider::ProviderKind,
+    reconcile::{Compare, ConcurrencyConfig},
+    report::Action,
+    state::{ManagedRecord, State},
+    ttl, Reconciler, ServiceConfig,
+};
+
+const RECORD_PATH: &str = "/livedns/domains/example.com/records/www/AAAA";
+const NOT_FOUND: &str = r#"{"code": 404, "message": "Record not found", "object": "HTTPNotFound", "cause": "Not Found"}"#;
+// Gandi answers both POST and PUT with this message
+const CREATED: &str = r#"{"message": "DNS Record Created"}"#;
+
+fn service() -> ServiceConfig {
+    toml::from_str(
+        r#"
+        suffix = "::1:2:3:4"
+        name = "www"
+        fqdn = "example.com"
+        ttl = 600
+        "#,
+    )
+    .unwrap()
+}
+
+fn public_ip() -> Ipv6Addr {
+    "2001:db8:aa:bb::1".parse().unwrap()
+}
+
+fn reconciler(server: &MockServer) -> Reconciler {
+    Reconciler::new(gandi::Client::with_base_url(
+        reqwest::Client::new(),
+        "secret-token",
+        server.url(),
+    ))
+}
+
+#[tokio::test]
+async fn creates_missing_record() {
+    let server = MockServer::start().await;
+    server.route("GET", RECORD_PATH, 404, NOT_FOUND);
+    server.route("POST", RECORD_PATH, 201, CREATED);
+
+    let report = reconciler(&server)
+        .reconcile_service("web", &service(), public_ip())
+        .await;
+
+    assert_eq!(report.action, Action::Created);
+    assert_eq!(report.error, None);
+    let posts = server.requests_to("POST");
+    assert_eq!(posts.len(), 1);
+    assert_eq!(
+        posts[0].header("Authorization"),
+        Some("ApiKey secret-token")
+    );
+    let body: serde_json::Value = serde_json::from_str(&posts[0].body).unwrap();
+    assert_eq!(
+        body,
+        serde_json::json!({"rrset_values": ["2001:db8:aa:bb:1:2:3:4"], "rrset_ttl": 600})
+    );
+}
+
+#[tokio::test]
+async fn updates_differing_record() {
+    let server = MockServer::start().await;
+    server.route(
+        "GET",
+        RECORD_PATH,
+        200,
+        r#"{"rrset_values": ["2001:db8:ff:ff:1:2:3:4"], "rrset_ttl": 600}"#,
+    );
+    server.route("PUT", RECORD_PATH, 201, CREATED);
+
+    let report = reconciler(&server)
+        .reconcile_service("web", &service(), public_ip())
+        .await;
+
+    assert_eq!(report.action, Action::Updated);
+    assert_eq!(report.old, Some(vec!["2001:db8:ff:ff:1:2:3:4".to_string()]));
+    assert_eq!(server.requests_to("PUT").len(), 1);
+}
+
+#[tokio::test]
+async fn leaves_correct_record_alone() {
+    let server = MockServer::start().await;
+    server.route(
+        "GET",
+        RECORD_PATH,
+        200,
+        r#"{"rrset_values": ["2001:db8:aa:bb:1:2:3:4"], "rrset_ttl": 600}"#,
+    );
+
+    let report = reconciler(&server)
+        .reconcile_service("web", &service(), public_ip())
+        .await;
+
+    assert_eq!(report.action, Action::Unchanged);
+    assert_eq!(server.requests().len(), 1);
+}
+
+#[tokio::test]
+async fn reports_api_errors() {
+    let server = MockServer::start().await;
+    server.route(
+        "GET",
+        RECORD_PATH,
+        403,
+        r#"{"code": 403, "message": "Access was denied to this resource.", "object": "HTTPForbidden", "cause": "Forbidden"}"#,
+    );
+
+    let report = reconciler(&server)
+        .reconcile_service("web", &service(), public_ip())
+        .await;
+
+    assert_eq!(report.action, Action::Failed);
+    assert!(report
+        .error
+        .unwrap()
+        .contains("domain example.com is not accessible to the Gandi token"));
+    assert!(server.requests_to("PUT").is_empty());
+    assert!(server.requests_to("POST").is_empty());
+}
+
+#[tokio::test]
+async fn explains_rejected_tokens() {
+    let cases = [
+        (
+            401,
+            r#"{"code": 401, "message": "The server could not verify that you are authorized to access the URL requested.", "object": "HTTPUnauthorized", "cause": "Unauthorized"}"#,
+            "Gandi rejected the token while fetching: it is invalid, expired or revoked",
+        ),
+        (
+            403,
+            r#"{"code": 403, "message": "The token does not have the required permission", "object": "HTTPForbidden", "cause": "Forbidden"}"#,
+            "the Gandi token lacks the LiveDNS scope needed for fetching",
+        ),
+    ];
+
+    for (status, body, expected) in cases {
+        let server = MockServer::start().await;
+        server.route("GET", RECORD_PATH, status, body);
+
+        let report = reconciler(&server)
+            .reconcile_service("web", &service(), public_ip())
+            .await;
+
+        assert_eq!(report.action, Action::Failed);
+        let error = report.error.unwrap();
+        assert!(error.contains(expected), "{error}");
+    }
+}
+
+#[tokio::test]
+async fn reports_failed_creation() {
+    let server = MockServer::start().await;
+    server.route("GET", RECORD_PATH, 404, NOT_FOUND);
+    server.route(
+        "POST",
+        RECORD_PATH,
+        400,
+        r#"{"code": 400, "message": "Bad Request", "object": "HTTPBadRequest", "cause": "rrset_ttl too low"}"#,
+    );
+
+    let report = reconciler(&server)
+        .reconcile_service("web", &service(), public_ip())
+        .await;
+
+    assert_eq!(report.action, Action::Failed);
+}
+
+#[tokio::test]
+async fn reconciles_every_name_of_a_service() {
+    let server = MockServer::start().await;
+    let home = "/livedns/domains/example.com/records/home/AAAA";
+    server.route(
+        "GET",
+        RECORD_PATH,
+        200,
+        r#"{"rrset_values": ["2001:db8:aa:bb:1:2:3:4"], "rrset_ttl": 600}"#,
+    );
+    server.route("GET", home, 404, NOT_FOUND);
+    server.route("POST", home, 201, CREATED);
+
+    let service: ServiceConfig = toml::from_str(
+        r#"
+        suffix = "::1:2:3:4"
+        names = ["www", "home"]
+        fqdn = "example.com"
+        ttl = 600
+        "#,
+    )
+    .unwrap();
+    let report = reconciler(&server)
+        .reconcile_service("web", &service, public_ip())
+        .await;
+
+    assert_eq!(report.action, Action::Created);
+    assert_eq!(report.error, None);
+    let posts = server.requests_to("POST");
+    assert_eq!(posts.len(), 1);
+    assert_eq!(posts[0].path, home);
+    assert!(server.requests_to("PUT").is_empty());
+}
+
+#[tokio::test]
+async fn escapes_wildcard_names() {
+    let server = MockServer::start().await;
+    let wildcard = "/livedns/domains/example.com/records/%2A.lab/AAAA";
+    server.route("GET", wildcard, 404, NOT_FOUND);
+    server.route("POST", wildcard, 201, CREATED);
+
+    let service = ServiceConfig {
+        name: "*.lab".to_string(),
+        ..service()
+    };
+    let report = reconciler(&server)
+        .reconcile_service("lab", &service, public_ip())
+        .await;
+
+    assert_eq!(report.action, Action::Created);
+    assert_eq!(report.record, "*.lab.example.com");
+}
+
+#[tokio::test]
+async fn keeps_the_dots_of_nested_names() {
+    let server = MockServer::start().await;
+    let nested = "/livedns/domains/example.com/records/host.internal/AAAA";
+    server.route("GET", nested, 404, NOT_FOUND);
+    server.route("POST", nested, 201, CREATED);
+
+    let service = ServiceConfig {
+        name: "host.internal".to_string(),
+        ..service()
+    };
+    let report = reconciler(&server)
+        .reconcile_service("nested", &service, public_ip())
+        .await;
+
+    assert_eq!(report.action, Action::Created);
+    assert_eq!(report.record, "host.internal.example.com");
+}
+
+#[tokio::test]
+async fn reports_apex_as_the_domain() {
+    let server = MockServer::start().await;
+    server.route(
+        "GET",
+        "/livedns/domains/example.com/records/@/AAAA",
+        200,
+        r#"{"rrset_values": ["2001:db8:aa:bb:1:2:3:4"], "rrset_ttl": 600}"#,
+    );
+
+    let service = ServiceConfig {
+        name: "@".to_string(),
+        ..service()
+    };
+    let report = reconciler(&server)
+        .reconcile_service("apex", &service, public_ip())
+        .await;
+
+    assert_eq!(report.action, Action::Unchanged);
+    assert_eq!(report.record, "example.com");
+}
+
+#[tokio::test]
+async fn uses_punycode_for_internationalized_names() {
+    let server = MockServer::start().await;
+    server.route(
+        "GET",
+        "/livedns/domains/xn--bcher-kva.example/records/xn--caf-dma/AAAA",
+        200,
+        r#"{"rrset_values": ["2001:db8:aa:bb:1:2:3:4"], "rrset_ttl": 600}"#,
+    );
+
+    let service = ServiceConfig {
+        name: "café".to_string(),
+        fqdn: "bücher.example".to_string(),
+        ..service()
+    };
+    let report = reconciler(&server)
+        .reconcile_service("books", &service, public_ip())
+        .await;
+
+    assert_eq!(report.action, Action::Unchanged);
+    assert_eq!(report.record, "café.bücher.example");
+}
+
+#[tokio::test]
+async fn compares_values_as_addresses() {
+    let server = MockServer::start().await;
+    server.route(
+        "GET",
+        RECORD_PATH,
+        200,
+        r#"{"rrset_values": ["2001:0DB8:00aa:bb:1:2:3:4"], "rrset_ttl": 600}"#,
+    );
+
+    let report = reconciler(&server)
+        .reconcile_service("web", &service(), public_ip())
+        .await;
+
+    assert_eq!(report.action, Action::Unchanged);
+}
+
+#[tokio::test]
+async fn rewrites_values_that_are_not_addresses() {
+    let server = MockServer::start().await;
+    server.route(
+        "GET",
+        RECORD_PATH,
+        200,
+        r#"{"rrset_values": ["garbage"], "rrset_ttl": 600}"#,
+    );
+    server.route("PUT", RECORD_PATH, 201, CREATED);
+
+    let report = reconciler(&server)
+        .reconcile_service("web", &service(), public_ip())
+        .await;
+
+    assert_eq!(report.action, Action::Updated);
+    assert_eq!(report.error, None);
+}
+
+#[tokio::test]
+async fn compares_the_whole_rrset() {
+    let server = MockServer::start().await;
+    server.route(
+        "GET",
+        RECORD_PATH,
+        200,
+        r#"{"rrset_values": ["2001:db8::99", "2001:db8:aa:bb:1:2:3:4"], "rrset_ttl": 600}"#,
+    );
+    server.route("PUT", RECORD_PATH, 201, CREATED);
+
+    // Exact by default, the extra value is removed
+    let report = reconciler(&server)
+        .reconcile_service("web", &service(), public_ip())
+        .await;
+    assert_eq!(report.action, Action::Updated);
+
+    let service = ServiceConfig {
+        compare: Compare::Contains,
+        ..service()
+    };
+    let report = reconciler(&server)
+        .reconcile_service("web", &service, public_ip())
+        .await;
+    assert_eq!(report.action, Action::Unchanged);
+    assert_eq!(server.requests_to("PUT").len(), 1);
+}
+
+#[tokio::test]
+async fn defers_changes_in_the_quiet_hours() {
+    let server = MockServer::start().await;
+    server.route(
+        "GET",
+        RECORD_PATH,
+        200,
+        r#"{"rrset_values": ["2001:db8:ff:ff:1:2:3:4"], "rrset_ttl": 600}"#,
+    );
+    let always_quiet = ServiceConfig {
+        quiet_hours: Some("* * * * *".parse().unwrap()),
+        ..service()
+    };
+
+    let report = reconciler(&server)
+        .reconcile_service("web", &always_quiet, public_ip())
+        .await;
+
+    assert_eq!(report.action, Action::Deferred);
+    assert!(server.requests_to("PUT").is_empty());
+}
+
+#[tokio::test]
+async fn fixes_missing_and_broken_records_in_the_quiet_hours() {
+    let server = MockServer::start().await;
+    server.route("GET", RECORD_PATH, 404, NOT_FOUND);
+    server.route("POST", RECORD_PATH, 201, CREATED);
+    server.route("PUT", RECORD_PATH, 201, CREATED);
+    let always_quiet = ServiceConfig {
+        quiet_hours: Some("* * * * *".parse().unwrap()),
+        ..service()
+    };
+
+    let missing = reconciler(&server)
+        .reconcile_service("web", &always_quiet, public_ip())
+        .await;
+    server.route(
+        "GET",
+        RECORD_PATH,
+        200,
+        r#"{"rrset_values": ["not an address"], "rrset_ttl": 600}"#,
+    );
+    let broken = reconciler(&server)
+        .reconcile_service("web", &always_quiet, public_ip())
+        .await;
+
+    assert_eq!(missing.action, Action::Created);
+    assert_eq!(broken.action, Action::Updated);
+}
+
+#[tokio::test]
+async fn plans_the_ttl_and_quiet_hours_like_a_run() {
+    let server = MockServer::start().await;
+    server.route(
+        "GET",
+        "/livedns/domains/example.com/records",
+        200,
+        r#"[{"rrset_name": "www", "rrset_type": "AAAA", "rrset_ttl": 600, "rrset_values": ["2001:db8:aa:bb:1:2:3:4"]}]"#,
+    );
+    let low_ttl = ServiceConfig {
+        low_ttl: Some(toml::from_str("ttl = 300").unwrap()),
+        ..service()
+    };
+    let mut services = HashMap::from([("web".to_string(), low_ttl)]);
+    let mut state = State::default();
+    state.address_changed.insert(
+        "web".to_string(),
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs(),
+    );
+    ttl::lower(&mut services, &state, SystemTime::now());
+
+    let plan = reconciler(&server)
+        .plan(&services, public_ip(), |_| true)
+        .await;
+    assert_eq!(plan.changes[0].action, PlannedAction::Update);
+    assert_eq!(plan.changes[0].ttl, 300);
+
+    services.get_mut("web").unwrap().quiet_hours = Some("* * * * *".parse().unwrap());
+    let plan = reconciler(&server)
+        .plan(&services, public_ip(), |_| true)
+        .await;
+    assert_eq!(plan.changes[0].action, PlannedAction::Defer);
+    let report = reconciler(&server).apply(plan, |_| true).await.unwrap();
+    assert_eq!(report.services[0].action, Action::Deferred);
+    assert!(server.requests_to("PUT").is_empty());
+}
+
+#[tokio::test]
+async fn skips_disabled_services() {
+    let server = MockServer::start().await;
+    let disabled = ServiceConfig {
+        enabled: false,
+        ..service()
+    };
+    let services = HashMap::from([("web".to_string(), disabled)]);
+
+    let report = reconciler(&server)
+        .reconcile(&services, public_ip(), |_| true)
+        .await;
+
+    assert_eq!(report.services.len(), 1);
+    assert_eq!(report.services[0].action, Action::Skipped);
+    assert!(server.requests().is_empty());
+    assert_eq!(report.exit_code(), ExitCode::SUCCESS);
+}
+
+#[tokio::test]
+async fn applies_a_saved_plan() {
+    let server = MockServer::start().await;
+    server.route(
+        "GET",
+        RECORD_PATH,
+        200,
+        r#"{"rrset_values": ["2001:db8:aa:bb::99"], "rrset_ttl": 600}"#,
+    );
+    server.route("PUT", RECORD_PATH, 201, CREATED);
+    let services = HashMap::from([("web".to_string(), service())]);
+    let plan = reconciler(&server)
+        .plan(&services, public_ip(), |_| true)
+        .await;
+    let path = std::env::temp_dir().join(format!("dynsix-plan-{}.json", std::process::id()));
+    plan.save(&path).unwrap();
+
+    // Applied later by another reconciler, without the services
+    let plan = Plan::load(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    let report = reconciler(&server).apply(plan, |_| true).await.unwrap();
+
+    assert_eq!(report.services[0].action, Action::Updated);
+    assert_eq!(server.requests_to("PUT").len(), 1);
+}
+
+#[tokio::test]
+async fn refuses_plans_of_records_changed_since() {
+    let server = MockServer::start().await;
+    server.route(
+        "GET",
+        RECORD_PATH,
+        200,
+        r#"{"rrset_values": ["2001:db8:aa:bb::99"], "rrset_ttl": 600}"#,
+    );
+    let services = HashMap::from([("web".to_string(), service())]);
+    let reconciler = reconciler(&server);
+    let plan = reconciler.plan(&services, public_ip(), |_| true).await;
+
+    // Another updater moves the record before the plan is applied
+    server.route(
+        "GET",
+        RECORD_PATH,
+        200,
+        r#"{"rrset_values": ["2001:db8:cc:dd::99"], "rrset_ttl": 600}"#,
+    );
+    let error = reconciler.apply(plan, |_| true).await.unwrap_err();
+
+    assert_eq!(
+        error.to_string(),
+        "the plan is out of date, www.example.com changed since it was made; make a new plan"
+    );
+    assert!(server.requests_to("PUT").is_empty());
+}
+
+#[tokio::test]
+async fn compares_plans_and_races_as_addresses() {
+    let server = MockServer::start().await;
+    server.route(
+        "GET",
+        RECORD_PATH,
+        200,
+        r#"{"rrset_values": ["2001:db8:aa:bb::99"], "rrset_ttl": 600}"#,
+    );
+    server.route("PUT", RECORD_PATH, 201, CREATED);
+    let services = HashMap::from([("web".to_string(), service())]);
+    let reconciler = reconciler(&server).with_race_check(true);
+    let plan = reconciler.plan(&services, public_ip(), |_| true).await;
+
+    // The same address, written another way
+    server.route(
+        "GET",
+        RECORD_PATH,
+        200,
+        r#"{"rrset_values": ["2001:DB8:AA:BB:0:0:0:99 "], "rrset_ttl": 600}"#,
+    );
+    let report = reconciler.apply(plan, |_| true).await.unwrap();
+
+    assert_eq!(report.services[0].action, Action::Updated);
+    assert_eq!(server.requests_to("PUT").len(), 1);
+}
+
+#[tokio::test]
+async fn undoes_the_changes_to_a_domain_when_a_service_fails() {
+    let server = MockServer::start().await;
+    let mail = "/livedns/domains/example.com/records/mail/AAAA";
+    let old = r#"{"rrset_values": ["2001:db8:aa:bb::99"], "rrset_ttl": 900}"#;
+    server.route("GET", RECORD_PATH, 200, old);
+    server.route("PUT", RECORD_PATH, 201, CREATED);
+    server.route("GET", mail, 200, old);
+    server.route(
+        "PUT",
+        mail,
+        500,
+        r#"{"code": 500, "message": "Internal Server Error", "object": "HTTPInternalServerError", "cause": "Internal Server Error"}"#,
+    );
+
+    let services = HashMap::from([
+        ("web".to_string(), service()),
+        (
+            "xmail".to_string(),
+            ServiceConfig {
+                name: "mail".to_string(),
+                ..service()
+            },
+        ),
+    ]);
+    let report = reconciler(&server)
+        .with_transactions(true)
+        .reconcile(&services, public_ip(), |_| true)
+        .await;
+
+    assert!(report
+        .services
+        .iter()
+        .all(|service| service.action == Action::Failed));
+    assert_eq!(
+        report.services[0].error.as_deref(),
+        Some("changes were undone as 'xmail' of example.com failed")
+    );
+    // www is written with the new address, then back with the old one
+    let puts: Vec<_> = server
+        .requests_to("PUT")
+        .into_iter()
+        .filter(|request| request.path == RECORD_PATH)
+        .collect();
+    assert_eq!(puts.len(), 2);
+    let restored: serde_json::Value = serde_json::from_str(&puts[1].body).unwrap();
+    assert_eq!(
+        restored,
+        serde_json::json!({ "rrset_values": ["2001:db8:aa:bb::99"], "rrset_ttl": 900 })
+    );
+}
+
+#[tokio::test]
+async fn reconciles_services_concurrently() {
+    let server = MockServer::start().await;
+    let mut services = HashMap::new();
+    for name in ["a", "b", "c", "d"] {
+        let path = format!("/livedns/domains/example.com/records/{name}/AAAA");
+        server.route("GET", &path, 404, NOT_FOUND);
+        server.route("POST", &path, 201, CREATED);
+        services.insert(
+            name.to_string(),
+            ServiceConfig {
+                name: name.to_string(),
+                ..service()
+            },
+        );
+    }
+    let concurrency: ConcurrencyConfig = toml::from_str(
+        r#"
+        max_concurrent_updates = 4
+        per_provider = { gandi = 2 }
+        "#,
+    )
+    .unwrap();
+
+    let report = reconciler(&server)
+        .with_concurrency(&concurrency)
+        .reconcile(&services, public_ip(), |_| true)
+        .await;
+
+    let reported: Vec<_> = report
+        .services
+        .iter()
+        .map(|service| (service.service.as_str(), service.action))
+        .collect();
+    assert_eq!(
+        reported,
+        [
+            ("a", Action::Created),
+            ("b", Action::Created),
+            ("c", Action::Created),
+            ("d", Action::Created),
+        ]
+    );
+    assert_eq!(server.requests_to("POST").len(), 4);
+}
+
+#[tokio::test]
+async fn carries_on_after_a_service_fails() {
+    let server = MockServer::start().await;
+    server.route("GET", RECORD_PATH, 404, NOT_FOUND);
+    server.route("POST", RECORD_PATH, 201, CREATED);
+    server.route(
+        "GET",
+        "/livedns/domains/example.com/records/mail/AAAA",
+        500,
+        r#"{"code": 500, "message": "Internal Server Error", "object": "HTTPInternalServerError", "cause": "Internal Server Error"}"#,
+    );
+
+    let services = HashMap::from([
+        ("web".to_string(), service()),
+        (
+            "mail".to_string(),
+            ServiceConfig {
+                name: "mail".to_string(),
+                ..service()
+            },
+        ),
+        (
+            "typo".to_string(),
+            ServiceConfig {
+                token_ref: Some("wrok".to_string()),
+                ..service()
+            },
+        ),
+    ]);
+    let reconciler = reconciler(&server).with_gandi_tokens(
+        &gandi::Client::new(reqwest::Client::new(), "secret-token"),
+        &HashMap::new(),
+        &services,
+    );
+
+    let report = reconciler.reconcile(&services, public_ip(), |_| true).await;
+    let reported: Vec<_> = report
+        .services
+        .iter()
+        .map(|service| (service.service.as_str(), service.action))
+        .collect();
+    assert_eq!(
+        reported,
+        [
+            ("mail", Action::Failed),
+            ("typo", Action::Failed),
+            ("web", Action::Created),
+        ]
+    );
+    assert_eq!(
+        report.services[1].error.as_deref(),
+        Some("service 'typo' can't be reconciled: token_ref 'wrok' is not in [tokens]")
+    );
+    // Listing the zone, which fails, then looking up and creating www and
+    // looking up mail
+    assert_eq!(
+        (
+            report.summary.created,
+            report.summary.failed,
+            report.summary.api_calls
+        ),
+        (1, 2, 4)
+    );
+
+    // Planning leaves out the services that fail, too
+    let plan = reconciler.plan(&services, public_ip(), |_| true).await;
+    assert_eq!(plan.changes.len(), 1);
+    let failed: Vec<_> = plan
+        .failed
+        .iter()
+        .map(|failure| failure.service.as_str())
+        .collect();
+    assert_eq!(failed, ["mail", "typo"]);
+}
+
+#[tokio::test]
+async fn lists_each_zone_once() {
+    let server = MockServer::start().await;
+    server.route(
+        "GET",
+        "/livedns/domains/example.com/records",
+        200,
+        r#"[
+            {"rrset_name": "www", "rrset_type": "AAAA", "rrset_ttl": 600, "rrset_values": ["2001:db8:aa:bb:1:2:3:4"]},
+            {"rrset_name": "mail", "rrset_type": "MX", "rrset_ttl": 600, "rrset_values": ["10 mail.example.com."]}
+        ]"#,
+    );
+    let mail = "/livedns/domains/example.com/records/mail/AAAA";
+    server.route("POST", mail, 201, CREATED);
+    let services = HashMap::from([
+        ("web".to_string(), service()),
+        (
+            "mail".to_string(),
+            ServiceConfig {
+                name: "mail".to_string(),
+                ..service()
+            },
+        ),
+    ]);
+
+    let report = reconciler(&server)
+        .reconcile(&services, public_ip(), |_| true)
+        .await;
+    let reported: Vec<_> = report
+        .services
+        .iter()
+        .map(|service| (service.service.as_str(), service.action))
+        .collect();
+    assert_eq!(
+        reported,
+        [("mail", Action::Created), ("web", Action::Unchanged)]
+    );
+    // Only the zone is read, the records aren't looked up one by one
+    let requests: Vec<_> = server
+        .requests()
+        .into_iter()
+        .map(|request| (request.method, request.path))
+        .collect();
+    assert_eq!(
+        requests,
+        [
+            (
+                "GET".to_string(),
+                "/livedns/domains/example.com/records".to_string()
+            ),
+            ("POST".to_string(), mail.to_string()),
+        ]
+    );
+    assert_eq!(report.summary.api_calls, 2);
+}
+
+#[tokio::test]
+async fn prunes_the_records_of_removed_services() {
+    let server = MockServer::start().await;
+    server.route(
+        "GET",
+        "/livedns/domains/example.com/records",
+        200,
+        r#"[
+            {"rrset_name": "www", "rrset_type": "AAAA", "rrset_ttl": 600, "rrset_values": ["2001:db8:aa:bb:1:2:3:4"]},
+            {"rrset_name": "ftp", "rrset_type": "AAAA", "rrset_ttl": 600, "rrset_values": ["2001:db8:aa:bb::21"]}
+        ]"#,
+    );
+    let ftp = "/livedns/domains/example.com/records/ftp/AAAA";
+    server.route("DELETE", ftp, 204, "");
+    let owned = |name: &str, service: &str| ManagedRecord {
+        provider: ProviderKind::Gandi,
+        fqdn: "example.com".to_string(),
+        name: name.to_string(),
+        service: service.to_string(),
+        address: None,
+    };
+    let services = HashMap::from([("web".to_string(), service())]);
+    let reconciler = reconciler(&server).with_pruning([owned("www", "web"), owned("ftp", "files")]);
+
+    let plan = reconciler.plan(&services, public_ip(), |_| true).await;
+    assert_eq!(plan.changes[0].action, PlannedAction::NoOp);
+    assert_eq!(plan.deletions.len(), 1);
+    assert_eq!(plan.deletions[0].name, "ftp");
+    assert_eq!(plan.deletions[0].current, ["2001:db8:aa:bb::21"]);
+    assert!(server.requests_to("DELETE").is_empty());
+
+    let report = reconciler.reconcile(&services, public_ip(), |_| true).await;
+    assert_eq!(report.deleted, [owned("ftp", "files")]);
+    assert_eq!(report.summary.deleted, 1);
+    let deletes = server.requests_to("DELETE");
+    assert_eq!(deletes.len(), 1);
+    assert_eq!(deletes[0].path, ftp);
+
+    // Records of services that aren't selected are kept
+    let report = reconciler
+        .reconcile(&services, public_ip(), |name| name == "web")
+        .await;
+    assert!(report.deleted.is_empty());
+}
@@ -0,0 +1,95 @@
+mod common;
+
+use std::{net::Ipv6Addr, time::Duration};
+
+use common::MockServer;
+use dynsix::{gandi, provider::Provider, retry::RetryPolicy};
+
+const RECORD_PATH: &str = "/livedns/domains/example.com/records/www/AAAA";
+const BAD_GATEWAY: &str = "<html>502 Bad Gateway</html>";
+const UPDATED: &str = r#"{"rrset_values": ["2001:db8::2"], "rrset_ttl": 300}"#;
+const OUTDATED: &str = r#"{"rrset_values": ["2001:db8::1"], "rrset_ttl": 300}"#;
+
+fn client(server: &MockServer) -> gandi::Client {
+    gandi::Client::with_base_url(reqwest::Client::new(), "secret-token", server.url()).with_retry(
+        RetryPolicy {
+            attempts: 3,
+            delay: Duration::from_millis(1),
+            max_delay: Duration::from_millis(5),
+        },
+    )
+}
+
+fn ip() -> Ipv6Addr {
+    "2001:db8::2".parse().unwrap()
+}
+
+#[tokio::test]
+async fn ambiguous_write_is_not_repeated_once_applied() {
+    let server = MockServer::start().await;
+    server.route("PUT", RECORD_PATH, 502, BAD_GATEWAY);
+    server.route("GET", RECORD_PATH, 200, UPDATED);
+
+    let result = Provider::update_record(&client(&server), "example.com", "www", 300, ip()).await;
+
+    assert!(result.is_ok(), "{result:?}");
+    assert_eq!(server.requests_to("PUT").len(), 1);
+    assert_eq!(server.requests_to("GET").len(), 1);
+}
+
+#[tokio::test]
+async fn ambiguous_write_is_repeated_after_reading_the_record() {
+    let server = MockServer::start().await;
+    server.route("PUT", RECORD_PATH, 502, BAD_GATEWAY);
+    server.route("GET", RECORD_PATH, 200, OUTDATED);
+
+    let result = Provider::update_record(&client(&server), "example.com", "www", 300, ip()).await;
+
+    assert!(result.is_err());
+    assert_eq!(server.requests_to("PUT").len(), 3);
+    assert_eq!(server.requests_to("GET").len(), 2);
+}
+
+#[tokio::test]
+async fn refused_write_is_repeated_without_reading() {
+    let server = MockServer::start().await;
+    server.route(
+        "PUT",
+        RECORD_PATH,
+        429,
+        r#"{"code": 429, "object": "HTTPTooManyRequests", "cause": "Too Many Requests", "message": "Rate limited"}"#,
+    );
+
+    let result = Provider::update_record(&client(&server), "example.com", "www", 300, ip()).await;
+
+    assert!(result.is_err());
+    assert_eq!(server.requests_to("PUT").len(), 3);
+    assert!(server.requests_to("GET").is_empty());
+}
+
+#[tokio::test]
+async fn client_errors_are_not_retried() {
+    let server = MockServer::start().await;
+    server.route(
+        "PUT",
+        RECORD_PATH,
+        403,
+        r#"{"code": 403, "object": "HTTPForbidden", "cause": "Forbidden", "message": "Access was denied"}"#,
+    );
+
+    let result = Provider::update_record(&client(&server), "example.com", "www", 300, ip()).await;
+
+    assert!(result.is_err());
+    assert_eq!(server.requests().len(), 1);
+}
+
+#[test]
+fn delay_doubles_up_to_the_limit() {
+    let policy = RetryPolicy {
+        attempts: 10,
+        delay: Duration::from_secs(1),
+        max_delay: Duration::from_secs(5),
+    };
+    let delays: Vec<u64> = (1..=5).map(|retry| policy.delay(retry).as_secs()).collect();
+    assert_eq!(delays, [1, 2, 4, 5, 5]);
+}
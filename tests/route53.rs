@@ -0,0 +1,95 @@
+mod common;
+
+use std::net::Ipv6Addr;
+
+use common::MockServer;
+use dynsix::{gandi, provider::ProvidersConfig, report::Action, Reconciler, ServiceConfig};
+
+const ZONES_PATH: &str = "/2013-04-01/hostedzonesbyname?dnsname=example.com";
+const RRSET_PATH: &str = "/2013-04-01/hostedzone/Z1/rrset";
+const ZONES: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<ListHostedZonesByNameResponse><HostedZones><HostedZone><Id>/hostedzone/Z1</Id><Name>example.com.</Name><Config><PrivateZone>false</PrivateZone></Config></HostedZone></HostedZones></ListHostedZonesByNameResponse>"#;
+const CHANGED: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<ChangeResourceRecordSetsResponse><ChangeInfo><Id>/change/C1</Id><Status>PENDING</Status></ChangeInfo></ChangeResourceRecordSetsResponse>"#;
+
+fn service() -> ServiceConfig {
+    toml::from_str(
+        r#"
+        suffix = "::1:2:3:4"
+        name = "www"
+        fqdn = "example.com"
+        ttl = 600
+        provider = "route53"
+        "#,
+    )
+    .unwrap()
+}
+
+fn public_ip() -> Ipv6Addr {
+    "2001:db8:aa:bb::1".parse().unwrap()
+}
+
+fn reconciler(server: &MockServer) -> Reconciler {
+    let providers: ProvidersConfig = toml::from_str(&format!(
+        r#"
+        [route53]
+        access_key_id = "AKIDEXAMPLE"
+        secret_access_key = "secret"
+        endpoint = "{}"
+        "#,
+        server.url()
+    ))
+    .unwrap();
+    Reconciler::new(gandi::Client::new(reqwest::Client::new(), "unused"))
+        .with_providers(reqwest::Client::new(), &providers)
+}
+
+#[tokio::test]
+async fn upserts_missing_record() {
+    let server = MockServer::start().await;
+    server.route("GET", ZONES_PATH, 200, ZONES);
+    server.route(
+        "GET",
+        &format!("{RRSET_PATH}?maxitems=1&name=www.example.com&type=AAAA"),
+        200,
+        "<ListResourceRecordSetsResponse><ResourceRecordSets></ResourceRecordSets></ListResourceRecordSetsResponse>",
+    );
+    server.route("POST", RRSET_PATH, 200, CHANGED);
+
+    let report = reconciler(&server)
+        .reconcile_service("web", &service(), public_ip())
+        .await;
+
+    assert_eq!(report.error, None);
+    assert_eq!(report.action, Action::Created);
+    let posts = server.requests_to("POST");
+    assert_eq!(posts.len(), 1);
+    assert!(posts[0]
+        .header("Authorization")
+        .unwrap()
+        .starts_with("AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/"));
+    assert!(posts[0].body.contains("<Action>UPSERT</Action>"));
+    assert!(posts[0]
+        .body
+        .contains("<Value>2001:db8:aa:bb:1:2:3:4</Value>"));
+}
+
+#[tokio::test]
+async fn leaves_matching_record_alone() {
+    let server = MockServer::start().await;
+    server.route("GET", ZONES_PATH, 200, ZONES);
+    server.route(
+        "GET",
+        &format!("{RRSET_PATH}?maxitems=1&name=www.example.com&type=AAAA"),
+        200,
+        "<ListResourceRecordSetsResponse><ResourceRecordSets><ResourceRecordSet><Name>www.example.com.</Name><Type>AAAA</Type><TTL>600</TTL><ResourceRecords><ResourceRecord><Value>2001:db8:aa:bb:1:2:3:4</Value></ResourceRecord></ResourceRecords></ResourceRecordSet></ResourceRecordSets></ListResourceRecordSetsResponse>",
+    );
+
+    let report = reconciler(&server)
+        .reconcile_service("web", &service(), public_ip())
+        .await;
+
+    assert_eq!(report.error, None);
+    assert_eq!(report.action, Action::Unchanged);
+    assert!(server.requests_to("POST").is_empty());
+}
@@ -0,0 +1,110 @@
+use std::{
+    collections::{HashMap, HashSet},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use dynsix::{
+    schedule::{is_due, Schedule},
+    ServiceConfig,
+};
+
+/// 2023-01-20T12:34:56Z, a Friday
+fn friday() -> SystemTime {
+    UNIX_EPOCH + Duration::from_secs(1_674_218_096)
+}
+
+fn next(expression: &str) -> u64 {
+    let schedule: Schedule = expression.parse().unwrap();
+    schedule
+        .next_after(friday())
+        .unwrap()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+#[test]
+fn finds_next_matching_minute() {
+    // 12:35
+    assert_eq!(next("* * * * *"), 1_674_218_100);
+    // 12:35, the next multiple of 5
+    assert_eq!(next("*/5 * * * *"), 1_674_218_100);
+    // 13:00
+    assert_eq!(next("@hourly"), 1_674_219_600);
+    // Sunday 2023-01-22 00:00
+    assert_eq!(next("0 0 * * 7"), 1_674_345_600);
+    // 2023-02-01 03:15
+    assert_eq!(next("15 3 1 * *"), 1_675_221_300);
+}
+
+#[test]
+fn day_of_month_or_day_of_week() {
+    // Saturday 2023-01-21 matches the day of week before the 1st of February
+    assert_eq!(next("0 0 1 * 6"), 1_674_259_200);
+}
+
+#[test]
+fn rejects_invalid_expressions() {
+    for expression in [
+        "* * * *",
+        "60 * * * *",
+        "* * 0 * *",
+        "*/0 * * * *",
+        "5-1 * * * *",
+    ] {
+        assert!(expression.parse::<Schedule>().is_err(), "{expression}");
+    }
+
+    let never: Schedule = "0 0 30 2 *".parse().unwrap();
+    assert_eq!(never.next_after(friday()), None);
+}
+
+#[test]
+fn windows_end_at_the_first_minute_outside() {
+    let business_hours: Schedule = "* 8-17 * * 1-5".parse().unwrap();
+    let weekend: Schedule = "* * * * 0,6".parse().unwrap();
+
+    assert!(business_hours.contains(friday()));
+    assert!(!weekend.contains(friday()));
+    // 18:00 the same day
+    let end = business_hours.end_after(friday()).unwrap();
+    assert_eq!(
+        end.duration_since(UNIX_EPOCH).unwrap().as_secs(),
+        1_674_237_600
+    );
+    assert!(!business_hours.contains(end));
+    assert_eq!(
+        "* * * * *".parse::<Schedule>().unwrap().end_after(friday()),
+        None
+    );
+}
+
+#[test]
+fn discovered_records_follow_the_interval() {
+    let service = |extra: &str| -> ServiceConfig {
+        toml::from_str(&format!(
+            r#"
+            suffix = "::1"
+            name = "www"
+            fqdn = "example.com"
+            ttl = 600
+            {extra}
+            "#
+        ))
+        .unwrap()
+    };
+    let services = HashMap::from([
+        ("web".to_string(), service("")),
+        ("nightly".to_string(), service(r#"schedule = "@daily""#)),
+    ]);
+    let nothing = HashSet::new();
+
+    // Configured and discovered services without a schedule of their own
+    assert!(is_due("web", &services, &nothing, true));
+    assert!(is_due("discovered-ftp", &services, &nothing, true));
+    assert!(!is_due("discovered-ftp", &services, &nothing, false));
+    // Services with a schedule only when it comes up
+    assert!(!is_due("nightly", &services, &nothing, true));
+    let nightly = HashSet::from(["nightly".to_string()]);
+    assert!(is_due("nightly", &services, &nightly, false));
+}
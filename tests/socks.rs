@@ -0,0 +1,80 @@
+mod common;
+
+use common::MockServer;
+use dynsix::{http_client::HttpConfig, ip::get_public_ip};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+
+/// A SOCKS5 server accepting `user`/`secret`, which connects every domain
+/// to localhost and records the domains asked for
+async fn socks_server() -> (String, tokio::sync::mpsc::UnboundedReceiver<String>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+    let (domains, received) = tokio::sync::mpsc::unbounded_channel();
+    tokio::spawn(async move {
+        while let Ok((mut client, _)) = listener.accept().await {
+            let domains = domains.clone();
+            tokio::spawn(async move {
+                let mut greeting = [0; 2];
+                client.read_exact(&mut greeting).await.unwrap();
+                let mut methods = vec![0; greeting[1] as usize];
+                client.read_exact(&mut methods).await.unwrap();
+                assert!(methods.contains(&2));
+                client.write_all(&[5, 2]).await.unwrap();
+
+                let mut field = [0; 2];
+                client.read_exact(&mut field).await.unwrap();
+                let mut username = vec![0; field[1] as usize];
+                client.read_exact(&mut username).await.unwrap();
+                let mut length = [0; 1];
+                client.read_exact(&mut length).await.unwrap();
+                let mut password = vec![0; length[0] as usize];
+                client.read_exact(&mut password).await.unwrap();
+                let accepted = username == b"user" && password == b"secret";
+                client.write_all(&[1, u8::from(!accepted)]).await.unwrap();
+
+                let mut request = [0; 5];
+                client.read_exact(&mut request).await.unwrap();
+                assert_eq!(request[3], 3, "expected a domain");
+                let mut domain = vec![0; request[4] as usize];
+                client.read_exact(&mut domain).await.unwrap();
+                let mut port = [0; 2];
+                client.read_exact(&mut port).await.unwrap();
+                domains.send(String::from_utf8(domain).unwrap()).unwrap();
+
+                let mut upstream = TcpStream::connect(("127.0.0.1", u16::from_be_bytes(port)))
+                    .await
+                    .unwrap();
+                client
+                    .write_all(&[5, 0, 0, 1, 127, 0, 0, 1, 0, 0])
+                    .await
+                    .unwrap();
+                let _ = tokio::io::copy_bidirectional(&mut client, &mut upstream).await;
+            });
+        }
+    });
+    (address, received)
+}
+
+#[tokio::test]
+async fn queries_through_socks5() {
+    let server = MockServer::start().await;
+    server.route("GET", "/", 200, r#"{"ip": "2001:db8::3"}"#);
+    let port = server.url().rsplit(':').next().unwrap().to_string();
+    let (socks, mut domains) = socks_server().await;
+
+    let client = dynsix::http_client::client(&HttpConfig {
+        proxy: Some(format!("socks5h://user:secret@{socks}")),
+        ..Default::default()
+    })
+    .unwrap();
+    let ip = get_public_ip(&client, &format!("http://ip.example.test:{port}/"))
+        .await
+        .unwrap();
+
+    assert_eq!(ip, "2001:db8::3".parse::<std::net::Ipv6Addr>().unwrap());
+    assert_eq!(domains.recv().await.unwrap(), "ip.example.test");
+    assert!(server.requests()[0].header("proxy-authorization").is_none());
+}
@@ -0,0 +1,56 @@
+use std::os::unix::fs::PermissionsExt;
+
+use dynsix::{sops, Config, DynsixError};
+
+const ENCRYPTED: &str = r#"token: ENC[AES256_GCM,data:aGVsbG8=,iv:aXY=,tag:dGFn,type:str]
+sops:
+    age:
+        - recipient: age1example
+    mac: ENC[AES256_GCM,data:bWFj,iv:aXY=,tag:dGFn,type:str]
+    version: 3.8.1
+"#;
+
+#[test]
+fn detects_encrypted_configs() {
+    assert!(sops::is_encrypted(ENCRYPTED.as_bytes()));
+    assert!(sops::is_encrypted(
+        br#"{"data": "ENC[AES256_GCM,data:dG9rZW4=,iv:aXY=,tag:dGFn,type:str]", "sops": {"version": "3.8.1"}}"#
+    ));
+    assert!(!sops::is_encrypted(b"token: plain\n"));
+    assert!(!sops::is_encrypted(b"# sops: not used\ntoken: plain\n"));
+}
+
+#[test]
+fn decrypts_with_the_sops_binary() {
+    let dir = std::env::temp_dir().join(format!("dynsix-sops-test-{}", std::process::id()));
+    let bin = dir.join("bin");
+    std::fs::create_dir_all(&bin).unwrap();
+    let path = dir.join("config.yaml");
+    std::fs::write(&path, ENCRYPTED).unwrap();
+
+    // Stands in for sops, which is not installed where the tests run
+    let fake = bin.join("sops");
+    std::fs::write(
+        &fake,
+        "#!/bin/sh\n[ \"$2\" = --input-type ] && [ \"$3\" = yaml ] || exit 2\necho 'token: decrypted'\n",
+    )
+    .unwrap();
+    std::fs::set_permissions(&fake, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+    let path_var = std::env::var_os("PATH").unwrap_or_default();
+    std::env::set_var("PATH", &bin);
+    let config = Config::load(&path);
+    std::fs::write(
+        &fake,
+        "#!/bin/sh\necho 'no key could decrypt it' >&2\nexit 128\n",
+    )
+    .unwrap();
+    let failed = Config::load(&path);
+    std::env::set_var("PATH", path_var);
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(config.unwrap().token.expose(), "decrypted");
+    let error = failed.unwrap_err();
+    assert!(matches!(error, DynsixError::Sops { .. }));
+    assert!(error.to_string().contains("no key could decrypt it"));
+}
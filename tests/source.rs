@@ -0,0 +1,88 @@
+use std::net::Ipv6Addr;
+
+use dynsix::{
+    ip::{read_prefix_file, Source},
+    Config,
+};
+
+#[test]
+fn reads_the_prefix_from_a_file() {
+    let dir = std::env::temp_dir().join(format!("dynsix-source-test-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("prefix");
+
+    let config: Config = toml::from_str(&format!(
+        "source = {{ file = {:?} }}",
+        path.display().to_string()
+    ))
+    .unwrap();
+    assert_eq!(config.source, Some(Source::File(path.clone())));
+
+    std::fs::write(&path, "2001:db8:1::/56\n").unwrap();
+    assert_eq!(
+        read_prefix_file(&path).unwrap(),
+        "2001:db8:1::".parse::<Ipv6Addr>().unwrap()
+    );
+
+    // Only the upper 64 bits are used, longer prefixes would lose bits
+    std::fs::write(&path, "2001:db8:1:2:3::/80").unwrap();
+    assert!(read_prefix_file(&path).is_err());
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn skips_temporary_and_deprecated_interface_addresses() {
+    let raw = "\
+fd000000000000000000000000000002 04 40 00 80     eth0
+20010db8000000010000000000000abc 04 40 00 01     eth0
+20010db8000000000000000000000001 04 40 00 20     eth0
+20010db800000001021122fffe334455 04 40 00 00     eth0
+20010db800000001000000000000000a 04 40 00 80     eth0
+fe8000000000000000fc00fffe000001 04 40 20 80     eth0
+20010db8000000020000000000000001 05 40 00 80     eth1
+";
+    let addresses = dynsix::ip::parse_if_inet6(raw);
+    assert_eq!(addresses.len(), 7);
+
+    // Manually configured addresses before SLAAC ones
+    assert_eq!(
+        dynsix::ip::select_address(&addresses, "eth0"),
+        Some("2001:db8:0:1::a".parse().unwrap())
+    );
+    assert_eq!(
+        dynsix::ip::select_address(&addresses[..4], "eth0"),
+        Some("2001:db8:0:1:211:22ff:fe33:4455".parse().unwrap())
+    );
+    assert_eq!(dynsix::ip::select_address(&addresses[..3], "eth0"), None);
+}
+
+#[test]
+fn notices_public_addresses_outside_the_local_prefixes() {
+    let addresses = |raw: &str| dynsix::ip::parse_if_inet6(raw);
+    let mismatch = |detected: &str, local: &str| {
+        dynsix::ip::prefix_mismatch(detected.parse().unwrap(), &addresses(local))
+    };
+    let local = "\
+20010db800000001021122fffe334455 04 40 00 00     eth0
+fe8000000000000002112233fffe4455 04 40 20 80     eth0
+";
+    assert_eq!(mismatch("2001:db8:0:1::99", local), None);
+    assert!(mismatch("2001:db8:0:2::99", local)
+        .unwrap()
+        .contains("no local interface"));
+    assert!(mismatch("2001:470:1f0b:1::2", local)
+        .unwrap()
+        .contains("Hurricane Electric"));
+    assert!(mismatch(
+        "2001:db8:0:1::99",
+        "fd000000000000010000000000000001 04 40 00 80     eth0\n"
+    )
+    .unwrap()
+    .contains("NAT66"));
+
+    let config: Config = toml::from_str("prefix_check = \"refuse\"").unwrap();
+    assert_eq!(config.prefix_check, dynsix::config::PrefixCheck::Refuse);
+    let config: Config = toml::from_str("").unwrap();
+    assert_eq!(config.prefix_check, dynsix::config::PrefixCheck::Warn);
+}
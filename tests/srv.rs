@@ -0,0 +1,78 @@
+mod common;
+
+use common::MockServer;
+use dynsix::{gandi, srv, Reconciler, ServiceConfig};
+
+const SRV_PATH: &str = "/livedns/domains/example.com/records/_sip._udp/SRV";
+const NOT_FOUND: &str = r#"{"code": 404, "message": "Record not found", "object": "HTTPNotFound", "cause": "Not Found"}"#;
+
+fn service() -> ServiceConfig {
+    toml::from_str(
+        r#"
+        suffix = "::1:2:3:4"
+        names = ["pbx", "voip"]
+        fqdn = "example.com"
+        ttl = 600
+        srv = [
+            { name = "_sip._udp", priority = 10, weight = 5, port = 5060 },
+            { name = "_sip._udp", priority = 20, port = 5060, target = "backup.example.net." },
+            { name = "_sips._tcp", port = 5061 },
+        ]
+        "#,
+    )
+    .unwrap()
+}
+
+#[test]
+fn points_at_the_first_record_of_the_service() {
+    let service = service();
+    let rrsets = srv::rrsets(&service);
+    assert_eq!(
+        rrsets.into_iter().collect::<Vec<_>>(),
+        [
+            (
+                "_sip._udp",
+                vec![
+                    "10 5 5060 pbx.example.com.".to_string(),
+                    "20 0 5060 backup.example.net.".to_string()
+                ]
+            ),
+            ("_sips._tcp", vec!["0 0 5061 pbx.example.com.".to_string()]),
+        ]
+    );
+
+    let wrong: srv::SrvRecord = toml::from_str("name = \"sip\"\nport = 5060").unwrap();
+    assert!(srv::problem(&wrong).is_some());
+    assert_eq!(srv::problem(&service.srv[0]), None);
+}
+
+#[tokio::test]
+async fn publishes_the_records_that_differ() {
+    let server = MockServer::start().await;
+    server.route("GET", SRV_PATH, 404, NOT_FOUND);
+    server.route("PUT", SRV_PATH, 201, r#"{"message": "DNS Record Created"}"#);
+    server.route(
+        "GET",
+        "/livedns/domains/example.com/records/_sips._tcp/SRV",
+        200,
+        r#"{"rrset_values": ["0 0 5061 pbx.example.com."], "rrset_ttl": 600}"#,
+    );
+    let reconciler = Reconciler::new(gandi::Client::with_base_url(
+        reqwest::Client::new(),
+        "secret-token",
+        server.url(),
+    ));
+
+    let changed = reconciler.publish_srv("pbx", &service()).await.unwrap();
+    assert_eq!(changed, ["_sip._udp.example.com"]);
+    let puts = server.requests_to("PUT");
+    assert_eq!(puts.len(), 1);
+    let body: serde_json::Value = serde_json::from_str(&puts[0].body).unwrap();
+    assert_eq!(
+        body["rrset_values"],
+        serde_json::json!([
+            "10 5 5060 pbx.example.com.",
+            "20 0 5060 backup.example.net."
+        ])
+    );
+}
@@ -0,0 +1,104 @@
+mod common;
+
+use common::MockServer;
+use dynsix::{gandi, provider::same_values, sshfp, Reconciler, ServiceConfig};
+
+const SSHFP_PATH: &str = "/livedns/domains/example.com/records/www/SSHFP";
+const NOT_FOUND: &str = r#"{"code": 404, "message": "Record not found", "object": "HTTPNotFound", "cause": "Not Found"}"#;
+const KEY: &str =
+    "ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAIDN3dee7eHql6yr5F/CNvVtmPzFtznbP9eVEW6GIVDoy root@host\n";
+/// As `ssh-keygen -r` prints it
+const VALUE: &str = "4 2 3ff9ab35cc9f156794bb167a846da1abb560a68ecfcfe7115b3187d033734b28";
+
+fn service(key: &std::path::Path) -> ServiceConfig {
+    toml::from_str(&format!(
+        r#"
+        suffix = "::1:2:3:4"
+        name = "www"
+        fqdn = "example.com"
+        ttl = 600
+        ssh_host_keys = [{:?}]
+        "#,
+        key.display().to_string()
+    ))
+    .unwrap()
+}
+
+fn reconciler(server: &MockServer) -> Reconciler {
+    Reconciler::new(gandi::Client::with_base_url(
+        reqwest::Client::new(),
+        "secret-token",
+        server.url(),
+    ))
+}
+
+fn key_file(test: &str) -> std::path::PathBuf {
+    let path = std::env::temp_dir().join(format!("dynsix-sshfp-{test}-{}.pub", std::process::id()));
+    std::fs::write(&path, KEY).unwrap();
+    path
+}
+
+#[test]
+fn fingerprints_public_keys() {
+    assert_eq!(sshfp::value(KEY).unwrap(), VALUE);
+    assert!(sshfp::value("ssh-foo AAAA").is_err());
+    assert!(sshfp::value("ssh-ed25519 not*base64").is_err());
+
+    assert!(same_values(
+        &[VALUE.to_uppercase().replace("4 2 ", "4  2 ")],
+        &[VALUE.to_string()]
+    ));
+    assert!(!same_values(&[], &[VALUE.to_string()]));
+}
+
+#[tokio::test]
+async fn publishes_missing_sshfp_records() {
+    let server = MockServer::start().await;
+    server.route("GET", SSHFP_PATH, 404, NOT_FOUND);
+    server.route(
+        "PUT",
+        SSHFP_PATH,
+        201,
+        r#"{"message": "DNS Record Created"}"#,
+    );
+    let key = key_file("missing");
+
+    let changed = reconciler(&server)
+        .publish_sshfp("web", &service(&key))
+        .await
+        .unwrap();
+    assert_eq!(changed, ["www.example.com"]);
+    let puts = server.requests_to("PUT");
+    assert_eq!(puts.len(), 1);
+    let body: serde_json::Value = serde_json::from_str(&puts[0].body).unwrap();
+    assert_eq!(
+        body,
+        serde_json::json!({"rrset_values": [VALUE], "rrset_ttl": 600})
+    );
+
+    std::fs::remove_file(key).unwrap();
+}
+
+#[tokio::test]
+async fn leaves_current_sshfp_records_alone() {
+    let server = MockServer::start().await;
+    server.route(
+        "GET",
+        SSHFP_PATH,
+        200,
+        &format!(
+            r#"{{"rrset_values": ["{}"], "rrset_ttl": 600}}"#,
+            VALUE.to_uppercase()
+        ),
+    );
+    let key = key_file("current");
+
+    let changed = reconciler(&server)
+        .publish_sshfp("web", &service(&key))
+        .await
+        .unwrap();
+    assert!(changed.is_empty());
+    assert!(server.requests_to("PUT").is_empty());
+
+    std::fs::remove_file(key).unwrap();
+}
@@ -0,0 +1,176 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    time::Duration,
+};
+
+use dynsix::{
+    provider::ProviderKind,
+    report::{Action, Reconciled, RunReport, ServiceReport},
+    state::{ManagedRecord, State},
+    DynsixError, ServiceConfig,
+};
+
+fn service(names: &str) -> ServiceConfig {
+    toml::from_str(&format!(
+        r#"
+        suffix = "::1"
+        names = {names}
+        fqdn = "example.com"
+        ttl = 300
+        "#
+    ))
+    .unwrap()
+}
+
+fn report(service: &str, action: Action) -> RunReport {
+    let mut report = RunReport::new("2001:db8::".parse().unwrap());
+    report.push(ServiceReport::new(
+        service.to_string(),
+        "www.example.com".to_string(),
+        "2001:db8::1".parse().unwrap(),
+        Ok(Reconciled { action, old: None }),
+        Duration::ZERO,
+    ));
+    report.finish();
+    report
+}
+
+#[test]
+fn tracks_created_records() {
+    let services = HashMap::from([("web".to_string(), service(r#"["www", "@"]"#))]);
+    let mut state = State::default();
+
+    state.track(&report("web", Action::Unchanged), &services);
+    assert!(state.managed.is_empty());
+
+    state.track(&report("web", Action::Created), &services);
+    let names: Vec<_> = state
+        .managed
+        .iter()
+        .map(|record| {
+            (
+                record.provider,
+                record.display_name(),
+                record.service.as_str(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        names,
+        [
+            (ProviderKind::Gandi, "example.com".to_string(), "web"),
+            (ProviderKind::Gandi, "www.example.com".to_string(), "web"),
+        ]
+    );
+}
+
+#[test]
+fn records_of_removed_services_are_orphaned() {
+    let services = HashMap::from([("web".to_string(), service(r#"["www", "api"]"#))]);
+    let mut state = State::default();
+    state.track(&report("web", Action::Updated), &services);
+
+    // The service was renamed and lost a name
+    let current = service(r#"["www"]"#);
+    let orphans: Vec<_> = state
+        .managed
+        .iter()
+        .filter(|record| !record.is_managed_by(&current))
+        .map(|record| record.display_name())
+        .collect();
+    assert_eq!(orphans, ["api.example.com"]);
+}
+
+#[test]
+fn forgets_deleted_records() {
+    let services = HashMap::from([("web".to_string(), service(r#"["www", "api"]"#))]);
+    let mut state = State::default();
+    state.track(&report("web", Action::Created), &services);
+
+    // Deleted by e.g. applying a plan, which doesn't know the address written
+    let mut deleted = RunReport::new("2001:db8::".parse().unwrap());
+    deleted.deleted.push(ManagedRecord {
+        provider: ProviderKind::Gandi,
+        fqdn: "example.com".to_string(),
+        name: "api".to_string(),
+        service: "web".to_string(),
+        address: None,
+    });
+    deleted.finish();
+    state.track(&deleted, &HashMap::new());
+
+    let names: Vec<_> = state
+        .managed
+        .iter()
+        .map(|record| record.display_name())
+        .collect();
+    assert_eq!(names, ["www.example.com"]);
+}
+
+#[test]
+fn old_state_files_have_no_managed_records() {
+    let state: State =
+        serde_json::from_str(r#"{"consecutive_failures": 1, "last_changed": {}}"#).unwrap();
+    assert!(state.managed.is_empty());
+}
+
+#[test]
+fn failed_attempts_keep_the_published_address() {
+    let mut state = State::default();
+    state.record_run(Some(&report("web", Action::Created)));
+    state.record_run(Some(&report("web", Action::Failed)));
+
+    let attempt = &state.last_attempt["web"];
+    assert_eq!(attempt.action, Action::Failed);
+    assert_eq!(attempt.published, Some("2001:db8::1".parse().unwrap()));
+
+    // And survive a restart
+    let state: State = serde_json::from_str(&serde_json::to_string(&state).unwrap()).unwrap();
+    assert_eq!(state.last_attempt["web"].record, "www.example.com");
+}
+
+#[test]
+fn adds_up_the_summaries_of_all_runs() {
+    let mut state = State::default();
+    state.record_run(Some(&report("web", Action::Created)));
+    state.record_run(Some(&report("web", Action::Unchanged)));
+    state.record_run(None);
+
+    assert_eq!(state.totals.runs, 3);
+    assert_eq!(
+        (
+            state.totals.created,
+            state.totals.unchanged,
+            state.totals.failed
+        ),
+        (1, 1, 0)
+    );
+}
+
+#[test]
+fn counts_errors_by_class() {
+    let mut state = State::default();
+    let mut failed = RunReport::new("2001:db8::".parse().unwrap());
+    failed.push(ServiceReport::new(
+        "web".to_string(),
+        "www.example.com".to_string(),
+        "2001:db8::1".parse().unwrap(),
+        Err(DynsixError::Provider {
+            provider: "deSEC",
+            operation: "updating",
+            message: "throttled".to_string(),
+        }),
+        Duration::ZERO,
+    ));
+    failed.finish();
+    state.record_run(Some(&report("mail", Action::Unchanged)));
+    state.record_run(Some(&failed));
+    state.count_error("address");
+
+    assert_eq!(
+        state.errors,
+        BTreeMap::from([("address".to_string(), 1), ("api".to_string(), 1)])
+    );
+    assert!(state.last_succeeded.contains_key("mail"));
+    assert!(!state.last_succeeded.contains_key("web"));
+}
@@ -0,0 +1,108 @@
+use std::net::Ipv6Addr;
+
+use dynsix::{
+    suffix::{Mac, Suffix},
+    ServiceConfig,
+};
+
+fn suffix(raw: &str) -> Result<Suffix, toml::de::Error> {
+    toml::from_str::<ServiceConfig>(&format!(
+        r#"
+        suffix = {raw}
+        name = "www"
+        fqdn = "example.com"
+        ttl = 600
+        "#
+    ))
+    .map(|service| service.suffix)
+}
+
+#[test]
+fn eui64_is_derived_like_slaac() {
+    let suffix = suffix(r#"{ eui64_from = "52:54:00:12:34:56" }"#).unwrap();
+    let prefix: Ipv6Addr = "2001:db8:1:2::".parse().unwrap();
+    assert_eq!(
+        suffix.address("www", prefix).unwrap(),
+        "2001:db8:1:2:5054:ff:fe12:3456"
+            .parse::<Ipv6Addr>()
+            .unwrap()
+    );
+}
+
+#[test]
+fn eui64_from_an_interface() {
+    assert_eq!(
+        suffix(r#"{ eui64_from = "eth0" }"#).unwrap(),
+        Suffix::Eui64(Mac::Interface("eth0".to_string()))
+    );
+    assert!(suffix(r#"{ eui64_from = "not/an interface" }"#).is_err());
+}
+
+// The kernel stores the digest in host byte order
+#[cfg(target_endian = "little")]
+#[test]
+fn stable_privacy_matches_the_kernel() {
+    // fe80::/64 of a veth device, which has no permanent MAC address, with
+    // stable_secret = 2001:db8:dead:beef::1 and addr_gen_mode = 2
+    let stable = suffix(
+        r#"{ stable_privacy = { secret = "2001:db8:dead:beef::1", mac_from = "00:00:00:00:00:00" } }"#,
+    )
+    .unwrap();
+    assert_eq!(
+        stable.address("www", "fe80::".parse().unwrap()).unwrap(),
+        "fe80::2f7b:db80:8d64:f77f".parse::<Ipv6Addr>().unwrap()
+    );
+
+    // Unlike EUI-64, it changes with the prefix
+    assert_ne!(
+        stable
+            .address("www", "2001:db8:1:2::".parse().unwrap())
+            .unwrap(),
+        "2001:db8:1:2:2f7b:db80:8d64:f77f"
+            .parse::<Ipv6Addr>()
+            .unwrap()
+    );
+    assert!(
+        suffix(r#"{ stable_privacy = { secret = "not an address", mac_from = "eth0" } }"#).is_err()
+    );
+}
+
+#[test]
+fn hashed_suffixes_are_stable_per_service_and_seed() {
+    let prefix: Ipv6Addr = "2001:db8:1:2::".parse().unwrap();
+    let hashed = suffix(r#"{ hash_of = "service", seed = "fleet" }"#).unwrap();
+    // Must never change, or every record moves to another address
+    assert_eq!(
+        hashed.address("www", prefix).unwrap(),
+        "2001:db8:1:2:1d1a:b993:a088:df95"
+            .parse::<Ipv6Addr>()
+            .unwrap()
+    );
+    assert_ne!(
+        hashed.address("www", prefix).unwrap(),
+        hashed.address("mail", prefix).unwrap()
+    );
+
+    let unseeded = suffix(r#"{ hash_of = "service" }"#).unwrap();
+    assert_ne!(
+        unseeded.address("www", prefix).unwrap(),
+        hashed.address("www", prefix).unwrap()
+    );
+}
+
+#[test]
+fn cidr_suffixes_state_the_split() {
+    assert_eq!(
+        suffix(r#""::1/64""#).unwrap(),
+        Suffix::Address("::1".parse().unwrap())
+    );
+
+    let split = suffix(r#""::12:0:0:0:1/56""#).unwrap();
+    assert_eq!(
+        split
+            .address("www", "2001:db8:1:2ff::".parse().unwrap())
+            .unwrap(),
+        "2001:db8:1:212::1".parse::<Ipv6Addr>().unwrap()
+    );
+    assert!(suffix(r#""::1/129""#).is_err());
+}
@@ -0,0 +1,47 @@
+use std::time::{Duration, Instant};
+
+use dynsix::{
+    http_client::{self, HttpConfig},
+    ip::get_public_ip,
+};
+use tokio::net::TcpListener;
+
+#[tokio::test]
+async fn gives_up_on_hanging_query_server() {
+    // Accepts connections but never answers
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}/", listener.local_addr().unwrap());
+    tokio::spawn(async move {
+        let mut connections = Vec::new();
+        while let Ok((stream, _)) = listener.accept().await {
+            connections.push(stream);
+        }
+    });
+
+    let client = http_client::client(&HttpConfig {
+        timeout: Duration::from_millis(300),
+        connect_timeout: Duration::from_millis(300),
+        ..Default::default()
+    })
+    .unwrap();
+    let start = Instant::now();
+    let result = get_public_ip(&client, &url).await;
+
+    assert!(result.is_err());
+    assert!(start.elapsed() < Duration::from_secs(5));
+}
+
+#[test]
+fn parses_timeouts() {
+    let config: HttpConfig = toml::from_str(
+        r#"
+        connect_timeout = "2s"
+        timeout = "1m"
+        pool_idle_timeout = "5s"
+        "#,
+    )
+    .unwrap();
+    assert_eq!(config.connect_timeout, Duration::from_secs(2));
+    assert_eq!(config.timeout, Duration::from_secs(60));
+    assert_eq!(config.pool_idle_timeout, Duration::from_secs(5));
+}
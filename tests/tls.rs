@@ -0,0 +1,109 @@
+use dynsix::{http_client::HttpConfig, ip::get_public_ip};
+use openssl::{
+    asn1::Asn1Time,
+    hash::MessageDigest,
+    pkey::{PKey, Private},
+    rsa::Rsa,
+    x509::{extension::SubjectAlternativeName, X509NameBuilder, X509},
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpListener,
+};
+use tokio_native_tls::{native_tls, TlsAcceptor};
+
+/// A self-signed certificate for localhost
+fn certificate() -> (X509, PKey<Private>) {
+    let key = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+    let mut name = X509NameBuilder::new().unwrap();
+    name.append_entry_by_text("CN", "localhost").unwrap();
+    let name = name.build();
+
+    let mut builder = X509::builder().unwrap();
+    builder.set_version(2).unwrap();
+    builder.set_subject_name(&name).unwrap();
+    builder.set_issuer_name(&name).unwrap();
+    builder.set_pubkey(&key).unwrap();
+    builder
+        .set_not_before(&Asn1Time::days_from_now(0).unwrap())
+        .unwrap();
+    builder
+        .set_not_after(&Asn1Time::days_from_now(1).unwrap())
+        .unwrap();
+    let san = SubjectAlternativeName::new()
+        .dns("localhost")
+        .build(&builder.x509v3_context(None, None))
+        .unwrap();
+    builder.append_extension(san).unwrap();
+    builder.sign(&key, MessageDigest::sha256()).unwrap();
+    (builder.build(), key)
+}
+
+/// Answers every connection with the JSON of the query server
+async fn https_server(certificate: &X509, key: &PKey<Private>) -> u16 {
+    let identity = native_tls::Identity::from_pkcs8(
+        &certificate.to_pem().unwrap(),
+        &key.private_key_to_pem_pkcs8().unwrap(),
+    )
+    .unwrap();
+    let acceptor = TlsAcceptor::from(native_tls::TlsAcceptor::new(identity).unwrap());
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            let acceptor = acceptor.clone();
+            tokio::spawn(async move {
+                let Ok(mut stream) = acceptor.accept(stream).await else {
+                    return;
+                };
+                let mut request = [0; 4096];
+                let _ = stream.read(&mut request).await;
+                let body = r#"{"ip": "2001:db8::4"}"#;
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                    body.len()
+                );
+                let _ = stream.write_all(response.as_bytes()).await;
+                let _ = stream.shutdown().await;
+            });
+        }
+    });
+    port
+}
+
+#[tokio::test]
+async fn trusts_the_configured_ca() {
+    let (certificate, key) = certificate();
+    let port = https_server(&certificate, &key).await;
+    let url = format!("https://localhost:{port}/");
+    let dir = std::env::temp_dir().join(format!("dynsix-tls-test-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let ca_file = dir.join("ca.pem");
+    std::fs::write(&ca_file, certificate.to_pem().unwrap()).unwrap();
+
+    let untrusted = dynsix::http_client::client(&HttpConfig::default()).unwrap();
+    assert!(get_public_ip(&untrusted, &url).await.is_err());
+
+    let trusted = dynsix::http_client::client(&HttpConfig {
+        ca_file: Some(ca_file),
+        system_roots: false,
+        ..Default::default()
+    })
+    .unwrap();
+    let ip = get_public_ip(&trusted, &url).await;
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(
+        ip.unwrap(),
+        "2001:db8::4".parse::<std::net::Ipv6Addr>().unwrap()
+    );
+}
+
+#[test]
+fn rejects_files_without_certificates() {
+    let config = HttpConfig {
+        ca_file: Some("Cargo.toml".into()),
+        ..Default::default()
+    };
+    assert!(dynsix::http_client::client(&config).is_err());
+}
@@ -0,0 +1,75 @@
+mod common;
+
+use common::MockServer;
+use dynsix::{gandi, provider::ProviderKind, tlsa, Reconciler};
+
+const TLSA_PATH: &str = "/livedns/domains/example.com/records/_443._tcp.www/TLSA";
+const NOT_FOUND: &str = r#"{"code": 404, "message": "Record not found", "object": "HTTPNotFound", "cause": "Not Found"}"#;
+const CERTIFICATE: &str = "\
+-----BEGIN CERTIFICATE-----
+MIIBiDCCAS+gAwIBAgIUEhzrU84w2Byw1M8SQZjVyH2kteswCgYIKoZIzj0EAwIw
+GjEYMBYGA1UEAwwPd3d3LmV4YW1wbGUuY29tMB4XDTI2MTAxNTExNTYwNVoXDTM2
+MTAxMjExNTYwNVowGjEYMBYGA1UEAwwPd3d3LmV4YW1wbGUuY29tMFkwEwYHKoZI
+zj0CAQYIKoZIzj0DAQcDQgAETFxu316AGk+J/WPW3XhSybnh+uCyGzH3TtxAylJa
+46W4KTngIBr51ra/gjA6uUa3gkqOQt8TkH8sy6F4dVx/lKNTMFEwHQYDVR0OBBYE
+FDEVO+mK5rqmJ1QlzoXvdv20kY63MB8GA1UdIwQYMBaAFDEVO+mK5rqmJ1QlzoXv
+dv20kY63MA8GA1UdEwEB/wQFMAMBAf8wCgYIKoZIzj0EAwIDRwAwRAIgd3JvTTo1
+fwyfxP6dP67d/Z3tlt+upg+qRoZrlKrpkMUCIFeFR2D2aC+0tuJDv7oP5piWbAaf
+7MZuiiq9ZyOvEBOL
+-----END CERTIFICATE-----
+";
+/// The SHA-256 of the public key, as `openssl pkey -pubin -outform der`
+/// hashes it
+const VALUE: &str = "3 1 1 10f8162a672dfa7904e8dc6fd8c46ade96fb41b602b814c725ca82e5695ae2ef";
+
+#[test]
+fn hashes_the_public_key_of_the_certificate() {
+    assert_eq!(tlsa::value(CERTIFICATE.as_bytes()).unwrap(), VALUE);
+    assert!(tlsa::value(b"not a certificate").is_err());
+}
+
+#[tokio::test]
+async fn replaces_the_record_when_the_key_changed() {
+    let server = MockServer::start().await;
+    let reconciler = Reconciler::new(gandi::Client::with_base_url(
+        reqwest::Client::new(),
+        "secret-token",
+        server.url(),
+    ));
+    let values = [VALUE.to_string()];
+    let publish = || {
+        reconciler.publish_records(
+            "",
+            ProviderKind::Gandi,
+            "example.com",
+            "_443._tcp.www",
+            tlsa::RECORD_TYPE,
+            3600,
+            &values,
+        )
+    };
+
+    server.route("GET", TLSA_PATH, 404, NOT_FOUND);
+    server.route(
+        "PUT",
+        TLSA_PATH,
+        201,
+        r#"{"message": "DNS Record Created"}"#,
+    );
+    assert!(publish().await.unwrap());
+    let puts = server.requests_to("PUT");
+    let body: serde_json::Value = serde_json::from_str(&puts[0].body).unwrap();
+    assert_eq!(
+        body,
+        serde_json::json!({"rrset_values": [VALUE], "rrset_ttl": 3600})
+    );
+
+    server.route(
+        "GET",
+        TLSA_PATH,
+        200,
+        &format!(r#"{{"rrset_values": ["{VALUE}"], "rrset_ttl": 3600}}"#),
+    );
+    assert!(!publish().await.unwrap());
+    assert_eq!(server.requests_to("PUT").len(), 1);
+}
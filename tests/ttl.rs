@@ -0,0 +1,139 @@
+mod common;
+
+use std::{
+    collections::HashMap,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use common::MockServer;
+use dynsix::{
+    gandi,
+    report::{Action, Reconciled, RunReport, ServiceReport},
+    state::State,
+    ttl::{self, LowTtl},
+    Reconciler, ServiceConfig,
+};
+
+const RECORD_PATH: &str = "/livedns/domains/example.com/records/www/AAAA";
+
+fn service() -> ServiceConfig {
+    toml::from_str(
+        r#"
+        suffix = "::1:2:3:4"
+        name = "www"
+        fqdn = "example.com"
+        ttl = 3600
+        low_ttl = { ttl = 300, expected_change = "0 4 * * *", before = "1h", stable_for = "30m" }
+        "#,
+    )
+    .unwrap()
+}
+
+fn low_ttl() -> LowTtl {
+    service().low_ttl.unwrap()
+}
+
+/// 2024-01-01 at `hour`:`minute` UTC
+fn at(hour: u64, minute: u64) -> SystemTime {
+    UNIX_EPOCH + Duration::from_secs(1_704_067_200 + hour * 3600 + minute * 60)
+}
+
+#[test]
+fn lowers_around_the_expected_change() {
+    let low_ttl = low_ttl();
+
+    assert!(!low_ttl.applies(at(2, 59), None));
+    assert!(low_ttl.applies(at(3, 0), None));
+    assert!(low_ttl.applies(at(4, 0), None));
+    assert!(low_ttl.applies(at(4, 29), None));
+    assert!(!low_ttl.applies(at(4, 31), None));
+}
+
+#[test]
+fn stays_low_until_the_address_is_stable() {
+    let low_ttl = low_ttl();
+    let changed = Some(at(12, 0));
+
+    assert!(low_ttl.applies(at(12, 10), changed));
+    assert!(!low_ttl.applies(at(12, 30), changed));
+}
+
+#[test]
+fn lowers_services_from_the_state() {
+    let mut services = HashMap::from([
+        ("web".to_string(), service()),
+        ("mail".to_string(), service()),
+    ]);
+    let mut state = State::default();
+    let now = at(12, 0);
+    let changed = now.duration_since(UNIX_EPOCH).unwrap().as_secs() - 60;
+    state.address_changed.insert("web".to_string(), changed);
+
+    ttl::lower(&mut services, &state, now);
+
+    assert_eq!(services["web"].ttl, 300);
+    assert_eq!(services["mail"].ttl, 3600);
+}
+
+#[test]
+fn only_address_changes_count() {
+    let mut report = RunReport::new("2001:db8::".parse().unwrap());
+    for (service, old) in [("ttl", "2001:db8::1"), ("address", "2001:db8:ff::1")] {
+        report.push(ServiceReport::new(
+            service.to_string(),
+            format!("{service}.example.com"),
+            "2001:db8::1".parse().unwrap(),
+            Ok(Reconciled {
+                action: Action::Updated,
+                old: Some(vec![old.to_string()]),
+            }),
+            Duration::ZERO,
+        ));
+    }
+    let mut state = State::default();
+    state.record_run(Some(&report));
+
+    assert!(state.address_changed.contains_key("address"));
+    assert!(!state.address_changed.contains_key("ttl"));
+}
+
+#[tokio::test]
+async fn updates_records_with_another_ttl() {
+    let server = MockServer::start().await;
+    server.route(
+        "GET",
+        RECORD_PATH,
+        200,
+        r#"{"rrset_values": ["2001:db8:aa:bb:1:2:3:4"], "rrset_ttl": 3600}"#,
+    );
+    server.route(
+        "PUT",
+        RECORD_PATH,
+        201,
+        r#"{"message": "DNS Record Created"}"#,
+    );
+    let reconciler = Reconciler::new(gandi::Client::with_base_url(
+        reqwest::Client::new(),
+        "secret-token",
+        server.url(),
+    ));
+    let public_ip = "2001:db8:aa:bb::1".parse().unwrap();
+
+    let unchanged = reconciler
+        .reconcile_service("web", &service(), public_ip)
+        .await;
+    let lowered = ServiceConfig {
+        ttl: 300,
+        ..service()
+    };
+    let updated = reconciler
+        .reconcile_service("web", &lowered, public_ip)
+        .await;
+
+    assert_eq!(unchanged.action, Action::Unchanged);
+    assert_eq!(updated.action, Action::Updated);
+    let puts = server.requests_to("PUT");
+    assert_eq!(puts.len(), 1);
+    let body: serde_json::Value = serde_json::from_str(&puts[0].body).unwrap();
+    assert_eq!(body["rrset_ttl"], 300);
+}
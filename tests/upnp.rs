@@ -0,0 +1,124 @@
+mod common;
+
+use common::MockServer;
+use dynsix::upnp::{control_url, external_address, location, search_request};
+use reqwest::Url;
+
+const DESCRIPTION: &str = r#"<?xml version="1.0"?>
+<root xmlns="urn:schemas-upnp-org:device-1-0">
+<device>
+<deviceType>urn:schemas-upnp-org:device:InternetGatewayDevice:1</deviceType>
+<deviceList><device>
+<deviceType>urn:schemas-upnp-org:device:WANConnectionDevice:1</deviceType>
+<serviceList>
+<service>
+<serviceType>urn:schemas-upnp-org:service:WANIPv6FirewallControl:1</serviceType>
+<controlURL>/igd2upnp/control/WANIPv6FirewallControl1</controlURL>
+</service>
+<service>
+<serviceType>urn:schemas-upnp-org:service:WANIPConnection:1</serviceType>
+<controlURL>/igdupnp/control/WANIPConn1</controlURL>
+</service>
+</serviceList>
+</device></deviceList>
+</device>
+</root>"#;
+
+fn answer(action: &str, argument: &str, value: &str) -> String {
+    format!(
+        r#"<?xml version="1.0"?>
+<s:Envelope xmlns:s="http://schemas.xmlsoap.org/soap/envelope/"><s:Body>
+<u:{action}Response xmlns:u="urn:schemas-upnp-org:service:WANIPConnection:1">
+<{argument}>{value}</{argument}>
+</u:{action}Response>
+</s:Body></s:Envelope>"#
+    )
+}
+
+#[test]
+fn finds_the_gateway_and_its_wan_connection() {
+    assert!(
+        search_request("urn:schemas-upnp-org:device:InternetGatewayDevice:1")
+            .contains("\r\nST: urn:schemas-upnp-org:device:InternetGatewayDevice:1\r\n")
+    );
+    assert_eq!(
+        location(
+            "HTTP/1.1 200 OK\r\nCACHE-CONTROL: max-age=1800\r\n\
+             Location: http://192.168.178.1:49000/igddesc.xml\r\nST: upnp:rootdevice\r\n\r\n"
+        ),
+        Some("http://192.168.178.1:49000/igddesc.xml")
+    );
+    assert_eq!(
+        location("NOTIFY * HTTP/1.1\r\nLOCATION: http://x/\r\n"),
+        None
+    );
+
+    let location = Url::parse("http://192.168.178.1:49000/igddesc.xml").unwrap();
+    let (service_type, url) = control_url(DESCRIPTION, &location).unwrap();
+    assert_eq!(
+        service_type,
+        "urn:schemas-upnp-org:service:WANIPConnection:1"
+    );
+    assert_eq!(
+        url.as_str(),
+        "http://192.168.178.1:49000/igdupnp/control/WANIPConn1"
+    );
+    assert!(control_url("<root></root>", &location).is_none());
+}
+
+#[tokio::test]
+async fn asks_the_gateway_for_the_prefix() {
+    let server = MockServer::start().await;
+    server.route("GET", "/igddesc.xml", 200, DESCRIPTION);
+    server.route(
+        "POST",
+        "/igdupnp/control/WANIPConn1",
+        200,
+        &answer(
+            "X_AVM_DE_GetIPv6Prefix",
+            "NewIPv6Prefix",
+            "2001:db8:1:200::",
+        ),
+    );
+
+    let location = format!("{}/igddesc.xml", server.url());
+    let address = external_address(&reqwest::Client::new(), &location)
+        .await
+        .unwrap();
+    assert_eq!(
+        address,
+        "2001:db8:1:200::".parse::<std::net::Ipv6Addr>().unwrap()
+    );
+
+    let calls = server.requests_to("POST");
+    assert_eq!(calls.len(), 1);
+    assert_eq!(
+        calls[0].header("SOAPAction"),
+        Some("\"urn:schemas-upnp-org:service:WANIPConnection:1#X_AVM_DE_GetIPv6Prefix\"")
+    );
+    assert!(calls[0].body.contains("<u:X_AVM_DE_GetIPv6Prefix "));
+}
+
+#[tokio::test]
+async fn fails_when_the_gateway_only_knows_ipv4() {
+    let server = MockServer::start().await;
+    server.route("GET", "/igddesc.xml", 200, DESCRIPTION);
+    server.route(
+        "POST",
+        "/igdupnp/control/WANIPConn1",
+        200,
+        &answer(
+            "GetExternalIPAddress",
+            "NewExternalIPAddress",
+            "203.0.113.7",
+        ),
+    );
+
+    let location = format!("{}/igddesc.xml", server.url());
+    let error = external_address(&reqwest::Client::new(), &location)
+        .await
+        .unwrap_err();
+    // Every action was tried
+    assert_eq!(server.requests_to("POST").len(), 3);
+    assert!(error.contains("GetExternalIPAddress: '203.0.113.7' is no IPv6 address"));
+}
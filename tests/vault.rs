@@ -0,0 +1,91 @@
+mod common;
+
+use std::time::Duration;
+
+use common::MockServer;
+use dynsix::{
+    vault::{self, VaultConfig},
+    DynsixError,
+};
+
+fn vault_config(address: &str, auth: &str) -> VaultConfig {
+    toml::from_str(&format!(
+        r#"
+        address = "{address}"
+        path = "secret/data/dynsix"
+        refresh = "2h"
+        auth = {auth}
+        "#
+    ))
+    .unwrap()
+}
+
+#[tokio::test]
+async fn reads_token_with_approle() {
+    let server = MockServer::start().await;
+    server.route(
+        "POST",
+        "/v1/auth/approle/login",
+        200,
+        r#"{"auth": {"client_token": "hvs.client", "lease_duration": 3600}}"#,
+    );
+    server.route(
+        "GET",
+        "/v1/secret/data/dynsix",
+        200,
+        r#"{"lease_duration": 0, "data": {"data": {"token": "gandi-pat"}, "metadata": {"version": 3}}}"#,
+    );
+
+    let config = vault_config(
+        server.url(),
+        r#"{ method = "approle", role_id = "role", secret_id = "secret" }"#,
+    );
+    let (token, valid) = vault::fetch_token(&reqwest::Client::new(), &config)
+        .await
+        .unwrap();
+
+    assert_eq!(token.expose(), "gandi-pat");
+    assert_eq!(valid, Duration::from_secs(7200));
+    let login = &server.requests_to("POST")[0];
+    assert!(login.body.contains(r#""role_id":"role""#));
+    let read = &server.requests_to("GET")[0];
+    assert_eq!(read.header("x-vault-token"), Some("hvs.client"));
+}
+
+#[tokio::test]
+async fn lease_shortens_validity() {
+    let server = MockServer::start().await;
+    server.route(
+        "GET",
+        "/v1/secret/data/dynsix",
+        200,
+        r#"{"lease_duration": 600, "data": {"token": "gandi-pat"}}"#,
+    );
+
+    let config = vault_config(server.url(), r#"{ method = "token", token = "hvs.root" }"#);
+    let (token, valid) = vault::fetch_token(&reqwest::Client::new(), &config)
+        .await
+        .unwrap();
+
+    assert_eq!(token.expose(), "gandi-pat");
+    assert_eq!(valid, Duration::from_secs(600));
+}
+
+#[tokio::test]
+async fn reports_vault_errors() {
+    let server = MockServer::start().await;
+    server.route(
+        "GET",
+        "/v1/secret/data/dynsix",
+        403,
+        r#"{"errors": ["permission denied"]}"#,
+    );
+
+    let config = vault_config(server.url(), r#"{ method = "token", token = "hvs.root" }"#);
+    let error = vault::fetch_token(&reqwest::Client::new(), &config)
+        .await
+        .unwrap_err();
+
+    assert!(matches!(error, DynsixError::Vault { .. }));
+    assert!(error.to_string().contains("permission denied"));
+}
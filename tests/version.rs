@@ -0,0 +1,29 @@
+use dynsix::{config::SCHEMA_VERSION, provider::ProviderKind, version};
+
+#[test]
+fn long_version_describes_the_build() {
+    let long = version::long_version("dynsix");
+    let mut lines = long.lines();
+    assert_eq!(
+        lines.next(),
+        Some(format!("dynsix {}", env!("CARGO_PKG_VERSION")).as_str())
+    );
+    assert!(long.contains(&format!("commit:         {}", version::GIT_COMMIT)));
+    assert!(long.contains(&format!("config schema:  {SCHEMA_VERSION}")));
+    assert_eq!(version::BUILD_DATE.len(), 10);
+    assert!(long
+        .ends_with("providers:      gandi, route53, hetzner, desec, http, dyndns2, porkbun, ovh"));
+}
+
+#[test]
+fn features_list_every_provider() {
+    let features = version::features();
+    let (_, providers) = features
+        .iter()
+        .find(|(kind, _)| *kind == "providers")
+        .unwrap();
+    assert_eq!(providers.len(), ProviderKind::ALL.len());
+    assert!(features
+        .iter()
+        .any(|(kind, names)| *kind == "ip sources" && names.contains(&"interface")));
+}
@@ -0,0 +1,74 @@
+mod common;
+
+use common::MockServer;
+use dynsix::{gandi, zone::ZoneBackup, DynsixError};
+
+const RECORDS_PATH: &str = "/livedns/domains/example.com/records";
+const RECORDS: &str = r#"[
+    {"rrset_name": "@", "rrset_type": "MX", "rrset_ttl": 10800, "rrset_values": ["10 mail.example.com."]},
+    {"rrset_name": "www", "rrset_type": "AAAA", "rrset_ttl": 300, "rrset_values": ["2001:db8::80"]}
+]"#;
+
+fn client(server: &MockServer) -> gandi::Client {
+    gandi::Client::with_base_url(reqwest::Client::new(), "secret-token", server.url())
+}
+
+#[tokio::test]
+async fn backs_up_and_restores_all_records() {
+    let server = MockServer::start().await;
+    server.route("GET", RECORDS_PATH, 200, RECORDS);
+    server.route(
+        "PUT",
+        "/livedns/domains/example.org/records",
+        201,
+        r#"{"message": "DNS Record Created"}"#,
+    );
+
+    let backup = ZoneBackup::fetch(&client(&server), "example.com")
+        .await
+        .unwrap();
+    assert_eq!(backup.domain, "example.com");
+    assert_eq!(backup.records.len(), 2);
+
+    // Through a file into another domain
+    let path = std::env::temp_dir().join(format!("dynsix-zone-test-{}.json", std::process::id()));
+    backup.save(&path).unwrap();
+    let loaded = ZoneBackup::load(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(loaded, backup);
+    loaded
+        .restore(&client(&server), "example.org")
+        .await
+        .unwrap();
+
+    let puts = server.requests_to("PUT");
+    assert_eq!(puts.len(), 1);
+    let body: serde_json::Value = serde_json::from_str(&puts[0].body).unwrap();
+    let records: serde_json::Value = serde_json::from_str(RECORDS).unwrap();
+    assert_eq!(body, serde_json::json!({ "items": records }));
+}
+
+#[tokio::test]
+async fn rejected_restores_fail() {
+    let server = MockServer::start().await;
+    server.route(
+        "PUT",
+        RECORDS_PATH,
+        403,
+        r#"{"code": 403, "message": "Access was denied", "object": "HTTPForbidden", "cause": "Forbidden"}"#,
+    );
+    let backup = ZoneBackup {
+        domain: "example.com".to_string(),
+        created: "2024-01-01T00:00:00Z".to_string(),
+        records: Vec::new(),
+    };
+
+    let error = backup
+        .restore(&client(&server), "example.com")
+        .await
+        .unwrap_err();
+    assert!(
+        matches!(error, DynsixError::GandiDomainForbidden { ref domain, .. } if domain == "example.com"),
+        "{error}"
+    );
+}